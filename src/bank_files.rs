@@ -0,0 +1,346 @@
+//! Reads bank statements exported as OFX, CAMT.053 XML or CSV into plain
+//! statement lines, so the statement import can treat every bank the same way.
+
+use crate::tabular::{parse_amount, parse_date, read_table, Table, TableOptions};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+pub const STATEMENT_EXTENSIONS: &[&str] = &["ofx", "qfx", "xml", "csv"];
+
+/// One booked line of a statement. `amount` is positive for money received.
+/// `external_id` is the bank's own id for the line, when the format has one.
+#[derive(Debug, Clone)]
+pub struct StatementLine {
+    pub line: usize,
+    pub date: String,
+    pub amount: f64,
+    pub currency: Option<String>,
+    pub description: String,
+    pub reference: String,
+    pub external_id: Option<String>,
+}
+
+/// The lines read from a statement file, and how many rows were passed over
+/// because they had no date or amount (balances, totals and the like).
+#[derive(Debug)]
+pub struct StatementFile {
+    pub format: &'static str,
+    pub lines: Vec<StatementLine>,
+    pub skipped_rows: usize,
+}
+
+/// Which columns of a bank CSV hold what. Unset columns are found by their
+/// usual header names; a file needs a date column and either an amount
+/// column or credit and debit columns. `header_row` is the 1-based row with
+/// the column names, for banks that put account details above it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BankCsvMapping {
+    pub date_column: Option<String>,
+    pub amount_column: Option<String>,
+    pub credit_column: Option<String>,
+    pub debit_column: Option<String>,
+    pub description_column: Option<String>,
+    pub reference_column: Option<String>,
+    pub header_row: Option<usize>,
+}
+
+struct CsvColumns {
+    date: Option<usize>,
+    amount: Option<usize>,
+    credit: Option<usize>,
+    debit: Option<usize>,
+    description: Option<usize>,
+    reference: Option<usize>,
+}
+
+impl CsvColumns {
+    fn resolve(table: &Table, mapping: &BankCsvMapping) -> Result<Self, String> {
+        let column = |configured: &Option<String>, aliases: &[&str]| -> Result<Option<usize>, String> {
+            match configured {
+                Some(name) => table
+                    .find_column(&[name.as_str()])
+                    .map(Some)
+                    .ok_or_else(|| format!("Column '{}' is not in the file", name)),
+                None => Ok(table.find_column(aliases)),
+            }
+        };
+        let columns = CsvColumns {
+            date: column(
+                &mapping.date_column,
+                &["date", "transaction date", "posting date", "booking date", "value date"],
+            )?,
+            amount: column(&mapping.amount_column, &["amount", "transaction amount", "value"])?,
+            credit: column(
+                &mapping.credit_column,
+                &["credit", "credit amount", "credits", "money in", "deposit", "deposits"],
+            )?,
+            debit: column(
+                &mapping.debit_column,
+                &[
+                    "debit",
+                    "debit amount",
+                    "debits",
+                    "money out",
+                    "withdrawal",
+                    "withdrawals",
+                ],
+            )?,
+            description: column(
+                &mapping.description_column,
+                &[
+                    "description",
+                    "narrative",
+                    "details",
+                    "transaction description",
+                    "memo",
+                    "payee",
+                ],
+            )?,
+            reference: column(
+                &mapping.reference_column,
+                &["reference", "ref", "payment reference", "customer reference"],
+            )?,
+        };
+        if columns.date.is_none() {
+            return Err("The file needs a date column".to_string());
+        }
+        if columns.amount.is_none() && columns.credit.is_none() {
+            return Err("The file needs an amount column or a credit column".to_string());
+        }
+        Ok(columns)
+    }
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .unwrap_or_default()
+}
+
+/// Reads a statement, telling OFX from CAMT.053 by content since both may
+/// come as `.xml`. The mapping only applies to CSV files.
+pub fn read_statement(path: &Path, mapping: &BankCsvMapping) -> Result<StatementFile, String> {
+    match extension(path).as_str() {
+        "csv" => read_csv_statement(path, mapping),
+        "ofx" | "qfx" | "xml" => {
+            let content =
+                std::fs::read_to_string(path).map_err(|e| format!("Could not open {}: {}", path.display(), e))?;
+            if content.contains("<OFX>") {
+                read_ofx(&content)
+            } else if content.contains("BkToCstmrStmt") {
+                read_camt(&content)
+            } else {
+                Err("The file is neither an OFX statement nor a CAMT.053 statement".to_string())
+            }
+        }
+        other => Err(format!(
+            "Unsupported statement type '{}'; expected one of {}",
+            other,
+            STATEMENT_EXTENSIONS.join(", ")
+        )),
+    }
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// The value of an OFX element. OFX 1 is SGML and leaves most elements
+/// unclosed, so a value runs to the next tag or line break.
+fn ofx_value(block: &str, tag: &str) -> Option<String> {
+    let start = block.find(&format!("<{}>", tag))? + tag.len() + 2;
+    let rest = &block[start..];
+    let end = rest.find(['<', '\r', '\n']).unwrap_or(rest.len());
+    let value = decode_entities(rest[..end].trim());
+    (!value.is_empty()).then_some(value)
+}
+
+/// OFX dates start YYYYMMDD and may carry a time and zone after it.
+fn ofx_date(value: &str) -> Option<String> {
+    let digits = value.get(..8).filter(|d| d.chars().all(|c| c.is_ascii_digit()))?;
+    Some(format!("{}-{}-{}", &digits[..4], &digits[4..6], &digits[6..]))
+}
+
+fn read_ofx(content: &str) -> Result<StatementFile, String> {
+    let currency = ofx_value(content, "CURDEF");
+    let mut lines = Vec::new();
+    for (offset, _) in content.match_indices("<STMTTRN>") {
+        let line = content[..offset].lines().count() + 1;
+        let block = &content[offset..];
+        let block = &block[..block.find("</STMTTRN>").unwrap_or(block.len())];
+
+        let amount = ofx_value(block, "TRNAMT")
+            .and_then(|amount| parse_amount(&amount))
+            .ok_or_else(|| format!("Line {}: the transaction has no amount", line))?;
+        let date = ofx_value(block, "DTPOSTED")
+            .and_then(|date| ofx_date(&date))
+            .ok_or_else(|| format!("Line {}: the transaction has no posting date", line))?;
+        let description = [ofx_value(block, "NAME"), ofx_value(block, "MEMO")]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(" - ");
+        lines.push(StatementLine {
+            line,
+            date,
+            amount,
+            currency: ofx_value(block, "CURSYM").or_else(|| currency.clone()),
+            description,
+            reference: ofx_value(block, "REFNUM")
+                .or_else(|| ofx_value(block, "CHECKNUM"))
+                .unwrap_or_default(),
+            external_id: ofx_value(block, "FITID"),
+        });
+    }
+    if lines.is_empty() && !content.contains("<BANKTRANLIST>") {
+        return Err("The OFX file holds no bank statement".to_string());
+    }
+    Ok(StatementFile {
+        format: "ofx",
+        lines,
+        skipped_rows: 0,
+    })
+}
+
+/// The first descendant reached by following `path` through child elements.
+fn xml_child<'a, 'input>(node: roxmltree::Node<'a, 'input>, path: &[&str]) -> Option<roxmltree::Node<'a, 'input>> {
+    path.iter().try_fold(node, |node, name| {
+        node.children()
+            .find(|child| child.is_element() && child.tag_name().name() == *name)
+    })
+}
+
+fn xml_text(node: roxmltree::Node, path: &[&str]) -> Option<String> {
+    xml_child(node, path)
+        .and_then(|node| node.text())
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+        .map(str::to_string)
+}
+
+/// Reads the entries of every statement in a CAMT.053 file. Entries booked as
+/// a batch are kept as one line with the first transaction's details.
+fn read_camt(content: &str) -> Result<StatementFile, String> {
+    let document = roxmltree::Document::parse(content).map_err(|e| format!("Invalid XML: {}", e))?;
+    let mut lines = Vec::new();
+    for entry in document
+        .descendants()
+        .filter(|node| node.is_element() && node.tag_name().name() == "Ntry")
+    {
+        let line = document.text_pos_at(entry.range().start).row as usize;
+        let amount_node =
+            xml_child(entry, &["Amt"]).ok_or_else(|| format!("Line {}: the entry has no amount", line))?;
+        let amount = amount_node
+            .text()
+            .and_then(|amount| amount.trim().parse::<f64>().ok())
+            .ok_or_else(|| format!("Line {}: the entry amount is not a number", line))?;
+        let sign = match xml_text(entry, &["CdtDbtInd"]).as_deref() {
+            Some("DBIT") => -1.0,
+            _ => 1.0,
+        };
+        let date = [
+            &["BookgDt", "Dt"][..],
+            &["BookgDt", "DtTm"],
+            &["ValDt", "Dt"],
+            &["ValDt", "DtTm"],
+        ]
+        .iter()
+        .find_map(|path| xml_text(entry, path))
+        .and_then(|date| date.get(..10).map(str::to_string))
+        .ok_or_else(|| format!("Line {}: the entry has no booking date", line))?;
+
+        let details = xml_child(entry, &["NtryDtls", "TxDtls"]);
+        let detail = |path: &[&str]| details.and_then(|details| xml_text(details, path));
+        let reference = [
+            detail(&["RmtInf", "Strd", "CdtrRefInf", "Ref"]),
+            detail(&["Refs", "EndToEndId"]).filter(|id| id != "NOTPROVIDED"),
+        ]
+        .into_iter()
+        .flatten()
+        .next()
+        .unwrap_or_default();
+        let unstructured = details
+            .map(|details| {
+                details
+                    .descendants()
+                    .filter(|node| node.is_element() && node.tag_name().name() == "Ustrd")
+                    .filter_map(|node| node.text().map(str::trim))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .filter(|text| !text.is_empty());
+        let description = [
+            detail(&["RltdPties", "Dbtr", "Nm"]).or_else(|| detail(&["RltdPties", "Dbtr", "Pty", "Nm"])),
+            unstructured,
+            xml_text(entry, &["AddtlNtryInf"]),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" - ");
+
+        lines.push(StatementLine {
+            line,
+            date,
+            amount: sign * amount,
+            currency: amount_node.attribute("Ccy").map(str::to_string),
+            description,
+            reference,
+            external_id: xml_text(entry, &["AcctSvcrRef"]).or_else(|| xml_text(entry, &["NtryRef"])),
+        });
+    }
+    if lines.is_empty() && !document.descendants().any(|node| node.tag_name().name() == "Stmt") {
+        return Err("The XML file holds no CAMT.053 statement".to_string());
+    }
+    Ok(StatementFile {
+        format: "camt053",
+        lines,
+        skipped_rows: 0,
+    })
+}
+
+fn read_csv_statement(path: &Path, mapping: &BankCsvMapping) -> Result<StatementFile, String> {
+    let table = read_table(
+        path,
+        &TableOptions {
+            sheet: None,
+            header_row: mapping.header_row,
+        },
+    )?;
+    let columns = CsvColumns::resolve(&table, mapping)?;
+
+    let mut lines = Vec::new();
+    let mut skipped_rows = 0;
+    for row in &table.rows {
+        let amount = match columns.amount {
+            Some(_) => parse_amount(row.get(columns.amount)),
+            None => {
+                let credit = parse_amount(row.get(columns.credit));
+                let debit = parse_amount(row.get(columns.debit));
+                (credit.is_some() || debit.is_some()).then(|| credit.unwrap_or(0.0).abs() - debit.unwrap_or(0.0).abs())
+            }
+        };
+        match (parse_date(row.get(columns.date)), amount) {
+            (Some(date), Some(amount)) => lines.push(StatementLine {
+                line: row.line,
+                date,
+                amount,
+                currency: None,
+                description: row.get(columns.description).to_string(),
+                reference: row.get(columns.reference).to_string(),
+                external_id: None,
+            }),
+            _ => skipped_rows += 1,
+        }
+    }
+    Ok(StatementFile {
+        format: "csv",
+        lines,
+        skipped_rows,
+    })
+}
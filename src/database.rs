@@ -1,8 +1,64 @@
-use rusqlite::{params, Connection, Result};
+mod accounting_export;
+mod bank_statements;
+mod billing_documents;
+mod clawbacks;
+mod client_import;
+mod commission_rates;
+mod commission_rules;
+mod credit_notes;
+mod currency_codes;
+mod debt_codes;
+mod dunning;
+mod exchange_rates;
+mod import_templates;
+mod invoice_run;
+mod invoice_status;
+mod invoice_tracking;
+mod partner_analytics;
+mod partner_hierarchy;
+mod partner_statements;
+mod payments;
+mod remittances;
+mod reports;
+mod snapshot;
+#[cfg(test)]
+mod test_support;
+
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use std::sync::Mutex;
 
+pub use accounting_export::{AccountMapping, AccountingJournal};
+pub use bank_statements::{BankMatchCandidate, BankStatementImportReport, BankStatementOptions, BankTransaction};
+pub use billing_documents::{BillingDocument, CompanyProfile};
+pub use clawbacks::ClawbackRule;
+pub use client_import::{ClientImportOptions, ClientImportPreview, ClientImportReport};
+pub use commission_rates::{CommissionRateHistory, CommissionRateRequest, ResolvedCommissionRate};
+pub use commission_rules::{CommissionCalculation, CommissionRuleSet};
+pub use credit_notes::{AccountBalance, CreditNote, CreditNoteRequest};
+pub use currency_codes::CurrencyNormalizationReport;
+pub use debt_codes::{ClientUpsert, DebtCodeReport};
+pub use dunning::{DunningCandidate, DunningNotice, DunningSettings};
+pub use exchange_rates::{
+    ConvertedAmount, ExchangeRate, ExchangeRateRequest, RateImportSummary, ReportingTotals,
+};
+pub use import_templates::{import_template, import_templates, ImportTemplate};
+pub use invoice_run::InvoiceRunSummary;
+pub use partner_analytics::{PartnerPerformanceReport, PerformanceFigures};
+pub use partner_hierarchy::{CommissionLine, CommissionSplit, DistributorRollup, PartnerHierarchyNode};
+pub use partner_statements::{
+    CommissionAdjustment, CommissionAdjustmentRequest, CommissionPayout, CommissionPayoutRequest, PartnerStatement,
+};
+pub use invoice_status::InvoiceStatusChange;
+pub use invoice_tracking::{UninvoicedVarClient, VarInvoiceMonthTracking};
+pub use payments::{AllocationRequest, ArAgingReport, OpenInvoice, Payment, PaymentRequest};
+pub use remittances::{RemittanceImportReport, RemittanceMapping};
+pub use reports::{ReportParameters, ReportResult};
+pub use snapshot::SnapshotSummary;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Client {
     pub id: String,
@@ -126,6 +182,8 @@ pub struct VarClientInvoice {
     pub notes: Option<String>,
     pub created_at: String,
     pub updated_at: String,
+    #[serde(default)]
+    pub commission_source: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -301,6 +359,39 @@ impl Database {
             [],
         )?;
 
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS document_sequences (
+                name TEXT PRIMARY KEY,
+                next_value INTEGER NOT NULL
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS app_settings (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        bank_statements::create_tables(&conn)?;
+        billing_documents::create_tables(&conn)?;
+        clawbacks::create_tables(&conn)?;
+        client_import::create_tables(&conn)?;
+        commission_rates::create_tables(&conn)?;
+        commission_rules::create_tables(&conn)?;
+        credit_notes::create_tables(&conn)?;
+        debt_codes::create_tables(&conn)?;
+        dunning::create_tables(&conn)?;
+        exchange_rates::create_tables(&conn)?;
+        invoice_run::create_tables(&conn)?;
+        invoice_status::create_tables(&conn)?;
+        invoice_tracking::create_tables(&conn)?;
+        partner_hierarchy::create_tables(&conn)?;
+        partner_statements::create_tables(&conn)?;
+        payments::create_tables(&conn)?;
+
         Ok(())
     }
 
@@ -364,74 +455,16 @@ impl Database {
         clients.collect()
     }
 
-    pub fn add_client(&self, client: Client) -> Result<()> {
+    pub fn add_client(&self, mut client: Client) -> Result<()> {
+        client.currency = checked_currency(&client.currency)?;
         let conn = self.conn.lock().unwrap();
-        conn.execute(
-            "INSERT INTO clients (
-                id, client_name, debt_code, users, billing_model, currency,
-                jan, feb, mar, apr, may, jun, jul, aug, sep, oct, nov, dec, total,
-                comments, deal_start_date, anniversary_month, billing_frequency,
-                installment_months, monthly_factor, implementation_fee, implementation_months,
-                implementation_start_date, implementation_complete_date, subscription_duration,
-                subscription_start_date, monthly_license_rate, commission_rate, var_partner,
-                is_active, created_at, custom_increase_rate, future_year_data, base_year_data
-            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16,
-                      ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25, ?26, ?27, ?28, ?29, ?30,
-                      ?31, ?32, ?33, ?34, ?35, ?36, ?37, ?38, ?39)",
-            params![
-                client.id, client.client_name, client.debt_code, client.users,
-                client.billing_model, client.currency, client.jan, client.feb,
-                client.mar, client.apr, client.may, client.jun, client.jul,
-                client.aug, client.sep, client.oct, client.nov, client.dec,
-                client.total, client.comments, client.deal_start_date,
-                client.anniversary_month, client.billing_frequency,
-                client.installment_months, client.monthly_factor,
-                client.implementation_fee, client.implementation_months,
-                client.implementation_start_date, client.implementation_complete_date,
-                client.subscription_duration, client.subscription_start_date,
-                client.monthly_license_rate, client.commission_rate,
-                client.var_partner, if client.is_active { 1 } else { 0 },
-                client.created_at, client.custom_increase_rate,
-                client.future_year_data, client.base_year_data
-            ],
-        )?;
-        Ok(())
+        insert_client(&conn, &client)
     }
 
-    pub fn update_client(&self, client: Client) -> Result<()> {
+    pub fn update_client(&self, mut client: Client) -> Result<()> {
+        client.currency = checked_currency(&client.currency)?;
         let conn = self.conn.lock().unwrap();
-        conn.execute(
-            "UPDATE clients SET
-                client_name = ?2, debt_code = ?3, users = ?4, billing_model = ?5,
-                currency = ?6, jan = ?7, feb = ?8, mar = ?9, apr = ?10, may = ?11,
-                jun = ?12, jul = ?13, aug = ?14, sep = ?15, oct = ?16, nov = ?17,
-                dec = ?18, total = ?19, comments = ?20, deal_start_date = ?21,
-                anniversary_month = ?22, billing_frequency = ?23, installment_months = ?24,
-                monthly_factor = ?25, implementation_fee = ?26, implementation_months = ?27,
-                implementation_start_date = ?28, implementation_complete_date = ?29,
-                subscription_duration = ?30, subscription_start_date = ?31,
-                monthly_license_rate = ?32, commission_rate = ?33, var_partner = ?34,
-                is_active = ?35, custom_increase_rate = ?36, future_year_data = ?37,
-                base_year_data = ?38
-             WHERE id = ?1",
-            params![
-                client.id, client.client_name, client.debt_code, client.users,
-                client.billing_model, client.currency, client.jan, client.feb,
-                client.mar, client.apr, client.may, client.jun, client.jul,
-                client.aug, client.sep, client.oct, client.nov, client.dec,
-                client.total, client.comments, client.deal_start_date,
-                client.anniversary_month, client.billing_frequency,
-                client.installment_months, client.monthly_factor,
-                client.implementation_fee, client.implementation_months,
-                client.implementation_start_date, client.implementation_complete_date,
-                client.subscription_duration, client.subscription_start_date,
-                client.monthly_license_rate, client.commission_rate,
-                client.var_partner, if client.is_active { 1 } else { 0 },
-                client.custom_increase_rate, client.future_year_data,
-                client.base_year_data
-            ],
-        )?;
-        Ok(())
+        update_client_row(&conn, &client)
     }
 
     pub fn delete_client(&self, id: &str) -> Result<()> {
@@ -464,8 +497,9 @@ impl Database {
     }
 
     pub fn add_var_partner(&self, partner: VarPartner) -> Result<()> {
-        let conn = self.conn.lock().unwrap();
-        conn.execute(
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute(
             "INSERT INTO var_partners (id, name, region, contact_person, email, phone, commission_rate, is_active)
              VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
             params![
@@ -474,12 +508,14 @@ impl Database {
                 if partner.is_active { 1 } else { 0 }
             ],
         )?;
-        Ok(())
+        commission_rates::record_partner_rate(&tx, &partner.id, partner.commission_rate)?;
+        tx.commit()
     }
 
     pub fn update_var_partner(&self, partner: VarPartner) -> Result<()> {
-        let conn = self.conn.lock().unwrap();
-        conn.execute(
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute(
             "UPDATE var_partners SET name = ?2, region = ?3, contact_person = ?4,
              email = ?5, phone = ?6, commission_rate = ?7, is_active = ?8
              WHERE id = ?1",
@@ -489,7 +525,8 @@ impl Database {
                 if partner.is_active { 1 } else { 0 }
             ],
         )?;
-        Ok(())
+        commission_rates::record_partner_rate(&tx, &partner.id, partner.commission_rate)?;
+        tx.commit()
     }
 
     pub fn delete_var_partner(&self, id: &str) -> Result<()> {
@@ -556,77 +593,33 @@ impl Database {
         clients.collect()
     }
 
-    pub fn add_var_client(&self, client: VarClient) -> Result<()> {
-        let conn = self.conn.lock().unwrap();
-        conn.execute(
-            "INSERT INTO var_clients (
-                id, client_name, debt_code, users, billing_model, currency,
-                jan, feb, mar, apr, may, jun, jul, aug, sep, oct, nov, dec, total,
-                comments, deal_start_date, anniversary_month, billing_frequency,
-                installment_months, monthly_factor, implementation_fee, implementation_months,
-                implementation_start_date, implementation_complete_date, subscription_duration,
-                var_partner_id, commission_rate, is_active, created_at, custom_increase_rate,
-                future_year_data, base_year_data
-            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16,
-                      ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25, ?26, ?27, ?28, ?29, ?30,
-                      ?31, ?32, ?33, ?34, ?35, ?36, ?37)",
-            params![
-                client.id, client.client_name, client.debt_code, client.users,
-                client.billing_model, client.currency, client.jan, client.feb,
-                client.mar, client.apr, client.may, client.jun, client.jul,
-                client.aug, client.sep, client.oct, client.nov, client.dec,
-                client.total, client.comments, client.deal_start_date,
-                client.anniversary_month, client.billing_frequency,
-                client.installment_months, client.monthly_factor,
-                client.implementation_fee, client.implementation_months,
-                client.implementation_start_date, client.implementation_complete_date,
-                client.subscription_duration, client.var_partner_id,
-                client.commission_rate, if client.is_active { 1 } else { 0 },
-                client.created_at, client.custom_increase_rate,
-                client.future_year_data, client.base_year_data
-            ],
-        )?;
-        Ok(())
+    pub fn add_var_client(&self, mut client: VarClient) -> Result<()> {
+        client.currency = checked_currency(&client.currency)?;
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        insert_var_client(&tx, &client)?;
+        tx.commit()
     }
 
-    pub fn update_var_client(&self, client: VarClient) -> Result<()> {
-        let conn = self.conn.lock().unwrap();
-        conn.execute(
-            "UPDATE var_clients SET
-                client_name = ?2, debt_code = ?3, users = ?4, billing_model = ?5,
-                currency = ?6, jan = ?7, feb = ?8, mar = ?9, apr = ?10, may = ?11,
-                jun = ?12, jul = ?13, aug = ?14, sep = ?15, oct = ?16, nov = ?17,
-                dec = ?18, total = ?19, comments = ?20, deal_start_date = ?21,
-                anniversary_month = ?22, billing_frequency = ?23, installment_months = ?24,
-                monthly_factor = ?25, implementation_fee = ?26, implementation_months = ?27,
-                implementation_start_date = ?28, implementation_complete_date = ?29,
-                subscription_duration = ?30, var_partner_id = ?31, commission_rate = ?32,
-                is_active = ?33, custom_increase_rate = ?34, future_year_data = ?35,
-                base_year_data = ?36
-             WHERE id = ?1",
-            params![
-                client.id, client.client_name, client.debt_code, client.users,
-                client.billing_model, client.currency, client.jan, client.feb,
-                client.mar, client.apr, client.may, client.jun, client.jul,
-                client.aug, client.sep, client.oct, client.nov, client.dec,
-                client.total, client.comments, client.deal_start_date,
-                client.anniversary_month, client.billing_frequency,
-                client.installment_months, client.monthly_factor,
-                client.implementation_fee, client.implementation_months,
-                client.implementation_start_date, client.implementation_complete_date,
-                client.subscription_duration, client.var_partner_id,
-                client.commission_rate, if client.is_active { 1 } else { 0 },
-                client.custom_increase_rate, client.future_year_data,
-                client.base_year_data
-            ],
-        )?;
-        Ok(())
+    /// Updates a VAR client. Returns the clawbacks taken when the update
+    /// deactivates it, or their reversals when it reactivates it.
+    pub fn update_var_client(&self, mut client: VarClient) -> Result<Vec<CommissionAdjustment>> {
+        client.currency = checked_currency(&client.currency)?;
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let adjustments = update_var_client_row(&tx, &client)?;
+        tx.commit()?;
+        Ok(adjustments)
     }
 
-    pub fn delete_var_client(&self, id: &str) -> Result<()> {
-        let conn = self.conn.lock().unwrap();
-        conn.execute("UPDATE var_clients SET is_active = 0 WHERE id = ?1", params![id])?;
-        Ok(())
+    /// Deactivates a VAR client and claws back commission from partners whose
+    /// clawback window it cancelled within. Returns the clawback adjustments.
+    pub fn delete_var_client(&self, id: &str) -> Result<Vec<CommissionAdjustment>> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let clawbacks = deactivate_var_client(&tx, id)?;
+        tx.commit()?;
+        Ok(clawbacks)
     }
 
     pub fn get_additional_licenses(&self, client_id: &str) -> Result<Vec<AdditionalLicense>> {
@@ -692,7 +685,7 @@ impl Database {
         let mut stmt = conn.prepare(
             "SELECT id, var_client_id, var_partner_id, billing_month, users,
              client_revenue, commission_rate, commission_amount, invoice_date,
-             invoice_status, notes, created_at, updated_at
+             invoice_status, notes, created_at, updated_at, commission_source
              FROM var_client_invoices
              ORDER BY billing_month DESC, created_at DESC"
         )?;
@@ -712,6 +705,7 @@ impl Database {
                 notes: row.get(10)?,
                 created_at: row.get(11)?,
                 updated_at: row.get(12)?,
+                commission_source: row.get(13)?,
             })
         })?;
 
@@ -723,8 +717,10 @@ impl Database {
     }
 
     pub fn create_var_client_invoice(&self, invoice: VarClientInvoice) -> Result<()> {
-        let conn = self.conn.lock().unwrap();
-        conn.execute(
+        invoice_status::check_initial_status(&invoice.invoice_status)?;
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute(
             "INSERT INTO var_client_invoices
              (id, var_client_id, var_partner_id, billing_month, users,
               client_revenue, commission_rate, commission_amount, invoice_date,
@@ -746,17 +742,59 @@ impl Database {
                 invoice.updated_at
             ],
         )?;
-        Ok(())
+        invoice_status::record_status(&tx, &invoice.id, None, &invoice.invoice_status, None)?;
+        commission_rules::recalculate_commissions(&tx, &invoice.var_partner_id, &invoice.billing_month)?;
+        tx.commit()
     }
 
+    /// Updates an invoice's details. A status change goes through the invoice
+    /// lifecycle, and issued invoices only accept status and note changes.
     pub fn update_var_client_invoice(&self, invoice: VarClientInvoice) -> Result<()> {
-        let conn = self.conn.lock().unwrap();
-        conn.execute(
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let previous: Option<(String, String, String)> = tx
+            .query_row(
+                "SELECT var_partner_id, billing_month, invoice_status FROM var_client_invoices WHERE id = ?1",
+                params![invoice.id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .optional()?;
+        let (var_partner_id, billing_month, status) =
+            previous.ok_or_else(|| rule_violation(format!("Invoice {} not found", invoice.id)))?;
+
+        if invoice_status::is_locked(&status) {
+            let unchanged: bool = tx.query_row(
+                "SELECT var_client_id = ?2 AND var_partner_id = ?3 AND billing_month = ?4
+                        AND users = ?5 AND client_revenue = ?6 AND invoice_date IS ?7
+                        AND commission_rate = ?8 AND commission_amount = ?9
+                 FROM var_client_invoices WHERE id = ?1",
+                params![
+                    invoice.id,
+                    invoice.var_client_id,
+                    invoice.var_partner_id,
+                    invoice.billing_month,
+                    invoice.users,
+                    invoice.client_revenue,
+                    invoice.invoice_date,
+                    invoice.commission_rate,
+                    invoice.commission_amount
+                ],
+                |row| row.get(0),
+            )?;
+            if !unchanged {
+                return Err(rule_violation(format!(
+                    "The invoice is {}; only its status and notes can change",
+                    status
+                )));
+            }
+        }
+
+        tx.execute(
             "UPDATE var_client_invoices
              SET var_client_id = ?2, var_partner_id = ?3, billing_month = ?4,
                  users = ?5, client_revenue = ?6, commission_rate = ?7,
-                 commission_amount = ?8, invoice_date = ?9, invoice_status = ?10,
-                 notes = ?11, updated_at = ?12
+                 commission_amount = ?8, invoice_date = ?9,
+                 notes = ?10, updated_at = ?11
              WHERE id = ?1",
             params![
                 invoice.id,
@@ -768,27 +806,66 @@ impl Database {
                 invoice.commission_rate,
                 invoice.commission_amount,
                 invoice.invoice_date,
-                invoice.invoice_status,
                 invoice.notes,
                 invoice.updated_at
             ],
         )?;
-        Ok(())
+        if invoice.invoice_status != status {
+            invoice_status::transition(&tx, &invoice.id, &invoice.invoice_status, None, None)?;
+        }
+        // Moving an invoice to another partner or month changes the volume of both periods.
+        commission_rules::recalculate_commissions(&tx, &var_partner_id, &billing_month)?;
+        commission_rules::recalculate_commissions(&tx, &invoice.var_partner_id, &invoice.billing_month)?;
+        tx.commit()
     }
 
+    /// Deletes a draft or pending invoice. Issued invoices have to be voided instead.
     pub fn delete_var_client_invoice(&self, id: &str) -> Result<()> {
-        let conn = self.conn.lock().unwrap();
-        conn.execute("DELETE FROM var_client_invoices WHERE id = ?1", params![id])?;
-        Ok(())
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let status = invoice_status::current_status(&tx, id)?;
+        if invoice_status::is_locked(&status) {
+            return Err(rule_violation(format!(
+                "The invoice is {} and cannot be deleted; void it instead",
+                status
+            )));
+        }
+        let (var_partner_id, billing_month): (String, String) = tx.query_row(
+            "SELECT var_partner_id, billing_month FROM var_client_invoices WHERE id = ?1",
+            params![id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        tx.execute("DELETE FROM var_invoice_status_history WHERE invoice_id = ?1", params![id])?;
+        tx.execute("DELETE FROM commission_lines WHERE invoice_id = ?1", params![id])?;
+        tx.execute("DELETE FROM var_client_invoices WHERE id = ?1", params![id])?;
+        commission_rules::recalculate_commissions(&tx, &var_partner_id, &billing_month)?;
+        tx.commit()
     }
 
-    pub fn toggle_var_invoice_status(&self, var_client_id: &str, is_invoiced: bool) -> Result<()> {
+    /// Marks a client invoiced or not. With a `billing_month` the flag is kept
+    /// for that month only; without one the client-wide flag is toggled.
+    pub fn toggle_var_invoice_status(
+        &self,
+        var_client_id: &str,
+        is_invoiced: bool,
+        billing_month: Option<&str>,
+        invoiced_by: Option<&str>,
+        invoice_id: Option<&str>,
+    ) -> Result<()> {
         let conn = self.conn.lock().unwrap();
 
+        if let Some(billing_month) = billing_month {
+            return invoice_tracking::set_month_invoiced(
+                &conn, var_client_id, billing_month, is_invoiced, invoiced_by, invoice_id,
+            );
+        }
+
         conn.execute(
-            "INSERT INTO var_invoice_tracking (var_client_id, is_invoiced)
-             VALUES (?1, ?2)
-             ON CONFLICT(var_client_id) DO UPDATE SET is_invoiced = ?2",
+            "INSERT INTO var_invoice_tracking (var_client_id, is_invoiced, invoiced_date)
+             VALUES (?1, ?2, CASE WHEN ?2 = 1 THEN date('now') END)
+             ON CONFLICT(var_client_id) DO UPDATE SET
+                 is_invoiced = ?2,
+                 invoiced_date = CASE WHEN ?2 = 1 THEN COALESCE(invoiced_date, date('now')) END",
             params![var_client_id, if is_invoiced { 1 } else { 0 }],
         )?;
         Ok(())
@@ -817,3 +894,257 @@ impl Database {
         Ok(tracking)
     }
 }
+
+fn insert_client(conn: &Connection, client: &Client) -> Result<()> {
+    debt_codes::ensure_unique_debt_code(conn, "clients", client.debt_code.as_deref(), &client.id)?;
+    conn.execute(
+        "INSERT INTO clients (
+            id, client_name, debt_code, users, billing_model, currency,
+            jan, feb, mar, apr, may, jun, jul, aug, sep, oct, nov, dec, total,
+            comments, deal_start_date, anniversary_month, billing_frequency,
+            installment_months, monthly_factor, implementation_fee, implementation_months,
+            implementation_start_date, implementation_complete_date, subscription_duration,
+            subscription_start_date, monthly_license_rate, commission_rate, var_partner,
+            is_active, created_at, custom_increase_rate, future_year_data, base_year_data
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16,
+                  ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25, ?26, ?27, ?28, ?29, ?30,
+                  ?31, ?32, ?33, ?34, ?35, ?36, ?37, ?38, ?39)",
+        params![
+            client.id, client.client_name, client.debt_code, client.users,
+            client.billing_model, client.currency, client.jan, client.feb,
+            client.mar, client.apr, client.may, client.jun, client.jul,
+            client.aug, client.sep, client.oct, client.nov, client.dec,
+            client.total, client.comments, client.deal_start_date,
+            client.anniversary_month, client.billing_frequency,
+            client.installment_months, client.monthly_factor,
+            client.implementation_fee, client.implementation_months,
+            client.implementation_start_date, client.implementation_complete_date,
+            client.subscription_duration, client.subscription_start_date,
+            client.monthly_license_rate, client.commission_rate,
+            client.var_partner, if client.is_active { 1 } else { 0 },
+            client.created_at, client.custom_increase_rate,
+            client.future_year_data, client.base_year_data
+        ],
+    )?;
+    Ok(())
+}
+
+/// Inserts a VAR client and opens its commission rate history.
+fn insert_var_client(conn: &Connection, client: &VarClient) -> Result<()> {
+    debt_codes::ensure_unique_debt_code(conn, "var_clients", client.debt_code.as_deref(), &client.id)?;
+    conn.execute(
+        "INSERT INTO var_clients (
+            id, client_name, debt_code, users, billing_model, currency,
+            jan, feb, mar, apr, may, jun, jul, aug, sep, oct, nov, dec, total,
+            comments, deal_start_date, anniversary_month, billing_frequency,
+            installment_months, monthly_factor, implementation_fee, implementation_months,
+            implementation_start_date, implementation_complete_date, subscription_duration,
+            var_partner_id, commission_rate, is_active, created_at, custom_increase_rate,
+            future_year_data, base_year_data
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16,
+                  ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25, ?26, ?27, ?28, ?29, ?30,
+                  ?31, ?32, ?33, ?34, ?35, ?36, ?37)",
+        params![
+            client.id, client.client_name, client.debt_code, client.users,
+            client.billing_model, client.currency, client.jan, client.feb,
+            client.mar, client.apr, client.may, client.jun, client.jul,
+            client.aug, client.sep, client.oct, client.nov, client.dec,
+            client.total, client.comments, client.deal_start_date,
+            client.anniversary_month, client.billing_frequency,
+            client.installment_months, client.monthly_factor,
+            client.implementation_fee, client.implementation_months,
+            client.implementation_start_date, client.implementation_complete_date,
+            client.subscription_duration, client.var_partner_id,
+            client.commission_rate, if client.is_active { 1 } else { 0 },
+            client.created_at, client.custom_increase_rate,
+            client.future_year_data, client.base_year_data
+        ],
+    )?;
+    commission_rates::record_client_rate(conn, &client.id, &client.var_partner_id, client.commission_rate)
+}
+
+fn update_client_row(conn: &Connection, client: &Client) -> Result<()> {
+    debt_codes::ensure_unique_debt_code(conn, "clients", client.debt_code.as_deref(), &client.id)?;
+    conn.execute(
+        "UPDATE clients SET
+            client_name = ?2, debt_code = ?3, users = ?4, billing_model = ?5,
+            currency = ?6, jan = ?7, feb = ?8, mar = ?9, apr = ?10, may = ?11,
+            jun = ?12, jul = ?13, aug = ?14, sep = ?15, oct = ?16, nov = ?17,
+            dec = ?18, total = ?19, comments = ?20, deal_start_date = ?21,
+            anniversary_month = ?22, billing_frequency = ?23, installment_months = ?24,
+            monthly_factor = ?25, implementation_fee = ?26, implementation_months = ?27,
+            implementation_start_date = ?28, implementation_complete_date = ?29,
+            subscription_duration = ?30, subscription_start_date = ?31,
+            monthly_license_rate = ?32, commission_rate = ?33, var_partner = ?34,
+            is_active = ?35, custom_increase_rate = ?36, future_year_data = ?37,
+            base_year_data = ?38
+         WHERE id = ?1",
+        params![
+            client.id, client.client_name, client.debt_code, client.users,
+            client.billing_model, client.currency, client.jan, client.feb,
+            client.mar, client.apr, client.may, client.jun, client.jul,
+            client.aug, client.sep, client.oct, client.nov, client.dec,
+            client.total, client.comments, client.deal_start_date,
+            client.anniversary_month, client.billing_frequency,
+            client.installment_months, client.monthly_factor,
+            client.implementation_fee, client.implementation_months,
+            client.implementation_start_date, client.implementation_complete_date,
+            client.subscription_duration, client.subscription_start_date,
+            client.monthly_license_rate, client.commission_rate,
+            client.var_partner, if client.is_active { 1 } else { 0 },
+            client.custom_increase_rate, client.future_year_data,
+            client.base_year_data
+        ],
+    )?;
+    Ok(())
+}
+
+/// Marks an active VAR client inactive as of today and applies any clawbacks
+/// its cancellation triggers. Already inactive clients are left as they are.
+fn deactivate_var_client(conn: &Connection, id: &str) -> Result<Vec<CommissionAdjustment>> {
+    let deactivated = conn.execute(
+        "UPDATE var_clients SET is_active = 0, deactivated_at = date('now') WHERE id = ?1 AND is_active = 1",
+        params![id],
+    )?;
+    if deactivated == 0 {
+        return Ok(Vec::new());
+    }
+    let cancelled_on: String = conn.query_row("SELECT date('now')", [], |row| row.get(0))?;
+    clawbacks::apply_clawbacks(conn, id, &cancelled_on)
+}
+
+/// Updates a VAR client, recording a rate change in its rate history.
+/// Deactivating the client goes through `deactivate_var_client`, and
+/// reactivating it reverses the clawbacks its cancellation took; either way
+/// the resulting commission adjustments are returned.
+fn update_var_client_row(conn: &Connection, client: &VarClient) -> Result<Vec<CommissionAdjustment>> {
+    debt_codes::ensure_unique_debt_code(conn, "var_clients", client.debt_code.as_deref(), &client.id)?;
+    let previous: Option<(String, f64, bool)> = conn
+        .query_row(
+            "SELECT var_partner_id, commission_rate, is_active = 1 FROM var_clients WHERE id = ?1",
+            params![client.id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()?;
+    conn.execute(
+        "UPDATE var_clients SET
+            client_name = ?2, debt_code = ?3, users = ?4, billing_model = ?5,
+            currency = ?6, jan = ?7, feb = ?8, mar = ?9, apr = ?10, may = ?11,
+            jun = ?12, jul = ?13, aug = ?14, sep = ?15, oct = ?16, nov = ?17,
+            dec = ?18, total = ?19, comments = ?20, deal_start_date = ?21,
+            anniversary_month = ?22, billing_frequency = ?23, installment_months = ?24,
+            monthly_factor = ?25, implementation_fee = ?26, implementation_months = ?27,
+            implementation_start_date = ?28, implementation_complete_date = ?29,
+            subscription_duration = ?30, var_partner_id = ?31, commission_rate = ?32,
+            is_active = CASE WHEN ?33 = 1 THEN 1 ELSE is_active END, custom_increase_rate = ?34,
+            future_year_data = ?35, base_year_data = ?36,
+            deactivated_at = CASE WHEN ?33 = 1 THEN NULL ELSE deactivated_at END
+         WHERE id = ?1",
+        params![
+            client.id, client.client_name, client.debt_code, client.users,
+            client.billing_model, client.currency, client.jan, client.feb,
+            client.mar, client.apr, client.may, client.jun, client.jul,
+            client.aug, client.sep, client.oct, client.nov, client.dec,
+            client.total, client.comments, client.deal_start_date,
+            client.anniversary_month, client.billing_frequency,
+            client.installment_months, client.monthly_factor,
+            client.implementation_fee, client.implementation_months,
+            client.implementation_start_date, client.implementation_complete_date,
+            client.subscription_duration, client.var_partner_id,
+            client.commission_rate, if client.is_active { 1 } else { 0 },
+            client.custom_increase_rate, client.future_year_data,
+            client.base_year_data
+        ],
+    )?;
+    // Only an edited rate or partner is a rate change; an unchanged rate may
+    // simply be the partner default the client was created with.
+    let was_active = previous.as_ref().is_some_and(|(_, _, active)| *active);
+    if previous.map(|(partner, rate, _)| (partner, rate)) != Some((client.var_partner_id.clone(), client.commission_rate))
+    {
+        commission_rates::record_client_rate(conn, &client.id, &client.var_partner_id, client.commission_rate)?;
+    }
+    if !client.is_active {
+        return deactivate_var_client(conn, &client.id);
+    }
+    if !was_active {
+        let reactivated_on: String = conn.query_row("SELECT date('now')", [], |row| row.get(0))?;
+        return clawbacks::reverse_clawbacks(conn, &client.id, &reactivated_on);
+    }
+    Ok(Vec::new())
+}
+
+/// Generates a random 32-character hex id for rows created by the backend.
+fn generate_id(conn: &Connection) -> Result<String> {
+    conn.query_row("SELECT lower(hex(randomblob(16)))", [], |row| row.get(0))
+}
+
+/// Current UTC time in the same ISO-8601 format the frontend uses for `created_at`.
+fn current_timestamp(conn: &Connection) -> Result<String> {
+    conn.query_row("SELECT strftime('%Y-%m-%dT%H:%M:%fZ', 'now')", [], |row| row.get(0))
+}
+
+/// Allocates the next number in a named document sequence, e.g. `CN-00001`.
+fn next_document_number(conn: &Connection, sequence: &str, prefix: &str) -> Result<String> {
+    conn.execute(
+        "INSERT INTO document_sequences (name, next_value) VALUES (?1, 1)
+         ON CONFLICT(name) DO UPDATE SET next_value = next_value + 1",
+        params![sequence],
+    )?;
+    let value: i64 = conn.query_row(
+        "SELECT next_value FROM document_sequences WHERE name = ?1",
+        params![sequence],
+        |row| row.get(0),
+    )?;
+    Ok(format!("{}-{:05}", prefix, value))
+}
+
+/// Reads a JSON-encoded value from `app_settings`.
+fn load_setting<T: DeserializeOwned>(conn: &Connection, key: &str) -> Result<Option<T>> {
+    let value: Option<String> = conn
+        .query_row("SELECT value FROM app_settings WHERE key = ?1", params![key], |row| row.get(0))
+        .optional()?;
+    value
+        .map(|json| {
+            serde_json::from_str(&json)
+                .map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, Type::Text, Box::new(e)))
+        })
+        .transpose()
+}
+
+/// Stores a value in `app_settings` as JSON, replacing any previous value.
+fn save_setting<T: Serialize>(conn: &Connection, key: &str, value: &T) -> Result<()> {
+    let json = serde_json::to_string(value).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+    conn.execute(
+        "INSERT INTO app_settings (key, value) VALUES (?1, ?2)
+         ON CONFLICT(key) DO UPDATE SET value = ?2",
+        params![key, json],
+    )?;
+    Ok(())
+}
+
+/// Reports a business-rule violation as a constraint failure so it travels
+/// through `rusqlite::Result` with its message intact.
+fn rule_violation(message: impl Into<String>) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_CONSTRAINT),
+        Some(message.into()),
+    )
+}
+
+/// Validates a currency against ISO 4217 and returns its canonical code.
+fn checked_currency(value: &str) -> Result<String> {
+    crate::currency::validate(value).map(str::to_string).map_err(rule_violation)
+}
+
+/// Adds a column to an existing table when a database predates it.
+fn add_column_if_missing(conn: &Connection, table: &str, column: &str, definition: &str) -> Result<()> {
+    let exists: bool = conn.query_row(
+        &format!("SELECT EXISTS (SELECT 1 FROM pragma_table_info('{table}') WHERE name = ?1)"),
+        params![column],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        conn.execute(&format!("ALTER TABLE {table} ADD COLUMN {column} {definition}"), [])?;
+    }
+    Ok(())
+}
@@ -0,0 +1,468 @@
+use super::billing_documents::invoice_reference;
+use super::exchange_rates::{convert, reporting_currency};
+use super::partner_hierarchy::commission_share;
+use super::{load_setting, rule_violation, save_setting, Database};
+use crate::currency::round_amount;
+use rusqlite::{params, Connection, Result};
+use serde::{Deserialize, Serialize};
+
+const MAPPING_KEY: &str = "accounting_mapping";
+
+/// Journal types in the order they are listed on any one day.
+pub const ENTRY_TYPES: &[&str] = &[
+    "invoice",
+    "credit_note",
+    "debit_note",
+    "payment",
+    "commission",
+    "commission_adjustment",
+    "commission_payout",
+];
+
+/// Kinds of revenue posted to a mapped account.
+pub const REVENUE_TYPES: &[&str] = &["invoice", "credit_note", "debit_note"];
+
+/// The account a kind of revenue is posted to. A blank billing model
+/// applies to every model without its own mapping.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RevenueAccount {
+    pub billing_model: String,
+    pub revenue_type: String,
+    pub account_code: String,
+}
+
+/// Account codes (or names, for QuickBooks) the journals post to, plus the
+/// tax code each package expects on lines with no tax.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccountMapping {
+    pub revenue_accounts: Vec<RevenueAccount>,
+    pub receivables_account: String,
+    pub bank_account: String,
+    pub commission_expense_account: String,
+    pub commission_payable_account: String,
+    pub xero_tax_rate: String,
+    pub sage_tax_code: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JournalLine {
+    pub account_code: String,
+    pub debit: f64,
+    pub credit: f64,
+}
+
+/// One balanced journal, in the reporting currency.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub date: String,
+    pub entry_type: String,
+    pub reference: String,
+    pub contact_name: String,
+    pub description: String,
+    pub lines: Vec<JournalLine>,
+}
+
+/// Everything posted over a period. Transactions with no exchange rate
+/// into the reporting currency are left out and listed in `missing_rates`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AccountingJournal {
+    pub period_from: String,
+    pub period_to: String,
+    pub currency: String,
+    pub xero_tax_rate: String,
+    pub sage_tax_code: String,
+    pub entries: Vec<JournalEntry>,
+    pub missing_rates: Vec<String>,
+}
+
+/// Which side of the books a transaction moves, before accounts are mapped.
+#[derive(Clone, Copy)]
+enum Account<'a> {
+    Revenue(&'a str),
+    Receivables,
+    Bank,
+    CommissionExpense,
+    CommissionPayable,
+}
+
+struct Transaction {
+    date: String,
+    entry_type: &'static str,
+    reference: String,
+    contact_name: String,
+    description: String,
+    billing_model: String,
+    currency: String,
+    amount: f64,
+}
+
+fn mapping(conn: &Connection) -> Result<AccountMapping> {
+    Ok(load_setting(conn, MAPPING_KEY)?.unwrap_or_default())
+}
+
+fn validate_mapping(mapping: &AccountMapping) -> Result<()> {
+    for (index, account) in mapping.revenue_accounts.iter().enumerate() {
+        if !REVENUE_TYPES.contains(&account.revenue_type.as_str()) {
+            return Err(rule_violation(format!(
+                "Unknown revenue type '{}'; expected one of {}",
+                account.revenue_type,
+                REVENUE_TYPES.join(", ")
+            )));
+        }
+        if account.account_code.trim().is_empty() {
+            return Err(rule_violation(format!(
+                "The {} account for {} clients has no code",
+                account.revenue_type,
+                model_label(&account.billing_model)
+            )));
+        }
+        let repeated = mapping.revenue_accounts[..index].iter().any(|other| {
+            other.revenue_type == account.revenue_type
+                && other
+                    .billing_model
+                    .trim()
+                    .eq_ignore_ascii_case(account.billing_model.trim())
+        });
+        if repeated {
+            return Err(rule_violation(format!(
+                "The {} account for {} clients is mapped twice",
+                account.revenue_type,
+                model_label(&account.billing_model)
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn model_label(billing_model: &str) -> &str {
+    match billing_model.trim() {
+        "" => "all",
+        model => model,
+    }
+}
+
+impl AccountMapping {
+    fn revenue_account(&self, billing_model: &str, revenue_type: &str) -> Option<&str> {
+        let find = |model: &str| {
+            self.revenue_accounts
+                .iter()
+                .find(|a| a.revenue_type == revenue_type && a.billing_model.trim().eq_ignore_ascii_case(model))
+                .map(|a| a.account_code.trim())
+        };
+        find(billing_model).or_else(|| find(""))
+    }
+
+    fn account_code(&self, account: Account, billing_model: &str) -> Result<String> {
+        let (code, name) = match account {
+            Account::Revenue(revenue_type) => (
+                self.revenue_account(billing_model, revenue_type).unwrap_or_default(),
+                format!("{} revenue for {} clients", revenue_type, billing_model),
+            ),
+            Account::Receivables => (self.receivables_account.trim(), "receivables".to_string()),
+            Account::Bank => (self.bank_account.trim(), "bank".to_string()),
+            Account::CommissionExpense => (self.commission_expense_account.trim(), "commission expense".to_string()),
+            Account::CommissionPayable => (self.commission_payable_account.trim(), "commission payable".to_string()),
+        };
+        if code.is_empty() {
+            return Err(rule_violation(format!(
+                "Map an account for {} before exporting journals",
+                name
+            )));
+        }
+        Ok(code.to_string())
+    }
+}
+
+/// The debit and credit sides of a transaction with a positive amount;
+/// negative amounts post the other way round.
+fn posting(entry_type: &str) -> (Account<'static>, Account<'static>) {
+    match entry_type {
+        "invoice" => (Account::Receivables, Account::Revenue("invoice")),
+        "credit_note" => (Account::Revenue("credit_note"), Account::Receivables),
+        "debit_note" => (Account::Receivables, Account::Revenue("debit_note")),
+        "payment" => (Account::Bank, Account::Receivables),
+        "commission" | "commission_adjustment" => (Account::CommissionExpense, Account::CommissionPayable),
+        _ => (Account::CommissionPayable, Account::Bank),
+    }
+}
+
+fn transactions(conn: &Connection, period_from: &str, period_to: &str) -> Result<Vec<Transaction>> {
+    let mut transactions = Vec::new();
+
+    // Invoices are posted once they leave draft; void and cancelled ones
+    // never reach the books.
+    let mut stmt = conn.prepare(
+        "SELECT COALESCE(i.invoice_date, i.billing_month || '-01') AS posted, i.billing_month, i.var_client_id,
+                c.client_name, c.debt_code, c.billing_model, c.currency, i.client_revenue
+         FROM var_client_invoices i
+         JOIN var_clients c ON c.id = i.var_client_id
+         WHERE i.invoice_status NOT IN ('draft', 'void', 'cancelled') AND posted BETWEEN ?1 AND ?2",
+    )?;
+    let invoices = stmt.query_map(params![period_from, period_to], |row| {
+        let billing_month: String = row.get(1)?;
+        let client_id: String = row.get(2)?;
+        let debt_code: Option<String> = row.get(4)?;
+        Ok(Transaction {
+            date: row.get(0)?,
+            entry_type: "invoice",
+            reference: invoice_reference(debt_code.as_deref(), &client_id, &billing_month),
+            contact_name: row.get(3)?,
+            description: format!("Software licences for {}", billing_month),
+            billing_model: row.get(5)?,
+            currency: row.get(6)?,
+            amount: row.get(7)?,
+        })
+    })?;
+    for invoice in invoices {
+        transactions.push(invoice?);
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT n.note_date, n.note_type, n.note_number, c.client_name, n.reason_code, c.billing_model,
+                c.currency, n.amount
+         FROM credit_notes n
+         JOIN var_client_invoices i ON i.id = n.invoice_id
+         JOIN var_clients c ON c.id = n.var_client_id
+         WHERE n.note_date BETWEEN ?1 AND ?2 AND i.invoice_status NOT IN ('void', 'cancelled')",
+    )?;
+    let notes = stmt.query_map(params![period_from, period_to], |row| {
+        let note_type: String = row.get(1)?;
+        let reason: String = row.get(4)?;
+        Ok(Transaction {
+            date: row.get(0)?,
+            entry_type: if note_type == "credit" {
+                "credit_note"
+            } else {
+                "debit_note"
+            },
+            reference: row.get(2)?,
+            contact_name: row.get(3)?,
+            description: format!(
+                "{} note: {}",
+                if note_type == "credit" { "Credit" } else { "Debit" },
+                reason
+            ),
+            billing_model: row.get(5)?,
+            currency: row.get(6)?,
+            amount: row.get(7)?,
+        })
+    })?;
+    for note in notes {
+        transactions.push(note?);
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT p.payment_date, p.payment_number, c.client_name, p.method, COALESCE(p.reference, ''), c.billing_model,
+                p.currency, p.amount
+         FROM payments p
+         JOIN var_clients c ON c.id = p.var_client_id
+         WHERE p.payment_date BETWEEN ?1 AND ?2",
+    )?;
+    let payments = stmt.query_map(params![period_from, period_to], |row| {
+        let method: String = row.get(3)?;
+        let reference: String = row.get(4)?;
+        Ok(Transaction {
+            date: row.get(0)?,
+            entry_type: "payment",
+            reference: row.get(1)?,
+            contact_name: row.get(2)?,
+            description: if reference.is_empty() {
+                format!("Payment received ({})", method)
+            } else {
+                format!("Payment received ({}) {}", method, reference)
+            },
+            billing_model: row.get(5)?,
+            currency: row.get(6)?,
+            amount: row.get(7)?,
+        })
+    })?;
+    for payment in payments {
+        transactions.push(payment?);
+    }
+
+    // Commission accrues to each partner in the hierarchy on the invoice
+    // date, and notes take back that partner's share of theirs.
+    let mut stmt = conn.prepare(
+        "SELECT COALESCE(i.invoice_date, i.billing_month || '-01') AS posted, i.billing_month, i.var_client_id,
+                c.debt_code, p.name, c.client_name, c.currency, l.commission_amount, 100
+         FROM commission_lines l
+         JOIN var_client_invoices i ON i.id = l.invoice_id
+         JOIN var_clients c ON c.id = i.var_client_id
+         JOIN var_partners p ON p.id = l.var_partner_id
+         WHERE i.invoice_status NOT IN ('draft', 'void', 'cancelled') AND posted BETWEEN ?1 AND ?2
+           AND l.commission_amount <> 0
+         UNION ALL
+         SELECT n.note_date, NULL, n.note_number, NULL, p.name, c.client_name, c.currency,
+                CASE WHEN n.note_type = 'credit' THEN -1 ELSE 1 END * n.commission_amount, l.share_percent
+         FROM credit_notes n
+         JOIN var_client_invoices i ON i.id = n.invoice_id
+         JOIN commission_lines l ON l.invoice_id = n.invoice_id
+         JOIN var_clients c ON c.id = n.var_client_id
+         JOIN var_partners p ON p.id = l.var_partner_id
+         WHERE n.note_date BETWEEN ?1 AND ?2 AND n.commission_amount <> 0
+           AND i.invoice_status NOT IN ('void', 'cancelled')",
+    )?;
+    let commissions = stmt.query_map(params![period_from, period_to], |row| {
+        let billing_month: Option<String> = row.get(1)?;
+        // The client's id for invoices, the note number for notes.
+        let source: String = row.get(2)?;
+        let debt_code: Option<String> = row.get(3)?;
+        let client_name: String = row.get(5)?;
+        let currency: String = row.get(6)?;
+        Ok(Transaction {
+            date: row.get(0)?,
+            entry_type: "commission",
+            reference: match &billing_month {
+                Some(month) => invoice_reference(debt_code.as_deref(), &source, month),
+                None => source,
+            },
+            contact_name: row.get(4)?,
+            description: match &billing_month {
+                Some(month) => format!("Commission on {} for {}", client_name, month),
+                None => format!("Commission on {} note", client_name),
+            },
+            billing_model: String::new(),
+            amount: commission_share(row.get(7)?, row.get(8)?, &currency),
+            currency,
+        })
+    })?;
+    for commission in commissions {
+        transactions.push(commission?);
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT a.adjustment_date, CASE WHEN a.source LIKE 'clawback%' THEN 'CLAWBACK' ELSE 'ADJ' END, p.name,
+                a.reason, a.currency, a.amount
+         FROM commission_adjustments a
+         JOIN var_partners p ON p.id = a.var_partner_id
+         WHERE a.adjustment_date BETWEEN ?1 AND ?2",
+    )?;
+    let adjustments = stmt.query_map(params![period_from, period_to], |row| {
+        Ok(Transaction {
+            date: row.get(0)?,
+            entry_type: "commission_adjustment",
+            reference: row.get(1)?,
+            contact_name: row.get(2)?,
+            description: row.get(3)?,
+            billing_model: String::new(),
+            currency: row.get(4)?,
+            amount: row.get(5)?,
+        })
+    })?;
+    for adjustment in adjustments {
+        transactions.push(adjustment?);
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT o.payout_date, o.payout_number, p.name, COALESCE(o.reference, ''), o.currency, o.amount
+         FROM commission_payouts o
+         JOIN var_partners p ON p.id = o.var_partner_id
+         WHERE o.payout_date BETWEEN ?1 AND ?2",
+    )?;
+    let payouts = stmt.query_map(params![period_from, period_to], |row| {
+        let reference: String = row.get(3)?;
+        Ok(Transaction {
+            date: row.get(0)?,
+            entry_type: "commission_payout",
+            reference: row.get(1)?,
+            contact_name: row.get(2)?,
+            description: format!("Commission paid {}", reference).trim_end().to_string(),
+            billing_model: String::new(),
+            currency: row.get(4)?,
+            amount: row.get(5)?,
+        })
+    })?;
+    for payout in payouts {
+        transactions.push(payout?);
+    }
+
+    let rank = |entry_type: &str| ENTRY_TYPES.iter().position(|t| *t == entry_type);
+    transactions.sort_by(|a, b| {
+        (&a.date, rank(a.entry_type), &a.reference, &a.contact_name).cmp(&(
+            &b.date,
+            rank(b.entry_type),
+            &b.reference,
+            &b.contact_name,
+        ))
+    });
+    Ok(transactions)
+}
+
+fn accounting_journal(conn: &Connection, period_from: &str, period_to: &str) -> Result<AccountingJournal> {
+    if period_from > period_to {
+        return Err(rule_violation("The export period ends before it starts"));
+    }
+    let mapping = mapping(conn)?;
+    let mut journal = AccountingJournal {
+        period_from: period_from.to_string(),
+        period_to: period_to.to_string(),
+        currency: reporting_currency(conn)?,
+        xero_tax_rate: mapping.xero_tax_rate.trim().to_string(),
+        sage_tax_code: mapping.sage_tax_code.trim().to_string(),
+        entries: Vec::new(),
+        missing_rates: Vec::new(),
+    };
+
+    for transaction in transactions(conn, period_from, period_to)? {
+        let Some(amount) = convert(
+            conn,
+            transaction.amount,
+            &transaction.currency,
+            &journal.currency,
+            &transaction.date,
+        )?
+        .converted_amount
+        else {
+            journal.missing_rates.push(transaction.reference);
+            continue;
+        };
+        let amount = round_amount(amount, &journal.currency);
+        if amount == 0.0 {
+            continue;
+        }
+        let (debit, credit) = posting(transaction.entry_type);
+        let (debit, credit) = if amount < 0.0 { (credit, debit) } else { (debit, credit) };
+        let amount = amount.abs();
+        journal.entries.push(JournalEntry {
+            date: transaction.date,
+            entry_type: transaction.entry_type.to_string(),
+            reference: transaction.reference,
+            contact_name: transaction.contact_name,
+            description: transaction.description,
+            lines: vec![
+                JournalLine {
+                    account_code: mapping.account_code(debit, &transaction.billing_model)?,
+                    debit: amount,
+                    credit: 0.0,
+                },
+                JournalLine {
+                    account_code: mapping.account_code(credit, &transaction.billing_model)?,
+                    debit: 0.0,
+                    credit: amount,
+                },
+            ],
+        });
+    }
+    Ok(journal)
+}
+
+impl Database {
+    pub fn get_account_mapping(&self) -> Result<AccountMapping> {
+        let conn = self.conn.lock().unwrap();
+        mapping(&conn)
+    }
+
+    pub fn update_account_mapping(&self, mapping: AccountMapping) -> Result<()> {
+        validate_mapping(&mapping)?;
+        let conn = self.conn.lock().unwrap();
+        save_setting(&conn, MAPPING_KEY, &mapping)
+    }
+
+    /// Builds double-entry journals for the invoices, credit and debit
+    /// notes, client payments and commission movements dated in a period.
+    pub fn get_accounting_journal(&self, period_from: &str, period_to: &str) -> Result<AccountingJournal> {
+        let conn = self.conn.lock().unwrap();
+        accounting_journal(&conn, period_from, period_to)
+    }
+}
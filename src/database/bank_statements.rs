@@ -0,0 +1,674 @@
+use super::billing_documents::invoice_reference;
+use super::exchange_rates::reporting_currency;
+use super::payments::{
+    insert_payment, invoice_outstanding, load_payment, open_invoices, AllocationRequest, Payment, PaymentRequest,
+};
+use super::{checked_currency, current_timestamp, generate_id, load_setting, rule_violation, save_setting, Database};
+use crate::bank_files::{read_statement, BankCsvMapping};
+use crate::currency::{normalize, round_amount};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// `unmatched` transactions have no invoice in view, `suggested` ones carry
+/// an automatic match awaiting confirmation, and `confirmed` ones have been
+/// recorded as a payment.
+pub const BANK_TRANSACTION_STATUSES: &[&str] = &["unmatched", "suggested", "confirmed", "ignored"];
+
+/// What each kind of evidence adds to a match's confidence, out of 100. A
+/// debt code is only counted when the invoice reference is not found, as the
+/// reference already contains it.
+const REFERENCE_SCORE: i64 = 60;
+const DEBT_CODE_SCORE: i64 = 30;
+const AMOUNT_SCORE: i64 = 40;
+const CLIENT_NAME_SCORE: i64 = 15;
+
+/// Taken off when another invoice scores as well as the best one.
+const AMBIGUITY_PENALTY: i64 = 15;
+
+/// Matches scoring below this are not suggested.
+const SUGGESTION_THRESHOLD: i64 = 50;
+
+/// Options for a statement import. `bank_account` names the account the
+/// statement belongs to; duplicates are detected and CSV mappings remembered
+/// per account. `currency` applies to files that do not state one and
+/// defaults to the reporting currency.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BankStatementOptions {
+    pub bank_account: String,
+    pub currency: Option<String>,
+    pub mapping: Option<BankCsvMapping>,
+}
+
+/// Money received on the bank account, with the invoice it was matched to.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BankTransaction {
+    pub id: String,
+    pub statement_id: String,
+    pub bank_account: String,
+    pub line: usize,
+    pub transaction_date: String,
+    pub amount: f64,
+    pub currency: String,
+    pub description: String,
+    pub reference: String,
+    pub status: String,
+    pub invoice_id: Option<String>,
+    pub var_client_id: Option<String>,
+    pub client_name: Option<String>,
+    pub billing_month: Option<String>,
+    pub confidence: Option<i64>,
+    pub match_reason: Option<String>,
+    pub payment_id: Option<String>,
+    pub created_at: String,
+}
+
+/// An open invoice a transaction could pay, with how well it fits.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BankMatchCandidate {
+    pub invoice_id: String,
+    pub invoice_reference: String,
+    pub var_client_id: String,
+    pub client_name: String,
+    pub debt_code: Option<String>,
+    pub billing_month: String,
+    pub currency: String,
+    pub outstanding: f64,
+    pub confidence: i64,
+    pub reasons: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BankStatementImportReport {
+    pub statement_id: String,
+    pub bank_account: String,
+    pub file_name: String,
+    pub format: String,
+    pub lines_read: usize,
+    pub imported: usize,
+    pub duplicates: usize,
+    pub debits_skipped: usize,
+    pub rows_skipped: usize,
+    pub suggested: usize,
+    pub unmatched: usize,
+    pub total_received: f64,
+}
+
+pub(super) fn create_tables(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS bank_statements (
+            id TEXT PRIMARY KEY,
+            bank_account TEXT NOT NULL,
+            file_name TEXT NOT NULL,
+            format TEXT NOT NULL,
+            imported_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS bank_transactions (
+            id TEXT PRIMARY KEY,
+            statement_id TEXT NOT NULL,
+            bank_account TEXT NOT NULL,
+            line INTEGER NOT NULL,
+            transaction_date TEXT NOT NULL,
+            amount REAL NOT NULL,
+            currency TEXT NOT NULL,
+            description TEXT NOT NULL,
+            reference TEXT NOT NULL,
+            external_id TEXT NOT NULL,
+            status TEXT NOT NULL DEFAULT 'unmatched',
+            invoice_id TEXT,
+            confidence INTEGER,
+            match_reason TEXT,
+            payment_id TEXT,
+            created_at TEXT NOT NULL,
+            UNIQUE (bank_account, external_id),
+            FOREIGN KEY (statement_id) REFERENCES bank_statements (id),
+            FOREIGN KEY (invoice_id) REFERENCES var_client_invoices (id),
+            FOREIGN KEY (payment_id) REFERENCES payments (id)
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_bank_transactions_status ON bank_transactions (status)",
+        [],
+    )?;
+
+    Ok(())
+}
+
+fn mapping_key(bank_account: &str) -> String {
+    format!("bank_csv_mapping.{}", bank_account)
+}
+
+/// Upper-case letters and digits only, so "inv-acme01 202609" and
+/// "INV ACME01-202609" compare equal.
+fn compact(text: &str) -> String {
+    text.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+/// Whether consecutive words of the text spell the compacted code, so "AC-1"
+/// is found as "AC-1", "AC 1" or "AC1" but not inside "AC10".
+fn mentions_code(words: &[String], code: &str) -> bool {
+    !code.is_empty()
+        && (0..words.len()).any(|start| {
+            let mut joined = String::new();
+            words[start..].iter().any(|word| {
+                joined.push_str(word);
+                !code.starts_with(joined.as_str()) || joined == code
+            }) && joined == code
+        })
+}
+
+/// Scores every open invoice in the transaction's currency, best first;
+/// equal scores go to the oldest invoice.
+fn candidates(
+    conn: &Connection,
+    amount: f64,
+    currency: &str,
+    description: &str,
+    reference: &str,
+) -> Result<Vec<BankMatchCandidate>> {
+    let text = format!("{} {}", reference, description);
+    let compacted = compact(&text);
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_ascii_uppercase)
+        .collect();
+
+    let mut candidates = Vec::new();
+    for invoice in open_invoices(conn, None)? {
+        if normalize(&invoice.currency) != Some(currency) || invoice.outstanding <= 0.0 {
+            continue;
+        }
+        let invoice_ref = invoice_reference(
+            invoice.debt_code.as_deref(),
+            &invoice.var_client_id,
+            &invoice.billing_month,
+        );
+        let mut confidence = 0;
+        let mut reasons = Vec::new();
+        if compacted.contains(&compact(&invoice_ref)) {
+            confidence += REFERENCE_SCORE;
+            reasons.push("invoice reference".to_string());
+        } else if let Some(code) = invoice
+            .debt_code
+            .as_deref()
+            .filter(|code| mentions_code(&words, &compact(code)))
+        {
+            confidence += DEBT_CODE_SCORE;
+            reasons.push(format!("debt code {}", code));
+        }
+        if (amount - invoice.outstanding).abs() < 0.005 {
+            confidence += AMOUNT_SCORE;
+            reasons.push("amount".to_string());
+        }
+        let name = compact(&invoice.client_name);
+        if name.len() >= 4 && compacted.contains(&name) {
+            confidence += CLIENT_NAME_SCORE;
+            reasons.push("client name".to_string());
+        }
+        candidates.push((
+            invoice.invoice_date.clone(),
+            BankMatchCandidate {
+                invoice_id: invoice.invoice_id,
+                invoice_reference: invoice_ref,
+                var_client_id: invoice.var_client_id,
+                client_name: invoice.client_name,
+                debt_code: invoice.debt_code,
+                billing_month: invoice.billing_month,
+                currency: invoice.currency,
+                outstanding: invoice.outstanding,
+                confidence: confidence.min(100),
+                reasons,
+            },
+        ));
+    }
+    candidates.sort_by(|(a_date, a), (b_date, b)| b.confidence.cmp(&a.confidence).then(a_date.cmp(b_date)));
+    Ok(candidates.into_iter().map(|(_, candidate)| candidate).collect())
+}
+
+/// Re-runs automatic matching for every transaction not yet confirmed or
+/// ignored, oldest first. An invoice is suggested for one transaction at a
+/// time. Returns how many transactions carry a suggestion.
+fn match_transactions(conn: &Connection) -> Result<usize> {
+    let mut stmt = conn.prepare(
+        "SELECT id, amount, currency, description, reference FROM bank_transactions
+         WHERE status IN ('unmatched', 'suggested')
+         ORDER BY transaction_date, line",
+    )?;
+    let pending = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, f64>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut claimed = HashSet::new();
+    let mut suggested = 0;
+    for (id, amount, currency, description, reference) in pending {
+        let ranked: Vec<BankMatchCandidate> = candidates(conn, amount, &currency, &description, &reference)?
+            .into_iter()
+            .filter(|candidate| !claimed.contains(&candidate.invoice_id))
+            .collect();
+        let best = ranked.first().map(|best| {
+            let tied = ranked.get(1).is_some_and(|next| next.confidence == best.confidence);
+            let confidence = if tied {
+                best.confidence - AMBIGUITY_PENALTY
+            } else {
+                best.confidence
+            };
+            (best, confidence)
+        });
+        match best.filter(|(_, confidence)| *confidence >= SUGGESTION_THRESHOLD) {
+            Some((best, confidence)) => {
+                conn.execute(
+                    "UPDATE bank_transactions
+                     SET status = 'suggested', invoice_id = ?2, confidence = ?3, match_reason = ?4
+                     WHERE id = ?1",
+                    params![id, best.invoice_id, confidence, best.reasons.join(", ")],
+                )?;
+                claimed.insert(best.invoice_id.clone());
+                suggested += 1;
+            }
+            None => {
+                conn.execute(
+                    "UPDATE bank_transactions
+                     SET status = 'unmatched', invoice_id = NULL, confidence = NULL, match_reason = NULL
+                     WHERE id = ?1",
+                    params![id],
+                )?;
+            }
+        }
+    }
+    Ok(suggested)
+}
+
+fn load_transactions(conn: &Connection, id: Option<&str>, status: Option<&str>) -> Result<Vec<BankTransaction>> {
+    let mut stmt = conn.prepare(
+        "SELECT t.id, t.statement_id, t.bank_account, t.line, t.transaction_date, t.amount, t.currency,
+                t.description, t.reference, t.status, t.invoice_id, i.var_client_id, c.client_name,
+                i.billing_month, t.confidence, t.match_reason, t.payment_id, t.created_at
+         FROM bank_transactions t
+         LEFT JOIN var_client_invoices i ON i.id = t.invoice_id
+         LEFT JOIN var_clients c ON c.id = i.var_client_id
+         WHERE (?1 IS NULL OR t.id = ?1) AND (?2 IS NULL OR t.status = ?2)
+         ORDER BY t.transaction_date, t.bank_account, t.line",
+    )?;
+    let rows = stmt.query_map(params![id, status], |row| {
+        Ok(BankTransaction {
+            id: row.get(0)?,
+            statement_id: row.get(1)?,
+            bank_account: row.get(2)?,
+            line: row.get(3)?,
+            transaction_date: row.get(4)?,
+            amount: row.get(5)?,
+            currency: row.get(6)?,
+            description: row.get(7)?,
+            reference: row.get(8)?,
+            status: row.get(9)?,
+            invoice_id: row.get(10)?,
+            var_client_id: row.get(11)?,
+            client_name: row.get(12)?,
+            billing_month: row.get(13)?,
+            confidence: row.get(14)?,
+            match_reason: row.get(15)?,
+            payment_id: row.get(16)?,
+            created_at: row.get(17)?,
+        })
+    })?;
+    rows.collect()
+}
+
+fn load_transaction(conn: &Connection, id: &str) -> Result<BankTransaction> {
+    load_transactions(conn, Some(id), None)?
+        .pop()
+        .ok_or_else(|| rule_violation(format!("Bank transaction {} not found", id)))
+}
+
+impl Database {
+    pub fn get_bank_csv_mapping(&self, bank_account: &str) -> Result<BankCsvMapping> {
+        let conn = self.conn.lock().unwrap();
+        Ok(load_setting(&conn, &mapping_key(bank_account))?.unwrap_or_default())
+    }
+
+    /// Imports the money received on a bank statement as unmatched
+    /// transactions and suggests the open invoice each one most likely pays.
+    /// Debits are skipped, as are lines already imported for the account.
+    /// A CSV mapping passed in is remembered for the account's next import.
+    pub fn import_bank_statement(
+        &self,
+        path: &Path,
+        options: BankStatementOptions,
+    ) -> Result<BankStatementImportReport> {
+        let bank_account = options.bank_account.trim().to_string();
+        if bank_account.is_empty() {
+            return Err(rule_violation("Choose the bank account the statement belongs to"));
+        }
+        let file_name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let mapping = match options.mapping {
+            Some(mapping) => {
+                save_setting(&tx, &mapping_key(&bank_account), &mapping)?;
+                mapping
+            }
+            None => load_setting(&tx, &mapping_key(&bank_account))?.unwrap_or_default(),
+        };
+        let statement = read_statement(path, &mapping).map_err(rule_violation)?;
+        let default_currency = match options.currency.as_deref().filter(|c| !c.is_empty()) {
+            Some(currency) => checked_currency(currency)?,
+            None => reporting_currency(&tx)?,
+        };
+
+        let statement_id = generate_id(&tx)?;
+        let now = current_timestamp(&tx)?;
+        tx.execute(
+            "INSERT INTO bank_statements (id, bank_account, file_name, format, imported_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![statement_id, bank_account, file_name, statement.format, now],
+        )?;
+
+        let mut report = BankStatementImportReport {
+            statement_id: statement_id.clone(),
+            bank_account: bank_account.clone(),
+            file_name,
+            format: statement.format.to_string(),
+            lines_read: statement.lines.len() + statement.skipped_rows,
+            imported: 0,
+            duplicates: 0,
+            debits_skipped: 0,
+            rows_skipped: statement.skipped_rows,
+            suggested: 0,
+            unmatched: 0,
+            total_received: 0.0,
+        };
+        // Lines without a bank id are told apart by their content, numbered
+        // so two identical payments on one day both import.
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        let mut imported_ids = Vec::new();
+        for line in statement.lines {
+            if line.amount <= 0.0 {
+                report.debits_skipped += 1;
+                continue;
+            }
+            let currency = match line.currency.as_deref() {
+                Some(currency) => checked_currency(currency)?,
+                None => default_currency.clone(),
+            };
+            let amount = round_amount(line.amount, &currency);
+            let external_id = match line.external_id {
+                Some(id) => id,
+                None => {
+                    let fingerprint = format!("{}|{:.2}|{}|{}", line.date, amount, line.description, line.reference);
+                    let occurrence = seen.entry(fingerprint.clone()).or_insert(0);
+                    *occurrence += 1;
+                    format!("{}#{}", fingerprint, occurrence)
+                }
+            };
+            let exists: Option<String> = tx
+                .query_row(
+                    "SELECT id FROM bank_transactions WHERE bank_account = ?1 AND external_id = ?2",
+                    params![bank_account, external_id],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            if exists.is_some() {
+                report.duplicates += 1;
+                continue;
+            }
+
+            let id = generate_id(&tx)?;
+            tx.execute(
+                "INSERT INTO bank_transactions
+                 (id, statement_id, bank_account, line, transaction_date, amount, currency, description,
+                  reference, external_id, status, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, 'unmatched', ?11)",
+                params![
+                    id,
+                    statement_id,
+                    bank_account,
+                    line.line,
+                    line.date,
+                    amount,
+                    currency,
+                    line.description,
+                    line.reference,
+                    external_id,
+                    now
+                ],
+            )?;
+            imported_ids.push(id);
+            report.imported += 1;
+            report.total_received = round_amount(report.total_received + amount, &currency);
+        }
+
+        match_transactions(&tx)?;
+        for id in &imported_ids {
+            match load_transaction(&tx, id)?.status.as_str() {
+                "suggested" => report.suggested += 1,
+                _ => report.unmatched += 1,
+            }
+        }
+
+        tx.commit()?;
+        Ok(report)
+    }
+
+    /// Bank transactions, optionally only those with the given status.
+    pub fn get_bank_transactions(&self, status: Option<&str>) -> Result<Vec<BankTransaction>> {
+        if let Some(status) = status.filter(|s| !BANK_TRANSACTION_STATUSES.contains(s)) {
+            return Err(rule_violation(format!("Unknown bank transaction status '{}'", status)));
+        }
+        let conn = self.conn.lock().unwrap();
+        load_transactions(&conn, None, status)
+    }
+
+    /// The open invoices a transaction could pay, most likely first, for
+    /// choosing a different match than the one suggested.
+    pub fn get_bank_match_candidates(&self, transaction_id: &str) -> Result<Vec<BankMatchCandidate>> {
+        let conn = self.conn.lock().unwrap();
+        let transaction = load_transaction(&conn, transaction_id)?;
+        candidates(
+            &conn,
+            transaction.amount,
+            &transaction.currency,
+            &transaction.description,
+            &transaction.reference,
+        )
+    }
+
+    /// Suggests matches again for every transaction still waiting, for
+    /// example after new invoices have been issued.
+    pub fn match_bank_transactions(&self) -> Result<usize> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let suggested = match_transactions(&tx)?;
+        tx.commit()?;
+        Ok(suggested)
+    }
+
+    /// Records a transaction as a payment against the suggested invoice, or
+    /// against `invoice_id` when the user re-assigns it. Money beyond what
+    /// the invoice still owes stays on the payment as unapplied credit.
+    pub fn confirm_bank_match(&self, transaction_id: &str, invoice_id: Option<&str>) -> Result<Payment> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let transaction = load_transaction(&tx, transaction_id)?;
+        if transaction.status == "confirmed" {
+            return Err(rule_violation(
+                "The bank transaction has already been recorded as a payment",
+            ));
+        }
+        let reassigned = invoice_id.is_some_and(|id| transaction.invoice_id.as_deref() != Some(id));
+        let invoice_id = invoice_id
+            .map(str::to_string)
+            .or(transaction.invoice_id.clone())
+            .ok_or_else(|| rule_violation("No invoice is suggested for this transaction; choose one to match it to"))?;
+
+        let var_client_id: String = tx
+            .query_row(
+                "SELECT var_client_id FROM var_client_invoices WHERE id = ?1",
+                params![invoice_id],
+                |row| row.get(0),
+            )
+            .optional()?
+            .ok_or_else(|| rule_violation(format!("Invoice {} not found", invoice_id)))?;
+        let outstanding = invoice_outstanding(&tx, &invoice_id)?;
+        if outstanding <= 0.0 {
+            return Err(rule_violation(format!("Invoice {} is already settled", invoice_id)));
+        }
+
+        let file_name: String = tx.query_row(
+            "SELECT file_name FROM bank_statements WHERE id = ?1",
+            params![transaction.statement_id],
+            |row| row.get(0),
+        )?;
+        let reference = [&transaction.reference, &transaction.description]
+            .into_iter()
+            .find(|text| !text.trim().is_empty())
+            .cloned();
+        let payment_id = insert_payment(
+            &tx,
+            PaymentRequest {
+                var_client_id,
+                payment_date: transaction.transaction_date.clone(),
+                amount: transaction.amount,
+                currency: transaction.currency.clone(),
+                method: "eft".to_string(),
+                reference,
+                notes: Some(format!(
+                    "Bank statement {} ({}), line {}",
+                    file_name, transaction.bank_account, transaction.line
+                )),
+                allocations: vec![AllocationRequest {
+                    invoice_id: invoice_id.clone(),
+                    amount: transaction.amount.min(outstanding),
+                }],
+            },
+        )?;
+
+        if reassigned {
+            tx.execute(
+                "UPDATE bank_transactions
+                 SET status = 'confirmed', invoice_id = ?2, payment_id = ?3, confidence = NULL,
+                     match_reason = 'assigned manually'
+                 WHERE id = ?1",
+                params![transaction_id, invoice_id, payment_id],
+            )?;
+        } else {
+            tx.execute(
+                "UPDATE bank_transactions SET status = 'confirmed', invoice_id = ?2, payment_id = ?3 WHERE id = ?1",
+                params![transaction_id, invoice_id, payment_id],
+            )?;
+        }
+        match_transactions(&tx)?;
+        let payment = load_payment(&tx, &payment_id)?;
+        tx.commit()?;
+        Ok(payment)
+    }
+
+    /// Sets a transaction aside, for money that does not pay an invoice.
+    pub fn ignore_bank_transaction(&self, transaction_id: &str) -> Result<BankTransaction> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        if load_transaction(&tx, transaction_id)?.status == "confirmed" {
+            return Err(rule_violation(
+                "The bank transaction has already been recorded as a payment",
+            ));
+        }
+        tx.execute(
+            "UPDATE bank_transactions
+             SET status = 'ignored', invoice_id = NULL, confidence = NULL, match_reason = NULL
+             WHERE id = ?1",
+            params![transaction_id],
+        )?;
+        match_transactions(&tx)?;
+        let transaction = load_transaction(&tx, transaction_id)?;
+        tx.commit()?;
+        Ok(transaction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{candidates, mentions_code};
+    use crate::database::test_support::{add_client, add_invoice, add_partner, test_db};
+    use crate::database::Database;
+
+    fn words(text: &str) -> Vec<String> {
+        text.split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(str::to_ascii_uppercase)
+            .collect()
+    }
+
+    fn issued(db: &Database, id: &str, var_client_id: &str, billing_month: &str, client_revenue: f64) {
+        add_invoice(db, id, var_client_id, "p1", billing_month, client_revenue, 20.0);
+        db.set_var_invoice_status(id, "invoiced", None, None).unwrap();
+    }
+
+    #[test]
+    fn finds_debt_codes_however_they_are_punctuated() {
+        for text in ["Payment AC-1", "payment ac 1 sept", "AC1", "ref:AC-1/09"] {
+            assert!(mentions_code(&words(text), "AC1"), "{text}");
+        }
+        for text in ["AC-10", "AC10 Sept", "BAC1", "AC", ""] {
+            assert!(!mentions_code(&words(text), "AC1"), "{text}");
+        }
+        assert!(!mentions_code(&words("AC1"), ""));
+    }
+
+    #[test]
+    fn ranks_open_invoices_by_evidence() {
+        let db = test_db();
+        add_partner(&db, "p1", 20.0);
+        add_client(&db, "1", "p1", 20.0, "ZAR");
+        add_client(&db, "10", "p1", 20.0, "ZAR");
+        add_client(&db, "usd", "p1", 20.0, "USD");
+        issued(&db, "i1", "1", "2026-09", 1000.0);
+        issued(&db, "i10", "10", "2026-09", 1000.0);
+        issued(&db, "iusd", "usd", "2026-09", 1000.0);
+        add_invoice(&db, "pending", "1", "p1", "2026-10", 1000.0, 20.0);
+
+        let conn = db.conn.lock().unwrap();
+        let ranked = |description: &str, amount: f64| -> Vec<(String, i64)> {
+            candidates(&conn, amount, "ZAR", description, "")
+                .unwrap()
+                .into_iter()
+                .map(|candidate| (candidate.invoice_id, candidate.confidence))
+                .collect()
+        };
+
+        // The debt code D1 must not be read into D10.
+        assert_eq!(
+            ranked("Payment D-1", 1000.0),
+            vec![("i1".to_string(), 70), ("i10".to_string(), 40)]
+        );
+        assert_eq!(
+            ranked("INV-D10-202609", 1000.0),
+            vec![("i10".to_string(), 100), ("i1".to_string(), 40)]
+        );
+        assert_eq!(
+            ranked("D10 settlement", 5.0),
+            vec![("i10".to_string(), 30), ("i1".to_string(), 0)]
+        );
+    }
+}
@@ -0,0 +1,752 @@
+use super::dunning;
+use super::exchange_rates::{convert, reporting_currency};
+use super::partner_hierarchy::commission_share;
+use super::{current_timestamp, load_setting, rule_violation, save_setting, Database};
+use crate::currency::{format_amount, round_amount};
+use crate::pdf::PdfImage;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+const PROFILE_KEY: &str = "company_profile";
+
+/// Logos are embedded in every document, so keep them small.
+const MAX_LOGO_BYTES: usize = 1024 * 1024;
+
+const MONTH_NAMES: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// Where clients should pay. Blank fields are left off documents.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BankDetails {
+    pub bank_name: String,
+    pub account_name: String,
+    pub account_number: String,
+    pub branch_code: String,
+    pub swift_code: String,
+}
+
+/// The company details printed at the top of every invoice and statement.
+/// `logo_file_name` is read-only; the logo itself is set separately.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompanyProfile {
+    pub company_name: String,
+    pub address_lines: Vec<String>,
+    pub registration_number: String,
+    pub vat_number: String,
+    pub email: String,
+    pub phone: String,
+    pub website: String,
+    pub bank: BankDetails,
+    pub footer_note: String,
+    #[serde(skip_deserializing)]
+    pub logo_file_name: Option<String>,
+}
+
+/// One row of a document's line table. Columns that no line uses are
+/// dropped when the document is rendered.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DocumentLine {
+    pub date: Option<String>,
+    pub reference: Option<String>,
+    pub description: String,
+    pub quantity: Option<f64>,
+    pub unit_price: Option<f64>,
+    pub amount: f64,
+    pub balance: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DocumentTotal {
+    pub label: String,
+    pub amount: f64,
+    pub emphasis: bool,
+}
+
+/// An invoice or statement laid out for printing, with every amount in
+/// `currency`. `payment_reference` is set on documents the recipient pays,
+/// which also carry the company's bank details.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BillingDocument {
+    pub document_type: String,
+    pub title: String,
+    pub reference: String,
+    pub issue_date: String,
+    pub due_date: Option<String>,
+    pub period: Option<String>,
+    pub currency: String,
+    pub company: CompanyProfile,
+    pub recipient_name: String,
+    pub recipient_lines: Vec<String>,
+    pub lines: Vec<DocumentLine>,
+    pub totals: Vec<DocumentTotal>,
+    pub notes: Vec<String>,
+    pub payment_reference: Option<String>,
+    #[serde(skip)]
+    pub logo: Option<Vec<u8>>,
+}
+
+pub(super) fn create_tables(conn: &Connection) -> Result<()> {
+    // A single row; the check keeps it that way.
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS company_logo (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            file_name TEXT NOT NULL,
+            data BLOB NOT NULL,
+            updated_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn company_profile(conn: &Connection) -> Result<CompanyProfile> {
+    let mut profile: CompanyProfile = load_setting(conn, PROFILE_KEY)?.unwrap_or_default();
+    profile.logo_file_name = conn
+        .query_row("SELECT file_name FROM company_logo WHERE id = 1", [], |row| row.get(0))
+        .optional()?;
+    Ok(profile)
+}
+
+fn company_logo(conn: &Connection) -> Result<Option<Vec<u8>>> {
+    conn.query_row("SELECT data FROM company_logo WHERE id = 1", [], |row| row.get(0))
+        .optional()
+}
+
+/// The issuing company and its logo, refusing to go on without a name.
+fn letterhead(conn: &Connection) -> Result<(CompanyProfile, Option<Vec<u8>>)> {
+    let profile = company_profile(conn)?;
+    if profile.company_name.trim().is_empty() {
+        return Err(rule_violation(
+            "Set the company name in the company profile before rendering documents",
+        ));
+    }
+    Ok((profile, company_logo(conn)?))
+}
+
+/// "2026-03" as "March 2026".
+fn month_label(month: &str) -> String {
+    match (month.get(..4), month.get(5..7).and_then(|m| m.parse::<usize>().ok())) {
+        (Some(year), Some(number @ 1..=12)) => format!("{} {}", MONTH_NAMES[number - 1], year),
+        _ => month.to_string(),
+    }
+}
+
+pub(super) fn checked_month(month: &str) -> Result<()> {
+    let valid = month.len() == 7
+        && month.as_bytes()[4] == b'-'
+        && month[..4].bytes().all(|b| b.is_ascii_digit())
+        && matches!(month[5..].parse::<u32>(), Ok(1..=12));
+    if !valid {
+        return Err(rule_violation(format!(
+            "Billing month must be YYYY-MM, got '{}'",
+            month
+        )));
+    }
+    Ok(())
+}
+
+/// A short, stable code for a client or partner in document references:
+/// the debt code when there is one, otherwise the start of the id.
+fn reference_code(debt_code: Option<&str>, id: &str) -> String {
+    debt_code
+        .map(str::trim)
+        .filter(|code| !code.is_empty())
+        .unwrap_or_else(|| id.get(..8).unwrap_or(id))
+        .to_uppercase()
+}
+
+/// The number a client invoice is known by, e.g. `INV-AC1-202608`: one
+/// invoice per client and billing month.
+pub(super) fn invoice_reference(debt_code: Option<&str>, var_client_id: &str, billing_month: &str) -> String {
+    format!(
+        "INV-{}-{}",
+        reference_code(debt_code, var_client_id),
+        billing_month.replace('-', "")
+    )
+}
+
+fn add_days(conn: &Connection, date: &str, days: i64) -> Result<String> {
+    conn.query_row(
+        "SELECT date(?1, ?2)",
+        params![date, format!("{:+} days", days)],
+        |row| row.get(0),
+    )
+}
+
+fn optional_lines(fields: &[(&str, &str)]) -> Vec<String> {
+    fields
+        .iter()
+        .filter(|(_, value)| !value.trim().is_empty())
+        .map(|(label, value)| format!("{}{}", label, value.trim()))
+        .collect()
+}
+
+fn partner_recipient(conn: &Connection, var_partner_id: &str) -> Result<(String, Vec<String>)> {
+    conn.query_row(
+        "SELECT name, contact_person, region, email, COALESCE(phone, '') FROM var_partners WHERE id = ?1",
+        params![var_partner_id],
+        |row| {
+            let (contact, region, email, phone): (String, String, String, String) =
+                (row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?);
+            Ok((
+                row.get(0)?,
+                optional_lines(&[("Attn: ", &contact), ("", &region), ("", &email), ("", &phone)]),
+            ))
+        },
+    )
+    .optional()?
+    .ok_or_else(|| rule_violation(format!("VAR partner {} not found", var_partner_id)))
+}
+
+fn total(label: &str, amount: f64, emphasis: bool) -> DocumentTotal {
+    DocumentTotal {
+        label: label.to_string(),
+        amount,
+        emphasis,
+    }
+}
+
+fn client_invoice_document(conn: &Connection, invoice_id: &str) -> Result<BillingDocument> {
+    let (company, logo) = letterhead(conn)?;
+    let invoice = conn
+        .query_row(
+            "SELECT i.billing_month, COALESCE(i.invoice_date, i.billing_month || '-01'), i.invoice_status,
+                    i.users, i.client_revenue, i.var_client_id, c.client_name, c.debt_code, c.currency, p.name
+             FROM var_client_invoices i
+             JOIN var_clients c ON c.id = i.var_client_id
+             JOIN var_partners p ON p.id = i.var_partner_id
+             WHERE i.id = ?1",
+            params![invoice_id],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, i32>(3)?,
+                    row.get::<_, f64>(4)?,
+                    row.get::<_, String>(5)?,
+                    row.get::<_, String>(6)?,
+                    row.get::<_, Option<String>>(7)?,
+                    row.get::<_, String>(8)?,
+                    row.get::<_, String>(9)?,
+                ))
+            },
+        )
+        .optional()?
+        .ok_or_else(|| rule_violation(format!("Invoice {} not found", invoice_id)))?;
+    let (billing_month, invoice_date, status, users, revenue, client_id, client_name, debt_code, currency, partner) =
+        invoice;
+
+    let title = match status.as_str() {
+        "draft" | "pending" => "Pro Forma Invoice",
+        "void" | "cancelled" => "Invoice (Void)",
+        _ => "Invoice",
+    };
+    let reference = invoice_reference(debt_code.as_deref(), &client_id, &billing_month);
+    let payment_terms = dunning::settings(conn)?.payment_terms_days;
+
+    let mut lines = vec![DocumentLine {
+        description: format!("Software licences for {}", month_label(&billing_month)),
+        quantity: Some(users as f64),
+        unit_price: (users > 0).then(|| round_amount(revenue / users as f64, &currency)),
+        amount: round_amount(revenue, &currency),
+        ..Default::default()
+    }];
+    let mut totals = vec![total("Invoice total", round_amount(revenue, &currency), false)];
+    let mut balance = revenue;
+
+    let mut stmt = conn.prepare(
+        "SELECT note_number, note_date, reason_code,
+                CASE WHEN note_type = 'credit' THEN -amount ELSE amount END, note_type
+         FROM credit_notes WHERE invoice_id = ?1
+         ORDER BY note_date, note_number",
+    )?;
+    let notes = stmt.query_map(params![invoice_id], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, f64>(3)?,
+            row.get::<_, String>(4)?,
+        ))
+    })?;
+    for note in notes {
+        let (number, date, reason, amount, note_type) = note?;
+        let kind = if note_type == "credit" {
+            "Credit note"
+        } else {
+            "Debit note"
+        };
+        lines.push(DocumentLine {
+            date: Some(date),
+            reference: Some(number),
+            description: format!("{} ({})", kind, reason),
+            amount,
+            ..Default::default()
+        });
+        balance += amount;
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT p.payment_number, p.payment_date, pa.amount
+         FROM payment_allocations pa
+         JOIN payments p ON p.id = pa.payment_id
+         WHERE pa.invoice_id = ?1
+         ORDER BY p.payment_date, p.payment_number",
+    )?;
+    let payments = stmt.query_map(params![invoice_id], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, f64>(2)?,
+        ))
+    })?;
+    for payment in payments {
+        let (number, date, amount) = payment?;
+        totals.push(total(&format!("Payment {} on {}", number, date), -amount, false));
+        balance -= amount;
+    }
+    let adjusted = lines.iter().map(|line| line.amount).sum::<f64>();
+    if lines.len() > 1 {
+        totals.insert(1, total("Adjusted total", round_amount(adjusted, &currency), false));
+    }
+    totals.push(total("Balance due", round_amount(balance, &currency), true));
+
+    let mut recipient_lines = optional_lines(&[("Account: ", debt_code.as_deref().unwrap_or_default())]);
+    recipient_lines.push(format!("Via {}", partner));
+    let mut document_notes = Vec::new();
+    if title != "Invoice" {
+        document_notes.push(format!("This invoice is {} and is not payable.", status));
+    }
+
+    Ok(BillingDocument {
+        document_type: "client_invoice".to_string(),
+        title: title.to_string(),
+        due_date: Some(add_days(conn, &invoice_date, payment_terms)?),
+        issue_date: invoice_date,
+        period: Some(month_label(&billing_month)),
+        currency,
+        company,
+        recipient_name: client_name,
+        recipient_lines,
+        lines,
+        totals,
+        notes: document_notes,
+        payment_reference: Some(reference.clone()),
+        reference,
+        logo,
+    })
+}
+
+fn commission_invoice_document(
+    conn: &Connection,
+    var_partner_id: &str,
+    billing_month: &str,
+) -> Result<BillingDocument> {
+    checked_month(billing_month)?;
+    let (company, logo) = letterhead(conn)?;
+    let (partner_name, recipient_lines) = partner_recipient(conn, var_partner_id)?;
+    let currency = reporting_currency(conn)?;
+    let issue_date: String = conn.query_row(
+        "SELECT date(?1 || '-01', '+1 month', '-1 day')",
+        params![billing_month],
+        |row| row.get(0),
+    )?;
+
+    // Commission is earned on invoices sent to clients, less the partner's
+    // share of any notes raised against them during the month.
+    let mut stmt = conn.prepare(
+        "SELECT COALESCE(i.invoice_date, i.billing_month || '-01'), NULL, c.client_name, c.debt_code, i.users,
+                i.client_revenue, c.currency, l.share_percent, l.commission_amount, NULL
+         FROM commission_lines l
+         JOIN var_client_invoices i ON i.id = l.invoice_id
+         JOIN var_clients c ON c.id = i.var_client_id
+         WHERE l.var_partner_id = ?1 AND i.billing_month = ?2
+           AND i.invoice_status NOT IN ('draft', 'void', 'cancelled')
+         UNION ALL
+         SELECT n.note_date, n.note_number, c.client_name, c.debt_code, NULL, NULL, c.currency, l.share_percent,
+                CASE WHEN n.note_type = 'credit' THEN -1 ELSE 1 END * n.commission_amount,
+                CASE WHEN n.note_type = 'credit' THEN 'Credit note' ELSE 'Debit note' END
+                    || ' (' || n.reason_code || ')'
+         FROM credit_notes n
+         JOIN commission_lines l ON l.invoice_id = n.invoice_id
+         JOIN var_clients c ON c.id = n.var_client_id
+         WHERE l.var_partner_id = ?1 AND substr(n.note_date, 1, 7) = ?2 AND n.commission_amount <> 0
+         ORDER BY 2 NULLS FIRST, 3, 4, 1",
+    )?;
+    let rows = stmt.query_map(params![var_partner_id, billing_month], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, Option<String>>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, Option<String>>(3)?,
+            row.get::<_, Option<i32>>(4)?,
+            row.get::<_, Option<f64>>(5)?,
+            row.get::<_, String>(6)?,
+            row.get::<_, f64>(7)?,
+            row.get::<_, f64>(8)?,
+            row.get::<_, Option<String>>(9)?,
+        ))
+    })?;
+
+    let mut lines = Vec::new();
+    for row in rows {
+        let (date, note_number, client_name, debt_code, users, revenue, client_currency, share, commission, note) =
+            row?;
+        // Notes carry the whole invoice's commission; the partner's line takes its share.
+        let commission = match note_number {
+            Some(_) => commission_share(commission, share, &client_currency),
+            None => commission,
+        };
+        let converted = convert(conn, commission, &client_currency, &currency, &date)?
+            .converted_amount
+            .ok_or_else(|| {
+                rule_violation(format!(
+                    "No exchange rate from {} to {} on {}",
+                    client_currency, currency, date
+                ))
+            })?;
+        let client = match debt_code.as_deref().map(str::trim).filter(|code| !code.is_empty()) {
+            Some(code) => format!("{} ({})", client_name, code),
+            None => client_name,
+        };
+        let mut description = match (note, users, revenue) {
+            (Some(note), _, _) => format!("{}: {}", client, note),
+            (None, Some(users), Some(revenue)) => format!(
+                "{}: {} users, revenue {}",
+                client,
+                users,
+                format_amount(revenue, &client_currency)
+            ),
+            _ => client,
+        };
+        if share < 100.0 {
+            description.push_str(&format!(", {}% share", share));
+        }
+        lines.push(DocumentLine {
+            date: note_number.is_some().then_some(date),
+            reference: note_number,
+            description,
+            amount: converted,
+            ..Default::default()
+        });
+    }
+    if lines.is_empty() {
+        return Err(rule_violation(format!(
+            "{} earned no commission on {} billing",
+            partner_name,
+            month_label(billing_month)
+        )));
+    }
+
+    let commission = round_amount(lines.iter().map(|line| line.amount).sum(), &currency);
+    Ok(BillingDocument {
+        document_type: "commission_invoice".to_string(),
+        title: "Commission Invoice".to_string(),
+        reference: format!(
+            "COM-{}-{}",
+            reference_code(None, var_partner_id),
+            billing_month.replace('-', "")
+        ),
+        issue_date,
+        due_date: None,
+        period: Some(month_label(billing_month)),
+        currency,
+        company,
+        recipient_name: partner_name.clone(),
+        recipient_lines,
+        lines,
+        totals: vec![total("Commission payable", commission, true)],
+        notes: vec![format!(
+            "Self-billed invoice issued on behalf of {} for commission on {} billing.",
+            partner_name,
+            month_label(billing_month)
+        )],
+        payment_reference: None,
+        logo,
+    })
+}
+
+fn client_statement_document(
+    conn: &Connection,
+    var_client_id: &str,
+    period_from: &str,
+    period_to: &str,
+) -> Result<BillingDocument> {
+    if period_from > period_to {
+        return Err(rule_violation("The statement period ends before it starts"));
+    }
+    let (company, logo) = letterhead(conn)?;
+    let (client_name, debt_code, currency, partner): (String, Option<String>, String, String) = conn
+        .query_row(
+            "SELECT c.client_name, c.debt_code, c.currency, COALESCE(p.name, '')
+             FROM var_clients c LEFT JOIN var_partners p ON p.id = c.var_partner_id
+             WHERE c.id = ?1",
+            params![var_client_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )
+        .optional()?
+        .ok_or_else(|| rule_violation(format!("VAR client {} not found", var_client_id)))?;
+
+    // Everything that moves the account, in the same terms as the aging
+    // report: drafts, void and cancelled invoices are left out.
+    let mut stmt = conn.prepare(
+        "SELECT COALESCE(i.invoice_date, i.billing_month || '-01'), 'invoice', i.billing_month, i.client_revenue
+         FROM var_client_invoices i
+         WHERE i.var_client_id = ?1 AND i.invoice_status NOT IN ('draft', 'void', 'cancelled')
+         UNION ALL
+         SELECT n.note_date, n.note_type, n.note_number || ' ' || n.reason_code,
+                CASE WHEN n.note_type = 'credit' THEN -n.amount ELSE n.amount END
+         FROM credit_notes n
+         JOIN var_client_invoices i ON i.id = n.invoice_id
+         WHERE n.var_client_id = ?1 AND i.invoice_status NOT IN ('void', 'cancelled')
+         UNION ALL
+         SELECT payment_date, 'payment', payment_number || ' ' || method, -amount
+         FROM payments WHERE var_client_id = ?1
+         ORDER BY 1, 2, 3",
+    )?;
+    let movements = stmt.query_map(params![var_client_id], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, f64>(3)?,
+        ))
+    })?;
+
+    let code = reference_code(debt_code.as_deref(), var_client_id);
+    let (mut opening, mut invoiced, mut notes, mut paid) = (0.0, 0.0, 0.0, 0.0);
+    let mut lines = Vec::new();
+    for movement in movements {
+        let (date, kind, detail, amount) = movement?;
+        if date.as_str() > period_to {
+            continue;
+        }
+        if date.as_str() < period_from {
+            opening += amount;
+            continue;
+        }
+        let (reference, description) = match kind.as_str() {
+            "invoice" => (
+                invoice_reference(debt_code.as_deref(), var_client_id, &detail),
+                format!("Software licences for {}", month_label(&detail)),
+            ),
+            "payment" => {
+                let (number, method) = detail.split_once(' ').unwrap_or((&detail, ""));
+                (number.to_string(), format!("Payment received ({})", method))
+            }
+            _ => {
+                let (number, reason) = detail.split_once(' ').unwrap_or((&detail, ""));
+                let label = if kind == "credit" { "Credit note" } else { "Debit note" };
+                (number.to_string(), format!("{} ({})", label, reason))
+            }
+        };
+        match kind.as_str() {
+            "invoice" => invoiced += amount,
+            "payment" => paid += amount,
+            _ => notes += amount,
+        }
+        let balance = opening + invoiced + notes + paid;
+        lines.push(DocumentLine {
+            date: Some(date),
+            reference: Some(reference),
+            description,
+            amount: round_amount(amount, &currency),
+            balance: Some(round_amount(balance, &currency)),
+            ..Default::default()
+        });
+    }
+
+    let closing = opening + invoiced + notes + paid;
+    let mut recipient_lines = optional_lines(&[("Account: ", debt_code.as_deref().unwrap_or_default())]);
+    if !partner.is_empty() {
+        recipient_lines.push(format!("Via {}", partner));
+    }
+    Ok(BillingDocument {
+        document_type: "client_statement".to_string(),
+        title: "Statement of Account".to_string(),
+        reference: format!("STM-{}-{}", code, period_to.replace('-', "")),
+        issue_date: period_to.to_string(),
+        due_date: None,
+        period: Some(format!("{} to {}", period_from, period_to)),
+        totals: vec![
+            total("Opening balance", round_amount(opening, &currency), false),
+            total("Invoiced", round_amount(invoiced, &currency), false),
+            total("Credit and debit notes", round_amount(notes, &currency), false),
+            total("Payments received", round_amount(paid, &currency), false),
+            total("Balance due", round_amount(closing, &currency), true),
+        ],
+        currency,
+        company,
+        recipient_name: client_name,
+        recipient_lines,
+        lines,
+        notes: Vec::new(),
+        payment_reference: Some(code),
+        logo,
+    })
+}
+
+impl Database {
+    pub fn get_company_profile(&self) -> Result<CompanyProfile> {
+        let conn = self.conn.lock().unwrap();
+        company_profile(&conn)
+    }
+
+    pub fn update_company_profile(&self, mut profile: CompanyProfile) -> Result<()> {
+        if profile.company_name.trim().is_empty() {
+            return Err(rule_violation("Company name is required"));
+        }
+        profile.address_lines.retain(|line| !line.trim().is_empty());
+        profile.logo_file_name = None;
+        let conn = self.conn.lock().unwrap();
+        save_setting(&conn, PROFILE_KEY, &profile)
+    }
+
+    /// Stores the JPEG or PNG at `path` as the logo for printed documents.
+    pub fn set_company_logo(&self, path: &Path) -> Result<()> {
+        let data = std::fs::read(path).map_err(|e| rule_violation(format!("Cannot read {}: {}", path.display(), e)))?;
+        if data.len() > MAX_LOGO_BYTES {
+            return Err(rule_violation("Logos must be 1 MB or smaller"));
+        }
+        PdfImage::from_bytes(&data).map_err(rule_violation)?;
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let conn = self.conn.lock().unwrap();
+        let now = current_timestamp(&conn)?;
+        conn.execute(
+            "INSERT INTO company_logo (id, file_name, data, updated_at) VALUES (1, ?1, ?2, ?3)
+             ON CONFLICT(id) DO UPDATE SET file_name = ?1, data = ?2, updated_at = ?3",
+            params![file_name, data, now],
+        )?;
+        Ok(())
+    }
+
+    pub fn clear_company_logo(&self) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM company_logo", [])?;
+        Ok(())
+    }
+
+    /// A VAR client invoice with its notes and payments. Drafts and pending
+    /// invoices print as pro forma; void and cancelled ones are marked void.
+    pub fn get_client_invoice_document(&self, invoice_id: &str) -> Result<BillingDocument> {
+        let conn = self.conn.lock().unwrap();
+        client_invoice_document(&conn, invoice_id)
+    }
+
+    /// A self-billed invoice for the commission a partner earned on one
+    /// billing month, in the reporting currency.
+    pub fn get_commission_invoice_document(
+        &self,
+        var_partner_id: &str,
+        billing_month: &str,
+    ) -> Result<BillingDocument> {
+        let conn = self.conn.lock().unwrap();
+        commission_invoice_document(&conn, var_partner_id, billing_month)
+    }
+
+    /// A VAR client's account over a period, with a running balance.
+    pub fn get_client_statement_document(
+        &self,
+        var_client_id: &str,
+        period_from: &str,
+        period_to: &str,
+    ) -> Result<BillingDocument> {
+        let conn = self.conn.lock().unwrap();
+        client_statement_document(&conn, var_client_id, period_from, period_to)
+    }
+
+    /// A partner's commission statement on the company letterhead.
+    pub fn get_partner_statement_document(
+        &self,
+        var_partner_id: &str,
+        period_from: &str,
+        period_to: &str,
+    ) -> Result<BillingDocument> {
+        let statement = self.get_partner_statement(var_partner_id, period_from, period_to)?;
+        let conn = self.conn.lock().unwrap();
+        let (company, logo) = letterhead(&conn)?;
+        let currency = statement.currency.clone();
+
+        let mut lines: Vec<DocumentLine> = statement
+            .client_lines
+            .iter()
+            .map(|line| {
+                let client = match line.debt_code.as_deref().map(str::trim).filter(|code| !code.is_empty()) {
+                    Some(code) => format!("{} ({})", line.client_name, code),
+                    None => line.client_name.clone(),
+                };
+                DocumentLine {
+                    description: format!(
+                        "{}: {} invoices, revenue {}",
+                        client,
+                        line.invoice_count,
+                        format_amount(line.client_revenue, &line.currency)
+                    ),
+                    quantity: Some(line.users as f64),
+                    amount: line.statement_amount,
+                    ..Default::default()
+                }
+            })
+            .collect();
+        for (sign, entries) in [(1.0, &statement.adjustment_lines), (-1.0, &statement.payment_lines)] {
+            lines.extend(entries.iter().map(|entry| DocumentLine {
+                date: Some(entry.date.clone()),
+                reference: Some(entry.reference.clone()),
+                description: entry.description.clone(),
+                amount: sign * entry.statement_amount,
+                ..Default::default()
+            }));
+        }
+
+        let mut notes = Vec::new();
+        if statement.has_missing_rates {
+            notes.push("Some amounts have no exchange rate and are excluded from the totals.".to_string());
+        }
+        Ok(BillingDocument {
+            document_type: "partner_statement".to_string(),
+            title: "Commission Statement".to_string(),
+            reference: format!(
+                "CST-{}-{}",
+                reference_code(None, var_partner_id),
+                statement.period_to.replace('-', "")
+            ),
+            issue_date: statement.period_to.clone(),
+            due_date: None,
+            period: Some(format!("{} to {}", statement.period_from, statement.period_to)),
+            currency,
+            company,
+            recipient_name: statement.partner_name.clone(),
+            recipient_lines: partner_recipient(&conn, var_partner_id)?.1,
+            lines,
+            totals: vec![
+                total("Opening balance", statement.opening_balance, false),
+                total("Commissions earned", statement.commissions_earned, false),
+                total("Adjustments", statement.adjustments, false),
+                total("Payments made", -statement.payments_made, false),
+                total("Closing balance", statement.closing_balance, true),
+            ],
+            notes,
+            payment_reference: None,
+            logo,
+        })
+    }
+}
@@ -0,0 +1,308 @@
+use super::partner_statements::{insert_adjustment, CommissionAdjustment};
+use super::{add_column_if_missing, current_timestamp, generate_id, rule_violation, Database};
+use crate::currency::round_amount;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+
+/// How much commission a partner gives back when a client cancels early: a
+/// client deactivated less than `window_months` after its deal start has
+/// `recovery_percent` of the commission earned on it recovered.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClawbackRule {
+    pub var_partner_id: String,
+    pub window_months: i32,
+    pub recovery_percent: f64,
+    #[serde(default)]
+    pub updated_at: String,
+}
+
+pub(super) fn create_tables(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS clawback_rules (
+            var_partner_id TEXT PRIMARY KEY,
+            window_months INTEGER NOT NULL,
+            recovery_percent REAL NOT NULL,
+            updated_at TEXT NOT NULL,
+            FOREIGN KEY (var_partner_id) REFERENCES var_partners (id)
+        )",
+        [],
+    )?;
+
+    add_column_if_missing(conn, "var_clients", "deactivated_at", "TEXT")?;
+
+    Ok(())
+}
+
+/// Whole months from `start` to `end`, both YYYY-MM-DD.
+fn months_between(start: &str, end: &str) -> Option<i32> {
+    let parse = |date: &str| -> Option<(i32, i32, i32)> {
+        let mut parts = date.get(..10)?.split('-').map(|p| p.parse::<i32>().ok());
+        Some((parts.next()??, parts.next()??, parts.next()??))
+    };
+    let (start_year, start_month, start_day) = parse(start)?;
+    let (end_year, end_month, end_day) = parse(end)?;
+    let months = (end_year - start_year) * 12 + end_month - start_month;
+    Some(if end_day < start_day { months - 1 } else { months })
+}
+
+/// Recovers commission from every partner that earned on a client cancelled
+/// on `cancelled_on` inside that partner's clawback window. A partner is
+/// only clawed back once per client, unless that clawback was reversed.
+pub(super) fn apply_clawbacks(
+    conn: &Connection,
+    var_client_id: &str,
+    cancelled_on: &str,
+) -> Result<Vec<CommissionAdjustment>> {
+    let (client_name, currency, deal_start_date): (String, String, String) = conn.query_row(
+        "SELECT client_name, currency, deal_start_date FROM var_clients WHERE id = ?1",
+        params![var_client_id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+    let Some(months) = months_between(&deal_start_date, cancelled_on) else {
+        log::warn!(
+            "Skipping clawback for {}: unreadable deal start '{}'",
+            client_name,
+            deal_start_date
+        );
+        return Ok(Vec::new());
+    };
+
+    // Commission each partner (distributors included) earned on this client,
+    // net of its share of credit and debit notes.
+    let mut stmt = conn.prepare(
+        "SELECT r.var_partner_id, r.window_months, r.recovery_percent,
+                (SELECT COALESCE(SUM(l.commission_amount
+                            + COALESCE((SELECT SUM(CASE WHEN n.note_type = 'credit' THEN -n.commission_amount
+                                                        ELSE n.commission_amount END)
+                                        FROM credit_notes n WHERE n.invoice_id = i.id), 0)
+                              * l.share_percent / 100), 0)
+                 FROM commission_lines l JOIN var_client_invoices i ON i.id = l.invoice_id
+                 WHERE i.var_client_id = ?1 AND l.var_partner_id = r.var_partner_id
+                   AND i.invoice_status NOT IN ('void', 'cancelled'))
+         FROM clawback_rules r
+         WHERE r.var_partner_id IN (SELECT l.var_partner_id FROM commission_lines l
+                                    JOIN var_client_invoices i ON i.id = l.invoice_id
+                                    WHERE i.var_client_id = ?1)
+           AND (SELECT COALESCE(round(SUM(amount), 6), 0) FROM commission_adjustments
+                WHERE var_client_id = ?1 AND var_partner_id = r.var_partner_id
+                  AND source IN ('clawback', 'clawback_reversal')) >= 0",
+    )?;
+    let candidates = stmt
+        .query_map(params![var_client_id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, i32>(1)?,
+                row.get::<_, f64>(2)?,
+                row.get::<_, f64>(3)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut adjustments = Vec::new();
+    for (var_partner_id, window_months, recovery_percent, earned) in candidates {
+        let amount = round_amount(earned * recovery_percent / 100.0, &currency);
+        if months >= window_months || amount <= 0.0 {
+            continue;
+        }
+        let adjustment = CommissionAdjustment {
+            id: generate_id(conn)?,
+            var_partner_id,
+            var_client_id: Some(var_client_id.to_string()),
+            adjustment_date: cancelled_on.to_string(),
+            amount: -amount,
+            currency: currency.clone(),
+            reason: format!(
+                "Clawback: {} cancelled after {} of {} months ({}% recovered)",
+                client_name, months, window_months, recovery_percent
+            ),
+            source: "clawback".to_string(),
+            created_at: current_timestamp(conn)?,
+        };
+        insert_adjustment(conn, &adjustment)?;
+        adjustments.push(adjustment);
+    }
+    Ok(adjustments)
+}
+
+/// Gives back the commission clawed back from each partner when a cancelled
+/// client is reactivated on `reactivated_on`.
+pub(super) fn reverse_clawbacks(
+    conn: &Connection,
+    var_client_id: &str,
+    reactivated_on: &str,
+) -> Result<Vec<CommissionAdjustment>> {
+    let client_name: String = conn.query_row(
+        "SELECT client_name FROM var_clients WHERE id = ?1",
+        params![var_client_id],
+        |row| row.get(0),
+    )?;
+    let mut stmt = conn.prepare(
+        "SELECT var_partner_id, currency, SUM(amount)
+         FROM commission_adjustments
+         WHERE var_client_id = ?1 AND source IN ('clawback', 'clawback_reversal')
+         GROUP BY var_partner_id, currency
+         HAVING round(SUM(amount), 6) < 0
+         ORDER BY var_partner_id",
+    )?;
+    let open = stmt
+        .query_map(params![var_client_id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, f64>(2)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut adjustments = Vec::new();
+    for (var_partner_id, currency, clawed_back) in open {
+        let adjustment = CommissionAdjustment {
+            id: generate_id(conn)?,
+            var_partner_id,
+            var_client_id: Some(var_client_id.to_string()),
+            adjustment_date: reactivated_on.to_string(),
+            amount: round_amount(-clawed_back, &currency),
+            currency,
+            reason: format!("Clawback reversed: {} reactivated", client_name),
+            source: "clawback_reversal".to_string(),
+            created_at: current_timestamp(conn)?,
+        };
+        insert_adjustment(conn, &adjustment)?;
+        adjustments.push(adjustment);
+    }
+    Ok(adjustments)
+}
+
+impl Database {
+    pub fn get_clawback_rules(&self) -> Result<Vec<ClawbackRule>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT var_partner_id, window_months, recovery_percent, updated_at
+             FROM clawback_rules ORDER BY var_partner_id",
+        )?;
+        let rules = stmt.query_map([], |row| {
+            Ok(ClawbackRule {
+                var_partner_id: row.get(0)?,
+                window_months: row.get(1)?,
+                recovery_percent: row.get(2)?,
+                updated_at: row.get(3)?,
+            })
+        })?;
+        rules.collect()
+    }
+
+    pub fn set_clawback_rule(&self, rule: ClawbackRule) -> Result<()> {
+        if rule.window_months <= 0 {
+            return Err(rule_violation("The clawback window must be at least one month"));
+        }
+        if !(0.0..=100.0).contains(&rule.recovery_percent) {
+            return Err(rule_violation("The recovery percentage must be between 0 and 100"));
+        }
+        let conn = self.conn.lock().unwrap();
+        let exists = conn
+            .query_row(
+                "SELECT 1 FROM var_partners WHERE id = ?1",
+                params![rule.var_partner_id],
+                |_| Ok(()),
+            )
+            .optional()?;
+        if exists.is_none() {
+            return Err(rule_violation(format!("Unknown partner '{}'", rule.var_partner_id)));
+        }
+        conn.execute(
+            "INSERT INTO clawback_rules (var_partner_id, window_months, recovery_percent, updated_at)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (var_partner_id) DO UPDATE SET
+                window_months = excluded.window_months,
+                recovery_percent = excluded.recovery_percent,
+                updated_at = excluded.updated_at",
+            params![
+                rule.var_partner_id,
+                rule.window_months,
+                rule.recovery_percent,
+                current_timestamp(&conn)?
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_clawback_rule(&self, var_partner_id: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "DELETE FROM clawback_rules WHERE var_partner_id = ?1",
+            params![var_partner_id],
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::test_support::{add_client, add_invoice, add_partner, client, test_db};
+
+    /// A partner with a 50% clawback and a client that earned it 100.
+    fn clawback_db() -> Database {
+        let db = test_db();
+        add_partner(&db, "p1", 10.0);
+        add_client(&db, "c1", "p1", 10.0, "ZAR");
+        add_invoice(&db, "i1", "c1", "p1", "2026-01", 1000.0, 10.0);
+        db.set_clawback_rule(ClawbackRule {
+            var_partner_id: "p1".to_string(),
+            window_months: 600,
+            recovery_percent: 50.0,
+            updated_at: String::new(),
+        })
+        .unwrap();
+        db
+    }
+
+    #[test]
+    fn counts_whole_months_between_dates() {
+        assert_eq!(months_between("2026-01-15", "2026-04-15"), Some(3));
+        assert_eq!(months_between("2026-01-15", "2026-04-14"), Some(2));
+        assert_eq!(months_between("2026-01", "2026-04-14"), None);
+    }
+
+    #[test]
+    fn deleting_a_client_claws_back_once() {
+        let db = clawback_db();
+        let clawbacks = db.delete_var_client("c1").unwrap();
+        assert_eq!(clawbacks.len(), 1);
+        assert_eq!(clawbacks[0].var_partner_id, "p1");
+        assert_eq!(clawbacks[0].amount, -50.0);
+        assert_eq!(clawbacks[0].source, "clawback");
+        assert!(db.delete_var_client("c1").unwrap().is_empty());
+    }
+
+    #[test]
+    fn updates_return_clawbacks_and_reactivation_reverses_them() {
+        let db = clawback_db();
+        let mut inactive = client("c1", "p1", 10.0, "ZAR");
+        inactive.is_active = false;
+        let clawbacks = db.update_var_client(inactive).unwrap();
+        assert_eq!(clawbacks.len(), 1);
+        assert_eq!(clawbacks[0].amount, -50.0);
+
+        let reversals = db.update_var_client(client("c1", "p1", 10.0, "ZAR")).unwrap();
+        assert_eq!(reversals.len(), 1);
+        assert_eq!(reversals[0].amount, 50.0);
+        assert_eq!(reversals[0].source, "clawback_reversal");
+        assert!(db
+            .update_var_client(client("c1", "p1", 10.0, "ZAR"))
+            .unwrap()
+            .is_empty());
+
+        // Cancelling again after the reversal claws back again.
+        let again = db.delete_var_client("c1").unwrap();
+        assert_eq!(again.len(), 1);
+        assert_eq!(again[0].amount, -50.0);
+        let net: f64 = db
+            .get_commission_adjustments(Some("p1"))
+            .unwrap()
+            .iter()
+            .map(|adjustment| adjustment.amount)
+            .sum();
+        assert_eq!(net, -50.0);
+    }
+}
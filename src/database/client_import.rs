@@ -0,0 +1,1070 @@
+use super::commission_rates::record_client_rate;
+use super::debt_codes::ensure_unique_debt_code;
+use super::exchange_rates::reporting_currency;
+use super::import_templates::strip_template_rows;
+use super::{
+    current_timestamp, generate_id, insert_client, insert_var_client, rule_violation, Client, Database, VarClient,
+};
+use crate::currency::{round_amount, validate};
+use crate::tabular::{parse_amount, parse_date, read_table, Table, TableOptions, TableRow};
+use rusqlite::{params, Connection, OptionalExtension, Result, Row};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::Path;
+
+/// The layouts offered in the import templates. `hybrid` files give each
+/// row's billing model; `var` files import VAR clients for one partner.
+pub const IMPORT_TEMPLATES: &[&str] = &["perpetual", "subscription", "hybrid", "var"];
+pub const BILLING_MODELS: &[&str] = &["perpetual", "subscription", "installment", "rentals"];
+
+/// The header names each field is read from, ignoring case and spacing.
+/// Generated templates use the first name.
+pub(super) const FIELD_HEADERS: [(&str, &[&str]); 10] = [
+    ("client_name", &["client name", "client", "name", "customer", "customer name"]),
+    ("debt_code", &["debt code", "debtcode", "account", "account code", "customer code"]),
+    ("users", &["users", "user count", "licenses", "licences"]),
+    ("billing_model", &["billing model", "model"]),
+    ("currency", &["currency"]),
+    ("total", &["total", "annual total"]),
+    ("comments", &["comments", "comment", "notes"]),
+    ("deal_start_date", &["deal start date", "deal start", "start date"]),
+    ("anniversary_month", &["anniversary month", "anniversary"]),
+    ("commission_rate", &["commission rate", "commission %", "commission"]),
+];
+
+pub(super) fn field_headers(field: &str) -> &'static [&'static str] {
+    FIELD_HEADERS
+        .iter()
+        .find(|(name, _)| *name == field)
+        .map(|(_, headers)| *headers)
+        .expect("every imported field has headers")
+}
+
+pub(super) const MONTH_COLUMNS: [[&str; 2]; 12] = [
+    ["jan", "january"],
+    ["feb", "february"],
+    ["mar", "march"],
+    ["apr", "april"],
+    ["may", "may"],
+    ["jun", "june"],
+    ["jul", "july"],
+    ["aug", "august"],
+    ["sep", "september"],
+    ["oct", "october"],
+    ["nov", "november"],
+    ["dec", "december"],
+];
+
+/// How to read an import file. `billing_model` and `currency` fill in rows
+/// that leave them blank; `var_partner_id` is required for the `var` template.
+/// `sheet` and `header_row` pick where the data sits in a workbook. With
+/// `upsert`, rows matching an existing client update it instead of being
+/// rejected.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClientImportOptions {
+    pub template: String,
+    pub var_partner_id: Option<String>,
+    pub billing_model: Option<String>,
+    pub currency: Option<String>,
+    #[serde(default)]
+    pub upsert: bool,
+    #[serde(flatten)]
+    pub source: TableOptions,
+}
+
+/// Why a row was rejected. `row` is the line or spreadsheet row number.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportRowError {
+    pub row: usize,
+    pub column: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClientImportReport {
+    pub template: String,
+    pub rows_read: usize,
+    pub imported: usize,
+    pub updated: usize,
+    pub rejected_rows: usize,
+    pub errors: Vec<ImportRowError>,
+}
+
+/// A field an import would change on an existing client.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub current: Value,
+    pub incoming: Value,
+}
+
+/// What confirming the import would do with one valid row. `status` is
+/// `new`, `updated`, `unchanged` or `conflicting`; `matched_by` says whether
+/// an existing client was found by `debt_code` or `name`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportPreviewRow {
+    pub row: usize,
+    pub status: String,
+    pub client_name: String,
+    pub debt_code: Option<String>,
+    pub existing_id: Option<String>,
+    pub matched_by: Option<String>,
+    pub changes: Vec<FieldChange>,
+    pub reason: Option<String>,
+}
+
+/// A dry run of an import. Nothing is written until the preview is confirmed
+/// with its `token`; invalid rows are listed in `errors` as for an import.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClientImportPreview {
+    pub token: String,
+    pub template: String,
+    pub rows_read: usize,
+    pub new_rows: usize,
+    pub updated_rows: usize,
+    pub unchanged_rows: usize,
+    pub conflicting_rows: usize,
+    pub rejected_rows: usize,
+    pub rows: Vec<ImportPreviewRow>,
+    pub errors: Vec<ImportRowError>,
+}
+
+struct ImportColumns {
+    client_name: usize,
+    debt_code: Option<usize>,
+    users: Option<usize>,
+    billing_model: Option<usize>,
+    currency: Option<usize>,
+    months: [Option<usize>; 12],
+    total: Option<usize>,
+    comments: Option<usize>,
+    deal_start_date: Option<usize>,
+    anniversary_month: Option<usize>,
+    commission_rate: Option<usize>,
+}
+
+/// The month a header stands for when it is not a plain month name, such as
+/// `Jan-26`, `January 2026` or a date cell formatted as a month.
+fn header_month(header: &str) -> Option<usize> {
+    let header = header.trim().to_lowercase();
+    let name_length = header.chars().take_while(|c| c.is_ascii_alphabetic()).count();
+    if name_length >= 3 {
+        let rest_is_year = header[name_length..]
+            .chars()
+            .all(|c| c.is_ascii_digit() || matches!(c, ' ' | '-' | '/' | '\'' | '.'));
+        return MONTH_COLUMNS
+            .iter()
+            .position(|[_, full]| full.starts_with(&header[..name_length]))
+            .filter(|_| rest_is_year);
+    }
+    let date = parse_date(&header)?;
+    date[5..7].parse::<usize>().ok().map(|month| month - 1)
+}
+
+fn month_column(table: &Table, month: usize) -> Option<usize> {
+    table.find_column(&MONTH_COLUMNS[month]).or_else(|| {
+        table
+            .headers
+            .iter()
+            .position(|header| header_month(header) == Some(month))
+    })
+}
+
+impl ImportColumns {
+    fn find(table: &Table) -> Result<Self> {
+        let find = |field| table.find_column(field_headers(field));
+        let client_name = find("client_name").ok_or_else(|| rule_violation("The file needs a Client Name column"))?;
+        Ok(ImportColumns {
+            client_name,
+            debt_code: find("debt_code"),
+            users: find("users"),
+            billing_model: find("billing_model"),
+            currency: find("currency"),
+            months: std::array::from_fn(|month| month_column(table, month)),
+            total: find("total"),
+            comments: find("comments"),
+            deal_start_date: find("deal_start_date"),
+            anniversary_month: find("anniversary_month"),
+            commission_rate: find("commission_rate"),
+        })
+    }
+}
+
+/// The stored fields of a client that imports read and write.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct ClientFields {
+    client_name: String,
+    debt_code: Option<String>,
+    users: i32,
+    billing_model: String,
+    currency: String,
+    months: [f64; 12],
+    total: f64,
+    comments: Option<String>,
+    deal_start_date: String,
+    anniversary_month: Option<i32>,
+    commission_rate: Option<f64>,
+}
+
+impl ClientFields {
+    fn values(&self) -> Vec<(&'static str, Value)> {
+        let mut values = vec![
+            ("client_name", json!(self.client_name)),
+            ("debt_code", json!(self.debt_code)),
+            ("users", json!(self.users)),
+            ("billing_model", json!(self.billing_model)),
+            ("currency", json!(self.currency)),
+        ];
+        values.extend(
+            MONTH_COLUMNS
+                .iter()
+                .zip(self.months)
+                .map(|([name, _], amount)| (*name, json!(amount))),
+        );
+        values.extend([
+            ("total", json!(self.total)),
+            ("comments", json!(self.comments)),
+            ("deal_start_date", json!(self.deal_start_date)),
+            ("anniversary_month", json!(self.anniversary_month)),
+            ("commission_rate", json!(self.commission_rate)),
+        ]);
+        values
+    }
+
+    fn changes_to(&self, incoming: &ClientFields) -> Vec<FieldChange> {
+        self.values()
+            .into_iter()
+            .zip(incoming.values())
+            .filter(|((_, current), (_, incoming))| current != incoming)
+            .map(|((field, current), (_, incoming))| FieldChange {
+                field: field.to_string(),
+                current,
+                incoming,
+            })
+            .collect()
+    }
+}
+
+/// A validated row. Empty fields were left blank or have no column, so an
+/// update keeps the existing value and a new client gets the default.
+#[derive(Debug, Clone)]
+struct ImportedClient {
+    client_name: String,
+    debt_code: Option<String>,
+    users: Option<i32>,
+    billing_model: Option<String>,
+    currency: Option<String>,
+    months: [Option<f64>; 12],
+    total: Option<f64>,
+    comments: Option<String>,
+    deal_start_date: Option<String>,
+    anniversary_month: Option<i32>,
+    commission_rate: Option<f64>,
+}
+
+/// Defaults that apply to every row of one import.
+struct ImportDefaults<'a> {
+    template: &'a str,
+    currency: &'a str,
+    today: &'a str,
+    partner_rate: Option<f64>,
+}
+
+impl ImportedClient {
+    /// The fields a confirmed row will store, on top of `base` for an update.
+    /// Only fails for a new client whose billing model cannot be worked out.
+    fn resolve(&self, base: Option<&ClientFields>, defaults: &ImportDefaults) -> Option<ClientFields> {
+        let billing_model = match (&self.billing_model, base, defaults.template) {
+            (Some(model), _, _) => model.clone(),
+            (None, Some(base), _) => base.billing_model.clone(),
+            (None, None, "perpetual") => "perpetual".to_string(),
+            (None, None, "subscription" | "var") => "subscription".to_string(),
+            (None, None, _) => return None,
+        };
+        let currency = self
+            .currency
+            .clone()
+            .or_else(|| base.map(|b| b.currency.clone()))
+            .unwrap_or_else(|| defaults.currency.to_string());
+        let months: [f64; 12] = std::array::from_fn(|month| {
+            let amount = self.months[month].or(base.map(|b| b.months[month])).unwrap_or(0.0);
+            round_amount(amount, &currency)
+        });
+        let total = self
+            .total
+            .or(base
+                .filter(|_| self.months.iter().all(Option::is_none))
+                .map(|b| b.total))
+            .unwrap_or_else(|| months.iter().sum());
+
+        Some(ClientFields {
+            client_name: self.client_name.clone(),
+            debt_code: self
+                .debt_code
+                .clone()
+                .or_else(|| base.and_then(|b| b.debt_code.clone())),
+            users: self.users.or(base.map(|b| b.users)).unwrap_or(0),
+            billing_model,
+            total: round_amount(total, &currency),
+            currency,
+            months,
+            comments: self.comments.clone().or_else(|| base.and_then(|b| b.comments.clone())),
+            deal_start_date: self
+                .deal_start_date
+                .clone()
+                .or_else(|| base.map(|b| b.deal_start_date.clone()))
+                .unwrap_or_else(|| defaults.today.to_string()),
+            anniversary_month: self.anniversary_month.or(base.and_then(|b| b.anniversary_month)),
+            commission_rate: self
+                .commission_rate
+                .or(base.and_then(|b| b.commission_rate))
+                .or(defaults.partner_rate),
+        })
+    }
+}
+
+/// Checks one row against the client rules, recording every problem found.
+fn validate_row(
+    row: &TableRow,
+    table: &Table,
+    columns: &ImportColumns,
+    billing_model: Option<&str>,
+    currency: Option<&str>,
+    errors: &mut Vec<ImportRowError>,
+) -> Option<ImportedClient> {
+    let errors_before = errors.len();
+    let mut reject = |column: Option<usize>, reason: String| {
+        errors.push(ImportRowError {
+            row: row.line,
+            column: column_name(table, column),
+            reason,
+        });
+    };
+
+    let client_name = row.get(Some(columns.client_name)).to_string();
+    if client_name.is_empty() {
+        reject(Some(columns.client_name), "The client name is required".to_string());
+    }
+
+    let users = match row.get(columns.users) {
+        "" => None,
+        value => match value.parse::<f64>() {
+            Ok(users) if users >= 0.0 && users.fract() == 0.0 && users <= i32::MAX as f64 => Some(users as i32),
+            _ => {
+                reject(columns.users, format!("'{}' is not a whole number of users", value));
+                None
+            }
+        },
+    };
+
+    let billing_model = match row.get(columns.billing_model).to_lowercase() {
+        value if value.is_empty() => billing_model.map(str::to_string),
+        value if BILLING_MODELS.contains(&value.as_str()) => Some(value),
+        value => {
+            reject(
+                columns.billing_model,
+                format!(
+                    "Unknown billing model '{}'; expected one of {}",
+                    value,
+                    BILLING_MODELS.join(", ")
+                ),
+            );
+            None
+        }
+    };
+
+    let currency = match row.get(columns.currency) {
+        "" => currency.map(str::to_string),
+        value => match validate(value) {
+            Ok(code) => Some(code.to_string()),
+            Err(message) => {
+                reject(columns.currency, message);
+                None
+            }
+        },
+    };
+
+    let mut months = [None; 12];
+    for (month, column) in months.iter_mut().zip(columns.months) {
+        if column.is_none() {
+            continue;
+        }
+        *month = match row.get(column) {
+            "" => Some(0.0),
+            value => match parse_amount(value) {
+                Some(amount) if amount >= 0.0 => Some(amount),
+                Some(_) => {
+                    reject(column, "Monthly amounts cannot be negative".to_string());
+                    None
+                }
+                None => {
+                    reject(column, format!("'{}' is not an amount", value));
+                    None
+                }
+            },
+        };
+    }
+    let scheduled: f64 = months.iter().flatten().sum();
+    let total = match row.get(columns.total) {
+        "" => None,
+        value => match parse_amount(value) {
+            Some(total) if (total - scheduled).abs() < 0.01 || columns.months.iter().all(Option::is_none) => {
+                Some(total)
+            }
+            Some(total) => {
+                reject(
+                    columns.total,
+                    format!(
+                        "The total {:.2} does not match the monthly amounts ({:.2})",
+                        total, scheduled
+                    ),
+                );
+                None
+            }
+            None => {
+                reject(columns.total, format!("'{}' is not an amount", value));
+                None
+            }
+        },
+    };
+
+    let deal_start_date = match row.get(columns.deal_start_date) {
+        "" => None,
+        value => {
+            let date = parse_date(value);
+            if date.is_none() {
+                reject(columns.deal_start_date, format!("'{}' is not a date", value));
+            }
+            date
+        }
+    };
+
+    let anniversary_month = match row.get(columns.anniversary_month) {
+        "" => None,
+        value => match value.parse::<i32>() {
+            Ok(month) if (1..=12).contains(&month) => Some(month),
+            _ => {
+                reject(
+                    columns.anniversary_month,
+                    format!("'{}' is not a month number from 1 to 12", value),
+                );
+                None
+            }
+        },
+    };
+
+    let commission_rate = match row.get(columns.commission_rate) {
+        "" => None,
+        value => match parse_amount(value) {
+            Some(rate) if (0.0..=100.0).contains(&rate) => Some(rate),
+            _ => {
+                reject(
+                    columns.commission_rate,
+                    format!("'{}' is not a rate from 0 to 100", value),
+                );
+                None
+            }
+        },
+    };
+
+    if errors.len() > errors_before {
+        return None;
+    }
+    Some(ImportedClient {
+        client_name,
+        debt_code: Some(row.get(columns.debt_code).to_string()).filter(|code| !code.is_empty()),
+        users,
+        billing_model,
+        currency,
+        months,
+        total,
+        comments: Some(row.get(columns.comments).to_string()).filter(|comment| !comment.is_empty()),
+        deal_start_date,
+        anniversary_month,
+        commission_rate,
+    })
+}
+
+fn column_name(table: &Table, column: Option<usize>) -> String {
+    column
+        .and_then(|index| table.headers.get(index))
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// A client already in the database that rows are matched against.
+struct ExistingClient {
+    id: String,
+    var_partner_id: Option<String>,
+    is_active: bool,
+    fields: ClientFields,
+}
+
+const FIELD_COLUMNS: &str = "client_name, debt_code, users, billing_model, currency,
+    jan, feb, mar, apr, may, jun, jul, aug, sep, oct, nov, dec, total,
+    comments, deal_start_date, anniversary_month, commission_rate";
+
+fn fields_from_row(row: &Row, offset: usize) -> Result<ClientFields> {
+    let mut months = [0.0; 12];
+    for (index, month) in months.iter_mut().enumerate() {
+        *month = row.get(offset + 5 + index)?;
+    }
+    Ok(ClientFields {
+        client_name: row.get(offset)?,
+        debt_code: row.get(offset + 1)?,
+        users: row.get(offset + 2)?,
+        billing_model: row.get(offset + 3)?,
+        currency: row.get(offset + 4)?,
+        months,
+        total: row.get(offset + 17)?,
+        comments: row.get(offset + 18)?,
+        deal_start_date: row.get(offset + 19)?,
+        anniversary_month: row.get(offset + 20)?,
+        commission_rate: row.get(offset + 21)?,
+    })
+}
+
+fn client_table(var_partner_id: Option<&str>) -> &'static str {
+    if var_partner_id.is_some() {
+        "var_clients"
+    } else {
+        "clients"
+    }
+}
+
+fn existing_clients(conn: &Connection, table: &str) -> Result<Vec<ExistingClient>> {
+    let partner_column = if table == "var_clients" {
+        "var_partner_id"
+    } else {
+        "NULL"
+    };
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id, {partner_column}, is_active, {FIELD_COLUMNS} FROM {table} ORDER BY created_at"
+    ))?;
+    let clients = stmt.query_map([], |row| {
+        Ok(ExistingClient {
+            id: row.get(0)?,
+            var_partner_id: row.get(1)?,
+            is_active: row.get::<_, i32>(2)? == 1,
+            fields: fields_from_row(row, 3)?,
+        })
+    })?;
+    clients.collect()
+}
+
+fn current_fields(conn: &Connection, table: &str, id: &str) -> Result<Option<ClientFields>> {
+    conn.query_row(
+        &format!("SELECT {FIELD_COLUMNS} FROM {table} WHERE id = ?1"),
+        params![id],
+        |row| fields_from_row(row, 0),
+    )
+    .optional()
+}
+
+/// A write a confirmed import will make: an insert, or an update of
+/// `existing_id` that expects the client to still hold `current`.
+#[derive(Debug, Serialize, Deserialize)]
+struct PlannedChange {
+    row: usize,
+    existing_id: Option<String>,
+    current: Option<ClientFields>,
+    fields: ClientFields,
+}
+
+/// Everything a preview decided, kept under its token until confirmed.
+#[derive(Debug, Serialize, Deserialize)]
+struct ImportPlan {
+    template: String,
+    var_partner_id: Option<String>,
+    rows_read: usize,
+    unchanged_rows: usize,
+    changes: Vec<PlannedChange>,
+}
+
+/// Validates and matches every row of an import file against the clients
+/// already stored. Matching is by debt code, falling back to the client name.
+fn plan_import(
+    conn: &Connection,
+    path: &Path,
+    options: &ClientImportOptions,
+) -> Result<(ImportPlan, ClientImportPreview)> {
+    if !IMPORT_TEMPLATES.contains(&options.template.as_str()) {
+        return Err(rule_violation(format!(
+            "Unknown import template '{}'; expected one of {}",
+            options.template,
+            IMPORT_TEMPLATES.join(", ")
+        )));
+    }
+    let billing_model = options.billing_model.as_deref().filter(|m| !m.is_empty());
+    if let Some(model) = billing_model.filter(|m| !BILLING_MODELS.contains(m)) {
+        return Err(rule_violation(format!("Unknown billing model '{}'", model)));
+    }
+    let currency = match options.currency.as_deref().filter(|c| !c.is_empty()) {
+        Some(currency) => Some(validate(currency).map_err(rule_violation)?),
+        None => None,
+    };
+    let table = read_table(path, &options.source).map_err(rule_violation)?;
+    let table = strip_template_rows(table, &options.template)?;
+    let columns = ImportColumns::find(&table)?;
+
+    let (var_partner_id, partner_rate) = match (options.template.as_str(), options.var_partner_id.as_deref()) {
+        ("var", Some(var_partner_id)) => {
+            let rate: Option<f64> = conn
+                .query_row(
+                    "SELECT commission_rate FROM var_partners WHERE id = ?1 AND is_active = 1",
+                    params![var_partner_id],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            match rate {
+                Some(rate) => (Some(var_partner_id), Some(rate)),
+                None => {
+                    return Err(rule_violation(format!(
+                        "Unknown or inactive partner '{}'",
+                        var_partner_id
+                    )))
+                }
+            }
+        }
+        ("var", None) => return Err(rule_violation("VAR client imports need a partner")),
+        _ => (None, None),
+    };
+    let reporting = reporting_currency(conn)?;
+    let today: String = conn.query_row("SELECT date('now')", [], |row| row.get(0))?;
+    let defaults = ImportDefaults {
+        template: &options.template,
+        currency: &reporting,
+        today: &today,
+        partner_rate,
+    };
+    let existing = existing_clients(conn, client_table(var_partner_id))?;
+
+    let mut plan = ImportPlan {
+        template: options.template.clone(),
+        var_partner_id: var_partner_id.map(str::to_string),
+        rows_read: table.rows.len(),
+        unchanged_rows: 0,
+        changes: Vec::new(),
+    };
+    let mut preview = ClientImportPreview {
+        token: String::new(),
+        template: options.template.clone(),
+        rows_read: table.rows.len(),
+        new_rows: 0,
+        updated_rows: 0,
+        unchanged_rows: 0,
+        conflicting_rows: 0,
+        rejected_rows: 0,
+        rows: Vec::new(),
+        errors: Vec::new(),
+    };
+    // Rows already claimed in this file, by existing client id or new client key.
+    let mut claimed: Vec<(String, usize)> = Vec::new();
+
+    for row in &table.rows {
+        let Some(imported) = validate_row(row, &table, &columns, billing_model, currency, &mut preview.errors) else {
+            preview.rejected_rows += 1;
+            continue;
+        };
+        let same_name = |client: &&ExistingClient| {
+            client
+                .fields
+                .client_name
+                .trim()
+                .eq_ignore_ascii_case(&imported.client_name)
+        };
+        let by_code: Vec<&ExistingClient> = match &imported.debt_code {
+            Some(code) => existing
+                .iter()
+                .filter(|c| {
+                    c.fields
+                        .debt_code
+                        .as_deref()
+                        .is_some_and(|d| d.trim().eq_ignore_ascii_case(code))
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        let (matched, matched_by) = if by_code.is_empty() {
+            (existing.iter().filter(same_name).collect::<Vec<_>>(), "name")
+        } else {
+            (by_code, "debt_code")
+        };
+
+        let mut preview_row = ImportPreviewRow {
+            row: row.line,
+            status: String::new(),
+            client_name: imported.client_name.clone(),
+            debt_code: imported.debt_code.clone(),
+            existing_id: None,
+            matched_by: None,
+            changes: Vec::new(),
+            reason: None,
+        };
+        let conflict = match matched.as_slice() {
+            [] => None,
+            [client] => {
+                preview_row.existing_id = Some(client.id.clone());
+                preview_row.matched_by = Some(matched_by.to_string());
+                if !client.is_active {
+                    Some("It matches an inactive client; reactivate that client first".to_string())
+                } else if client
+                    .var_partner_id
+                    .as_deref()
+                    .is_some_and(|p| Some(p) != var_partner_id)
+                {
+                    Some("It matches a VAR client of another partner".to_string())
+                } else if let (Some(incoming), Some(stored)) = (&imported.debt_code, &client.fields.debt_code) {
+                    (matched_by == "name" && !incoming.eq_ignore_ascii_case(stored.trim()))
+                        .then(|| format!("A client with this name already has debt code '{}'", stored))
+                } else {
+                    None
+                }
+            }
+            several => Some(format!(
+                "{} existing clients share this {}",
+                several.len(),
+                matched_by.replace('_', " ")
+            )),
+        };
+        let key = match &preview_row.existing_id {
+            Some(id) => id.clone(),
+            None => match &imported.debt_code {
+                Some(code) => format!("code:{}", code.to_lowercase()),
+                None => format!("name:{}", imported.client_name.to_lowercase()),
+            },
+        };
+        let conflict = conflict.or_else(|| {
+            claimed
+                .iter()
+                .find(|(claimed_key, _)| *claimed_key == key)
+                .map(|(_, line)| format!("Row {} already imports this client", line))
+        });
+        if let Some(reason) = conflict {
+            preview_row.status = "conflicting".to_string();
+            preview_row.reason = Some(reason);
+            preview.conflicting_rows += 1;
+            preview.rows.push(preview_row);
+            continue;
+        }
+
+        let base = matched.first().map(|client| &client.fields);
+        let Some(fields) = imported.resolve(base, &defaults) else {
+            preview.errors.push(ImportRowError {
+                row: row.line,
+                column: column_name(&table, columns.billing_model),
+                reason: "Hybrid imports need a billing model for every new client".to_string(),
+            });
+            preview.rejected_rows += 1;
+            continue;
+        };
+        claimed.push((key, row.line));
+        match base {
+            None => {
+                preview_row.status = "new".to_string();
+                preview.new_rows += 1;
+            }
+            Some(current) => {
+                preview_row.changes = current.changes_to(&fields);
+                if preview_row.changes.is_empty() {
+                    preview_row.status = "unchanged".to_string();
+                    preview.unchanged_rows += 1;
+                    plan.unchanged_rows += 1;
+                    preview.rows.push(preview_row);
+                    continue;
+                }
+                preview_row.status = "updated".to_string();
+                preview.updated_rows += 1;
+            }
+        }
+        plan.changes.push(PlannedChange {
+            row: row.line,
+            existing_id: preview_row.existing_id.clone(),
+            current: base.cloned(),
+            fields,
+        });
+        preview.rows.push(preview_row);
+    }
+    Ok((plan, preview))
+}
+
+fn insert_fields(
+    conn: &Connection,
+    fields: ClientFields,
+    var_partner_id: Option<&str>,
+    created_at: &str,
+) -> Result<()> {
+    let [jan, feb, mar, apr, may, jun, jul, aug, sep, oct, nov, dec] = fields.months;
+    match var_partner_id {
+        Some(var_partner_id) => insert_var_client(
+            conn,
+            &VarClient {
+                id: generate_id(conn)?,
+                client_name: fields.client_name,
+                debt_code: fields.debt_code,
+                users: fields.users,
+                billing_model: fields.billing_model,
+                currency: fields.currency,
+                jan,
+                feb,
+                mar,
+                apr,
+                may,
+                jun,
+                jul,
+                aug,
+                sep,
+                oct,
+                nov,
+                dec,
+                total: fields.total,
+                comments: fields.comments,
+                deal_start_date: fields.deal_start_date,
+                anniversary_month: fields.anniversary_month,
+                billing_frequency: None,
+                installment_months: None,
+                monthly_factor: None,
+                implementation_fee: None,
+                implementation_months: None,
+                implementation_start_date: None,
+                implementation_complete_date: None,
+                subscription_duration: None,
+                var_partner_id: var_partner_id.to_string(),
+                commission_rate: fields.commission_rate.unwrap_or(0.0),
+                is_active: true,
+                created_at: created_at.to_string(),
+                custom_increase_rate: None,
+                future_year_data: None,
+                base_year_data: None,
+            },
+        ),
+        None => insert_client(
+            conn,
+            &Client {
+                id: generate_id(conn)?,
+                client_name: fields.client_name,
+                debt_code: fields.debt_code,
+                users: fields.users,
+                billing_model: fields.billing_model,
+                currency: fields.currency,
+                jan,
+                feb,
+                mar,
+                apr,
+                may,
+                jun,
+                jul,
+                aug,
+                sep,
+                oct,
+                nov,
+                dec,
+                total: fields.total,
+                comments: fields.comments,
+                deal_start_date: fields.deal_start_date,
+                anniversary_month: fields.anniversary_month,
+                billing_frequency: None,
+                installment_months: None,
+                monthly_factor: None,
+                implementation_fee: None,
+                implementation_months: None,
+                implementation_start_date: None,
+                implementation_complete_date: None,
+                subscription_duration: None,
+                subscription_start_date: None,
+                monthly_license_rate: None,
+                commission_rate: fields.commission_rate,
+                var_partner: None,
+                is_active: true,
+                created_at: created_at.to_string(),
+                custom_increase_rate: None,
+                future_year_data: None,
+                base_year_data: None,
+            },
+        ),
+    }
+}
+
+fn update_fields(conn: &Connection, table: &str, id: &str, fields: &ClientFields) -> Result<()> {
+    ensure_unique_debt_code(conn, table, fields.debt_code.as_deref(), id)?;
+    let [jan, feb, mar, apr, may, jun, jul, aug, sep, oct, nov, dec] = fields.months;
+    conn.execute(
+        &format!(
+            "UPDATE {table} SET
+                client_name = ?2, debt_code = ?3, users = ?4, billing_model = ?5, currency = ?6,
+                jan = ?7, feb = ?8, mar = ?9, apr = ?10, may = ?11, jun = ?12, jul = ?13,
+                aug = ?14, sep = ?15, oct = ?16, nov = ?17, dec = ?18, total = ?19,
+                comments = ?20, deal_start_date = ?21, anniversary_month = ?22, commission_rate = ?23
+             WHERE id = ?1"
+        ),
+        params![
+            id,
+            fields.client_name,
+            fields.debt_code,
+            fields.users,
+            fields.billing_model,
+            fields.currency,
+            jan,
+            feb,
+            mar,
+            apr,
+            may,
+            jun,
+            jul,
+            aug,
+            sep,
+            oct,
+            nov,
+            dec,
+            fields.total,
+            fields.comments,
+            fields.deal_start_date,
+            fields.anniversary_month,
+            fields.commission_rate
+        ],
+    )?;
+    Ok(())
+}
+
+/// Writes a plan's changes, refusing if a matched client has changed since
+/// the plan was made. Returns the number of inserts and updates.
+fn apply_plan(conn: &Connection, plan: &ImportPlan, include_updates: bool) -> Result<(usize, usize)> {
+    let var_partner_id = plan.var_partner_id.as_deref();
+    let table = client_table(var_partner_id);
+    let created_at = current_timestamp(conn)?;
+    let (mut inserted, mut updated) = (0, 0);
+    for change in &plan.changes {
+        match &change.existing_id {
+            None => {
+                insert_fields(conn, change.fields.clone(), var_partner_id, &created_at)?;
+                inserted += 1;
+            }
+            Some(id) if include_updates => {
+                if current_fields(conn, table, id)? != change.current {
+                    return Err(rule_violation(format!(
+                        "The client on row {} changed after the preview; preview the import again",
+                        change.row
+                    )));
+                }
+                update_fields(conn, table, id, &change.fields)?;
+                if let (Some(partner), Some(rate)) = (var_partner_id, change.fields.commission_rate) {
+                    if change.current.as_ref().and_then(|c| c.commission_rate) != Some(rate) {
+                        record_client_rate(conn, id, partner, rate)?;
+                    }
+                }
+                updated += 1;
+            }
+            Some(_) => {}
+        }
+    }
+    Ok((inserted, updated))
+}
+
+pub(super) fn create_tables(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS client_import_previews (
+            token TEXT PRIMARY KEY,
+            plan TEXT NOT NULL,
+            created_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Previews older than this are discarded and can no longer be confirmed.
+const PREVIEW_LIFETIME: &str = "-1 day";
+
+impl Database {
+    /// Imports clients from a template file. Every row is validated first and
+    /// the changes are written together. Rows matching an existing client are
+    /// rejected unless `upsert` is set, in which case they update it; invalid
+    /// and conflicting rows are listed in the report with the reason.
+    pub fn import_clients(&self, path: &Path, options: ClientImportOptions) -> Result<ClientImportReport> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let (plan, preview) = plan_import(&tx, path, &options)?;
+        let (imported, updated) = apply_plan(&tx, &plan, options.upsert)?;
+
+        let mut errors = preview.errors;
+        for row in &preview.rows {
+            let reason = match (row.status.as_str(), row.matched_by.as_deref()) {
+                ("new", _) => continue,
+                ("updated" | "unchanged", _) if options.upsert => continue,
+                ("conflicting", _) => row.reason.clone().unwrap_or_default(),
+                (_, Some("debt_code")) => "A client with this debt code already exists".to_string(),
+                _ => "A client with this name already exists".to_string(),
+            };
+            errors.push(ImportRowError {
+                row: row.row,
+                column: String::new(),
+                reason,
+            });
+        }
+        errors.sort_by_key(|error| error.row);
+        let unchanged = if options.upsert { preview.unchanged_rows } else { 0 };
+        tx.commit()?;
+        Ok(ClientImportReport {
+            template: plan.template,
+            rows_read: plan.rows_read,
+            imported,
+            updated,
+            rejected_rows: plan.rows_read - imported - updated - unchanged,
+            errors,
+        })
+    }
+
+    /// Works out what importing a file would do without changing any client.
+    /// The returned token confirms exactly this preview.
+    pub fn preview_client_import(&self, path: &Path, options: ClientImportOptions) -> Result<ClientImportPreview> {
+        let conn = self.conn.lock().unwrap();
+        let (plan, mut preview) = plan_import(&conn, path, &options)?;
+        conn.execute(
+            "DELETE FROM client_import_previews WHERE created_at < strftime('%Y-%m-%dT%H:%M:%fZ', 'now', ?1)",
+            params![PREVIEW_LIFETIME],
+        )?;
+        preview.token = generate_id(&conn)?;
+        let plan = serde_json::to_string(&plan).map_err(|e| rule_violation(e.to_string()))?;
+        conn.execute(
+            "INSERT INTO client_import_previews (token, plan, created_at) VALUES (?1, ?2, ?3)",
+            params![preview.token, plan, current_timestamp(&conn)?],
+        )?;
+        Ok(preview)
+    }
+
+    /// Applies a previewed import: new clients are inserted and changed ones
+    /// updated, all or nothing. Conflicting and invalid rows are skipped.
+    pub fn confirm_client_import(&self, token: &str) -> Result<ClientImportReport> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let plan: Option<String> = tx
+            .query_row(
+                "SELECT plan FROM client_import_previews
+                 WHERE token = ?1 AND created_at >= strftime('%Y-%m-%dT%H:%M:%fZ', 'now', ?2)",
+                params![token, PREVIEW_LIFETIME],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let plan: ImportPlan = match plan {
+            Some(plan) => serde_json::from_str(&plan).map_err(|e| rule_violation(e.to_string()))?,
+            None => return Err(rule_violation("The import preview has expired or was already used")),
+        };
+        let (imported, updated) = apply_plan(&tx, &plan, true)?;
+        tx.execute("DELETE FROM client_import_previews WHERE token = ?1", params![token])?;
+        tx.commit()?;
+        Ok(ClientImportReport {
+            template: plan.template,
+            rows_read: plan.rows_read,
+            imported,
+            updated,
+            rejected_rows: plan.rows_read - imported - updated - plan.unchanged_rows,
+            errors: Vec::new(),
+        })
+    }
+
+    pub fn discard_client_import(&self, token: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM client_import_previews WHERE token = ?1", params![token])?;
+        Ok(())
+    }
+}
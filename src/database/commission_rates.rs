@@ -0,0 +1,556 @@
+use super::commission_rules::CommissionCalculation;
+use super::{add_column_if_missing, current_timestamp, generate_id, rule_violation, Database};
+use crate::currency::round_amount;
+use rusqlite::{params, Connection, OptionalExtension, Result, Row};
+use serde::{Deserialize, Serialize};
+
+/// Start date given to rates that were in force before history was kept, so
+/// they keep covering every earlier billing month.
+const OPEN_START: &str = "1900-01-01";
+
+/// One period during which a commission rate applied. Partner defaults have no
+/// `var_client_id`; client overrides carry both ids. `valid_from` and
+/// `valid_to` are inclusive dates (YYYY-MM-DD) and an open `valid_to` means
+/// the rate is still in force.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommissionRateHistory {
+    pub id: String,
+    pub var_partner_id: String,
+    pub var_client_id: Option<String>,
+    pub rate: f64,
+    pub valid_from: String,
+    pub valid_to: Option<String>,
+    pub created_at: String,
+}
+
+/// Input for `add_commission_rate`. Leave `var_client_id` empty to change the
+/// partner default.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommissionRateRequest {
+    pub var_partner_id: String,
+    pub var_client_id: Option<String>,
+    pub rate: f64,
+    pub valid_from: String,
+    pub valid_to: Option<String>,
+}
+
+/// The rate in force for a client in a billing month and where it came from:
+/// `client_override` or `partner_default`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResolvedCommissionRate {
+    pub var_client_id: String,
+    pub billing_month: String,
+    pub rate: f64,
+    pub source: String,
+    pub history_id: Option<String>,
+    pub valid_from: Option<String>,
+    pub valid_to: Option<String>,
+}
+
+pub(super) fn create_tables(conn: &Connection) -> Result<()> {
+    add_column_if_missing(conn, "var_client_invoices", "commission_source", "TEXT")?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS commission_rate_history (
+            id TEXT PRIMARY KEY,
+            var_partner_id TEXT NOT NULL,
+            var_client_id TEXT,
+            rate REAL NOT NULL,
+            valid_from TEXT NOT NULL,
+            valid_to TEXT,
+            created_at TEXT NOT NULL,
+            FOREIGN KEY (var_partner_id) REFERENCES var_partners (id),
+            FOREIGN KEY (var_client_id) REFERENCES var_clients (id)
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_commission_rate_history_target
+         ON commission_rate_history (var_partner_id, var_client_id, valid_from)",
+        [],
+    )?;
+
+    // Seed history from the rates stored before it existed. Partners start with
+    // their current default; clients whose rate differs from it get an override.
+    conn.execute(
+        "INSERT INTO commission_rate_history (id, var_partner_id, var_client_id, rate, valid_from, created_at)
+         SELECT lower(hex(randomblob(16))), p.id, NULL, p.commission_rate, ?1,
+                strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+         FROM var_partners p
+         WHERE NOT EXISTS (
+             SELECT 1 FROM commission_rate_history h
+             WHERE h.var_partner_id = p.id AND h.var_client_id IS NULL
+         )",
+        params![OPEN_START],
+    )?;
+    conn.execute(
+        "INSERT INTO commission_rate_history (id, var_partner_id, var_client_id, rate, valid_from, created_at)
+         SELECT lower(hex(randomblob(16))), c.var_partner_id, c.id, c.commission_rate, ?1,
+                strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+         FROM var_clients c
+         JOIN var_partners p ON p.id = c.var_partner_id
+         WHERE c.commission_rate <> p.commission_rate
+           AND NOT EXISTS (SELECT 1 FROM commission_rate_history h WHERE h.var_client_id = c.id)",
+        params![OPEN_START],
+    )?;
+
+    Ok(())
+}
+
+const HISTORY_COLUMNS: &str = "id, var_partner_id, var_client_id, rate, valid_from, valid_to, created_at";
+
+fn history_from_row(row: &Row) -> Result<CommissionRateHistory> {
+    Ok(CommissionRateHistory {
+        id: row.get(0)?,
+        var_partner_id: row.get(1)?,
+        var_client_id: row.get(2)?,
+        rate: row.get(3)?,
+        valid_from: row.get(4)?,
+        valid_to: row.get(5)?,
+        created_at: row.get(6)?,
+    })
+}
+
+/// Entry in force on `date` for a client override (`Some`) or a partner default (`None`).
+fn entry_in_force(
+    conn: &Connection,
+    var_partner_id: &str,
+    var_client_id: Option<&str>,
+    date: &str,
+) -> Result<Option<CommissionRateHistory>> {
+    conn.query_row(
+        &format!(
+            "SELECT {HISTORY_COLUMNS} FROM commission_rate_history
+             WHERE var_partner_id = ?1 AND var_client_id IS ?2
+               AND valid_from <= ?3 AND (valid_to IS NULL OR valid_to >= ?3)
+             ORDER BY valid_from DESC LIMIT 1"
+        ),
+        params![var_partner_id, var_client_id, date],
+        history_from_row,
+    )
+    .optional()
+}
+
+/// Adds a history entry. An open-ended entry that started earlier is closed the
+/// day before the new one starts; one that starts on the same day is replaced.
+/// Any other overlap is rejected.
+pub(super) fn insert_rate(conn: &Connection, request: &CommissionRateRequest) -> Result<CommissionRateHistory> {
+    if !(0.0..=100.0).contains(&request.rate) {
+        return Err(rule_violation("Commission rates must be between 0 and 100"));
+    }
+    let valid_from: Option<String> =
+        conn.query_row("SELECT date(?1)", params![request.valid_from], |row| row.get(0))?;
+    let valid_from = valid_from.ok_or_else(|| rule_violation(format!("Invalid date '{}'", request.valid_from)))?;
+    let valid_to = match &request.valid_to {
+        Some(value) => {
+            let date: Option<String> = conn.query_row("SELECT date(?1)", params![value], |row| row.get(0))?;
+            let date = date.ok_or_else(|| rule_violation(format!("Invalid date '{}'", value)))?;
+            if date < valid_from {
+                return Err(rule_violation("A rate cannot end before it starts"));
+            }
+            Some(date)
+        }
+        None => None,
+    };
+
+    let var_client_id = request.var_client_id.as_deref();
+    conn.execute(
+        "DELETE FROM commission_rate_history
+         WHERE var_partner_id = ?1 AND var_client_id IS ?2 AND valid_to IS NULL AND valid_from = ?3",
+        params![request.var_partner_id, var_client_id, valid_from],
+    )?;
+    conn.execute(
+        "UPDATE commission_rate_history SET valid_to = date(?3, '-1 day')
+         WHERE var_partner_id = ?1 AND var_client_id IS ?2 AND valid_to IS NULL AND valid_from < ?3",
+        params![request.var_partner_id, var_client_id, valid_from],
+    )?;
+
+    let overlapping: Option<String> = conn
+        .query_row(
+            "SELECT valid_from FROM commission_rate_history
+             WHERE var_partner_id = ?1 AND var_client_id IS ?2
+               AND valid_from <= COALESCE(?4, '9999-12-31')
+               AND COALESCE(valid_to, '9999-12-31') >= ?3
+             LIMIT 1",
+            params![request.var_partner_id, var_client_id, valid_from, valid_to],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if let Some(existing) = overlapping {
+        return Err(rule_violation(format!(
+            "The rate overlaps an existing rate starting {}",
+            existing
+        )));
+    }
+
+    let entry = CommissionRateHistory {
+        id: generate_id(conn)?,
+        var_partner_id: request.var_partner_id.clone(),
+        var_client_id: request.var_client_id.clone(),
+        rate: request.rate,
+        valid_from,
+        valid_to,
+        created_at: current_timestamp(conn)?,
+    };
+    conn.execute(
+        "INSERT INTO commission_rate_history
+         (id, var_partner_id, var_client_id, rate, valid_from, valid_to, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            entry.id,
+            entry.var_partner_id,
+            entry.var_client_id,
+            entry.rate,
+            entry.valid_from,
+            entry.valid_to,
+            entry.created_at
+        ],
+    )?;
+    Ok(entry)
+}
+
+/// Records a partner's new default rate from today when it has changed.
+pub(super) fn record_partner_rate(conn: &Connection, var_partner_id: &str, rate: f64) -> Result<()> {
+    let today: String = conn.query_row("SELECT date('now')", [], |row| row.get(0))?;
+    let current = entry_in_force(conn, var_partner_id, None, &today)?;
+    if current.map(|entry| entry.rate) == Some(rate) {
+        return Ok(());
+    }
+    let valid_from = if has_history(conn, var_partner_id, None)? {
+        today
+    } else {
+        OPEN_START.to_string()
+    };
+    insert_rate(
+        conn,
+        &CommissionRateRequest {
+            var_partner_id: var_partner_id.to_string(),
+            var_client_id: None,
+            rate,
+            valid_from,
+            valid_to: None,
+        },
+    )?;
+    sync_current_rates(conn, var_partner_id)
+}
+
+/// Records a client's rate from today when it no longer matches what would be
+/// resolved for it. A rate equal to the partner default ends any open override.
+pub(super) fn record_client_rate(
+    conn: &Connection,
+    var_client_id: &str,
+    var_partner_id: &str,
+    rate: f64,
+) -> Result<()> {
+    let today: String = conn.query_row("SELECT date('now')", [], |row| row.get(0))?;
+    let resolved = resolve_rate(conn, var_client_id, var_partner_id, &today)?;
+    if resolved.rate == rate {
+        return Ok(());
+    }
+
+    let partner_rate = entry_in_force(conn, var_partner_id, None, &today)?.map(|entry| entry.rate);
+    if partner_rate == Some(rate) {
+        // An override that only started today is dropped rather than ended
+        // the day before it began.
+        conn.execute(
+            "DELETE FROM commission_rate_history
+             WHERE var_client_id = ?1 AND valid_to IS NULL AND valid_from >= ?2",
+            params![var_client_id, today],
+        )?;
+        conn.execute(
+            "UPDATE commission_rate_history SET valid_to = date(?2, '-1 day')
+             WHERE var_client_id = ?1 AND valid_to IS NULL AND valid_from < ?2",
+            params![var_client_id, today],
+        )?;
+        return Ok(());
+    }
+
+    let valid_from = if has_history(conn, var_partner_id, Some(var_client_id))? {
+        today
+    } else {
+        OPEN_START.to_string()
+    };
+    insert_rate(
+        conn,
+        &CommissionRateRequest {
+            var_partner_id: var_partner_id.to_string(),
+            var_client_id: Some(var_client_id.to_string()),
+            rate,
+            valid_from,
+            valid_to: None,
+        },
+    )?;
+    Ok(())
+}
+
+/// Brings the stored `commission_rate` of a partner and its clients in line
+/// with the rates in force today, so the edit forms show the current rate.
+fn sync_current_rates(conn: &Connection, var_partner_id: &str) -> Result<()> {
+    let today: String = conn.query_row("SELECT date('now')", [], |row| row.get(0))?;
+    if let Some(entry) = entry_in_force(conn, var_partner_id, None, &today)? {
+        conn.execute(
+            "UPDATE var_partners SET commission_rate = ?2 WHERE id = ?1",
+            params![var_partner_id, entry.rate],
+        )?;
+    }
+
+    let clients = {
+        let mut stmt = conn.prepare("SELECT id FROM var_clients WHERE var_partner_id = ?1")?;
+        let rows = stmt.query_map(params![var_partner_id], |row| row.get::<_, String>(0))?;
+        rows.collect::<Result<Vec<_>>>()?
+    };
+    for var_client_id in clients {
+        let resolved = resolve_rate(conn, &var_client_id, var_partner_id, &today)?;
+        conn.execute(
+            "UPDATE var_clients SET commission_rate = ?2 WHERE id = ?1",
+            params![var_client_id, resolved.rate],
+        )?;
+    }
+    Ok(())
+}
+
+fn has_history(conn: &Connection, var_partner_id: &str, var_client_id: Option<&str>) -> Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM commission_rate_history WHERE var_partner_id = ?1 AND var_client_id IS ?2)",
+        params![var_partner_id, var_client_id],
+        |row| row.get(0),
+    )
+}
+
+/// Resolves the rate in force on `date`: a client override wins over the
+/// partner default, and the partner's stored rate is the last resort.
+fn resolve_rate(
+    conn: &Connection,
+    var_client_id: &str,
+    var_partner_id: &str,
+    date: &str,
+) -> Result<ResolvedCommissionRate> {
+    let (entry, source) = match entry_in_force(conn, var_partner_id, Some(var_client_id), date)? {
+        Some(entry) => (Some(entry), "client_override"),
+        None => (entry_in_force(conn, var_partner_id, None, date)?, "partner_default"),
+    };
+    let rate = match &entry {
+        Some(entry) => entry.rate,
+        None => conn.query_row(
+            "SELECT commission_rate FROM var_partners WHERE id = ?1",
+            params![var_partner_id],
+            |row| row.get(0),
+        )?,
+    };
+
+    Ok(ResolvedCommissionRate {
+        var_client_id: var_client_id.to_string(),
+        billing_month: date.get(..7).unwrap_or(date).to_string(),
+        rate,
+        source: source.to_string(),
+        history_id: entry.as_ref().map(|entry| entry.id.clone()),
+        valid_from: entry.as_ref().map(|entry| entry.valid_from.clone()),
+        valid_to: entry.and_then(|entry| entry.valid_to),
+    })
+}
+
+/// Rate in force for a client's billing month, judged on the first day of the month.
+pub(super) fn rate_for_month(
+    conn: &Connection,
+    var_client_id: &str,
+    var_partner_id: &str,
+    billing_month: &str,
+) -> Result<ResolvedCommissionRate> {
+    let mut resolved = resolve_rate(conn, var_client_id, var_partner_id, &format!("{}-01", billing_month))?;
+    resolved.billing_month = billing_month.to_string();
+    Ok(resolved)
+}
+
+/// Whether a client override is in force for the billing month.
+pub(super) fn has_client_override(
+    conn: &Connection,
+    var_client_id: &str,
+    var_partner_id: &str,
+    billing_month: &str,
+) -> Result<bool> {
+    Ok(entry_in_force(
+        conn,
+        var_partner_id,
+        Some(var_client_id),
+        &format!("{}-01", billing_month),
+    )?
+    .is_some())
+}
+
+/// Sets the historical rate on every invoice a partner has in `billing_month`
+/// that is still a draft or pending; issued invoices keep their figures.
+pub(super) fn apply_rate_history(
+    conn: &Connection,
+    var_partner_id: &str,
+    billing_month: &str,
+) -> Result<Vec<CommissionCalculation>> {
+    let invoices = {
+        let mut stmt = conn.prepare(
+            "SELECT i.id, i.var_client_id, i.client_revenue, c.currency
+             FROM var_client_invoices i
+             JOIN var_clients c ON c.id = i.var_client_id
+             WHERE i.var_partner_id = ?1 AND i.billing_month = ?2
+               AND i.invoice_status IN ('draft', 'pending')",
+        )?;
+        let rows = stmt.query_map(params![var_partner_id, billing_month], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, f64>(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        })?;
+        rows.collect::<Result<Vec<_>>>()?
+    };
+
+    let mut calculations = Vec::new();
+    for (invoice_id, var_client_id, client_revenue, currency) in invoices {
+        let resolved = rate_for_month(conn, &var_client_id, var_partner_id, billing_month)?;
+        let commission_amount = round_amount(client_revenue * resolved.rate / 100.0, &currency);
+        conn.execute(
+            "UPDATE var_client_invoices SET commission_rate = ?2, commission_amount = ?3, commission_source = ?4
+             WHERE id = ?1",
+            params![invoice_id, resolved.rate, commission_amount, resolved.source],
+        )?;
+        calculations.push(CommissionCalculation {
+            invoice_id,
+            var_client_id,
+            billing_month: billing_month.to_string(),
+            source: resolved.source,
+            rule_set_id: None,
+            basis_value: None,
+            commission_rate: resolved.rate,
+            commission_amount,
+        });
+    }
+
+    Ok(calculations)
+}
+
+impl Database {
+    pub fn get_commission_rate_history(
+        &self,
+        var_partner_id: &str,
+        var_client_id: Option<&str>,
+    ) -> Result<Vec<CommissionRateHistory>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {HISTORY_COLUMNS} FROM commission_rate_history
+             WHERE var_partner_id = ?1 AND (?2 IS NULL OR var_client_id = ?2 OR var_client_id IS NULL)
+             ORDER BY var_client_id IS NOT NULL, var_client_id, valid_from DESC"
+        ))?;
+
+        let history = stmt.query_map(params![var_partner_id, var_client_id], history_from_row)?;
+        history.collect()
+    }
+
+    /// Adds a rate with explicit dates, e.g. to backdate a change. Invoices are
+    /// not touched until their month is recalculated.
+    pub fn add_commission_rate(&self, request: CommissionRateRequest) -> Result<CommissionRateHistory> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let entry = insert_rate(&tx, &request)?;
+        sync_current_rates(&tx, &entry.var_partner_id)?;
+        tx.commit()?;
+        Ok(entry)
+    }
+
+    pub fn delete_commission_rate(&self, id: &str) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let var_partner_id: Option<String> = tx
+            .query_row(
+                "SELECT var_partner_id FROM commission_rate_history WHERE id = ?1",
+                params![id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        tx.execute("DELETE FROM commission_rate_history WHERE id = ?1", params![id])?;
+        if let Some(var_partner_id) = var_partner_id {
+            sync_current_rates(&tx, &var_partner_id)?;
+        }
+        tx.commit()
+    }
+
+    pub fn resolve_commission_rate(&self, var_client_id: &str, billing_month: &str) -> Result<ResolvedCommissionRate> {
+        let conn = self.conn.lock().unwrap();
+        let var_partner_id: String = conn.query_row(
+            "SELECT var_partner_id FROM var_clients WHERE id = ?1",
+            params![var_client_id],
+            |row| row.get(0),
+        )?;
+        rate_for_month(&conn, var_client_id, &var_partner_id, billing_month)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CommissionRateRequest;
+    use crate::database::test_support::{add_client, add_partner, client, test_db};
+    use crate::database::Database;
+
+    fn request(rate: f64, valid_from: &str, valid_to: Option<&str>) -> CommissionRateRequest {
+        CommissionRateRequest {
+            var_partner_id: "p1".to_string(),
+            var_client_id: None,
+            rate,
+            valid_from: valid_from.to_string(),
+            valid_to: valid_to.map(str::to_string),
+        }
+    }
+
+    fn resolved(db: &Database, var_client_id: &str, billing_month: &str) -> (f64, String) {
+        let resolved = db.resolve_commission_rate(var_client_id, billing_month).unwrap();
+        (resolved.rate, resolved.source)
+    }
+
+    #[test]
+    fn a_new_rate_closes_the_one_before_it() {
+        let db = test_db();
+        add_partner(&db, "p1", 10.0);
+        add_client(&db, "c1", "p1", 10.0, "ZAR");
+        db.add_commission_rate(request(12.0, "2026-04-01", None)).unwrap();
+
+        let history = db.get_commission_rate_history("p1", None).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!((history[0].rate, history[0].valid_to.as_deref()), (12.0, None));
+        assert_eq!(
+            (history[1].rate, history[1].valid_to.as_deref()),
+            (10.0, Some("2026-03-31"))
+        );
+
+        assert_eq!(resolved(&db, "c1", "2026-03"), (10.0, "partner_default".to_string()));
+        assert_eq!(resolved(&db, "c1", "2026-04"), (12.0, "partner_default".to_string()));
+
+        assert!(db
+            .add_commission_rate(request(11.0, "2026-02-01", Some("2026-05-31")))
+            .is_err());
+        assert!(db.add_commission_rate(request(101.0, "2026-06-01", None)).is_err());
+        assert!(db
+            .add_commission_rate(request(11.0, "2026-06-01", Some("2026-05-01")))
+            .is_err());
+    }
+
+    #[test]
+    fn client_overrides_win_until_the_rate_matches_the_partner_again() {
+        let db = test_db();
+        add_partner(&db, "p1", 10.0);
+        add_client(&db, "c1", "p1", 15.0, "ZAR");
+        assert_eq!(resolved(&db, "c1", "2026-01"), (15.0, "client_override".to_string()));
+
+        // Setting the client back to the partner rate ends the override from
+        // today, so earlier months keep it.
+        db.update_var_client(client("c1", "p1", 10.0, "ZAR")).unwrap();
+        assert_eq!(resolved(&db, "c1", "2026-01"), (15.0, "client_override".to_string()));
+        assert_eq!(resolved(&db, "c1", "2100-01"), (10.0, "partner_default".to_string()));
+        let overrides: Vec<_> = db
+            .get_commission_rate_history("p1", Some("c1"))
+            .unwrap()
+            .into_iter()
+            .filter(|entry| entry.var_client_id.is_some())
+            .collect();
+        assert_eq!(overrides.len(), 1);
+        assert!(overrides[0].valid_to.is_some());
+    }
+}
@@ -0,0 +1,566 @@
+use super::billing_documents::checked_month;
+use super::commission_rates::{apply_rate_history, has_client_override};
+use super::partner_hierarchy::split_commissions;
+use super::{rule_violation, Database};
+use crate::currency::round_amount;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+
+pub const RULE_BASES: &[&str] = &["revenue", "users", "clients"];
+pub const RULE_APPLICATIONS: &[&str] = &["marginal", "whole_volume"];
+pub const RULE_PERIODS: &[&str] = &["monthly", "quarterly", "annual"];
+
+/// A partner's tiered commission agreement. `basis` picks the volume measured
+/// over each `period` (partner revenue, user count or client count), and
+/// `application` decides whether a tier's rate applies only to the volume
+/// inside its bracket (`marginal`) or to the whole volume (`whole_volume`).
+/// `effective_from` and `effective_to` are inclusive billing months (YYYY-MM).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommissionRuleSet {
+    pub id: String,
+    pub var_partner_id: String,
+    pub name: String,
+    pub basis: String,
+    pub application: String,
+    pub period: String,
+    pub effective_from: String,
+    pub effective_to: Option<String>,
+    pub is_active: bool,
+    pub created_at: String,
+    pub tiers: Vec<CommissionTier>,
+}
+
+/// A bracket starting at `lower_bound`; it runs up to the next tier's lower bound.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommissionTier {
+    pub lower_bound: f64,
+    pub rate: f64,
+}
+
+/// The commission worked out for one invoice. `source` is `rule_set`,
+/// `client_override` or `partner_default`; the rule set fields are only set
+/// for `rule_set`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommissionCalculation {
+    pub invoice_id: String,
+    pub var_client_id: String,
+    pub billing_month: String,
+    pub source: String,
+    pub rule_set_id: Option<String>,
+    pub basis_value: Option<f64>,
+    pub commission_rate: f64,
+    pub commission_amount: f64,
+}
+
+pub(super) fn create_tables(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS commission_rule_sets (
+            id TEXT PRIMARY KEY,
+            var_partner_id TEXT NOT NULL,
+            name TEXT NOT NULL,
+            basis TEXT NOT NULL,
+            application TEXT NOT NULL,
+            period TEXT NOT NULL,
+            effective_from TEXT NOT NULL,
+            effective_to TEXT,
+            is_active INTEGER NOT NULL DEFAULT 1,
+            created_at TEXT NOT NULL,
+            FOREIGN KEY (var_partner_id) REFERENCES var_partners (id)
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS commission_tiers (
+            rule_set_id TEXT NOT NULL,
+            lower_bound REAL NOT NULL,
+            rate REAL NOT NULL,
+            PRIMARY KEY (rule_set_id, lower_bound),
+            FOREIGN KEY (rule_set_id) REFERENCES commission_rule_sets (id)
+        )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+fn validate_rule_set(conn: &Connection, rule_set: &CommissionRuleSet) -> Result<()> {
+    checked_month(&rule_set.effective_from)?;
+    if let Some(effective_to) = &rule_set.effective_to {
+        checked_month(effective_to)?;
+    }
+    if !RULE_BASES.contains(&rule_set.basis.as_str()) {
+        return Err(rule_violation(format!("Unknown commission basis '{}'", rule_set.basis)));
+    }
+    if !RULE_APPLICATIONS.contains(&rule_set.application.as_str()) {
+        return Err(rule_violation(format!(
+            "Unknown tier application '{}'",
+            rule_set.application
+        )));
+    }
+    if !RULE_PERIODS.contains(&rule_set.period.as_str()) {
+        return Err(rule_violation(format!(
+            "Unknown commission period '{}'",
+            rule_set.period
+        )));
+    }
+    if rule_set.tiers.first().map(|t| t.lower_bound) != Some(0.0) {
+        return Err(rule_violation("The first commission tier must start at zero"));
+    }
+    if !rule_set.tiers.windows(2).all(|w| w[0].lower_bound < w[1].lower_bound) {
+        return Err(rule_violation("Commission tiers must have increasing lower bounds"));
+    }
+    if rule_set.tiers.iter().any(|t| !(0.0..=100.0).contains(&t.rate)) {
+        return Err(rule_violation("Commission tier rates must be between 0 and 100"));
+    }
+    if matches!(&rule_set.effective_to, Some(to) if *to < rule_set.effective_from) {
+        return Err(rule_violation("A rule set cannot end before it starts"));
+    }
+
+    if rule_set.is_active {
+        let overlapping: Option<String> = conn
+            .query_row(
+                "SELECT name FROM commission_rule_sets
+                 WHERE var_partner_id = ?1 AND id <> ?2 AND is_active = 1
+                   AND effective_from <= COALESCE(?4, '9999-12')
+                   AND COALESCE(effective_to, '9999-12') >= ?3
+                 LIMIT 1",
+                params![
+                    rule_set.var_partner_id,
+                    rule_set.id,
+                    rule_set.effective_from,
+                    rule_set.effective_to
+                ],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if let Some(name) = overlapping {
+            return Err(rule_violation(format!("Rule set overlaps the dates of '{}'", name)));
+        }
+    }
+
+    Ok(())
+}
+
+fn save_tiers(conn: &Connection, rule_set: &CommissionRuleSet) -> Result<()> {
+    conn.execute(
+        "DELETE FROM commission_tiers WHERE rule_set_id = ?1",
+        params![rule_set.id],
+    )?;
+    for tier in &rule_set.tiers {
+        conn.execute(
+            "INSERT INTO commission_tiers (rule_set_id, lower_bound, rate) VALUES (?1, ?2, ?3)",
+            params![rule_set.id, tier.lower_bound, tier.rate],
+        )?;
+    }
+    Ok(())
+}
+
+fn load_tiers(conn: &Connection, rule_set_id: &str) -> Result<Vec<CommissionTier>> {
+    let mut stmt = conn.prepare(
+        "SELECT lower_bound, rate FROM commission_tiers
+         WHERE rule_set_id = ?1
+         ORDER BY lower_bound",
+    )?;
+    let tiers = stmt.query_map(params![rule_set_id], |row| {
+        Ok(CommissionTier {
+            lower_bound: row.get(0)?,
+            rate: row.get(1)?,
+        })
+    })?;
+    tiers.collect()
+}
+
+fn load_rule_sets(
+    conn: &Connection,
+    where_clause: &str,
+    args: &[&dyn rusqlite::ToSql],
+) -> Result<Vec<CommissionRuleSet>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id, var_partner_id, name, basis, application, period, effective_from, effective_to,
+                is_active, created_at
+         FROM commission_rule_sets
+         WHERE {where_clause}
+         ORDER BY var_partner_id, effective_from"
+    ))?;
+    let rule_sets = stmt
+        .query_map(args, |row| {
+            Ok(CommissionRuleSet {
+                id: row.get(0)?,
+                var_partner_id: row.get(1)?,
+                name: row.get(2)?,
+                basis: row.get(3)?,
+                application: row.get(4)?,
+                period: row.get(5)?,
+                effective_from: row.get(6)?,
+                effective_to: row.get(7)?,
+                is_active: row.get::<_, i32>(8)? == 1,
+                created_at: row.get(9)?,
+                tiers: Vec::new(),
+            })
+        })?
+        .collect::<Result<Vec<_>>>()?;
+
+    rule_sets
+        .into_iter()
+        .map(|mut rule_set| {
+            rule_set.tiers = load_tiers(conn, &rule_set.id)?;
+            Ok(rule_set)
+        })
+        .collect()
+}
+
+/// The active rule set covering `billing_month` for a partner, if any.
+pub(super) fn rule_set_for(
+    conn: &Connection,
+    var_partner_id: &str,
+    billing_month: &str,
+) -> Result<Option<CommissionRuleSet>> {
+    Ok(load_rule_sets(
+        conn,
+        "var_partner_id = ?1 AND is_active = 1 AND effective_from <= ?2
+         AND (effective_to IS NULL OR effective_to >= ?2)",
+        &[&var_partner_id, &billing_month],
+    )?
+    .pop())
+}
+
+/// First and last billing month of the rule period containing `billing_month`.
+fn period_bounds(period: &str, billing_month: &str) -> (String, String) {
+    let year = billing_month.get(..4).unwrap_or_default();
+    let month: u32 = billing_month.get(5..7).and_then(|m| m.parse().ok()).unwrap_or(1);
+    match period {
+        "quarterly" => {
+            let first = (month - 1) / 3 * 3 + 1;
+            (format!("{}-{:02}", year, first), format!("{}-{:02}", year, first + 2))
+        }
+        "annual" => (format!("{}-01", year), format!("{}-12", year)),
+        _ => (billing_month.to_string(), billing_month.to_string()),
+    }
+}
+
+/// Partner volume for the rule's basis over the period containing `billing_month`.
+/// Users are a point-in-time count, so they are taken from the billing month itself.
+fn basis_value(conn: &Connection, rule_set: &CommissionRuleSet, billing_month: &str) -> Result<f64> {
+    let (first, last) = period_bounds(&rule_set.period, billing_month);
+    let (expression, first, last) = match rule_set.basis.as_str() {
+        "users" => ("SUM(users)", billing_month.to_string(), billing_month.to_string()),
+        "clients" => ("COUNT(DISTINCT var_client_id)", first, last),
+        _ => ("SUM(client_revenue)", first, last),
+    };
+    conn.query_row(
+        &format!(
+            "SELECT COALESCE({expression}, 0) FROM var_client_invoices
+             WHERE var_partner_id = ?1 AND billing_month BETWEEN ?2 AND ?3
+               AND invoice_status NOT IN ('void', 'cancelled')"
+        ),
+        params![rule_set.var_partner_id, first, last],
+        |row| row.get(0),
+    )
+}
+
+/// Effective percentage for `volume` under a set of tiers. Marginal rates are
+/// blended across the brackets the volume passes through.
+pub(super) fn effective_rate(tiers: &[CommissionTier], application: &str, volume: f64) -> f64 {
+    let reached = tiers.iter().take_while(|t| t.lower_bound <= volume).count().max(1);
+    if application != "marginal" || volume <= 0.0 {
+        return tiers.get(reached - 1).map(|t| t.rate).unwrap_or(0.0);
+    }
+
+    let mut weighted = 0.0;
+    for (i, tier) in tiers.iter().enumerate().take(reached) {
+        let upper = tiers.get(i + 1).map(|t| t.lower_bound.min(volume)).unwrap_or(volume);
+        weighted += (upper - tier.lower_bound) * tier.rate;
+    }
+    weighted / volume
+}
+
+/// Recomputes commission on every invoice a partner has in the rule period
+/// containing `billing_month`, since adding or changing one invoice moves the
+/// volume all of them are measured against. Partners without a rule set,
+/// clients with a rate override in force and issued invoices, whose figures
+/// are locked, are left alone.
+fn apply_commission_rules(
+    conn: &Connection,
+    var_partner_id: &str,
+    billing_month: &str,
+) -> Result<Vec<CommissionCalculation>> {
+    let Some(rule_set) = rule_set_for(conn, var_partner_id, billing_month)? else {
+        return Ok(Vec::new());
+    };
+    let (first, last) = period_bounds(&rule_set.period, billing_month);
+
+    let invoices = {
+        let mut stmt = conn.prepare(
+            "SELECT i.id, i.var_client_id, i.billing_month, i.client_revenue, c.currency
+             FROM var_client_invoices i
+             JOIN var_clients c ON c.id = i.var_client_id
+             WHERE i.var_partner_id = ?1 AND i.billing_month BETWEEN ?2 AND ?3
+               AND i.invoice_status IN ('draft', 'pending')",
+        )?;
+        let rows = stmt.query_map(params![var_partner_id, first, last], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, f64>(3)?,
+                row.get::<_, String>(4)?,
+            ))
+        })?;
+        rows.collect::<Result<Vec<_>>>()?
+    };
+
+    let mut calculations = Vec::new();
+    for (invoice_id, var_client_id, month, client_revenue, currency) in invoices {
+        if has_client_override(conn, &var_client_id, var_partner_id, &month)? {
+            continue;
+        }
+        let basis_value = basis_value(conn, &rule_set, &month)?;
+        let commission_rate = effective_rate(&rule_set.tiers, &rule_set.application, basis_value);
+        let commission_amount = round_amount(client_revenue * commission_rate / 100.0, &currency);
+
+        conn.execute(
+            "UPDATE var_client_invoices SET commission_rate = ?2, commission_amount = ?3, commission_source = 'rule_set'
+             WHERE id = ?1",
+            params![invoice_id, commission_rate, commission_amount],
+        )?;
+        calculations.push(CommissionCalculation {
+            invoice_id,
+            var_client_id,
+            billing_month: month,
+            source: "rule_set".to_string(),
+            rule_set_id: Some(rule_set.id.clone()),
+            basis_value: Some(basis_value),
+            commission_rate,
+            commission_amount,
+        });
+    }
+
+    Ok(calculations)
+}
+
+/// Recomputes a partner's commission for `billing_month`: the rate history
+/// first, then any tiered rule set on top of it, then the distributor split
+/// of every month whose invoices were repriced.
+pub(super) fn recalculate_commissions(
+    conn: &Connection,
+    var_partner_id: &str,
+    billing_month: &str,
+) -> Result<Vec<CommissionCalculation>> {
+    let mut calculations = apply_rate_history(conn, var_partner_id, billing_month)?;
+    let rule_calculations = apply_commission_rules(conn, var_partner_id, billing_month)?;
+    calculations.retain(|c| !rule_calculations.iter().any(|r| r.invoice_id == c.invoice_id));
+    calculations.extend(rule_calculations);
+
+    let mut months: Vec<&str> = calculations.iter().map(|c| c.billing_month.as_str()).collect();
+    months.push(billing_month);
+    months.sort_unstable();
+    months.dedup();
+    for month in months {
+        split_commissions(conn, var_partner_id, Some(month))?;
+    }
+    Ok(calculations)
+}
+
+/// Recomputes commission on a partner's invoices billed from `from` to `to`
+/// (open-ended without one), after a rule set covering them changed.
+fn recalculate_rule_months(conn: &Connection, var_partner_id: &str, from: &str, to: Option<&str>) -> Result<()> {
+    let months = {
+        let mut stmt = conn.prepare(
+            "SELECT DISTINCT billing_month FROM var_client_invoices
+             WHERE var_partner_id = ?1 AND billing_month >= ?2 AND (?3 IS NULL OR billing_month <= ?3)
+             ORDER BY billing_month",
+        )?;
+        let rows = stmt.query_map(params![var_partner_id, from, to], |row| row.get::<_, String>(0))?;
+        rows.collect::<Result<Vec<_>>>()?
+    };
+    for month in months {
+        recalculate_commissions(conn, var_partner_id, &month)?;
+    }
+    Ok(())
+}
+
+impl Database {
+    pub fn get_commission_rule_sets(&self, var_partner_id: Option<&str>) -> Result<Vec<CommissionRuleSet>> {
+        let conn = self.conn.lock().unwrap();
+        load_rule_sets(&conn, "?1 IS NULL OR var_partner_id = ?1", &[&var_partner_id])
+    }
+
+    pub fn add_commission_rule_set(&self, rule_set: CommissionRuleSet) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        validate_rule_set(&tx, &rule_set)?;
+        tx.execute(
+            "INSERT INTO commission_rule_sets
+             (id, var_partner_id, name, basis, application, period, effective_from, effective_to,
+              is_active, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                rule_set.id,
+                rule_set.var_partner_id,
+                rule_set.name,
+                rule_set.basis,
+                rule_set.application,
+                rule_set.period,
+                rule_set.effective_from,
+                rule_set.effective_to,
+                if rule_set.is_active { 1 } else { 0 },
+                rule_set.created_at
+            ],
+        )?;
+        save_tiers(&tx, &rule_set)?;
+        recalculate_rule_months(
+            &tx,
+            &rule_set.var_partner_id,
+            &rule_set.effective_from,
+            rule_set.effective_to.as_deref(),
+        )?;
+        tx.commit()
+    }
+
+    pub fn update_commission_rule_set(&self, rule_set: CommissionRuleSet) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        validate_rule_set(&tx, &rule_set)?;
+        let previous = load_rule_sets(&tx, "id = ?1", &[&rule_set.id])?.pop();
+        let updated = tx.execute(
+            "UPDATE commission_rule_sets SET var_partner_id = ?2, name = ?3, basis = ?4,
+             application = ?5, period = ?6, effective_from = ?7, effective_to = ?8, is_active = ?9
+             WHERE id = ?1",
+            params![
+                rule_set.id,
+                rule_set.var_partner_id,
+                rule_set.name,
+                rule_set.basis,
+                rule_set.application,
+                rule_set.period,
+                rule_set.effective_from,
+                rule_set.effective_to,
+                if rule_set.is_active { 1 } else { 0 }
+            ],
+        )?;
+        let Some(previous) = previous.filter(|_| updated > 0) else {
+            return Err(rule_violation("Rule set not found"));
+        };
+        save_tiers(&tx, &rule_set)?;
+
+        // Months the rule set no longer covers fall back to the rate history.
+        recalculate_rule_months(
+            &tx,
+            &previous.var_partner_id,
+            &previous.effective_from,
+            previous.effective_to.as_deref(),
+        )?;
+        recalculate_rule_months(
+            &tx,
+            &rule_set.var_partner_id,
+            &rule_set.effective_from,
+            rule_set.effective_to.as_deref(),
+        )?;
+        tx.commit()
+    }
+
+    pub fn delete_commission_rule_set(&self, id: &str) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "UPDATE commission_rule_sets SET is_active = 0 WHERE id = ?1",
+            params![id],
+        )?;
+        if let Some(rule_set) = load_rule_sets(&tx, "id = ?1", &[&id])?.pop() {
+            recalculate_rule_months(
+                &tx,
+                &rule_set.var_partner_id,
+                &rule_set.effective_from,
+                rule_set.effective_to.as_deref(),
+            )?;
+        }
+        tx.commit()
+    }
+
+    /// Re-resolves each partner's rates for `billing_month` from the rate
+    /// history, applies tiered rules across the rule period containing it, and
+    /// returns the resulting commission per invoice.
+    pub fn recalculate_var_commissions(&self, billing_month: &str) -> Result<Vec<CommissionCalculation>> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let partners = {
+            let mut stmt =
+                tx.prepare("SELECT DISTINCT var_partner_id FROM var_client_invoices WHERE billing_month = ?1")?;
+            let rows = stmt.query_map(params![billing_month], |row| row.get::<_, String>(0))?;
+            rows.collect::<Result<Vec<_>>>()?
+        };
+
+        let mut calculations = Vec::new();
+        for var_partner_id in partners {
+            calculations.extend(recalculate_commissions(&tx, &var_partner_id, billing_month)?);
+        }
+        tx.commit()?;
+        Ok(calculations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{effective_rate, CommissionRuleSet, CommissionTier};
+    use crate::database::test_support::{add_client, add_invoice, add_partner, test_db};
+
+    fn tiers(tiers: &[(f64, f64)]) -> Vec<CommissionTier> {
+        tiers
+            .iter()
+            .map(|&(lower_bound, rate)| CommissionTier { lower_bound, rate })
+            .collect()
+    }
+
+    fn rule_set(period: &str, application: &str, tiers: Vec<CommissionTier>) -> CommissionRuleSet {
+        CommissionRuleSet {
+            id: "r1".to_string(),
+            var_partner_id: "p1".to_string(),
+            name: "Volume".to_string(),
+            basis: "revenue".to_string(),
+            application: application.to_string(),
+            period: period.to_string(),
+            effective_from: "2026-01".to_string(),
+            effective_to: None,
+            is_active: true,
+            created_at: String::new(),
+            tiers,
+        }
+    }
+
+    #[test]
+    fn blends_marginal_rates_across_brackets() {
+        let tiers = tiers(&[(0.0, 10.0), (1000.0, 20.0), (2000.0, 30.0)]);
+        assert_eq!(effective_rate(&tiers, "whole_volume", 1500.0), 20.0);
+        assert_eq!(effective_rate(&tiers, "marginal", 500.0), 10.0);
+        assert_eq!(effective_rate(&tiers, "marginal", 2000.0), 15.0);
+        assert_eq!(effective_rate(&tiers, "marginal", 0.0), 10.0);
+    }
+
+    #[test]
+    fn later_invoices_reprice_and_resplit_the_whole_quarter() {
+        let db = test_db();
+        add_partner(&db, "d1", 5.0);
+        add_partner(&db, "p1", 10.0);
+        add_client(&db, "c1", "p1", 10.0, "ZAR");
+        db.set_partner_parent("p1", Some("d1"), Some(50.0)).unwrap();
+        db.add_commission_rule_set(rule_set("quarterly", "whole_volume", tiers(&[(0.0, 10.0), (2500.0, 20.0)])))
+            .unwrap();
+        add_invoice(&db, "i1", "c1", "p1", "2026-07", 1000.0, 10.0);
+        add_invoice(&db, "i2", "c1", "p1", "2026-08", 1000.0, 10.0);
+        let july = |db: &crate::database::Database| -> Vec<f64> {
+            db.get_commission_lines(None, Some("2026-07"))
+                .unwrap()
+                .iter()
+                .map(|l| l.commission_amount)
+                .collect()
+        };
+        assert_eq!(july(&db), vec![50.0, 50.0]);
+
+        // September takes the quarter past 2,500, so July earns 20% as well.
+        add_invoice(&db, "i3", "c1", "p1", "2026-09", 1000.0, 10.0);
+        let invoice = db.get_var_client_invoices().unwrap().into_iter().find(|i| i.id == "i1").unwrap();
+        assert_eq!((invoice.commission_rate, invoice.commission_amount), (20.0, 200.0));
+        assert_eq!(july(&db), vec![100.0, 100.0]);
+    }
+}
@@ -0,0 +1,392 @@
+use super::payments::invoice_outstanding;
+use super::{current_timestamp, generate_id, next_document_number, rule_violation, Database};
+use crate::currency::round_amount;
+use rusqlite::{params, Connection, OptionalExtension, Result, Row};
+use serde::{Deserialize, Serialize};
+
+/// Reason codes accepted on credit and debit notes.
+pub const REASON_CODES: &[&str] = &[
+    "billing_error",
+    "price_adjustment",
+    "license_reduction",
+    "service_credit",
+    "duplicate_invoice",
+    "goodwill",
+    "other",
+];
+
+/// A credit note (reduces) or debit note (increases) against a VAR client invoice.
+/// Both kinds share one table and are told apart by `note_type`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreditNote {
+    pub id: String,
+    pub note_number: String,
+    pub note_type: String,
+    pub invoice_id: String,
+    pub var_client_id: String,
+    pub var_partner_id: String,
+    pub amount: f64,
+    pub commission_amount: f64,
+    pub reason_code: String,
+    pub reason: Option<String>,
+    pub note_date: String,
+    pub created_at: String,
+}
+
+/// Input for `create_credit_note`. Leaving `amount` empty credits whatever
+/// is still outstanding on the invoice.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreditNoteRequest {
+    pub invoice_id: String,
+    pub note_type: String,
+    pub amount: Option<f64>,
+    pub reason_code: String,
+    pub reason: Option<String>,
+    pub note_date: String,
+}
+
+/// Invoice totals netted against credit and debit notes for one client or
+/// partner, in one currency. Commission adjustments, clawbacks included,
+/// count towards `net_commission`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AccountBalance {
+    pub account_id: String,
+    pub account_name: String,
+    pub currency: String,
+    pub invoiced: f64,
+    pub credited: f64,
+    pub debited: f64,
+    pub net_invoiced: f64,
+    pub commission: f64,
+    pub commission_adjusted: f64,
+    pub net_commission: f64,
+}
+
+pub(super) fn create_tables(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS credit_notes (
+            id TEXT PRIMARY KEY,
+            note_number TEXT NOT NULL UNIQUE,
+            note_type TEXT NOT NULL,
+            invoice_id TEXT NOT NULL,
+            var_client_id TEXT NOT NULL,
+            var_partner_id TEXT NOT NULL,
+            amount REAL NOT NULL,
+            commission_amount REAL NOT NULL,
+            reason_code TEXT NOT NULL,
+            reason TEXT,
+            note_date TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            FOREIGN KEY (invoice_id) REFERENCES var_client_invoices (id),
+            FOREIGN KEY (var_client_id) REFERENCES var_clients (id),
+            FOREIGN KEY (var_partner_id) REFERENCES var_partners (id)
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_credit_notes_invoice ON credit_notes (invoice_id)",
+        [],
+    )?;
+
+    Ok(())
+}
+
+fn credit_note_from_row(row: &Row) -> Result<CreditNote> {
+    Ok(CreditNote {
+        id: row.get(0)?,
+        note_number: row.get(1)?,
+        note_type: row.get(2)?,
+        invoice_id: row.get(3)?,
+        var_client_id: row.get(4)?,
+        var_partner_id: row.get(5)?,
+        amount: row.get(6)?,
+        commission_amount: row.get(7)?,
+        reason_code: row.get(8)?,
+        reason: row.get(9)?,
+        note_date: row.get(10)?,
+        created_at: row.get(11)?,
+    })
+}
+
+const CREDIT_NOTE_COLUMNS: &str = "id, note_number, note_type, invoice_id, var_client_id, var_partner_id,
+     amount, commission_amount, reason_code, reason, note_date, created_at";
+
+/// Amount that can still be credited on an invoice: its revenue plus any
+/// debit notes, less the credit notes already issued.
+pub(super) fn invoice_net_amount(conn: &Connection, invoice_id: &str) -> Result<f64> {
+    conn.query_row(
+        "SELECT i.client_revenue
+                + COALESCE(SUM(CASE WHEN n.note_type = 'debit' THEN n.amount ELSE 0 END), 0)
+                - COALESCE(SUM(CASE WHEN n.note_type = 'credit' THEN n.amount ELSE 0 END), 0)
+         FROM var_client_invoices i
+         LEFT JOIN credit_notes n ON n.invoice_id = i.id
+         WHERE i.id = ?1
+         GROUP BY i.id",
+        params![invoice_id],
+        |row| row.get(0),
+    )
+}
+
+/// Revenue is grouped by `group_column` of invoices and notes; commission by
+/// `commission_column` of the commission lines (`l`) or their invoice (`i`),
+/// so a distributor's share counts towards the distributor. Amounts are never
+/// added across currencies: an account billed in several has a balance in
+/// each of its clients' currencies.
+fn query_balances(
+    conn: &Connection,
+    group_column: &str,
+    commission_column: &str,
+    accounts_table: &str,
+    name_column: &str,
+) -> Result<Vec<AccountBalance>> {
+    let live = "i.invoice_status NOT IN ('void', 'cancelled')";
+    let sql = format!(
+        "SELECT a.id, a.{name_column}, t.currency, SUM(t.invoiced), SUM(t.credited), SUM(t.debited),
+                SUM(t.commission), SUM(t.commission_credited), SUM(t.commission_debited), SUM(t.adjusted)
+         FROM (
+             SELECT i.{group_column} AS account_id, c.currency, i.client_revenue AS invoiced, 0 AS credited,
+                    0 AS debited, 0 AS commission, 0 AS commission_credited, 0 AS commission_debited,
+                    0 AS adjusted
+             FROM var_client_invoices i
+             JOIN var_clients c ON c.id = i.var_client_id
+             WHERE {live}
+             UNION ALL
+             SELECT n.{group_column}, c.currency, 0,
+                    CASE WHEN n.note_type = 'credit' THEN n.amount ELSE 0 END,
+                    CASE WHEN n.note_type = 'debit' THEN n.amount ELSE 0 END, 0, 0, 0, 0
+             FROM credit_notes n
+             JOIN var_client_invoices i ON i.id = n.invoice_id
+             JOIN var_clients c ON c.id = i.var_client_id
+             WHERE {live}
+             UNION ALL
+             SELECT {commission_column}, c.currency, 0, 0, 0, l.commission_amount,
+                    COALESCE(k.credited, 0) * l.share_percent / 100,
+                    COALESCE(k.debited, 0) * l.share_percent / 100, 0
+             FROM commission_lines l
+             JOIN var_client_invoices i ON i.id = l.invoice_id
+             JOIN var_clients c ON c.id = i.var_client_id
+             LEFT JOIN (
+                 SELECT invoice_id,
+                        SUM(CASE WHEN note_type = 'credit' THEN commission_amount ELSE 0 END) AS credited,
+                        SUM(CASE WHEN note_type = 'debit' THEN commission_amount ELSE 0 END) AS debited
+                 FROM credit_notes GROUP BY invoice_id
+             ) k ON k.invoice_id = l.invoice_id
+             WHERE {live}
+             UNION ALL
+             SELECT {group_column}, currency, 0, 0, 0, 0, 0, 0, amount FROM commission_adjustments
+         ) t
+         JOIN {accounts_table} a ON a.id = t.account_id
+         GROUP BY a.id, t.currency
+         ORDER BY a.{name_column}, t.currency"
+    );
+
+    let mut stmt = conn.prepare(&sql)?;
+    let balances = stmt.query_map([], |row| {
+        let currency: String = row.get(2)?;
+        let amount = |index: usize| -> Result<f64> { Ok(round_amount(row.get(index)?, &currency)) };
+        let (invoiced, credited, debited) = (amount(3)?, amount(4)?, amount(5)?);
+        let (commission, commission_credited, commission_debited) = (amount(6)?, amount(7)?, amount(8)?);
+        let commission_adjusted = amount(9)?;
+        Ok(AccountBalance {
+            account_id: row.get(0)?,
+            account_name: row.get(1)?,
+            invoiced,
+            credited,
+            debited,
+            net_invoiced: round_amount(invoiced - credited + debited, &currency),
+            commission,
+            commission_adjusted,
+            net_commission: round_amount(
+                commission - commission_credited + commission_debited + commission_adjusted,
+                &currency,
+            ),
+            currency,
+        })
+    })?;
+
+    balances.collect()
+}
+
+impl Database {
+    pub fn create_credit_note(&self, request: CreditNoteRequest) -> Result<CreditNote> {
+        let (prefix, sequence) = match request.note_type.as_str() {
+            "credit" => ("CN", "credit_note"),
+            "debit" => ("DN", "debit_note"),
+            other => return Err(rule_violation(format!("Unknown note type '{}'", other))),
+        };
+        if !REASON_CODES.contains(&request.reason_code.as_str()) {
+            return Err(rule_violation(format!("Unknown reason code '{}'", request.reason_code)));
+        }
+
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        let invoice = tx
+            .query_row(
+                "SELECT i.var_client_id, i.var_partner_id, i.client_revenue, i.commission_amount, c.currency,
+                        i.invoice_status
+                 FROM var_client_invoices i
+                 JOIN var_clients c ON c.id = i.var_client_id
+                 WHERE i.id = ?1",
+                params![request.invoice_id],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, f64>(2)?,
+                        row.get::<_, f64>(3)?,
+                        row.get::<_, String>(4)?,
+                        row.get::<_, String>(5)?,
+                    ))
+                },
+            )
+            .optional()?;
+        let (var_client_id, var_partner_id, client_revenue, invoice_commission, currency, status) =
+            invoice.ok_or_else(|| rule_violation(format!("Invoice {} not found", request.invoice_id)))?;
+        if matches!(status.as_str(), "void" | "cancelled") {
+            return Err(rule_violation(format!(
+                "Notes cannot be raised against a {} invoice",
+                status
+            )));
+        }
+
+        // Payments already allocated to the invoice cannot be credited again.
+        let outstanding = invoice_outstanding(&tx, &request.invoice_id)?;
+        let amount = match (request.note_type.as_str(), request.amount) {
+            ("credit", None) => outstanding,
+            (_, Some(amount)) => round_amount(amount, &currency),
+            (_, None) => return Err(rule_violation("A debit note needs an amount")),
+        };
+        if amount <= 0.0 {
+            return Err(rule_violation("Note amount must be greater than zero"));
+        }
+        if request.note_type == "credit" && amount > outstanding {
+            return Err(rule_violation(format!(
+                "Credit of {:.2} exceeds the {:.2} still outstanding on the invoice",
+                amount, outstanding
+            )));
+        }
+
+        // Commission follows the note in the same proportion as the invoice it adjusts.
+        let commission_amount = if client_revenue != 0.0 {
+            round_amount(invoice_commission * amount / client_revenue, &currency)
+        } else {
+            0.0
+        };
+
+        let note = CreditNote {
+            id: generate_id(&tx)?,
+            note_number: next_document_number(&tx, sequence, prefix)?,
+            note_type: request.note_type,
+            invoice_id: request.invoice_id,
+            var_client_id,
+            var_partner_id,
+            amount,
+            commission_amount,
+            reason_code: request.reason_code,
+            reason: request.reason,
+            note_date: request.note_date,
+            created_at: current_timestamp(&tx)?,
+        };
+
+        tx.execute(
+            "INSERT INTO credit_notes
+             (id, note_number, note_type, invoice_id, var_client_id, var_partner_id,
+              amount, commission_amount, reason_code, reason, note_date, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![
+                note.id,
+                note.note_number,
+                note.note_type,
+                note.invoice_id,
+                note.var_client_id,
+                note.var_partner_id,
+                note.amount,
+                note.commission_amount,
+                note.reason_code,
+                note.reason,
+                note.note_date,
+                note.created_at
+            ],
+        )?;
+        tx.commit()?;
+
+        Ok(note)
+    }
+
+    pub fn get_credit_notes(&self, invoice_id: Option<&str>) -> Result<Vec<CreditNote>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {CREDIT_NOTE_COLUMNS}
+             FROM credit_notes
+             WHERE ?1 IS NULL OR invoice_id = ?1
+             ORDER BY note_date DESC, note_number DESC"
+        ))?;
+
+        let notes = stmt.query_map(params![invoice_id], credit_note_from_row)?;
+        notes.collect()
+    }
+
+    pub fn get_var_client_balances(&self) -> Result<Vec<AccountBalance>> {
+        let conn = self.conn.lock().unwrap();
+        query_balances(&conn, "var_client_id", "i.var_client_id", "var_clients", "client_name")
+    }
+
+    pub fn get_var_partner_balances(&self) -> Result<Vec<AccountBalance>> {
+        let conn = self.conn.lock().unwrap();
+        query_balances(&conn, "var_partner_id", "l.var_partner_id", "var_partners", "name")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CreditNoteRequest;
+    use crate::database::test_support::{add_client, add_invoice, add_partner, test_db};
+
+    fn credit(invoice_id: &str, amount: Option<f64>) -> CreditNoteRequest {
+        CreditNoteRequest {
+            invoice_id: invoice_id.to_string(),
+            note_type: "credit".to_string(),
+            amount,
+            reason_code: "goodwill".to_string(),
+            reason: None,
+            note_date: "2026-09-20".to_string(),
+        }
+    }
+
+    #[test]
+    fn credits_net_off_invoices_and_their_commission() {
+        let db = test_db();
+        add_partner(&db, "p1", 20.0);
+        add_client(&db, "c1", "p1", 20.0, "ZAR");
+        add_invoice(&db, "i1", "c1", "p1", "2026-09", 1000.0, 20.0);
+
+        let note = db.create_credit_note(credit("i1", Some(300.0))).unwrap();
+        assert_eq!((note.note_number.as_str(), note.commission_amount), ("CN-00001", 60.0));
+        assert!(db.create_credit_note(credit("i1", Some(800.0))).is_err());
+        assert_eq!(db.create_credit_note(credit("i1", None)).unwrap().amount, 700.0);
+
+        let balance = &db.get_var_client_balances().unwrap()[0];
+        assert_eq!((balance.invoiced, balance.credited, balance.net_invoiced), (1000.0, 1000.0, 0.0));
+        assert_eq!((balance.commission, balance.net_commission), (200.0, 0.0));
+    }
+
+    #[test]
+    fn partner_balances_are_kept_apart_per_currency() {
+        let db = test_db();
+        add_partner(&db, "p1", 10.0);
+        add_client(&db, "c1", "p1", 10.0, "ZAR");
+        add_client(&db, "c2", "p1", 10.0, "USD");
+        add_invoice(&db, "i1", "c1", "p1", "2026-09", 1000.0, 10.0);
+        add_invoice(&db, "i2", "c2", "p1", "2026-09", 100.0, 10.0);
+        db.create_credit_note(credit("i2", Some(40.0))).unwrap();
+
+        let balances = db.get_var_partner_balances().unwrap();
+        let figures: Vec<_> = balances
+            .iter()
+            .map(|b| (b.currency.as_str(), b.net_invoiced, b.net_commission))
+            .collect();
+        assert_eq!(figures, vec![("USD", 60.0, 6.0), ("ZAR", 1000.0, 100.0)]);
+    }
+}
@@ -0,0 +1,91 @@
+use super::Database;
+use crate::currency::normalize;
+use rusqlite::{params, Result};
+use serde::{Deserialize, Serialize};
+
+/// Tables whose `currency` column is normalized, with the column used to name each row.
+const CURRENCY_TABLES: &[(&str, &str)] = &[
+    ("clients", "client_name"),
+    ("var_clients", "client_name"),
+    ("payments", "payment_number"),
+];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CurrencyChange {
+    pub table: String,
+    pub id: String,
+    pub name: String,
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnmappedCurrency {
+    pub table: String,
+    pub id: String,
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CurrencyNormalizationReport {
+    pub changed: Vec<CurrencyChange>,
+    pub unmapped: Vec<UnmappedCurrency>,
+}
+
+impl Database {
+    /// Rewrites stored currency values ("R", "Rand", "zar") as ISO 4217 codes.
+    /// Values that can't be mapped are left untouched and listed in the report.
+    pub fn normalize_currency_codes(&self) -> Result<CurrencyNormalizationReport> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let mut report = CurrencyNormalizationReport {
+            changed: Vec::new(),
+            unmapped: Vec::new(),
+        };
+
+        for (table, name_column) in CURRENCY_TABLES {
+            let rows = {
+                let mut stmt = tx.prepare(&format!("SELECT id, {name_column}, currency FROM {table}"))?;
+                let rows = stmt
+                    .query_map([], |row| {
+                        Ok((
+                            row.get::<_, String>(0)?,
+                            row.get::<_, String>(1)?,
+                            row.get::<_, String>(2)?,
+                        ))
+                    })?
+                    .collect::<Result<Vec<_>>>()?;
+                rows
+            };
+
+            for (id, name, value) in rows {
+                match normalize(&value) {
+                    Some(code) if code == value => {}
+                    Some(code) => {
+                        tx.execute(
+                            &format!("UPDATE {table} SET currency = ?2 WHERE id = ?1"),
+                            params![id, code],
+                        )?;
+                        report.changed.push(CurrencyChange {
+                            table: table.to_string(),
+                            id,
+                            name,
+                            from: value,
+                            to: code.to_string(),
+                        });
+                    }
+                    None => report.unmapped.push(UnmappedCurrency {
+                        table: table.to_string(),
+                        id,
+                        name,
+                        value,
+                    }),
+                }
+            }
+        }
+
+        tx.commit()?;
+        Ok(report)
+    }
+}
@@ -0,0 +1,287 @@
+use super::{
+    checked_currency, insert_client, insert_var_client, rule_violation, update_client_row, update_var_client_row,
+    Client, CommissionAdjustment, Database, VarClient,
+};
+use rusqlite::{params, Connection, Result};
+use serde::{Deserialize, Serialize};
+
+/// Client tables whose debt codes must be unique, with their unique index.
+const DEBT_CODE_TABLES: [(&str, &str); 2] = [
+    ("clients", "idx_clients_debt_code"),
+    ("var_clients", "idx_var_clients_debt_code"),
+];
+
+/// Debt codes compare case-insensitively and ignoring surrounding spaces, so
+/// "ac-1 " and "AC-1" are the same code. Blank codes are not codes at all.
+const DEBT_CODE_KEY: &str = "lower(trim(debt_code))";
+
+/// Whether a table's debt codes are enforced by its unique index.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DebtCodeConstraint {
+    pub table_name: String,
+    pub applied: bool,
+    pub duplicate_codes: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DuplicateDebtCodeClient {
+    pub id: String,
+    pub client_name: String,
+    pub debt_code: String,
+    pub is_active: bool,
+    pub created_at: String,
+}
+
+/// Clients sharing one debt code. These must be given distinct codes (or
+/// have theirs cleared) before the table's constraint can be applied.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DuplicateDebtCode {
+    pub table_name: String,
+    pub debt_code: String,
+    pub clients: Vec<DuplicateDebtCodeClient>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DebtCodeReport {
+    pub constraints: Vec<DebtCodeConstraint>,
+    pub duplicates: Vec<DuplicateDebtCode>,
+}
+
+/// The outcome of an upsert: the stored client's id, whether it was new and,
+/// for VAR clients, the clawbacks a change of active state took or reversed.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClientUpsert {
+    pub id: String,
+    pub created: bool,
+    pub commission_adjustments: Vec<CommissionAdjustment>,
+}
+
+pub(super) fn create_tables(conn: &Connection) -> Result<()> {
+    for (table, _) in DEBT_CODE_TABLES {
+        let duplicates = duplicate_debt_codes(conn, table)?;
+        if duplicates.is_empty() {
+            apply_constraint(conn, table)?;
+        } else {
+            log::warn!(
+                "{} debt codes are shared by more than one row of {}; resolve them to apply the unique constraint",
+                duplicates.len(),
+                table
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Removes the unique indexes so rows can be loaded before their duplicates
+/// are known; `create_tables` puts back those that still hold.
+pub(super) fn drop_constraints(conn: &Connection) -> Result<()> {
+    for (_, index) in DEBT_CODE_TABLES {
+        conn.execute(&format!("DROP INDEX IF EXISTS {index}"), [])?;
+    }
+    Ok(())
+}
+
+fn index_name(table: &str) -> &'static str {
+    DEBT_CODE_TABLES
+        .iter()
+        .find(|(name, _)| *name == table)
+        .map(|(_, index)| *index)
+        .expect("debt codes are only tracked on client tables")
+}
+
+fn apply_constraint(conn: &Connection, table: &str) -> Result<()> {
+    conn.execute(
+        &format!(
+            "CREATE UNIQUE INDEX IF NOT EXISTS {} ON {table} ({DEBT_CODE_KEY})
+             WHERE trim(debt_code) != ''",
+            index_name(table)
+        ),
+        [],
+    )?;
+    Ok(())
+}
+
+fn constraint_applied(conn: &Connection, table: &str) -> Result<bool> {
+    conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'index' AND name = ?1",
+        params![index_name(table)],
+        |row| row.get::<_, i64>(0).map(|count| count > 0),
+    )
+}
+
+fn duplicate_debt_codes(conn: &Connection, table: &str) -> Result<Vec<DuplicateDebtCode>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id, client_name, debt_code, is_active, created_at, {DEBT_CODE_KEY} AS code_key
+         FROM {table}
+         WHERE {DEBT_CODE_KEY} IN (
+             SELECT {DEBT_CODE_KEY} FROM {table} WHERE trim(debt_code) != ''
+             GROUP BY {DEBT_CODE_KEY} HAVING COUNT(*) > 1
+         )
+         ORDER BY code_key, created_at"
+    ))?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, String>(5)?,
+            DuplicateDebtCodeClient {
+                id: row.get(0)?,
+                client_name: row.get(1)?,
+                debt_code: row.get(2)?,
+                is_active: row.get::<_, i32>(3)? == 1,
+                created_at: row.get(4)?,
+            },
+        ))
+    })?;
+
+    let mut duplicates: Vec<(String, DuplicateDebtCode)> = Vec::new();
+    for row in rows {
+        let (key, client) = row?;
+        match duplicates.last_mut() {
+            Some((last_key, duplicate)) if *last_key == key => duplicate.clients.push(client),
+            _ => duplicates.push((
+                key,
+                DuplicateDebtCode {
+                    table_name: table.to_string(),
+                    debt_code: client.debt_code.trim().to_string(),
+                    clients: vec![client],
+                },
+            )),
+        }
+    }
+    Ok(duplicates.into_iter().map(|(_, duplicate)| duplicate).collect())
+}
+
+fn debt_code_report(conn: &Connection) -> Result<DebtCodeReport> {
+    let mut report = DebtCodeReport {
+        constraints: Vec::new(),
+        duplicates: Vec::new(),
+    };
+    for (table, _) in DEBT_CODE_TABLES {
+        let duplicates = duplicate_debt_codes(conn, table)?;
+        report.constraints.push(DebtCodeConstraint {
+            table_name: table.to_string(),
+            applied: constraint_applied(conn, table)?,
+            duplicate_codes: duplicates.len(),
+        });
+        report.duplicates.extend(duplicates);
+    }
+    Ok(report)
+}
+
+/// The ids of the rows in `table` holding `debt_code`, other than `except_id`.
+fn find_by_debt_code(conn: &Connection, table: &str, debt_code: Option<&str>, except_id: &str) -> Result<Vec<String>> {
+    let Some(debt_code) = debt_code.map(str::trim).filter(|code| !code.is_empty()) else {
+        return Ok(Vec::new());
+    };
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id FROM {table} WHERE {DEBT_CODE_KEY} = lower(?1) AND id != ?2 ORDER BY created_at"
+    ))?;
+    let ids = stmt.query_map(params![debt_code, except_id], |row| row.get(0))?;
+    ids.collect()
+}
+
+/// The client an upsert should update, refusing a code several clients share.
+fn upsert_target(conn: &Connection, table: &str, debt_code: Option<&str>) -> Result<Option<String>> {
+    let mut ids = find_by_debt_code(conn, table, debt_code, "")?;
+    if ids.len() > 1 {
+        return Err(rule_violation(format!(
+            "{} clients share debt code '{}'; resolve the duplicates before upserting",
+            ids.len(),
+            debt_code.unwrap_or_default().trim()
+        )));
+    }
+    Ok(ids.pop())
+}
+
+/// Rejects a debt code another row of `table` already has. This holds even
+/// while the unique index is waiting on existing duplicates to be resolved.
+pub(super) fn ensure_unique_debt_code(conn: &Connection, table: &str, debt_code: Option<&str>, id: &str) -> Result<()> {
+    if !find_by_debt_code(conn, table, debt_code, id)?.is_empty() {
+        return Err(rule_violation(format!(
+            "Debt code '{}' is already used by another client",
+            debt_code.unwrap_or_default().trim()
+        )));
+    }
+    Ok(())
+}
+
+impl Database {
+    /// Lists debt codes shared by several clients and whether each table's
+    /// unique constraint is in place.
+    pub fn get_debt_code_report(&self) -> Result<DebtCodeReport> {
+        let conn = self.conn.lock().unwrap();
+        debt_code_report(&conn)
+    }
+
+    /// Applies the unique debt code constraint to every table that no longer
+    /// has duplicates, and reports what is still blocking the others.
+    pub fn apply_debt_code_constraints(&self) -> Result<DebtCodeReport> {
+        let conn = self.conn.lock().unwrap();
+        for (table, _) in DEBT_CODE_TABLES {
+            if duplicate_debt_codes(&conn, table)?.is_empty() {
+                apply_constraint(&conn, table)?;
+            }
+        }
+        debt_code_report(&conn)
+    }
+
+    /// Updates the client with the same debt code, keeping its id and created
+    /// date, or adds the client if there is none.
+    pub fn upsert_client(&self, mut client: Client) -> Result<ClientUpsert> {
+        client.currency = checked_currency(&client.currency)?;
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let existing = upsert_target(&tx, "clients", client.debt_code.as_deref())?;
+        let created = existing.is_none();
+        match existing {
+            Some(id) => {
+                client.id = id;
+                update_client_row(&tx, &client)?;
+            }
+            None => insert_client(&tx, &client)?,
+        }
+        tx.commit()?;
+        Ok(ClientUpsert {
+            id: client.id,
+            created,
+            commission_adjustments: Vec::new(),
+        })
+    }
+
+    /// Updates the VAR client with the same debt code, keeping its id and
+    /// created date, or adds the client if there is none. A debt code held by
+    /// another partner's client is rejected.
+    pub fn upsert_var_client(&self, mut client: VarClient) -> Result<ClientUpsert> {
+        client.currency = checked_currency(&client.currency)?;
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let existing = upsert_target(&tx, "var_clients", client.debt_code.as_deref())?;
+        let created = existing.is_none();
+        let commission_adjustments = match existing {
+            Some(id) => {
+                let partner: String = tx.query_row(
+                    "SELECT var_partner_id FROM var_clients WHERE id = ?1",
+                    params![id],
+                    |row| row.get(0),
+                )?;
+                if partner != client.var_partner_id {
+                    return Err(rule_violation(format!(
+                        "Debt code '{}' matches a VAR client of another partner",
+                        client.debt_code.as_deref().unwrap_or_default()
+                    )));
+                }
+                client.id = id;
+                update_var_client_row(&tx, &client)?
+            }
+            None => {
+                insert_var_client(&tx, &client)?;
+                Vec::new()
+            }
+        };
+        tx.commit()?;
+        Ok(ClientUpsert {
+            id: client.id,
+            created,
+            commission_adjustments,
+        })
+    }
+}
@@ -0,0 +1,373 @@
+use super::payments::{open_invoices, OpenInvoice};
+use super::{
+    current_timestamp, generate_id, load_setting, next_document_number, rule_violation, save_setting, Database,
+};
+use crate::currency::{format_amount, round_amount};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+
+const SETTINGS_KEY: &str = "dunning";
+
+/// Escalation levels in the order they must be sent.
+pub const DUNNING_LEVELS: [&str; 3] = ["friendly_reminder", "second_notice", "final_demand"];
+
+/// Day thresholds for the dunning run. An invoice is overdue once it is older
+/// than `payment_terms_days`; each level becomes due once the invoice has been
+/// overdue for at least its threshold.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DunningSettings {
+    pub payment_terms_days: i64,
+    pub friendly_reminder_days: i64,
+    pub second_notice_days: i64,
+    pub final_demand_days: i64,
+}
+
+impl Default for DunningSettings {
+    fn default() -> Self {
+        DunningSettings {
+            payment_terms_days: 30,
+            friendly_reminder_days: 1,
+            second_notice_days: 14,
+            final_demand_days: 30,
+        }
+    }
+}
+
+impl DunningSettings {
+    fn threshold(&self, level: i64) -> i64 {
+        match level {
+            1 => self.friendly_reminder_days,
+            2 => self.second_notice_days,
+            _ => self.final_demand_days,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DunningItem {
+    pub invoice_id: String,
+    pub billing_month: String,
+    pub invoice_date: String,
+    pub level: i64,
+    pub days_overdue: i64,
+    pub amount_due: f64,
+}
+
+/// A client that is due a reminder at one level, with the overdue invoices
+/// it covers. A client with invoices at several levels gets one per level.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DunningCandidate {
+    pub var_client_id: String,
+    pub client_name: String,
+    pub debt_code: Option<String>,
+    pub currency: String,
+    pub level: i64,
+    pub level_name: String,
+    pub total_due: f64,
+    pub items: Vec<DunningItem>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DunningNotice {
+    pub id: String,
+    pub notice_number: String,
+    pub var_client_id: String,
+    pub level: i64,
+    pub level_name: String,
+    pub issued_date: String,
+    pub total_due: f64,
+    pub currency: String,
+    pub document: String,
+    pub created_at: String,
+}
+
+pub(super) fn create_tables(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS dunning_notices (
+            id TEXT PRIMARY KEY,
+            notice_number TEXT NOT NULL UNIQUE,
+            var_client_id TEXT NOT NULL,
+            level INTEGER NOT NULL,
+            level_name TEXT NOT NULL,
+            issued_date TEXT NOT NULL,
+            total_due REAL NOT NULL,
+            currency TEXT NOT NULL,
+            document TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            FOREIGN KEY (var_client_id) REFERENCES var_clients (id)
+        )",
+        [],
+    )?;
+
+    // One row per invoice and level, so a level can never be sent twice.
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS dunning_notice_items (
+            notice_id TEXT NOT NULL,
+            invoice_id TEXT NOT NULL,
+            level INTEGER NOT NULL,
+            days_overdue INTEGER NOT NULL,
+            amount_due REAL NOT NULL,
+            PRIMARY KEY (invoice_id, level),
+            FOREIGN KEY (notice_id) REFERENCES dunning_notices (id),
+            FOREIGN KEY (invoice_id) REFERENCES var_client_invoices (id)
+        )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+pub(super) fn settings(conn: &Connection) -> Result<DunningSettings> {
+    Ok(load_setting(conn, SETTINGS_KEY)?.unwrap_or_default())
+}
+
+/// Works out which overdue invoices are due their next reminder level.
+/// Levels are sent strictly in order, and a level is only sent once the gap
+/// since the previous notice covers the difference between their thresholds.
+/// Invoices are grouped per client and level, so a notice's wording always
+/// matches every invoice on it.
+fn dunning_candidates(conn: &Connection, as_of: &str) -> Result<Vec<DunningCandidate>> {
+    let settings = settings(conn)?;
+    let mut candidates: Vec<DunningCandidate> = Vec::new();
+
+    for invoice in open_invoices(conn, Some(as_of))? {
+        let OpenInvoice {
+            invoice_id,
+            var_client_id,
+            client_name,
+            debt_code,
+            currency,
+            billing_month,
+            invoice_date,
+            age_days,
+            outstanding,
+            ..
+        } = invoice;
+        let days_overdue = age_days - settings.payment_terms_days;
+        if outstanding <= 0.0 || days_overdue <= 0 {
+            continue;
+        }
+
+        let last: Option<(i64, i64)> = conn
+            .query_row(
+                "SELECT i.level, CAST(julianday(?2) - julianday(n.issued_date) AS INTEGER)
+                 FROM dunning_notice_items i
+                 JOIN dunning_notices n ON n.id = i.notice_id
+                 WHERE i.invoice_id = ?1
+                 ORDER BY i.level DESC LIMIT 1",
+                params![invoice_id, as_of],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+
+        let level = match last {
+            None => 1,
+            Some((last_level, _)) if last_level >= DUNNING_LEVELS.len() as i64 => continue,
+            Some((last_level, days_since)) => {
+                let gap = settings.threshold(last_level + 1) - settings.threshold(last_level);
+                if days_since < gap {
+                    continue;
+                }
+                last_level + 1
+            }
+        };
+        if days_overdue < settings.threshold(level) {
+            continue;
+        }
+
+        let item = DunningItem {
+            invoice_id,
+            billing_month,
+            invoice_date,
+            level,
+            days_overdue,
+            amount_due: outstanding,
+        };
+        match candidates
+            .iter_mut()
+            .find(|c| c.var_client_id == var_client_id && c.level == level)
+        {
+            Some(candidate) => {
+                candidate.total_due = round_amount(candidate.total_due + item.amount_due, &candidate.currency);
+                candidate.items.push(item);
+            }
+            None => candidates.push(DunningCandidate {
+                var_client_id,
+                client_name,
+                debt_code,
+                currency,
+                level,
+                level_name: DUNNING_LEVELS[(level - 1) as usize].to_string(),
+                total_due: item.amount_due,
+                items: vec![item],
+            }),
+        }
+    }
+
+    candidates.sort_by(|a, b| a.client_name.cmp(&b.client_name).then(a.level.cmp(&b.level)));
+    Ok(candidates)
+}
+
+fn render_notice(candidate: &DunningCandidate, notice_number: &str, issued_date: &str) -> String {
+    let (title, opening, closing) = match candidate.level {
+        1 => (
+            "PAYMENT REMINDER",
+            "This is a friendly reminder that the following invoices are past their due date.",
+            "If you have already paid, please disregard this reminder.",
+        ),
+        2 => (
+            "SECOND NOTICE",
+            "We have not yet received payment for the following overdue invoices.",
+            "Please arrange payment within 7 days or contact us to discuss.",
+        ),
+        _ => (
+            "FINAL DEMAND",
+            "Despite previous reminders, the following invoices remain unpaid.",
+            "Unless payment is received within 7 days, we will suspend service and refer the account for collection.",
+        ),
+    };
+
+    let mut document = String::new();
+    document.push_str(&format!("{}\n", title));
+    document.push_str(&format!("Notice: {}\nDate: {}\n\n", notice_number, issued_date));
+    document.push_str(&format!("To: {}", candidate.client_name));
+    if let Some(debt_code) = &candidate.debt_code {
+        document.push_str(&format!(" ({})", debt_code));
+    }
+    document.push_str(&format!("\n\n{}\n\n", opening));
+    document.push_str(&format!(
+        "{:<14}{:<14}{:>14}{:>22}\n",
+        "Billing month", "Invoice date", "Days overdue", "Amount due"
+    ));
+    for item in &candidate.items {
+        document.push_str(&format!(
+            "{:<14}{:<14}{:>14}{:>22}\n",
+            item.billing_month,
+            item.invoice_date.get(..10).unwrap_or(&item.invoice_date),
+            item.days_overdue,
+            format_amount(item.amount_due, &candidate.currency)
+        ));
+    }
+    document.push_str(&format!(
+        "\nTotal due: {}\n\n{}\n",
+        format_amount(candidate.total_due, &candidate.currency),
+        closing
+    ));
+    document
+}
+
+impl Database {
+    pub fn get_dunning_settings(&self) -> Result<DunningSettings> {
+        let conn = self.conn.lock().unwrap();
+        settings(&conn)
+    }
+
+    pub fn update_dunning_settings(&self, settings: DunningSettings) -> Result<()> {
+        let thresholds = [
+            settings.friendly_reminder_days,
+            settings.second_notice_days,
+            settings.final_demand_days,
+        ];
+        if settings.payment_terms_days < 0 || thresholds[0] < 1 || !thresholds.windows(2).all(|w| w[0] < w[1]) {
+            return Err(rule_violation(
+                "Dunning thresholds must be positive and increase with each level",
+            ));
+        }
+        let conn = self.conn.lock().unwrap();
+        save_setting(&conn, SETTINGS_KEY, &settings)
+    }
+
+    /// Previews the reminders a dunning run on `as_of` would send.
+    pub fn get_dunning_candidates(&self, as_of: &str) -> Result<Vec<DunningCandidate>> {
+        let conn = self.conn.lock().unwrap();
+        dunning_candidates(&conn, as_of)
+    }
+
+    /// Issues one reminder document per client and level that is due and
+    /// records the level sent for each invoice.
+    pub fn run_dunning(&self, as_of: &str) -> Result<Vec<DunningNotice>> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let mut notices = Vec::new();
+
+        for candidate in dunning_candidates(&tx, as_of)? {
+            let notice_number = next_document_number(&tx, "dunning_notice", "DUN")?;
+            let notice = DunningNotice {
+                id: generate_id(&tx)?,
+                document: render_notice(&candidate, &notice_number, as_of),
+                notice_number,
+                var_client_id: candidate.var_client_id,
+                level: candidate.level,
+                level_name: candidate.level_name,
+                issued_date: as_of.to_string(),
+                total_due: candidate.total_due,
+                currency: candidate.currency,
+                created_at: current_timestamp(&tx)?,
+            };
+
+            tx.execute(
+                "INSERT INTO dunning_notices
+                 (id, notice_number, var_client_id, level, level_name, issued_date, total_due,
+                  currency, document, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                params![
+                    notice.id,
+                    notice.notice_number,
+                    notice.var_client_id,
+                    notice.level,
+                    notice.level_name,
+                    notice.issued_date,
+                    notice.total_due,
+                    notice.currency,
+                    notice.document,
+                    notice.created_at
+                ],
+            )?;
+            for item in &candidate.items {
+                tx.execute(
+                    "INSERT INTO dunning_notice_items (notice_id, invoice_id, level, days_overdue, amount_due)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![
+                        notice.id,
+                        item.invoice_id,
+                        item.level,
+                        item.days_overdue,
+                        item.amount_due
+                    ],
+                )?;
+            }
+            notices.push(notice);
+        }
+
+        tx.commit()?;
+        Ok(notices)
+    }
+
+    pub fn get_dunning_notices(&self, var_client_id: Option<&str>) -> Result<Vec<DunningNotice>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, notice_number, var_client_id, level, level_name, issued_date, total_due,
+                    currency, document, created_at
+             FROM dunning_notices
+             WHERE ?1 IS NULL OR var_client_id = ?1
+             ORDER BY issued_date DESC, notice_number DESC",
+        )?;
+
+        let notices = stmt.query_map(params![var_client_id], |row| {
+            Ok(DunningNotice {
+                id: row.get(0)?,
+                notice_number: row.get(1)?,
+                var_client_id: row.get(2)?,
+                level: row.get(3)?,
+                level_name: row.get(4)?,
+                issued_date: row.get(5)?,
+                total_due: row.get(6)?,
+                currency: row.get(7)?,
+                document: row.get(8)?,
+                created_at: row.get(9)?,
+            })
+        })?;
+
+        notices.collect()
+    }
+}
@@ -0,0 +1,395 @@
+use super::{checked_currency, current_timestamp, generate_id, load_setting, rule_violation, save_setting, Database};
+use crate::currency::round_amount;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+const REPORTING_CURRENCY_KEY: &str = "reporting_currency";
+const DEFAULT_REPORTING_CURRENCY: &str = "ZAR";
+
+/// Units of `to_currency` per one unit of `from_currency`, effective from
+/// `effective_date` until a later rate for the same pair replaces it.
+/// Monthly rates are stored against the first day of their month.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExchangeRate {
+    pub id: String,
+    pub from_currency: String,
+    pub to_currency: String,
+    pub rate: f64,
+    pub effective_date: String,
+    pub period: String,
+    pub source: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExchangeRateRequest {
+    pub from_currency: String,
+    pub to_currency: String,
+    pub rate: f64,
+    pub effective_date: String,
+    pub period: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RateImportError {
+    pub line: usize,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RateImportSummary {
+    pub imported: usize,
+    pub errors: Vec<RateImportError>,
+}
+
+/// An amount in its own currency alongside its value in the reporting
+/// currency. `converted_amount` is empty and `missing_rate` set when no rate
+/// was in force on the conversion date.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConvertedAmount {
+    pub currency: String,
+    pub amount: f64,
+    pub reporting_currency: String,
+    pub converted_amount: Option<f64>,
+    pub rate: Option<f64>,
+    pub rate_date: Option<String>,
+    pub missing_rate: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CurrencyTotal {
+    pub record_count: i64,
+    pub amount: ConvertedAmount,
+}
+
+/// Client and VAR client totals per currency, converted at the rates in force on `as_of`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReportingTotals {
+    pub reporting_currency: String,
+    pub as_of: String,
+    pub clients: Vec<CurrencyTotal>,
+    pub var_clients: Vec<CurrencyTotal>,
+    pub converted_total: f64,
+    pub has_missing_rates: bool,
+}
+
+pub(super) fn create_tables(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS exchange_rates (
+            id TEXT PRIMARY KEY,
+            from_currency TEXT NOT NULL,
+            to_currency TEXT NOT NULL,
+            rate REAL NOT NULL,
+            effective_date TEXT NOT NULL,
+            period TEXT NOT NULL,
+            source TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            UNIQUE (from_currency, to_currency, effective_date, period)
+        )",
+        [],
+    )?;
+
+    Ok(())
+}
+
+pub(super) fn reporting_currency(conn: &Connection) -> Result<String> {
+    Ok(load_setting(conn, REPORTING_CURRENCY_KEY)?.unwrap_or_else(|| DEFAULT_REPORTING_CURRENCY.to_string()))
+}
+
+/// Finds the rate in force on `date` for converting `from` into `to`, using
+/// the inverse of the opposite pair when only that has been entered. Daily
+/// rates win over a monthly rate taking effect on the same day.
+pub(super) fn find_rate(conn: &Connection, from: &str, to: &str, date: &str) -> Result<Option<(f64, String)>> {
+    if from.eq_ignore_ascii_case(to) {
+        return Ok(Some((1.0, date.to_string())));
+    }
+
+    conn.query_row(
+        "SELECT rate, effective_date FROM (
+             SELECT rate, effective_date, period FROM exchange_rates
+             WHERE from_currency = upper(?1) AND to_currency = upper(?2) AND effective_date <= ?3
+             UNION ALL
+             SELECT 1.0 / rate, effective_date, period FROM exchange_rates
+             WHERE from_currency = upper(?2) AND to_currency = upper(?1) AND effective_date <= ?3
+         )
+         ORDER BY effective_date DESC, period = 'daily' DESC
+         LIMIT 1",
+        params![from, to, date],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .optional()
+}
+
+pub(super) fn convert(
+    conn: &Connection,
+    amount: f64,
+    currency: &str,
+    reporting: &str,
+    date: &str,
+) -> Result<ConvertedAmount> {
+    let rate = find_rate(conn, currency, reporting, date)?;
+    Ok(ConvertedAmount {
+        currency: currency.to_string(),
+        amount: round_amount(amount, currency),
+        reporting_currency: reporting.to_string(),
+        converted_amount: rate.as_ref().map(|(rate, _)| round_amount(amount * rate, reporting)),
+        rate: rate.as_ref().map(|(rate, _)| *rate),
+        missing_rate: rate.is_none(),
+        rate_date: rate.map(|(_, date)| date),
+    })
+}
+
+fn insert_rate(conn: &Connection, request: &ExchangeRateRequest, source: &str) -> Result<ExchangeRate> {
+    let from_currency = checked_currency(&request.from_currency)?;
+    let to_currency = checked_currency(&request.to_currency)?;
+    if from_currency == to_currency {
+        return Err(rule_violation("An exchange rate needs two different currencies"));
+    }
+    if !(request.rate.is_finite() && request.rate > 0.0) {
+        return Err(rule_violation("Exchange rates must be greater than zero"));
+    }
+
+    let date_input = match request.period.as_str() {
+        "daily" => request.effective_date.trim().to_string(),
+        "monthly" => format!("{}-01", request.effective_date.trim().get(..7).unwrap_or_default()),
+        other => return Err(rule_violation(format!("Unknown rate period '{}'", other))),
+    };
+    let effective_date: Option<String> = conn.query_row("SELECT date(?1)", params![date_input], |row| row.get(0))?;
+    let effective_date =
+        effective_date.ok_or_else(|| rule_violation(format!("Invalid effective date '{}'", request.effective_date)))?;
+
+    let mut rate = ExchangeRate {
+        id: generate_id(conn)?,
+        from_currency,
+        to_currency,
+        rate: request.rate,
+        effective_date,
+        period: request.period.clone(),
+        source: source.to_string(),
+        created_at: current_timestamp(conn)?,
+    };
+
+    conn.execute(
+        "INSERT INTO exchange_rates
+         (id, from_currency, to_currency, rate, effective_date, period, source, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+         ON CONFLICT (from_currency, to_currency, effective_date, period)
+         DO UPDATE SET rate = excluded.rate, source = excluded.source, created_at = excluded.created_at",
+        params![
+            rate.id,
+            rate.from_currency,
+            rate.to_currency,
+            rate.rate,
+            rate.effective_date,
+            rate.period,
+            rate.source,
+            rate.created_at
+        ],
+    )?;
+    // Re-entering a rate updates the existing row, which keeps its original id.
+    rate.id = conn.query_row(
+        "SELECT id FROM exchange_rates
+         WHERE from_currency = ?1 AND to_currency = ?2 AND effective_date = ?3 AND period = ?4",
+        params![rate.from_currency, rate.to_currency, rate.effective_date, rate.period],
+        |row| row.get(0),
+    )?;
+
+    Ok(rate)
+}
+
+fn currency_totals(conn: &Connection, table: &str, reporting: &str, as_of: &str) -> Result<Vec<CurrencyTotal>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT currency, COUNT(*), SUM(total) FROM {table}
+         WHERE is_active = 1
+         GROUP BY currency
+         ORDER BY currency"
+    ))?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?, row.get::<_, f64>(2)?))
+        })?
+        .collect::<Result<Vec<_>>>()?;
+
+    rows.into_iter()
+        .map(|(currency, record_count, amount)| {
+            Ok(CurrencyTotal {
+                record_count,
+                amount: convert(conn, amount, &currency, reporting, as_of)?,
+            })
+        })
+        .collect()
+}
+
+/// Column positions in a rate CSV, found by header name.
+struct RateColumns {
+    date: usize,
+    from: usize,
+    to: usize,
+    rate: usize,
+    period: Option<usize>,
+}
+
+impl RateColumns {
+    fn from_headers(headers: &csv::StringRecord) -> std::result::Result<Self, String> {
+        let find = |names: &[&str]| {
+            headers
+                .iter()
+                .position(|h| names.contains(&h.trim().to_lowercase().replace(' ', "_").as_str()))
+        };
+        let require = |names: &[&str]| find(names).ok_or_else(|| format!("Missing '{}' column", names[0]));
+
+        Ok(RateColumns {
+            date: require(&["date", "effective_date"])?,
+            from: require(&["from_currency", "from", "base"])?,
+            to: require(&["to_currency", "to", "quote"])?,
+            rate: require(&["rate", "exchange_rate"])?,
+            period: find(&["period"]),
+        })
+    }
+}
+
+impl Database {
+    pub fn get_exchange_rates(&self) -> Result<Vec<ExchangeRate>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, from_currency, to_currency, rate, effective_date, period, source, created_at
+             FROM exchange_rates
+             ORDER BY from_currency, to_currency, effective_date DESC",
+        )?;
+
+        let rates = stmt.query_map([], |row| {
+            Ok(ExchangeRate {
+                id: row.get(0)?,
+                from_currency: row.get(1)?,
+                to_currency: row.get(2)?,
+                rate: row.get(3)?,
+                effective_date: row.get(4)?,
+                period: row.get(5)?,
+                source: row.get(6)?,
+                created_at: row.get(7)?,
+            })
+        })?;
+
+        rates.collect()
+    }
+
+    /// Adds a manually entered rate, replacing any rate for the same pair,
+    /// date and period.
+    pub fn add_exchange_rate(&self, request: ExchangeRateRequest) -> Result<ExchangeRate> {
+        let conn = self.conn.lock().unwrap();
+        insert_rate(&conn, &request, "manual")
+    }
+
+    pub fn delete_exchange_rate(&self, id: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM exchange_rates WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+
+    /// Imports rates from a CSV file with `date`, `from_currency`,
+    /// `to_currency`, `rate` and an optional `period` column. Valid lines are
+    /// imported even when others fail; failures are reported by line number.
+    pub fn import_exchange_rates_csv(&self, path: &Path) -> Result<RateImportSummary> {
+        let mut reader = csv::Reader::from_path(path)
+            .map_err(|e| rule_violation(format!("Could not open {}: {}", path.display(), e)))?;
+        let headers = reader
+            .headers()
+            .map_err(|e| rule_violation(format!("Could not read CSV headers: {}", e)))?
+            .clone();
+        let columns = RateColumns::from_headers(&headers).map_err(rule_violation)?;
+
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let mut summary = RateImportSummary {
+            imported: 0,
+            errors: Vec::new(),
+        };
+
+        for (index, record) in reader.records().enumerate() {
+            let line = index + 2;
+            let record = match record {
+                Ok(record) => record,
+                Err(e) => {
+                    summary.errors.push(RateImportError {
+                        line,
+                        message: e.to_string(),
+                    });
+                    continue;
+                }
+            };
+            let field = |i: usize| record.get(i).unwrap_or_default().trim().to_string();
+
+            let rate = match field(columns.rate).replace(',', ".").parse::<f64>() {
+                Ok(rate) => rate,
+                Err(_) => {
+                    summary.errors.push(RateImportError {
+                        line,
+                        message: format!("Invalid rate '{}'", field(columns.rate)),
+                    });
+                    continue;
+                }
+            };
+            let request = ExchangeRateRequest {
+                from_currency: field(columns.from),
+                to_currency: field(columns.to),
+                rate,
+                effective_date: field(columns.date),
+                period: columns
+                    .period
+                    .map(|i| field(i).to_lowercase())
+                    .filter(|p| !p.is_empty())
+                    .unwrap_or_else(|| "daily".to_string()),
+            };
+
+            match insert_rate(&tx, &request, "csv") {
+                Ok(_) => summary.imported += 1,
+                Err(e) => summary.errors.push(RateImportError {
+                    line,
+                    message: e.to_string(),
+                }),
+            }
+        }
+
+        tx.commit()?;
+        Ok(summary)
+    }
+
+    pub fn get_reporting_currency(&self) -> Result<String> {
+        let conn = self.conn.lock().unwrap();
+        reporting_currency(&conn)
+    }
+
+    pub fn set_reporting_currency(&self, currency: &str) -> Result<()> {
+        let currency = checked_currency(currency)?;
+        let conn = self.conn.lock().unwrap();
+        save_setting(&conn, REPORTING_CURRENCY_KEY, &currency)
+    }
+
+    /// Converts `amount` into the reporting currency at the rate in force on `date`.
+    pub fn convert_to_reporting_currency(&self, amount: f64, currency: &str, date: &str) -> Result<ConvertedAmount> {
+        let conn = self.conn.lock().unwrap();
+        let reporting = reporting_currency(&conn)?;
+        convert(&conn, amount, currency, &reporting, date)
+    }
+
+    pub fn get_reporting_totals(&self, as_of: &str) -> Result<ReportingTotals> {
+        let conn = self.conn.lock().unwrap();
+        let reporting = reporting_currency(&conn)?;
+        let clients = currency_totals(&conn, "clients", &reporting, as_of)?;
+        let var_clients = currency_totals(&conn, "var_clients", &reporting, as_of)?;
+
+        let all = || clients.iter().chain(var_clients.iter());
+        let converted_total = round_amount(all().filter_map(|t| t.amount.converted_amount).sum(), &reporting);
+        let has_missing_rates = all().any(|t| t.amount.missing_rate);
+
+        Ok(ReportingTotals {
+            reporting_currency: reporting,
+            as_of: as_of.to_string(),
+            clients,
+            var_clients,
+            converted_total,
+            has_missing_rates,
+        })
+    }
+}
@@ -0,0 +1,285 @@
+use super::client_import::{field_headers, BILLING_MODELS, IMPORT_TEMPLATES, MONTH_COLUMNS};
+use super::rule_violation;
+use crate::tabular::Table;
+use rusqlite::Result;
+use serde::{Deserialize, Serialize};
+
+/// First cell of the stamp row at the top of every generated template.
+pub const TEMPLATE_STAMP: &str = "BuildSmart Billing import template";
+
+/// Bumped whenever template columns are added, removed or renamed, so files
+/// made from an older template are caught before they import.
+pub const TEMPLATE_VERSION: u32 = 1;
+
+/// The client name on the example row; rows still holding it are skipped.
+const EXAMPLE_CLIENT: &str = "Example client (replace or delete this row)";
+
+const REQUIRED: &str = "Required.";
+const OPTIONAL: &str = "Optional.";
+
+/// One column of a template, with the guidance shown under its header.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TemplateColumn {
+    pub field: String,
+    pub header: String,
+    pub required: bool,
+    pub allowed_values: Vec<String>,
+    pub description: String,
+    pub example: String,
+}
+
+/// A client import template built from the fields the importer reads.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportTemplate {
+    pub template: String,
+    pub version: u32,
+    pub name: String,
+    pub description: String,
+    pub file_name: String,
+    pub columns: Vec<TemplateColumn>,
+}
+
+/// "deal start date" as "Deal Start Date".
+fn title(header: &str) -> String {
+    header
+        .split(' ')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn column(field: &str, required: bool, description: &str, example: &str) -> TemplateColumn {
+    TemplateColumn {
+        field: field.to_string(),
+        header: title(field_headers(field)[0]),
+        required,
+        allowed_values: Vec::new(),
+        description: description.to_string(),
+        example: example.to_string(),
+    }
+}
+
+/// Builds the template for one of the `IMPORT_TEMPLATES`.
+pub fn import_template(template: &str) -> Result<ImportTemplate> {
+    let (name, description, file_name, example_model) = match template {
+        "perpetual" => (
+            "Main Clients - Perpetual",
+            "For perpetual license clients with S&M fees",
+            "main-clients-perpetual-template",
+            "perpetual",
+        ),
+        "subscription" => (
+            "Main Clients - Subscription",
+            "For subscription-based recurring clients",
+            "main-clients-subscription-template",
+            "subscription",
+        ),
+        "hybrid" => (
+            "Main Clients - Hybrid",
+            "For hybrid model (perpetual + subscription)",
+            "main-clients-hybrid-template",
+            "perpetual",
+        ),
+        "var" => (
+            "VAR Clients",
+            "For VAR/reseller clients with commission tracking",
+            "var-clients-template",
+            "subscription",
+        ),
+        other => {
+            return Err(rule_violation(format!(
+                "Unknown import template '{}'; expected one of {}",
+                other,
+                IMPORT_TEMPLATES.join(", ")
+            )))
+        }
+    };
+
+    // Perpetual clients pay S&M once a year in their anniversary month;
+    // the others are billed the same amount every month.
+    let monthly: [f64; 12] = match example_model {
+        "perpetual" => std::array::from_fn(|month| if month == 2 { 12000.0 } else { 0.0 }),
+        _ => [1500.0; 12],
+    };
+
+    let mut columns = vec![
+        column("client_name", true, "The client's name.", EXAMPLE_CLIENT),
+        column(
+            "debt_code",
+            false,
+            "The client's account code, unique across clients. Rows are matched to existing clients by it.",
+            "EXAMPLE01",
+        ),
+        column("users", false, "A whole number of users; 0 when blank.", "25"),
+    ];
+    match template {
+        "hybrid" | "var" => {
+            let mut billing_model = column(
+                "billing_model",
+                template == "hybrid",
+                if template == "hybrid" {
+                    "How the client is billed."
+                } else {
+                    "How the client is billed; subscription when blank."
+                },
+                example_model,
+            );
+            billing_model.allowed_values = BILLING_MODELS.iter().map(|model| model.to_string()).collect();
+            columns.push(billing_model);
+        }
+        _ => {}
+    }
+    columns.push(column(
+        "currency",
+        false,
+        "A three-letter currency code; the reporting currency when blank.",
+        "ZAR",
+    ));
+    for ([_, month], amount) in MONTH_COLUMNS.iter().zip(monthly) {
+        columns.push(TemplateColumn {
+            field: month.to_string(),
+            header: title(&month[..3]),
+            required: false,
+            allowed_values: Vec::new(),
+            description: format!("The amount billed in {}; 0 when blank.", title(month)),
+            example: format!("{:.2}", amount),
+        });
+    }
+    columns.push(column(
+        "total",
+        false,
+        "The sum of the monthly amounts, checked against them when given.",
+        &format!("{:.2}", monthly.iter().sum::<f64>()),
+    ));
+    columns.push(column("comments", false, "Free-text notes.", ""));
+    columns.push(column(
+        "deal_start_date",
+        false,
+        "The date the deal started, as YYYY-MM-DD; today when blank.",
+        "2026-03-01",
+    ));
+    if template != "subscription" {
+        columns.push(column(
+            "anniversary_month",
+            false,
+            "The month number, 1 to 12, the licence renews in.",
+            if example_model == "perpetual" { "3" } else { "" },
+        ));
+    }
+    if template == "var" {
+        columns.push(column(
+            "commission_rate",
+            false,
+            "The partner's commission percentage, 0 to 100; the partner's rate when blank.",
+            "20",
+        ));
+    }
+
+    Ok(ImportTemplate {
+        template: template.to_string(),
+        version: TEMPLATE_VERSION,
+        name: name.to_string(),
+        description: description.to_string(),
+        file_name: file_name.to_string(),
+        columns,
+    })
+}
+
+/// Every import template, in the order they are offered.
+pub fn import_templates() -> Vec<ImportTemplate> {
+    IMPORT_TEMPLATES
+        .iter()
+        .filter_map(|template| import_template(template).ok())
+        .collect()
+}
+
+impl ImportTemplate {
+    /// The file layout: a stamp row naming the template and its version,
+    /// the headers, a guide row saying what each column takes, and an
+    /// example row.
+    pub fn rows(&self) -> Vec<Vec<String>> {
+        let guide = self
+            .columns
+            .iter()
+            .map(|column| {
+                let mut guide = format!(
+                    "{} {}",
+                    if column.required { REQUIRED } else { OPTIONAL },
+                    column.description
+                );
+                if !column.allowed_values.is_empty() {
+                    guide.push_str(&format!(" One of: {}.", column.allowed_values.join(", ")));
+                }
+                guide
+            })
+            .collect();
+        vec![
+            vec![
+                TEMPLATE_STAMP.to_string(),
+                self.template.clone(),
+                format!("version {}", self.version),
+            ],
+            self.columns.iter().map(|column| column.header.clone()).collect(),
+            guide,
+            self.columns.iter().map(|column| column.example.clone()).collect(),
+        ]
+    }
+}
+
+/// Prepares a file made from a generated template for import. A stamp row
+/// must name `template` and the current version; the headers below it are
+/// then used, and the guide row and an untouched example row are dropped.
+/// Files without a stamp pass through unchanged apart from those rows.
+pub(super) fn strip_template_rows(mut table: Table, template: &str) -> Result<Table> {
+    if table.headers.first().map(|cell| cell.trim()) == Some(TEMPLATE_STAMP) {
+        let stamped = table.headers.get(1).map(|cell| cell.trim()).unwrap_or_default();
+        let version = table
+            .headers
+            .get(2)
+            .and_then(|cell| cell.trim().strip_prefix("version "))
+            .and_then(|version| version.trim().parse::<u32>().ok());
+        if version != Some(TEMPLATE_VERSION) {
+            return Err(rule_violation(format!(
+                "The file was made from an older or unknown version of the import template; \
+                 download the current template (version {}) and copy the data into it",
+                TEMPLATE_VERSION
+            )));
+        }
+        if stamped != template {
+            return Err(rule_violation(format!(
+                "The file was made from the '{}' template; import it as {} clients",
+                stamped, stamped
+            )));
+        }
+        if table.rows.is_empty() {
+            return Err(rule_violation("The template has no header row"));
+        }
+        let header_row = table.rows.remove(0);
+        table.headers = header_row.cells.iter().map(|cell| cell.trim().to_string()).collect();
+    }
+
+    let is_guide = |cells: &[String]| {
+        let filled: Vec<&str> = cells
+            .iter()
+            .map(|cell| cell.trim())
+            .filter(|cell| !cell.is_empty())
+            .collect();
+        !filled.is_empty()
+            && filled
+                .iter()
+                .all(|cell| cell.starts_with(REQUIRED) || cell.starts_with(OPTIONAL))
+    };
+    if table.rows.first().is_some_and(|row| is_guide(&row.cells)) {
+        table.rows.remove(0);
+    }
+    if let Some(client_name) = table.find_column(field_headers("client_name")) {
+        table.rows.retain(|row| row.get(Some(client_name)) != EXAMPLE_CLIENT);
+    }
+    Ok(table)
+}
@@ -0,0 +1,272 @@
+use super::billing_documents::checked_month;
+use super::commission_rates::rate_for_month;
+use super::commission_rules::recalculate_commissions;
+use super::invoice_status::record_status;
+use super::{current_timestamp, generate_id, rule_violation, Database};
+use crate::currency::round_amount;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+
+const SCHEDULE_COLUMNS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+/// Outcome of the invoice run for one client. `reason` explains a skip or failure.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InvoiceRunItem {
+    pub var_client_id: String,
+    pub client_name: String,
+    pub invoice_id: Option<String>,
+    pub client_revenue: f64,
+    pub commission_rate: f64,
+    pub commission_amount: f64,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InvoiceRunSummary {
+    pub billing_month: String,
+    pub created: Vec<InvoiceRunItem>,
+    pub skipped: Vec<InvoiceRunItem>,
+    pub failed: Vec<InvoiceRunItem>,
+}
+
+struct ScheduledClient {
+    id: String,
+    client_name: String,
+    var_partner_id: String,
+    partner_active: bool,
+    users: i32,
+    currency: String,
+    deal_start_date: String,
+    scheduled: f64,
+}
+
+pub(super) fn create_tables(conn: &Connection) -> Result<()> {
+    // Voided and cancelled invoices do not count, so a month can be re-issued.
+    conn.execute("DROP INDEX IF EXISTS idx_var_client_invoices_client_month", [])?;
+
+    // Older databases may already hold duplicate rows for a client and month;
+    // those have to be cleaned up by hand before the constraint can be added.
+    let duplicates: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM (
+             SELECT 1 FROM var_client_invoices
+             WHERE invoice_status NOT IN ('void', 'cancelled')
+             GROUP BY var_client_id, billing_month HAVING COUNT(*) > 1
+         )",
+        [],
+        |row| row.get(0),
+    )?;
+    if duplicates > 0 {
+        log::warn!(
+            "{} client/month pairs have more than one VAR invoice; the uniqueness constraint was not added",
+            duplicates
+        );
+        return Ok(());
+    }
+
+    conn.execute(
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_var_client_invoices_live_month
+         ON var_client_invoices (var_client_id, billing_month)
+         WHERE invoice_status NOT IN ('void', 'cancelled')",
+        [],
+    )?;
+
+    Ok(())
+}
+
+fn scheduled_clients(conn: &Connection, billing_month: &str) -> Result<Vec<ScheduledClient>> {
+    checked_month(billing_month)?;
+    let month: usize = billing_month[5..]
+        .parse()
+        .expect("checked_month accepts months 01 to 12");
+    let column = SCHEDULE_COLUMNS[month - 1];
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT c.id, c.client_name, c.var_partner_id, COALESCE(p.is_active, 0), c.users, c.currency,
+                c.deal_start_date, c.{column}
+         FROM var_clients c
+         LEFT JOIN var_partners p ON p.id = c.var_partner_id
+         WHERE c.is_active = 1
+         ORDER BY c.client_name"
+    ))?;
+    let clients = stmt.query_map([], |row| {
+        Ok(ScheduledClient {
+            id: row.get(0)?,
+            client_name: row.get(1)?,
+            var_partner_id: row.get(2)?,
+            partner_active: row.get::<_, i32>(3)? == 1,
+            users: row.get(4)?,
+            currency: row.get(5)?,
+            deal_start_date: row.get(6)?,
+            scheduled: row.get(7)?,
+        })
+    })?;
+    clients.collect()
+}
+
+fn run_item(client: &ScheduledClient, reason: impl Into<String>) -> InvoiceRunItem {
+    InvoiceRunItem {
+        var_client_id: client.id.clone(),
+        client_name: client.client_name.clone(),
+        invoice_id: None,
+        client_revenue: 0.0,
+        commission_rate: 0.0,
+        commission_amount: 0.0,
+        reason: Some(reason.into()),
+    }
+}
+
+/// Why a client gets no invoice this month, if it should be skipped.
+fn skip_reason(conn: &Connection, client: &ScheduledClient, billing_month: &str) -> Result<Option<String>> {
+    let existing: Option<String> = conn
+        .query_row(
+            "SELECT id FROM var_client_invoices
+             WHERE var_client_id = ?1 AND billing_month = ?2 AND invoice_status NOT IN ('void', 'cancelled')",
+            params![client.id, billing_month],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if existing.is_some() {
+        return Ok(Some("Already invoiced for this month".to_string()));
+    }
+    if client.deal_start_date.get(..7).unwrap_or_default() > billing_month {
+        return Ok(Some(format!("Deal starts on {}", client.deal_start_date)));
+    }
+    if client.scheduled <= 0.0 {
+        return Ok(Some("Nothing scheduled for this month".to_string()));
+    }
+    Ok(None)
+}
+
+fn insert_invoice(
+    conn: &Connection,
+    client: &ScheduledClient,
+    billing_month: &str,
+    invoice_date: &str,
+) -> Result<InvoiceRunItem> {
+    if !client.partner_active {
+        return Err(rule_violation("The client's VAR partner is inactive"));
+    }
+
+    let client_revenue = round_amount(client.scheduled, &client.currency);
+    let resolved = rate_for_month(conn, &client.id, &client.var_partner_id, billing_month)?;
+    let commission_amount = round_amount(client_revenue * resolved.rate / 100.0, &client.currency);
+    let invoice_id = generate_id(conn)?;
+    let timestamp = current_timestamp(conn)?;
+
+    conn.execute(
+        "INSERT INTO var_client_invoices
+         (id, var_client_id, var_partner_id, billing_month, users, client_revenue, commission_rate,
+          commission_amount, invoice_date, invoice_status, notes, created_at, updated_at, commission_source)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, 'pending', NULL, ?10, ?10, ?11)",
+        params![
+            invoice_id,
+            client.id,
+            client.var_partner_id,
+            billing_month,
+            client.users,
+            client_revenue,
+            resolved.rate,
+            commission_amount,
+            invoice_date,
+            timestamp,
+            resolved.source
+        ],
+    )?;
+    record_status(conn, &invoice_id, None, "pending", None)?;
+
+    Ok(InvoiceRunItem {
+        var_client_id: client.id.clone(),
+        client_name: client.client_name.clone(),
+        invoice_id: Some(invoice_id),
+        client_revenue,
+        commission_rate: resolved.rate,
+        commission_amount,
+        reason: None,
+    })
+}
+
+impl Database {
+    /// Creates the `billing_month` invoice for every active VAR client from its
+    /// monthly schedule and the commission rate in force. Clients that already
+    /// have an invoice for the month are skipped, so the run can be repeated.
+    pub fn run_var_invoices(&self, billing_month: &str, invoice_date: Option<&str>) -> Result<InvoiceRunSummary> {
+        let mut conn = self.conn.lock().unwrap();
+        let mut tx = conn.transaction()?;
+        let invoice_date = match invoice_date {
+            Some(date) => date.to_string(),
+            None => tx.query_row("SELECT date('now')", [], |row| row.get(0))?,
+        };
+
+        let mut summary = InvoiceRunSummary {
+            billing_month: billing_month.to_string(),
+            created: Vec::new(),
+            skipped: Vec::new(),
+            failed: Vec::new(),
+        };
+        for client in scheduled_clients(&tx, billing_month)? {
+            if let Some(reason) = skip_reason(&tx, &client, billing_month)? {
+                summary.skipped.push(run_item(&client, reason));
+                continue;
+            }
+
+            // Each client gets its own savepoint so one bad row does not stop the run.
+            let savepoint = tx.savepoint()?;
+            match insert_invoice(&savepoint, &client, billing_month, &invoice_date) {
+                Ok(item) => {
+                    savepoint.commit()?;
+                    summary.created.push(item);
+                }
+                Err(e) => summary.failed.push(run_item(&client, e.to_string())),
+            }
+        }
+
+        // Tiered rules depend on the partner's whole volume, so they are applied
+        // once every invoice for the month exists.
+        let mut partners: Vec<String> = Vec::new();
+        for item in &summary.created {
+            let var_partner_id: String = tx.query_row(
+                "SELECT var_partner_id FROM var_client_invoices WHERE id = ?1",
+                params![item.invoice_id],
+                |row| row.get(0),
+            )?;
+            if !partners.contains(&var_partner_id) {
+                partners.push(var_partner_id);
+            }
+        }
+        for var_partner_id in &partners {
+            for calculation in recalculate_commissions(&tx, var_partner_id, billing_month)? {
+                if let Some(item) = summary
+                    .created
+                    .iter_mut()
+                    .find(|item| item.invoice_id.as_deref() == Some(&calculation.invoice_id))
+                {
+                    item.commission_rate = calculation.commission_rate;
+                    item.commission_amount = calculation.commission_amount;
+                }
+            }
+        }
+
+        tx.commit()?;
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::test_support::{add_client, add_partner, test_db};
+
+    #[test]
+    fn rejects_malformed_billing_months() {
+        let db = test_db();
+        add_partner(&db, "p1", 10.0);
+        add_client(&db, "c1", "p1", 10.0, "ZAR");
+        for month in ["2026-13", "2026-00", "2026/05", "26-05", "2026-05-01"] {
+            assert!(db.run_var_invoices(month, None).is_err(), "{month} was accepted");
+        }
+        let summary = db.run_var_invoices("2026-05", Some("2026-05-01")).unwrap();
+        assert_eq!(summary.created.len(), 1);
+        assert_eq!(summary.created[0].client_revenue, 1000.0);
+    }
+}
@@ -0,0 +1,326 @@
+use super::commission_rules::recalculate_commissions;
+use super::invoice_tracking::sync_invoice_tracking;
+use super::{current_timestamp, generate_id, rule_violation, Database};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+
+pub const INVOICE_STATUSES: &[&str] = &["draft", "pending", "invoiced", "paid", "void", "cancelled"];
+
+/// Allowed moves out of each status. Drafts and pending invoices can still be
+/// cancelled; once invoiced, the only way out is payment or voiding.
+const TRANSITIONS: &[(&str, &[&str])] = &[
+    ("draft", &["pending", "cancelled"]),
+    ("pending", &["draft", "invoiced", "cancelled"]),
+    ("invoiced", &["paid", "void"]),
+    ("paid", &["void"]),
+    ("void", &[]),
+    ("cancelled", &[]),
+];
+
+/// Free-text statuses written before the lifecycle existed, and the status
+/// each one becomes.
+const LEGACY_STATUSES: &[(&str, &str)] = &[
+    ("", "pending"),
+    ("open", "pending"),
+    ("new", "pending"),
+    ("not invoiced", "pending"),
+    ("not_invoiced", "pending"),
+    ("uninvoiced", "pending"),
+    ("sent", "invoiced"),
+    ("issued", "invoiced"),
+    ("billed", "invoiced"),
+    ("unpaid", "invoiced"),
+    ("overdue", "invoiced"),
+    ("outstanding", "invoiced"),
+    ("settled", "paid"),
+    ("received", "paid"),
+    ("voided", "void"),
+    ("canceled", "cancelled"),
+];
+
+/// One status change on a VAR invoice. The first entry has no `from_status`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InvoiceStatusChange {
+    pub id: String,
+    pub invoice_id: String,
+    pub from_status: Option<String>,
+    pub to_status: String,
+    pub changed_at: String,
+    pub note: Option<String>,
+}
+
+pub(super) fn create_tables(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS var_invoice_status_history (
+            id TEXT PRIMARY KEY,
+            invoice_id TEXT NOT NULL,
+            from_status TEXT,
+            to_status TEXT NOT NULL,
+            changed_at TEXT NOT NULL,
+            note TEXT,
+            FOREIGN KEY (invoice_id) REFERENCES var_client_invoices (id)
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_var_invoice_status_history_invoice
+         ON var_invoice_status_history (invoice_id, changed_at)",
+        [],
+    )?;
+
+    // Statuses were free text before the lifecycle existed. Known spellings are
+    // mapped to their status; anything else is left as it was, rather than
+    // guessed at, and reported so it can be corrected by hand.
+    let known = INVOICE_STATUSES.iter().map(|status| (*status, *status));
+    for (legacy, status) in known.chain(LEGACY_STATUSES.iter().copied()) {
+        conn.execute(
+            "UPDATE var_client_invoices SET invoice_status = ?2
+             WHERE lower(trim(invoice_status)) = ?1 AND invoice_status <> ?2",
+            params![legacy, status],
+        )?;
+    }
+    let unknown = {
+        let mut stmt = conn.prepare(
+            "SELECT invoice_status, COUNT(*) FROM var_client_invoices
+             WHERE invoice_status NOT IN ('draft', 'pending', 'invoiced', 'paid', 'void', 'cancelled')
+             GROUP BY invoice_status ORDER BY invoice_status",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(format!("'{}' ({})", row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+        })?;
+        rows.collect::<Result<Vec<_>>>()?
+    };
+    if !unknown.is_empty() {
+        log::warn!(
+            "VAR invoices have statuses that cannot be mapped to {} and were left unchanged: {}",
+            INVOICE_STATUSES.join(", "),
+            unknown.join(", ")
+        );
+    }
+
+    // Invoices created before history was kept start with their current status.
+    conn.execute(
+        "INSERT INTO var_invoice_status_history (id, invoice_id, from_status, to_status, changed_at, note)
+         SELECT lower(hex(randomblob(16))), i.id, NULL, i.invoice_status, i.created_at, NULL
+         FROM var_client_invoices i
+         WHERE NOT EXISTS (SELECT 1 FROM var_invoice_status_history h WHERE h.invoice_id = i.id)",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// Invoices past pending are issued documents: their figures are fixed and
+/// they can only be voided, never edited or deleted.
+pub(super) fn is_locked(status: &str) -> bool {
+    !matches!(status, "draft" | "pending")
+}
+
+/// New invoices start as a draft or pending.
+pub(super) fn check_initial_status(status: &str) -> Result<()> {
+    if is_locked(status) || !INVOICE_STATUSES.contains(&status) {
+        return Err(rule_violation(format!(
+            "New invoices must be draft or pending, not '{}'",
+            status
+        )));
+    }
+    Ok(())
+}
+
+fn check_transition(from: &str, to: &str) -> Result<()> {
+    if !INVOICE_STATUSES.contains(&to) {
+        return Err(rule_violation(format!("Unknown invoice status '{}'", to)));
+    }
+    let allowed = TRANSITIONS
+        .iter()
+        .find(|(status, _)| *status == from)
+        .map(|(_, next)| *next)
+        .unwrap_or(&[]);
+    if !allowed.contains(&to) {
+        return Err(rule_violation(format!(
+            "An invoice cannot move from {} to {}",
+            from, to
+        )));
+    }
+    Ok(())
+}
+
+pub(super) fn record_status(
+    conn: &Connection,
+    invoice_id: &str,
+    from_status: Option<&str>,
+    to_status: &str,
+    note: Option<&str>,
+) -> Result<InvoiceStatusChange> {
+    let change = InvoiceStatusChange {
+        id: generate_id(conn)?,
+        invoice_id: invoice_id.to_string(),
+        from_status: from_status.map(str::to_string),
+        to_status: to_status.to_string(),
+        changed_at: current_timestamp(conn)?,
+        note: note.map(str::to_string),
+    };
+    conn.execute(
+        "INSERT INTO var_invoice_status_history (id, invoice_id, from_status, to_status, changed_at, note)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            change.id,
+            change.invoice_id,
+            change.from_status,
+            change.to_status,
+            change.changed_at,
+            change.note
+        ],
+    )?;
+    Ok(change)
+}
+
+pub(super) fn current_status(conn: &Connection, invoice_id: &str) -> Result<String> {
+    conn.query_row(
+        "SELECT invoice_status FROM var_client_invoices WHERE id = ?1",
+        params![invoice_id],
+        |row| row.get(0),
+    )
+    .optional()?
+    .ok_or_else(|| rule_violation(format!("Invoice {} not found", invoice_id)))
+}
+
+/// Moves an invoice to `to_status` if the lifecycle allows it. Issuing an
+/// invoice without a date dates it today and marks the month invoiced by
+/// `changed_by`. Voiding or cancelling one releases its payment allocations
+/// back to unapplied credit and takes it out of the partner's commission
+/// volume, so the period is recalculated.
+pub(super) fn transition(
+    conn: &Connection,
+    invoice_id: &str,
+    to_status: &str,
+    note: Option<&str>,
+    changed_by: Option<&str>,
+) -> Result<InvoiceStatusChange> {
+    let from_status = current_status(conn, invoice_id)?;
+    check_transition(&from_status, to_status)?;
+
+    let change = record_status(conn, invoice_id, Some(&from_status), to_status, note)?;
+    conn.execute(
+        "UPDATE var_client_invoices SET invoice_status = ?2, updated_at = ?3,
+             invoice_date = CASE WHEN ?2 = 'invoiced' THEN COALESCE(invoice_date, date('now')) ELSE invoice_date END
+         WHERE id = ?1",
+        params![invoice_id, to_status, change.changed_at],
+    )?;
+    sync_invoice_tracking(conn, invoice_id, to_status, changed_by)?;
+
+    if matches!(to_status, "void" | "cancelled") {
+        conn.execute("DELETE FROM payment_allocations WHERE invoice_id = ?1", params![invoice_id])?;
+        let (var_partner_id, billing_month): (String, String) = conn.query_row(
+            "SELECT var_partner_id, billing_month FROM var_client_invoices WHERE id = ?1",
+            params![invoice_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        recalculate_commissions(conn, &var_partner_id, &billing_month)?;
+    }
+    Ok(change)
+}
+
+impl Database {
+    pub fn set_var_invoice_status(
+        &self,
+        invoice_id: &str,
+        status: &str,
+        note: Option<&str>,
+        changed_by: Option<&str>,
+    ) -> Result<InvoiceStatusChange> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let change = transition(&tx, invoice_id, status, note, changed_by)?;
+        tx.commit()?;
+        Ok(change)
+    }
+
+    pub fn get_var_invoice_status_history(&self, invoice_id: &str) -> Result<Vec<InvoiceStatusChange>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, invoice_id, from_status, to_status, changed_at, note
+             FROM var_invoice_status_history
+             WHERE invoice_id = ?1
+             ORDER BY changed_at, rowid",
+        )?;
+
+        let history = stmt.query_map(params![invoice_id], |row| {
+            Ok(InvoiceStatusChange {
+                id: row.get(0)?,
+                invoice_id: row.get(1)?,
+                from_status: row.get(2)?,
+                to_status: row.get(3)?,
+                changed_at: row.get(4)?,
+                note: row.get(5)?,
+            })
+        })?;
+        history.collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::create_tables;
+    use crate::database::test_support::{add_client, add_invoice, add_partner, test_db};
+    use crate::database::{AllocationRequest, PaymentRequest};
+    use rusqlite::params;
+
+    #[test]
+    fn voiding_a_paid_invoice_releases_its_payment() {
+        let db = test_db();
+        add_partner(&db, "p1", 20.0);
+        add_client(&db, "c1", "p1", 20.0, "ZAR");
+        add_invoice(&db, "i1", "c1", "p1", "2026-09", 1000.0, 20.0);
+        db.set_var_invoice_status("i1", "invoiced", None, None).unwrap();
+        let payment = db
+            .record_payment(PaymentRequest {
+                var_client_id: "c1".to_string(),
+                payment_date: "2026-09-10".to_string(),
+                amount: 1000.0,
+                currency: "ZAR".to_string(),
+                method: "eft".to_string(),
+                reference: None,
+                notes: None,
+                allocations: vec![AllocationRequest { invoice_id: "i1".to_string(), amount: 1000.0 }],
+            })
+            .unwrap();
+        assert_eq!(payment.unapplied_amount, 0.0);
+        assert_eq!(db.get_var_invoice_status_history("i1").unwrap().last().unwrap().to_status, "paid");
+
+        db.set_var_invoice_status("i1", "void", None, None).unwrap();
+        let payment = db.get_payments(Some("c1")).unwrap().remove(0);
+        assert!(payment.allocations.is_empty());
+        assert_eq!(payment.unapplied_amount, 1000.0);
+        let aging = db.get_ar_aging("2026-09-30").unwrap();
+        assert_eq!(aging.clients[0].buckets.total, 0.0);
+        assert_eq!(aging.clients[0].unapplied_credit, 1000.0);
+    }
+
+    #[test]
+    fn unknown_legacy_statuses_are_left_in_place() {
+        let db = test_db();
+        add_partner(&db, "p1", 20.0);
+        add_client(&db, "c1", "p1", 20.0, "ZAR");
+        add_invoice(&db, "i1", "c1", "p1", "2026-08", 1000.0, 20.0);
+        add_invoice(&db, "i2", "c1", "p1", "2026-09", 1000.0, 20.0);
+
+        let conn = db.conn.lock().unwrap();
+        conn.execute("UPDATE var_client_invoices SET invoice_status = ' Sent' WHERE id = 'i1'", [])
+            .unwrap();
+        conn.execute("UPDATE var_client_invoices SET invoice_status = 'on hold' WHERE id = 'i2'", [])
+            .unwrap();
+        create_tables(&conn).unwrap();
+        let status = |id: &str| -> String {
+            conn.query_row(
+                "SELECT invoice_status FROM var_client_invoices WHERE id = ?1",
+                params![id],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+        assert_eq!(status("i1"), "invoiced");
+        assert_eq!(status("i2"), "on hold");
+    }
+}
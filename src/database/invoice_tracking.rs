@@ -0,0 +1,204 @@
+use super::{current_timestamp, Database};
+use rusqlite::{params, Connection, Result};
+use serde::{Deserialize, Serialize};
+
+/// Whether a VAR client has been invoiced for one billing month, when, by whom
+/// and with which invoice.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VarInvoiceMonthTracking {
+    pub var_client_id: String,
+    pub client_name: String,
+    pub var_partner_id: String,
+    pub partner_name: String,
+    pub billing_month: String,
+    pub is_invoiced: bool,
+    pub invoiced_date: Option<String>,
+    pub invoiced_by: Option<String>,
+    pub invoice_id: Option<String>,
+}
+
+/// An active VAR client not yet invoiced for a month, with any draft or
+/// pending invoice that already exists for it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UninvoicedVarClient {
+    pub var_client_id: String,
+    pub client_name: String,
+    pub debt_code: Option<String>,
+    pub var_partner_id: String,
+    pub partner_name: String,
+    pub invoice_id: Option<String>,
+    pub invoice_status: Option<String>,
+}
+
+pub(super) fn create_tables(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS var_invoice_month_tracking (
+            var_client_id TEXT NOT NULL,
+            billing_month TEXT NOT NULL,
+            is_invoiced INTEGER NOT NULL DEFAULT 0,
+            invoiced_date TEXT,
+            invoiced_by TEXT,
+            invoice_id TEXT,
+            updated_at TEXT NOT NULL,
+            PRIMARY KEY (var_client_id, billing_month),
+            FOREIGN KEY (var_client_id) REFERENCES var_clients (id),
+            FOREIGN KEY (invoice_id) REFERENCES var_client_invoices (id)
+        )",
+        [],
+    )?;
+
+    // Invoices already issued before per-month tracking existed count as invoiced.
+    conn.execute(
+        "INSERT OR IGNORE INTO var_invoice_month_tracking
+         (var_client_id, billing_month, is_invoiced, invoiced_date, invoice_id, updated_at)
+         SELECT var_client_id, billing_month, 1, invoice_date, id, updated_at
+         FROM var_client_invoices
+         WHERE invoice_status IN ('invoiced', 'paid')",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// Records whether a client was invoiced for `billing_month`. Marking a month
+/// invoiced without a date uses today; unmarking clears the date, user and invoice.
+pub(super) fn set_month_invoiced(
+    conn: &Connection,
+    var_client_id: &str,
+    billing_month: &str,
+    is_invoiced: bool,
+    invoiced_by: Option<&str>,
+    invoice_id: Option<&str>,
+) -> Result<()> {
+    let updated_at = current_timestamp(conn)?;
+    conn.execute(
+        "INSERT INTO var_invoice_month_tracking
+         (var_client_id, billing_month, is_invoiced, invoiced_date, invoiced_by, invoice_id, updated_at)
+         VALUES (?1, ?2, ?3,
+                 CASE WHEN ?3 = 1 THEN COALESCE(
+                     (SELECT invoice_date FROM var_client_invoices WHERE id = ?5), date('now')) END,
+                 CASE WHEN ?3 = 1 THEN ?4 END,
+                 CASE WHEN ?3 = 1 THEN ?5 END,
+                 ?6)
+         ON CONFLICT(var_client_id, billing_month) DO UPDATE SET
+             is_invoiced = excluded.is_invoiced,
+             invoiced_date = excluded.invoiced_date,
+             invoiced_by = CASE WHEN excluded.is_invoiced = 1
+                 THEN COALESCE(excluded.invoiced_by, invoiced_by) END,
+             invoice_id = CASE WHEN excluded.is_invoiced = 1
+                 THEN COALESCE(excluded.invoice_id, invoice_id) END,
+             updated_at = excluded.updated_at",
+        params![
+            var_client_id,
+            billing_month,
+            if is_invoiced { 1 } else { 0 },
+            invoiced_by,
+            invoice_id,
+            updated_at
+        ],
+    )?;
+    Ok(())
+}
+
+/// Keeps month tracking in step with an invoice's status: issuing it marks
+/// the month invoiced by `invoiced_by`, voiding the linked invoice unmarks it.
+pub(super) fn sync_invoice_tracking(
+    conn: &Connection,
+    invoice_id: &str,
+    status: &str,
+    invoiced_by: Option<&str>,
+) -> Result<()> {
+    let (var_client_id, billing_month): (String, String) = conn.query_row(
+        "SELECT var_client_id, billing_month FROM var_client_invoices WHERE id = ?1",
+        params![invoice_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    match status {
+        "invoiced" => set_month_invoiced(
+            conn,
+            &var_client_id,
+            &billing_month,
+            true,
+            invoiced_by,
+            Some(invoice_id),
+        ),
+        "void" | "cancelled" => {
+            conn.execute(
+                "UPDATE var_invoice_month_tracking
+                 SET is_invoiced = 0, invoiced_date = NULL, invoiced_by = NULL, invoice_id = NULL,
+                     updated_at = ?2
+                 WHERE invoice_id = ?1",
+                params![invoice_id, current_timestamp(conn)?],
+            )?;
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+impl Database {
+    /// Tracking for every active VAR client in `billing_month`, including
+    /// clients with no tracking row yet (reported as not invoiced).
+    pub fn get_var_invoice_month_tracking(&self, billing_month: &str) -> Result<Vec<VarInvoiceMonthTracking>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT c.id, c.client_name, c.var_partner_id, COALESCE(p.name, ''), ?1,
+                    COALESCE(t.is_invoiced, 0), t.invoiced_date, t.invoiced_by, t.invoice_id
+             FROM var_clients c
+             LEFT JOIN var_partners p ON p.id = c.var_partner_id
+             LEFT JOIN var_invoice_month_tracking t ON t.var_client_id = c.id AND t.billing_month = ?1
+             WHERE c.is_active = 1 OR t.is_invoiced = 1
+             ORDER BY p.name, c.client_name",
+        )?;
+
+        let tracking = stmt.query_map(params![billing_month], |row| {
+            Ok(VarInvoiceMonthTracking {
+                var_client_id: row.get(0)?,
+                client_name: row.get(1)?,
+                var_partner_id: row.get(2)?,
+                partner_name: row.get(3)?,
+                billing_month: row.get(4)?,
+                is_invoiced: row.get::<_, i32>(5)? == 1,
+                invoiced_date: row.get(6)?,
+                invoiced_by: row.get(7)?,
+                invoice_id: row.get(8)?,
+            })
+        })?;
+        tracking.collect()
+    }
+
+    /// Active VAR clients whose deal had started by `billing_month` and that
+    /// are not marked invoiced for it.
+    pub fn get_uninvoiced_var_clients(&self, billing_month: &str) -> Result<Vec<UninvoicedVarClient>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT c.id, c.client_name, c.debt_code, c.var_partner_id, COALESCE(p.name, ''),
+                    i.id, i.invoice_status
+             FROM var_clients c
+             LEFT JOIN var_partners p ON p.id = c.var_partner_id
+             LEFT JOIN var_client_invoices i
+                    ON i.var_client_id = c.id AND i.billing_month = ?1
+                   AND i.invoice_status NOT IN ('void', 'cancelled')
+             WHERE c.is_active = 1
+               AND substr(c.deal_start_date, 1, 7) <= ?1
+               AND NOT EXISTS (
+                   SELECT 1 FROM var_invoice_month_tracking t
+                   WHERE t.var_client_id = c.id AND t.billing_month = ?1 AND t.is_invoiced = 1
+               )
+             ORDER BY p.name, c.client_name",
+        )?;
+
+        let clients = stmt.query_map(params![billing_month], |row| {
+            Ok(UninvoicedVarClient {
+                var_client_id: row.get(0)?,
+                client_name: row.get(1)?,
+                debt_code: row.get(2)?,
+                var_partner_id: row.get(3)?,
+                partner_name: row.get(4)?,
+                invoice_id: row.get(5)?,
+                invoice_status: row.get(6)?,
+            })
+        })?;
+        clients.collect()
+    }
+}
@@ -0,0 +1,351 @@
+use super::exchange_rates::{convert, reporting_currency};
+use super::{rule_violation, Database};
+use crate::currency::round_amount;
+use rusqlite::{params, Result};
+use serde::{Deserialize, Serialize};
+
+/// Performance figures for one partner or one region. Money is in the
+/// reporting currency; growth and commission share are percentages and are
+/// empty when there is nothing to compare against.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PerformanceFigures {
+    pub revenue: f64,
+    pub prior_revenue: f64,
+    pub revenue_growth_percent: Option<f64>,
+    pub active_clients: i64,
+    pub users: i64,
+    pub new_clients: i64,
+    pub churned_clients: i64,
+    pub commission_cost: f64,
+    pub commission_share_percent: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PartnerPerformance {
+    pub rank: usize,
+    pub var_partner_id: String,
+    pub name: String,
+    pub region: String,
+    pub is_active: bool,
+    #[serde(flatten)]
+    pub figures: PerformanceFigures,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RegionPerformance {
+    pub rank: usize,
+    pub region: String,
+    pub partner_count: i64,
+    #[serde(flatten)]
+    pub figures: PerformanceFigures,
+}
+
+/// Partners and regions ranked by revenue over an inclusive range of billing
+/// months, compared with the same number of months just before it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PartnerPerformanceReport {
+    pub period_from: String,
+    pub period_to: String,
+    pub prior_from: String,
+    pub prior_to: String,
+    pub currency: String,
+    pub partners: Vec<PartnerPerformance>,
+    pub regions: Vec<RegionPerformance>,
+    pub totals: PerformanceFigures,
+    pub has_missing_rates: bool,
+}
+
+impl PerformanceFigures {
+    fn empty() -> Self {
+        PerformanceFigures {
+            revenue: 0.0,
+            prior_revenue: 0.0,
+            revenue_growth_percent: None,
+            active_clients: 0,
+            users: 0,
+            new_clients: 0,
+            churned_clients: 0,
+            commission_cost: 0.0,
+            commission_share_percent: None,
+        }
+    }
+
+    fn add(&mut self, other: &PerformanceFigures) {
+        self.revenue += other.revenue;
+        self.prior_revenue += other.prior_revenue;
+        self.active_clients += other.active_clients;
+        self.users += other.users;
+        self.new_clients += other.new_clients;
+        self.churned_clients += other.churned_clients;
+        self.commission_cost += other.commission_cost;
+    }
+
+    /// Rounds the totals to `currency` and works out the percentages from them.
+    fn finish(&mut self, currency: &str) {
+        self.revenue = round_amount(self.revenue, currency);
+        self.prior_revenue = round_amount(self.prior_revenue, currency);
+        self.commission_cost = round_amount(self.commission_cost, currency);
+        self.revenue_growth_percent = (self.prior_revenue != 0.0)
+            .then(|| round_percent((self.revenue - self.prior_revenue) / self.prior_revenue.abs() * 100.0));
+        self.commission_share_percent =
+            (self.revenue != 0.0).then(|| round_percent(self.commission_cost / self.revenue * 100.0));
+    }
+}
+
+fn round_percent(percent: f64) -> f64 {
+    (percent * 100.0).round() / 100.0
+}
+
+/// Parses YYYY-MM into a count of months since year 0.
+pub(super) fn month_index(month: &str) -> Option<i32> {
+    let (year, month) = month.trim().split_once('-')?;
+    let (year, month) = (year.parse::<i32>().ok()?, month.parse::<i32>().ok()?);
+    (year >= 1000 && (1..=12).contains(&month)).then(|| year * 12 + month - 1)
+}
+
+fn month_label(index: i32) -> String {
+    format!("{:04}-{:02}", index / 12, index % 12 + 1)
+}
+
+fn ranked<T>(items: &mut [T], revenue: impl Fn(&T) -> f64, set_rank: impl Fn(&mut T, usize)) {
+    items.sort_by(|a, b| revenue(b).total_cmp(&revenue(a)));
+    for (index, item) in items.iter_mut().enumerate() {
+        set_rank(item, index + 1);
+    }
+}
+
+impl Database {
+    /// Ranks partners, and the regions they belong to, over the billing
+    /// months `period_from` to `period_to` (YYYY-MM, inclusive). Revenue comes
+    /// from live invoices and commission cost from each partner's commission
+    /// lines, so distributors carry their share; both are converted at the
+    /// invoice date. Client counts follow deal start and deactivation dates.
+    pub fn get_partner_performance(
+        &self,
+        period_from: &str,
+        period_to: &str,
+        region: Option<&str>,
+    ) -> Result<PartnerPerformanceReport> {
+        let (Some(from), Some(to)) = (month_index(period_from), month_index(period_to)) else {
+            return Err(rule_violation("Periods are billing months in the form YYYY-MM"));
+        };
+        if from > to {
+            return Err(rule_violation("The period ends before it starts"));
+        }
+        let (prior_from, prior_to) = (month_label(from - (to - from + 1)), month_label(from - 1));
+        let (period_from, period_to) = (month_label(from), month_label(to));
+
+        let conn = self.conn.lock().unwrap();
+        let currency = reporting_currency(&conn)?;
+
+        let mut stmt = conn.prepare(
+            "SELECT p.id, p.name, p.region, p.is_active,
+                    COUNT(c.id) FILTER (WHERE substr(c.deal_start_date, 1, 7) <= ?2
+                        AND (c.deactivated_at IS NULL AND c.is_active = 1 OR substr(c.deactivated_at, 1, 7) > ?2)),
+                    COALESCE(SUM(c.users) FILTER (WHERE substr(c.deal_start_date, 1, 7) <= ?2
+                        AND (c.deactivated_at IS NULL AND c.is_active = 1 OR substr(c.deactivated_at, 1, 7) > ?2)), 0),
+                    COUNT(c.id) FILTER (WHERE substr(c.deal_start_date, 1, 7) BETWEEN ?1 AND ?2),
+                    COUNT(c.id) FILTER (WHERE substr(c.deactivated_at, 1, 7) BETWEEN ?1 AND ?2)
+             FROM var_partners p
+             LEFT JOIN var_clients c ON c.var_partner_id = p.id
+             WHERE ?3 IS NULL OR p.region = ?3
+             GROUP BY p.id
+             ORDER BY p.name",
+        )?;
+        let mut partners = stmt
+            .query_map(params![period_from, period_to, region], |row| {
+                Ok(PartnerPerformance {
+                    rank: 0,
+                    var_partner_id: row.get(0)?,
+                    name: row.get(1)?,
+                    region: row.get(2)?,
+                    is_active: row.get::<_, i32>(3)? == 1,
+                    figures: PerformanceFigures {
+                        active_clients: row.get(4)?,
+                        users: row.get(5)?,
+                        new_clients: row.get(6)?,
+                        churned_clients: row.get(7)?,
+                        ..PerformanceFigures::empty()
+                    },
+                })
+            })?
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut stmt = conn.prepare(
+            "SELECT i.var_partner_id, i.billing_month >= ?2, i.client_revenue, c.currency,
+                    COALESCE(i.invoice_date, i.billing_month || '-01')
+             FROM var_client_invoices i
+             JOIN var_clients c ON c.id = i.var_client_id
+             WHERE i.billing_month BETWEEN ?1 AND ?3 AND i.invoice_status NOT IN ('void', 'cancelled')",
+        )?;
+        let invoices = stmt
+            .query_map(params![prior_from, period_from, period_to], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, bool>(1)?,
+                    row.get::<_, f64>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, String>(4)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut has_missing_rates = false;
+        for (var_partner_id, in_period, revenue, invoice_currency, rate_date) in invoices {
+            let Some(partner) = partners.iter_mut().find(|p| p.var_partner_id == var_partner_id) else {
+                continue;
+            };
+            let revenue = convert(&conn, revenue, &invoice_currency, &currency, &rate_date)?;
+            has_missing_rates |= revenue.missing_rate;
+            let revenue = revenue.converted_amount.unwrap_or(0.0);
+            if in_period {
+                partner.figures.revenue += revenue;
+            } else {
+                partner.figures.prior_revenue += revenue;
+            }
+        }
+
+        let mut stmt = conn.prepare(
+            "SELECT l.var_partner_id, SUM(l.commission_amount), c.currency,
+                    COALESCE(i.invoice_date, i.billing_month || '-01') AS rate_date
+             FROM commission_lines l
+             JOIN var_client_invoices i ON i.id = l.invoice_id
+             JOIN var_clients c ON c.id = i.var_client_id
+             WHERE i.billing_month BETWEEN ?1 AND ?2 AND i.invoice_status NOT IN ('void', 'cancelled')
+             GROUP BY l.var_partner_id, c.currency, rate_date",
+        )?;
+        let commissions = stmt
+            .query_map(params![period_from, period_to], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, f64>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>>>()?;
+
+        for (var_partner_id, commission, invoice_currency, rate_date) in commissions {
+            let Some(partner) = partners.iter_mut().find(|p| p.var_partner_id == var_partner_id) else {
+                continue;
+            };
+            let commission = convert(&conn, commission, &invoice_currency, &currency, &rate_date)?;
+            has_missing_rates |= commission.missing_rate;
+            partner.figures.commission_cost += commission.converted_amount.unwrap_or(0.0);
+        }
+
+        let mut regions: Vec<RegionPerformance> = Vec::new();
+        let mut totals = PerformanceFigures::empty();
+        for partner in &mut partners {
+            totals.add(&partner.figures);
+            match regions.iter_mut().find(|r| r.region == partner.region) {
+                Some(region) => {
+                    region.partner_count += 1;
+                    region.figures.add(&partner.figures);
+                }
+                None => regions.push(RegionPerformance {
+                    rank: 0,
+                    region: partner.region.clone(),
+                    partner_count: 1,
+                    figures: partner.figures.clone(),
+                }),
+            }
+            partner.figures.finish(&currency);
+        }
+        for region in &mut regions {
+            region.figures.finish(&currency);
+        }
+        totals.finish(&currency);
+        ranked(&mut partners, |p| p.figures.revenue, |p, rank| p.rank = rank);
+        ranked(&mut regions, |r| r.figures.revenue, |r, rank| r.rank = rank);
+
+        Ok(PartnerPerformanceReport {
+            period_from,
+            period_to,
+            prior_from,
+            prior_to,
+            currency,
+            partners,
+            regions,
+            totals,
+            has_missing_rates,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::month_index;
+    use crate::database::test_support::{add_client, add_invoice, add_partner, test_db};
+    use crate::database::ExchangeRateRequest;
+
+    #[test]
+    fn parses_billing_months() {
+        assert_eq!(month_index("2026-01"), Some(2026 * 12));
+        assert_eq!(month_index(" 2026-12 "), Some(2026 * 12 + 11));
+        assert_eq!(month_index("2026-13"), None);
+        assert_eq!(month_index("26-01"), None);
+    }
+
+    #[test]
+    fn commission_cost_comes_from_each_partners_lines() {
+        let db = test_db();
+        add_partner(&db, "d1", 10.0);
+        add_partner(&db, "p1", 20.0);
+        add_client(&db, "c1", "p1", 20.0, "ZAR");
+        db.set_partner_parent("p1", Some("d1"), Some(25.0)).unwrap();
+        add_invoice(&db, "i1", "c1", "p1", "2026-08", 500.0, 20.0);
+        add_invoice(&db, "i2", "c1", "p1", "2026-09", 1000.0, 20.0);
+
+        let report = db.get_partner_performance("2026-09", "2026-09", None).unwrap();
+        assert_eq!(
+            (report.prior_from.as_str(), report.prior_to.as_str()),
+            ("2026-08", "2026-08")
+        );
+        assert!(!report.has_missing_rates);
+
+        let p1 = &report.partners[0];
+        assert_eq!((p1.var_partner_id.as_str(), p1.rank), ("p1", 1));
+        assert_eq!(p1.figures.revenue, 1000.0);
+        assert_eq!(p1.figures.revenue_growth_percent, Some(100.0));
+        assert_eq!(p1.figures.commission_cost, 150.0);
+        assert_eq!(p1.figures.commission_share_percent, Some(15.0));
+
+        let d1 = &report.partners[1];
+        assert_eq!(d1.var_partner_id, "d1");
+        assert_eq!(d1.figures.revenue, 0.0);
+        assert_eq!(d1.figures.commission_cost, 50.0);
+
+        assert_eq!(report.regions.len(), 1);
+        assert_eq!(report.regions[0].partner_count, 2);
+        assert_eq!(report.totals.commission_cost, 200.0);
+        assert_eq!(report.totals.commission_share_percent, Some(20.0));
+    }
+
+    #[test]
+    fn flags_amounts_without_an_exchange_rate() {
+        let db = test_db();
+        add_partner(&db, "p1", 20.0);
+        add_client(&db, "c1", "p1", 20.0, "USD");
+        add_invoice(&db, "i1", "c1", "p1", "2026-09", 1000.0, 20.0);
+
+        let report = db.get_partner_performance("2026-09", "2026-09", None).unwrap();
+        assert!(report.has_missing_rates);
+        assert_eq!(report.totals.revenue, 0.0);
+        assert_eq!(report.totals.commission_cost, 0.0);
+
+        db.add_exchange_rate(ExchangeRateRequest {
+            from_currency: "USD".to_string(),
+            to_currency: "ZAR".to_string(),
+            rate: 18.0,
+            effective_date: "2026-09".to_string(),
+            period: "monthly".to_string(),
+        })
+        .unwrap();
+        let report = db.get_partner_performance("2026-09", "2026-09", None).unwrap();
+        assert!(!report.has_missing_rates);
+        assert_eq!(report.totals.revenue, 18000.0);
+        assert_eq!(report.totals.commission_cost, 3600.0);
+        assert!(db.get_partner_performance("2026-09", "2026-08", None).is_err());
+    }
+}
@@ -0,0 +1,592 @@
+use super::exchange_rates::{convert, reporting_currency};
+use super::{add_column_if_missing, current_timestamp, generate_id, rule_violation, Database};
+use crate::currency::round_amount;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+
+/// A partner's place in the distributor hierarchy. `parent_share_percent` is
+/// the part of this partner's commission its distributor takes by default.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PartnerHierarchyNode {
+    pub var_partner_id: String,
+    pub name: String,
+    pub parent_partner_id: Option<String>,
+    pub parent_share_percent: Option<f64>,
+    pub depth: i32,
+    pub is_active: bool,
+}
+
+/// A per-client override of the distributor's share.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommissionSplit {
+    pub var_client_id: String,
+    pub parent_share_percent: f64,
+    #[serde(default)]
+    pub updated_at: String,
+}
+
+/// One partner's part of an invoice's commission. `role` is `direct` for the
+/// partner that sold the client and `distributor` for each partner above it;
+/// `share_percent` is the part of the invoice's commission the line carries.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommissionLine {
+    pub id: String,
+    pub invoice_id: String,
+    pub var_client_id: String,
+    pub client_name: String,
+    pub billing_month: String,
+    pub var_partner_id: String,
+    pub role: String,
+    pub share_percent: f64,
+    pub commission_amount: f64,
+    pub currency: String,
+}
+
+/// Commission of one partner in a distributor's tree, in the reporting currency.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RollupPartnerLine {
+    pub var_partner_id: String,
+    pub name: String,
+    pub parent_partner_id: Option<String>,
+    pub depth: i32,
+    pub client_count: i64,
+    pub client_revenue: f64,
+    pub direct_commission: f64,
+    pub distributor_commission: f64,
+    pub total_commission: f64,
+}
+
+/// A distributor's commission with that of every partner below it, over an
+/// inclusive range of billing months.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DistributorRollup {
+    pub var_partner_id: String,
+    pub name: String,
+    pub from_month: String,
+    pub to_month: String,
+    pub currency: String,
+    pub client_revenue: f64,
+    pub total_commission: f64,
+    pub partners: Vec<RollupPartnerLine>,
+    pub has_missing_rates: bool,
+}
+
+pub(super) fn create_tables(conn: &Connection) -> Result<()> {
+    add_column_if_missing(
+        conn,
+        "var_partners",
+        "parent_partner_id",
+        "TEXT REFERENCES var_partners (id)",
+    )?;
+    add_column_if_missing(conn, "var_partners", "parent_share_percent", "REAL")?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS commission_splits (
+            var_client_id TEXT PRIMARY KEY,
+            parent_share_percent REAL NOT NULL,
+            updated_at TEXT NOT NULL,
+            FOREIGN KEY (var_client_id) REFERENCES var_clients (id)
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS commission_lines (
+            id TEXT PRIMARY KEY,
+            invoice_id TEXT NOT NULL,
+            var_partner_id TEXT NOT NULL,
+            role TEXT NOT NULL,
+            share_percent REAL NOT NULL,
+            commission_amount REAL NOT NULL,
+            FOREIGN KEY (invoice_id) REFERENCES var_client_invoices (id),
+            FOREIGN KEY (var_partner_id) REFERENCES var_partners (id)
+        )",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_commission_lines_invoice ON commission_lines (invoice_id)",
+        [],
+    )?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_commission_lines_partner ON commission_lines (var_partner_id)",
+        [],
+    )?;
+
+    // Invoices from before the hierarchy existed belong wholly to their partner.
+    conn.execute(
+        "INSERT INTO commission_lines (id, invoice_id, var_partner_id, role, share_percent, commission_amount)
+         SELECT lower(hex(randomblob(16))), i.id, i.var_partner_id, 'direct', 100, i.commission_amount
+         FROM var_client_invoices i
+         WHERE NOT EXISTS (SELECT 1 FROM commission_lines l WHERE l.invoice_id = i.id)",
+        [],
+    )?;
+
+    Ok(())
+}
+
+fn check_share(share: f64) -> Result<()> {
+    if (0.0..=100.0).contains(&share) {
+        Ok(())
+    } else {
+        Err(rule_violation("A distributor share must be between 0 and 100 percent"))
+    }
+}
+
+/// A partner's share of a commission amount, rounded to the currency.
+pub(super) fn commission_share(commission: f64, share_percent: f64, currency: &str) -> f64 {
+    round_amount(commission * share_percent / 100.0, currency)
+}
+
+/// The distributors above a partner, nearest first, each with the default
+/// share it takes of the commission reaching the partner below it.
+fn distributor_chain(conn: &Connection, var_partner_id: &str) -> Result<Vec<(String, f64)>> {
+    let mut stmt = conn.prepare(
+        "WITH RECURSIVE chain (id, parent_id, share, depth) AS (
+             SELECT id, parent_partner_id, parent_share_percent, 0 FROM var_partners WHERE id = ?1
+             UNION ALL
+             SELECT p.id, p.parent_partner_id, p.parent_share_percent, c.depth + 1
+             FROM var_partners p JOIN chain c ON p.id = c.parent_id
+             WHERE c.depth < 64
+         )
+         SELECT c.parent_id, COALESCE(c.share, 0) FROM chain c
+         WHERE c.parent_id IN (SELECT id FROM var_partners)
+         ORDER BY c.depth",
+    )?;
+    let chain = stmt.query_map(params![var_partner_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    chain.collect()
+}
+
+/// Every partner below a distributor, at any depth.
+fn descendants(conn: &Connection, var_partner_id: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "WITH RECURSIVE tree (id) AS (
+             SELECT id FROM var_partners WHERE parent_partner_id = ?1
+             UNION
+             SELECT p.id FROM var_partners p JOIN tree t ON p.parent_partner_id = t.id
+         )
+         SELECT id FROM tree",
+    )?;
+    let ids = stmt.query_map(params![var_partner_id], |row| row.get(0))?;
+    ids.collect()
+}
+
+/// Rebuilds the commission lines of a partner's invoices, for one billing
+/// month or, without one, for every month. Each distributor up the chain
+/// takes its share of what reaches the partner below it, the client's split
+/// overriding the first one, and the partner keeps the rest. Issued invoices
+/// keep the lines they were issued with.
+pub(super) fn split_commissions(conn: &Connection, var_partner_id: &str, billing_month: Option<&str>) -> Result<()> {
+    conn.execute(
+        "DELETE FROM commission_lines WHERE invoice_id IN (
+             SELECT id FROM var_client_invoices
+             WHERE var_partner_id = ?1 AND (?2 IS NULL OR billing_month = ?2)
+               AND invoice_status IN ('draft', 'pending'))",
+        params![var_partner_id, billing_month],
+    )?;
+
+    let mut stmt = conn.prepare(
+        "SELECT i.id, i.commission_amount, c.currency, COALESCE(s.parent_share_percent, p.parent_share_percent, 0)
+         FROM var_client_invoices i
+         JOIN var_clients c ON c.id = i.var_client_id
+         JOIN var_partners p ON p.id = i.var_partner_id
+         LEFT JOIN commission_splits s ON s.var_client_id = i.var_client_id
+         WHERE i.var_partner_id = ?1 AND (?2 IS NULL OR i.billing_month = ?2)
+           AND i.invoice_status IN ('draft', 'pending')",
+    )?;
+    let invoices = stmt
+        .query_map(params![var_partner_id, billing_month], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, f64>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, f64>(3)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>>>()?;
+    let chain = distributor_chain(conn, var_partner_id)?;
+
+    let mut insert = conn.prepare(
+        "INSERT INTO commission_lines (id, invoice_id, var_partner_id, role, share_percent, commission_amount)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+    )?;
+    for (invoice_id, commission, currency, first_share) in invoices {
+        let (mut holder, mut role) = (var_partner_id, "direct");
+        let (mut received, mut received_percent) = (commission, 100.0);
+        for (level, (parent, default_share)) in chain.iter().enumerate() {
+            let share = if level == 0 { first_share } else { *default_share };
+            if share <= 0.0 {
+                break;
+            }
+            let passed = commission_share(received, share, &currency);
+            let passed_percent = received_percent * share / 100.0;
+            insert.execute(params![
+                generate_id(conn)?,
+                invoice_id,
+                holder,
+                role,
+                received_percent - passed_percent,
+                round_amount(received - passed, &currency)
+            ])?;
+            (holder, role) = (parent.as_str(), "distributor");
+            (received, received_percent) = (passed, passed_percent);
+        }
+        insert.execute(params![
+            generate_id(conn)?,
+            invoice_id,
+            holder,
+            role,
+            received_percent,
+            received
+        ])?;
+    }
+    Ok(())
+}
+
+fn client_partner(conn: &Connection, var_client_id: &str) -> Result<String> {
+    conn.query_row(
+        "SELECT var_partner_id FROM var_clients WHERE id = ?1",
+        params![var_client_id],
+        |row| row.get(0),
+    )
+    .optional()?
+    .ok_or_else(|| rule_violation(format!("Unknown client '{}'", var_client_id)))
+}
+
+impl Database {
+    /// Every partner with its distributor, distributors first.
+    pub fn get_partner_hierarchy(&self) -> Result<Vec<PartnerHierarchyNode>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "WITH RECURSIVE tree (id, depth, path) AS (
+                 SELECT id, 0, name FROM var_partners
+                 WHERE parent_partner_id IS NULL OR parent_partner_id NOT IN (SELECT id FROM var_partners)
+                 UNION ALL
+                 SELECT p.id, t.depth + 1, t.path || char(31) || p.name
+                 FROM var_partners p JOIN tree t ON p.parent_partner_id = t.id
+             )
+             SELECT p.id, p.name, p.parent_partner_id, p.parent_share_percent, t.depth, p.is_active
+             FROM tree t JOIN var_partners p ON p.id = t.id
+             ORDER BY t.path",
+        )?;
+        let nodes = stmt.query_map([], |row| {
+            Ok(PartnerHierarchyNode {
+                var_partner_id: row.get(0)?,
+                name: row.get(1)?,
+                parent_partner_id: row.get(2)?,
+                parent_share_percent: row.get(3)?,
+                depth: row.get(4)?,
+                is_active: row.get::<_, i32>(5)? == 1,
+            })
+        })?;
+        nodes.collect()
+    }
+
+    /// Places a partner under a distributor, or makes it independent again
+    /// when `parent_partner_id` is empty, and re-splits its commission and
+    /// that of every partner below it.
+    pub fn set_partner_parent(
+        &self,
+        var_partner_id: &str,
+        parent_partner_id: Option<&str>,
+        parent_share_percent: Option<f64>,
+    ) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let share = match parent_partner_id {
+            Some(parent) => {
+                if parent == var_partner_id {
+                    return Err(rule_violation("A partner cannot be its own distributor"));
+                }
+                // Walking up from the new parent must not lead back to this partner.
+                let creates_cycle: bool = tx.query_row(
+                    "WITH RECURSIVE ancestors (id) AS (
+                         SELECT ?1
+                         UNION
+                         SELECT p.parent_partner_id FROM var_partners p JOIN ancestors a ON p.id = a.id
+                         WHERE p.parent_partner_id IS NOT NULL
+                     )
+                     SELECT EXISTS (SELECT 1 FROM ancestors WHERE id = ?2)",
+                    params![parent, var_partner_id],
+                    |row| row.get(0),
+                )?;
+                if creates_cycle {
+                    return Err(rule_violation("That distributor already sits below this partner"));
+                }
+                let share = parent_share_percent
+                    .ok_or_else(|| rule_violation("Give the distributor's share of the commission"))?;
+                check_share(share)?;
+                Some(share)
+            }
+            None => None,
+        };
+        let updated = tx.execute(
+            "UPDATE var_partners SET parent_partner_id = ?2, parent_share_percent = ?3 WHERE id = ?1",
+            params![var_partner_id, parent_partner_id, share],
+        )?;
+        if updated == 0 {
+            return Err(rule_violation(format!("Unknown partner '{}'", var_partner_id)));
+        }
+        split_commissions(&tx, var_partner_id, None)?;
+        for descendant in descendants(&tx, var_partner_id)? {
+            split_commissions(&tx, &descendant, None)?;
+        }
+        tx.commit()
+    }
+
+    pub fn get_commission_splits(&self) -> Result<Vec<CommissionSplit>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT var_client_id, parent_share_percent, updated_at FROM commission_splits ORDER BY var_client_id",
+        )?;
+        let splits = stmt.query_map([], |row| {
+            Ok(CommissionSplit {
+                var_client_id: row.get(0)?,
+                parent_share_percent: row.get(1)?,
+                updated_at: row.get(2)?,
+            })
+        })?;
+        splits.collect()
+    }
+
+    pub fn set_commission_split(&self, split: CommissionSplit) -> Result<()> {
+        check_share(split.parent_share_percent)?;
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let var_partner_id = client_partner(&tx, &split.var_client_id)?;
+        tx.execute(
+            "INSERT INTO commission_splits (var_client_id, parent_share_percent, updated_at)
+             VALUES (?1, ?2, ?3)
+             ON CONFLICT (var_client_id) DO UPDATE SET
+                parent_share_percent = excluded.parent_share_percent,
+                updated_at = excluded.updated_at",
+            params![split.var_client_id, split.parent_share_percent, current_timestamp(&tx)?],
+        )?;
+        split_commissions(&tx, &var_partner_id, None)?;
+        tx.commit()
+    }
+
+    pub fn delete_commission_split(&self, var_client_id: &str) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let var_partner_id = client_partner(&tx, var_client_id)?;
+        tx.execute(
+            "DELETE FROM commission_splits WHERE var_client_id = ?1",
+            params![var_client_id],
+        )?;
+        split_commissions(&tx, &var_partner_id, None)?;
+        tx.commit()
+    }
+
+    /// Commission lines on live invoices, optionally for one partner or month.
+    pub fn get_commission_lines(
+        &self,
+        var_partner_id: Option<&str>,
+        billing_month: Option<&str>,
+    ) -> Result<Vec<CommissionLine>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT l.id, l.invoice_id, i.var_client_id, c.client_name, i.billing_month, l.var_partner_id,
+                    l.role, l.share_percent, l.commission_amount, c.currency
+             FROM commission_lines l
+             JOIN var_client_invoices i ON i.id = l.invoice_id
+             JOIN var_clients c ON c.id = i.var_client_id
+             WHERE i.invoice_status NOT IN ('void', 'cancelled')
+               AND (?1 IS NULL OR l.var_partner_id = ?1)
+               AND (?2 IS NULL OR i.billing_month = ?2)
+             ORDER BY i.billing_month DESC, c.client_name, l.role DESC",
+        )?;
+        let lines = stmt.query_map(params![var_partner_id, billing_month], |row| {
+            Ok(CommissionLine {
+                id: row.get(0)?,
+                invoice_id: row.get(1)?,
+                var_client_id: row.get(2)?,
+                client_name: row.get(3)?,
+                billing_month: row.get(4)?,
+                var_partner_id: row.get(5)?,
+                role: row.get(6)?,
+                share_percent: row.get(7)?,
+                commission_amount: row.get(8)?,
+                currency: row.get(9)?,
+            })
+        })?;
+        lines.collect()
+    }
+
+    /// Rolls up commission for a distributor and every partner below it over
+    /// an inclusive range of billing months (YYYY-MM), converted to the
+    /// reporting currency at each invoice date.
+    pub fn get_distributor_rollup(
+        &self,
+        var_partner_id: &str,
+        from_month: &str,
+        to_month: &str,
+    ) -> Result<DistributorRollup> {
+        if from_month > to_month {
+            return Err(rule_violation("The rollup period ends before it starts"));
+        }
+        let conn = self.conn.lock().unwrap();
+        let currency = reporting_currency(&conn)?;
+
+        let mut stmt = conn.prepare(
+            "WITH RECURSIVE tree (id, depth, path) AS (
+                 SELECT id, 0, name FROM var_partners WHERE id = ?1
+                 UNION ALL
+                 SELECT p.id, t.depth + 1, t.path || char(31) || p.name
+                 FROM var_partners p JOIN tree t ON p.parent_partner_id = t.id
+             )
+             SELECT p.id, p.name, p.parent_partner_id, t.depth,
+                    (SELECT COUNT(DISTINCT var_client_id) FROM var_client_invoices
+                     WHERE var_partner_id = p.id AND billing_month BETWEEN ?2 AND ?3
+                       AND invoice_status NOT IN ('void', 'cancelled'))
+             FROM tree t JOIN var_partners p ON p.id = t.id
+             ORDER BY t.path",
+        )?;
+        let mut partners = stmt
+            .query_map(params![var_partner_id, from_month, to_month], |row| {
+                Ok(RollupPartnerLine {
+                    var_partner_id: row.get(0)?,
+                    name: row.get(1)?,
+                    parent_partner_id: row.get(2)?,
+                    depth: row.get(3)?,
+                    client_count: row.get(4)?,
+                    client_revenue: 0.0,
+                    direct_commission: 0.0,
+                    distributor_commission: 0.0,
+                    total_commission: 0.0,
+                })
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        let Some(name) = partners.first().map(|p| p.name.clone()) else {
+            return Err(rule_violation(format!("Unknown partner '{}'", var_partner_id)));
+        };
+
+        let mut stmt = conn.prepare(
+            "SELECT l.var_partner_id, l.role, l.commission_amount, i.client_revenue, c.currency,
+                    COALESCE(i.invoice_date, i.billing_month || '-01')
+             FROM commission_lines l
+             JOIN var_client_invoices i ON i.id = l.invoice_id
+             JOIN var_clients c ON c.id = i.var_client_id
+             WHERE i.billing_month BETWEEN ?1 AND ?2 AND i.invoice_status NOT IN ('void', 'cancelled')",
+        )?;
+        let lines = stmt
+            .query_map(params![from_month, to_month], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, f64>(2)?,
+                    row.get::<_, f64>(3)?,
+                    row.get::<_, String>(4)?,
+                    row.get::<_, String>(5)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut has_missing_rates = false;
+        for (line_partner, role, commission, revenue, line_currency, rate_date) in lines {
+            let Some(partner) = partners.iter_mut().find(|p| p.var_partner_id == line_partner) else {
+                continue;
+            };
+            let commission = convert(&conn, commission, &line_currency, &currency, &rate_date)?;
+            has_missing_rates |= commission.missing_rate;
+            let commission = commission.converted_amount.unwrap_or(0.0);
+            if role == "direct" {
+                let revenue = convert(&conn, revenue, &line_currency, &currency, &rate_date)?;
+                partner.client_revenue += revenue.converted_amount.unwrap_or(0.0);
+                partner.direct_commission += commission;
+            } else {
+                partner.distributor_commission += commission;
+            }
+        }
+
+        for partner in &mut partners {
+            partner.client_revenue = round_amount(partner.client_revenue, &currency);
+            partner.direct_commission = round_amount(partner.direct_commission, &currency);
+            partner.distributor_commission = round_amount(partner.distributor_commission, &currency);
+            partner.total_commission =
+                round_amount(partner.direct_commission + partner.distributor_commission, &currency);
+        }
+        Ok(DistributorRollup {
+            var_partner_id: var_partner_id.to_string(),
+            name,
+            from_month: from_month.to_string(),
+            to_month: to_month.to_string(),
+            client_revenue: round_amount(partners.iter().map(|p| p.client_revenue).sum(), &currency),
+            total_commission: round_amount(partners.iter().map(|p| p.total_commission).sum(), &currency),
+            currency,
+            partners,
+            has_missing_rates,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CommissionSplit;
+    use crate::database::test_support::{add_client, add_invoice, add_partner, test_db};
+    use crate::database::Database;
+
+    fn lines(db: &Database, billing_month: &str) -> Vec<(String, String, f64, f64)> {
+        let mut lines: Vec<_> = db
+            .get_commission_lines(None, Some(billing_month))
+            .unwrap()
+            .into_iter()
+            .map(|l| (l.var_partner_id, l.role, l.share_percent, l.commission_amount))
+            .collect();
+        lines.sort_by(|a, b| a.0.cmp(&b.0));
+        lines
+    }
+
+    fn line(partner: &str, role: &str, share: f64, amount: f64) -> (String, String, f64, f64) {
+        (partner.to_string(), role.to_string(), share, amount)
+    }
+
+    #[test]
+    fn distributor_takes_its_share_and_client_splits_override_it() {
+        let db = test_db();
+        add_partner(&db, "d1", 10.0);
+        add_partner(&db, "p1", 20.0);
+        add_client(&db, "c1", "p1", 20.0, "ZAR");
+        add_client(&db, "c2", "p1", 20.0, "ZAR");
+        add_invoice(&db, "i1", "c1", "p1", "2026-09", 1000.0, 20.0);
+        assert_eq!(lines(&db, "2026-09"), vec![line("p1", "direct", 100.0, 200.0)]);
+
+        db.set_partner_parent("p1", Some("d1"), Some(25.0)).unwrap();
+        assert_eq!(
+            lines(&db, "2026-09"),
+            vec![line("d1", "distributor", 25.0, 50.0), line("p1", "direct", 75.0, 150.0)]
+        );
+
+        db.set_commission_split(CommissionSplit {
+            var_client_id: "c2".to_string(),
+            parent_share_percent: 0.0,
+            updated_at: String::new(),
+        })
+        .unwrap();
+        add_invoice(&db, "i2", "c2", "p1", "2026-10", 333.33, 20.0);
+        assert_eq!(lines(&db, "2026-10"), vec![line("p1", "direct", 100.0, 66.67)]);
+    }
+
+    #[test]
+    fn every_distributor_up_the_chain_gets_a_line() {
+        let db = test_db();
+        add_partner(&db, "d2", 5.0);
+        add_partner(&db, "d1", 10.0);
+        add_partner(&db, "p1", 20.0);
+        add_client(&db, "c1", "p1", 20.0, "ZAR");
+        db.set_partner_parent("p1", Some("d1"), Some(30.0)).unwrap();
+        add_invoice(&db, "i1", "c1", "p1", "2026-09", 1000.0, 20.0);
+
+        // Placing d1 under d2 re-splits the invoices of the partners below d1.
+        db.set_partner_parent("d1", Some("d2"), Some(10.0)).unwrap();
+        let lines = lines(&db, "2026-09");
+        assert_eq!(
+            lines,
+            vec![
+                line("d1", "distributor", 27.0, 54.0),
+                line("d2", "distributor", 3.0, 6.0),
+                line("p1", "direct", 70.0, 140.0),
+            ]
+        );
+        assert_eq!(lines.iter().map(|l| l.3).sum::<f64>(), 200.0);
+    }
+}
@@ -0,0 +1,567 @@
+use super::exchange_rates::{convert, reporting_currency};
+use super::partner_hierarchy::commission_share;
+use super::{
+    add_column_if_missing, checked_currency, current_timestamp, generate_id, next_document_number, rule_violation,
+    Database,
+};
+use crate::currency::round_amount;
+use rusqlite::{params, Connection, Result, Row};
+use serde::{Deserialize, Serialize};
+
+/// Commission paid out to a partner.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommissionPayout {
+    pub id: String,
+    pub payout_number: String,
+    pub var_partner_id: String,
+    pub payout_date: String,
+    pub amount: f64,
+    pub currency: String,
+    pub reference: Option<String>,
+    pub notes: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommissionPayoutRequest {
+    pub var_partner_id: String,
+    pub payout_date: String,
+    pub amount: f64,
+    pub currency: String,
+    pub reference: Option<String>,
+    pub notes: Option<String>,
+}
+
+/// A change to what a partner is owed. Positive amounts add commission,
+/// negative amounts recover it. `source` is `manual`, `clawback` or
+/// `clawback_reversal`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommissionAdjustment {
+    pub id: String,
+    pub var_partner_id: String,
+    pub var_client_id: Option<String>,
+    pub adjustment_date: String,
+    pub amount: f64,
+    pub currency: String,
+    pub reason: String,
+    pub source: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommissionAdjustmentRequest {
+    pub var_partner_id: String,
+    pub var_client_id: Option<String>,
+    pub adjustment_date: String,
+    pub amount: f64,
+    pub currency: String,
+    pub reason: String,
+}
+
+/// Commission earned on one client's invoices within the statement period,
+/// in the client's currency and in the statement currency.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StatementClientLine {
+    pub var_client_id: String,
+    pub client_name: String,
+    pub debt_code: Option<String>,
+    pub currency: String,
+    pub invoice_count: i64,
+    pub users: i32,
+    pub client_revenue: f64,
+    pub commission_amount: f64,
+    pub statement_amount: f64,
+}
+
+/// An adjustment or payout line on a statement.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StatementEntry {
+    pub date: String,
+    pub reference: String,
+    pub description: String,
+    pub currency: String,
+    pub amount: f64,
+    pub statement_amount: f64,
+}
+
+/// What a partner is owed over a period, in the reporting currency. Amounts
+/// without an exchange rate are left out of the totals and flagged with
+/// `has_missing_rates`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PartnerStatement {
+    pub var_partner_id: String,
+    pub partner_name: String,
+    pub region: String,
+    pub contact_person: String,
+    pub email: String,
+    pub period_from: String,
+    pub period_to: String,
+    pub currency: String,
+    pub opening_balance: f64,
+    pub commissions_earned: f64,
+    pub adjustments: f64,
+    pub payments_made: f64,
+    pub closing_balance: f64,
+    pub client_lines: Vec<StatementClientLine>,
+    pub adjustment_lines: Vec<StatementEntry>,
+    pub payment_lines: Vec<StatementEntry>,
+    pub has_missing_rates: bool,
+}
+
+pub(super) fn create_tables(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS commission_payouts (
+            id TEXT PRIMARY KEY,
+            payout_number TEXT NOT NULL UNIQUE,
+            var_partner_id TEXT NOT NULL,
+            payout_date TEXT NOT NULL,
+            amount REAL NOT NULL,
+            currency TEXT NOT NULL,
+            reference TEXT,
+            notes TEXT,
+            created_at TEXT NOT NULL,
+            FOREIGN KEY (var_partner_id) REFERENCES var_partners (id)
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS commission_adjustments (
+            id TEXT PRIMARY KEY,
+            var_partner_id TEXT NOT NULL,
+            var_client_id TEXT,
+            adjustment_date TEXT NOT NULL,
+            amount REAL NOT NULL,
+            currency TEXT NOT NULL,
+            reason TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            FOREIGN KEY (var_partner_id) REFERENCES var_partners (id),
+            FOREIGN KEY (var_client_id) REFERENCES var_clients (id)
+        )",
+        [],
+    )?;
+    add_column_if_missing(
+        conn,
+        "commission_adjustments",
+        "source",
+        "TEXT NOT NULL DEFAULT 'manual'",
+    )?;
+
+    Ok(())
+}
+
+fn payout_from_row(row: &Row) -> Result<CommissionPayout> {
+    Ok(CommissionPayout {
+        id: row.get(0)?,
+        payout_number: row.get(1)?,
+        var_partner_id: row.get(2)?,
+        payout_date: row.get(3)?,
+        amount: row.get(4)?,
+        currency: row.get(5)?,
+        reference: row.get(6)?,
+        notes: row.get(7)?,
+        created_at: row.get(8)?,
+    })
+}
+
+fn adjustment_from_row(row: &Row) -> Result<CommissionAdjustment> {
+    Ok(CommissionAdjustment {
+        id: row.get(0)?,
+        var_partner_id: row.get(1)?,
+        var_client_id: row.get(2)?,
+        adjustment_date: row.get(3)?,
+        amount: row.get(4)?,
+        currency: row.get(5)?,
+        reason: row.get(6)?,
+        source: row.get(7)?,
+        created_at: row.get(8)?,
+    })
+}
+
+pub(super) fn insert_adjustment(conn: &Connection, adjustment: &CommissionAdjustment) -> Result<()> {
+    conn.execute(
+        "INSERT INTO commission_adjustments
+         (id, var_partner_id, var_client_id, adjustment_date, amount, currency, reason, source, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            adjustment.id,
+            adjustment.var_partner_id,
+            adjustment.var_client_id,
+            adjustment.adjustment_date,
+            adjustment.amount,
+            adjustment.currency,
+            adjustment.reason,
+            adjustment.source,
+            adjustment.created_at
+        ],
+    )?;
+    Ok(())
+}
+
+/// One dated movement on a partner's commission account, in its own currency.
+struct Movement {
+    date: String,
+    rate_date: String,
+    currency: String,
+    amount: f64,
+    detail: MovementDetail,
+}
+
+enum MovementDetail {
+    Invoice {
+        var_client_id: String,
+        client_name: String,
+        debt_code: Option<String>,
+        users: i32,
+        client_revenue: f64,
+    },
+    Adjustment {
+        reference: String,
+        description: String,
+    },
+    Payout {
+        reference: String,
+        description: String,
+    },
+}
+
+fn movements(conn: &Connection, var_partner_id: &str, period_to: &str) -> Result<Vec<Movement>> {
+    let mut movements = Vec::new();
+
+    let mut stmt = conn.prepare(
+        "SELECT i.billing_month || '-01', COALESCE(i.invoice_date, i.billing_month || '-01'), c.currency,
+                l.commission_amount, i.var_client_id, c.client_name, c.debt_code, i.users, i.client_revenue
+         FROM commission_lines l
+         JOIN var_client_invoices i ON i.id = l.invoice_id
+         JOIN var_clients c ON c.id = i.var_client_id
+         WHERE l.var_partner_id = ?1 AND i.billing_month || '-01' <= ?2
+           AND i.invoice_status NOT IN ('void', 'cancelled')",
+    )?;
+    let invoices = stmt.query_map(params![var_partner_id, period_to], |row| {
+        Ok(Movement {
+            date: row.get(0)?,
+            rate_date: row.get(1)?,
+            currency: row.get(2)?,
+            amount: row.get(3)?,
+            detail: MovementDetail::Invoice {
+                var_client_id: row.get(4)?,
+                client_name: row.get(5)?,
+                debt_code: row.get(6)?,
+                users: row.get(7)?,
+                client_revenue: row.get(8)?,
+            },
+        })
+    })?;
+    for invoice in invoices {
+        movements.push(invoice?);
+    }
+
+    // Credit and debit notes carry their share of the invoice's commission,
+    // split between partner and distributor like the invoice itself.
+    let mut stmt = conn.prepare(
+        "SELECT n.note_date, c.currency,
+                CASE WHEN n.note_type = 'credit' THEN -1 ELSE 1 END * n.commission_amount, l.share_percent,
+                n.note_number, c.client_name || ' ' || n.reason_code
+         FROM credit_notes n
+         JOIN var_client_invoices i ON i.id = n.invoice_id
+         JOIN commission_lines l ON l.invoice_id = n.invoice_id
+         JOIN var_clients c ON c.id = n.var_client_id
+         WHERE l.var_partner_id = ?1 AND n.note_date <= ?2 AND n.commission_amount <> 0
+           AND i.invoice_status NOT IN ('void', 'cancelled')",
+    )?;
+    let notes = stmt.query_map(params![var_partner_id, period_to], |row| {
+        let currency: String = row.get(1)?;
+        Ok(Movement {
+            date: row.get(0)?,
+            rate_date: row.get(0)?,
+            amount: commission_share(row.get(2)?, row.get(3)?, &currency),
+            currency,
+            detail: MovementDetail::Adjustment {
+                reference: row.get(4)?,
+                description: row.get(5)?,
+            },
+        })
+    })?;
+    for note in notes {
+        movements.push(note?);
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT adjustment_date, currency, amount, reason,
+                CASE WHEN source LIKE 'clawback%' THEN 'CLAWBACK' ELSE 'ADJ' END
+         FROM commission_adjustments
+         WHERE var_partner_id = ?1 AND adjustment_date <= ?2",
+    )?;
+    let adjustments = stmt.query_map(params![var_partner_id, period_to], |row| {
+        Ok(Movement {
+            date: row.get(0)?,
+            rate_date: row.get(0)?,
+            currency: row.get(1)?,
+            amount: row.get(2)?,
+            detail: MovementDetail::Adjustment {
+                reference: row.get(4)?,
+                description: row.get(3)?,
+            },
+        })
+    })?;
+    for adjustment in adjustments {
+        movements.push(adjustment?);
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT payout_date, currency, amount, payout_number, COALESCE(reference, '')
+         FROM commission_payouts
+         WHERE var_partner_id = ?1 AND payout_date <= ?2",
+    )?;
+    let payouts = stmt.query_map(params![var_partner_id, period_to], |row| {
+        Ok(Movement {
+            date: row.get(0)?,
+            rate_date: row.get(0)?,
+            currency: row.get(1)?,
+            amount: row.get(2)?,
+            detail: MovementDetail::Payout {
+                reference: row.get(3)?,
+                description: row.get(4)?,
+            },
+        })
+    })?;
+    for payout in payouts {
+        movements.push(payout?);
+    }
+
+    movements.sort_by(|a, b| a.date.cmp(&b.date));
+    Ok(movements)
+}
+
+fn build_statement(
+    conn: &Connection,
+    var_partner_id: &str,
+    period_from: &str,
+    period_to: &str,
+) -> Result<PartnerStatement> {
+    if period_from > period_to {
+        return Err(rule_violation("The statement period ends before it starts"));
+    }
+    let mut statement = conn.query_row(
+        "SELECT id, name, region, contact_person, email FROM var_partners WHERE id = ?1",
+        params![var_partner_id],
+        |row| {
+            Ok(PartnerStatement {
+                var_partner_id: row.get(0)?,
+                partner_name: row.get(1)?,
+                region: row.get(2)?,
+                contact_person: row.get(3)?,
+                email: row.get(4)?,
+                period_from: period_from.to_string(),
+                period_to: period_to.to_string(),
+                currency: String::new(),
+                opening_balance: 0.0,
+                commissions_earned: 0.0,
+                adjustments: 0.0,
+                payments_made: 0.0,
+                closing_balance: 0.0,
+                client_lines: Vec::new(),
+                adjustment_lines: Vec::new(),
+                payment_lines: Vec::new(),
+                has_missing_rates: false,
+            })
+        },
+    )?;
+    statement.currency = reporting_currency(conn)?;
+
+    for movement in movements(conn, var_partner_id, period_to)? {
+        let converted = convert(
+            conn,
+            movement.amount,
+            &movement.currency,
+            &statement.currency,
+            &movement.rate_date,
+        )?;
+        statement.has_missing_rates |= converted.missing_rate;
+        let statement_amount = converted.converted_amount.unwrap_or(0.0);
+        let sign = if matches!(movement.detail, MovementDetail::Payout { .. }) {
+            -1.0
+        } else {
+            1.0
+        };
+
+        if movement.date.as_str() < period_from {
+            statement.opening_balance += sign * statement_amount;
+            continue;
+        }
+
+        match movement.detail {
+            MovementDetail::Invoice {
+                var_client_id,
+                client_name,
+                debt_code,
+                users,
+                client_revenue,
+            } => {
+                statement.commissions_earned += statement_amount;
+                match statement
+                    .client_lines
+                    .iter_mut()
+                    .find(|l| l.var_client_id == var_client_id)
+                {
+                    Some(line) => {
+                        line.invoice_count += 1;
+                        line.users = line.users.max(users);
+                        line.client_revenue = round_amount(line.client_revenue + client_revenue, &line.currency);
+                        line.commission_amount = round_amount(line.commission_amount + movement.amount, &line.currency);
+                        line.statement_amount =
+                            round_amount(line.statement_amount + statement_amount, &statement.currency);
+                    }
+                    None => statement.client_lines.push(StatementClientLine {
+                        var_client_id,
+                        client_name,
+                        debt_code,
+                        currency: movement.currency,
+                        invoice_count: 1,
+                        users,
+                        client_revenue,
+                        commission_amount: movement.amount,
+                        statement_amount,
+                    }),
+                }
+            }
+            MovementDetail::Adjustment { reference, description } => {
+                statement.adjustments += statement_amount;
+                statement.adjustment_lines.push(StatementEntry {
+                    date: movement.date,
+                    reference,
+                    description,
+                    currency: movement.currency,
+                    amount: movement.amount,
+                    statement_amount,
+                });
+            }
+            MovementDetail::Payout { reference, description } => {
+                statement.payments_made += statement_amount;
+                statement.payment_lines.push(StatementEntry {
+                    date: movement.date,
+                    reference,
+                    description,
+                    currency: movement.currency,
+                    amount: movement.amount,
+                    statement_amount,
+                });
+            }
+        }
+    }
+
+    statement.client_lines.sort_by(|a, b| a.client_name.cmp(&b.client_name));
+    statement.opening_balance = round_amount(statement.opening_balance, &statement.currency);
+    statement.commissions_earned = round_amount(statement.commissions_earned, &statement.currency);
+    statement.adjustments = round_amount(statement.adjustments, &statement.currency);
+    statement.payments_made = round_amount(statement.payments_made, &statement.currency);
+    statement.closing_balance = round_amount(
+        statement.opening_balance + statement.commissions_earned + statement.adjustments - statement.payments_made,
+        &statement.currency,
+    );
+    Ok(statement)
+}
+
+impl Database {
+    pub fn record_commission_payout(&self, request: CommissionPayoutRequest) -> Result<CommissionPayout> {
+        let currency = checked_currency(&request.currency)?;
+        let amount = round_amount(request.amount, &currency);
+        if amount <= 0.0 {
+            return Err(rule_violation("Payout amount must be greater than zero"));
+        }
+
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let payout = CommissionPayout {
+            id: generate_id(&tx)?,
+            payout_number: next_document_number(&tx, "commission_payout", "CP")?,
+            var_partner_id: request.var_partner_id,
+            payout_date: request.payout_date,
+            amount,
+            currency,
+            reference: request.reference,
+            notes: request.notes,
+            created_at: current_timestamp(&tx)?,
+        };
+        tx.execute(
+            "INSERT INTO commission_payouts
+             (id, payout_number, var_partner_id, payout_date, amount, currency, reference, notes, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                payout.id,
+                payout.payout_number,
+                payout.var_partner_id,
+                payout.payout_date,
+                payout.amount,
+                payout.currency,
+                payout.reference,
+                payout.notes,
+                payout.created_at
+            ],
+        )?;
+        tx.commit()?;
+        Ok(payout)
+    }
+
+    pub fn get_commission_payouts(&self, var_partner_id: Option<&str>) -> Result<Vec<CommissionPayout>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, payout_number, var_partner_id, payout_date, amount, currency, reference, notes, created_at
+             FROM commission_payouts
+             WHERE ?1 IS NULL OR var_partner_id = ?1
+             ORDER BY payout_date DESC, payout_number DESC",
+        )?;
+        let payouts = stmt.query_map(params![var_partner_id], payout_from_row)?;
+        payouts.collect()
+    }
+
+    pub fn add_commission_adjustment(&self, request: CommissionAdjustmentRequest) -> Result<CommissionAdjustment> {
+        let currency = checked_currency(&request.currency)?;
+        let amount = round_amount(request.amount, &currency);
+        if amount == 0.0 {
+            return Err(rule_violation("Adjustment amount cannot be zero"));
+        }
+        if request.reason.trim().is_empty() {
+            return Err(rule_violation("Adjustments need a reason"));
+        }
+
+        let conn = self.conn.lock().unwrap();
+        let adjustment = CommissionAdjustment {
+            id: generate_id(&conn)?,
+            var_partner_id: request.var_partner_id,
+            var_client_id: request.var_client_id,
+            adjustment_date: request.adjustment_date,
+            amount,
+            currency,
+            reason: request.reason,
+            source: "manual".to_string(),
+            created_at: current_timestamp(&conn)?,
+        };
+        insert_adjustment(&conn, &adjustment)?;
+        Ok(adjustment)
+    }
+
+    pub fn get_commission_adjustments(&self, var_partner_id: Option<&str>) -> Result<Vec<CommissionAdjustment>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, var_partner_id, var_client_id, adjustment_date, amount, currency, reason, source, created_at
+             FROM commission_adjustments
+             WHERE ?1 IS NULL OR var_partner_id = ?1
+             ORDER BY adjustment_date DESC, created_at DESC",
+        )?;
+        let adjustments = stmt.query_map(params![var_partner_id], adjustment_from_row)?;
+        adjustments.collect()
+    }
+
+    /// Builds a partner's commission statement for an inclusive date range
+    /// (YYYY-MM-DD). Invoices count on the first day of their billing month.
+    pub fn get_partner_statement(
+        &self,
+        var_partner_id: &str,
+        period_from: &str,
+        period_to: &str,
+    ) -> Result<PartnerStatement> {
+        let conn = self.conn.lock().unwrap();
+        build_statement(&conn, var_partner_id, period_from, period_to)
+    }
+}
@@ -0,0 +1,686 @@
+use super::invoice_status::transition;
+use super::{
+    checked_currency, current_timestamp, generate_id, next_document_number, rule_violation, Database,
+};
+use crate::currency::{format_amount, normalize, round_amount};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+
+/// Payment methods accepted when recording a payment.
+pub const PAYMENT_METHODS: &[&str] = &["eft", "card", "cash", "cheque", "debit_order", "other"];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Payment {
+    pub id: String,
+    pub payment_number: String,
+    pub var_client_id: String,
+    pub payment_date: String,
+    pub amount: f64,
+    pub currency: String,
+    pub method: String,
+    pub reference: Option<String>,
+    pub notes: Option<String>,
+    pub created_at: String,
+    pub allocations: Vec<PaymentAllocation>,
+    pub unapplied_amount: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PaymentAllocation {
+    pub id: String,
+    pub payment_id: String,
+    pub invoice_id: String,
+    pub amount: f64,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PaymentRequest {
+    pub var_client_id: String,
+    pub payment_date: String,
+    pub amount: f64,
+    pub currency: String,
+    pub method: String,
+    pub reference: Option<String>,
+    pub notes: Option<String>,
+    pub allocations: Vec<AllocationRequest>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AllocationRequest {
+    pub invoice_id: String,
+    pub amount: f64,
+}
+
+/// An invoice with money still owing on it, as of a given date.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpenInvoice {
+    pub invoice_id: String,
+    pub var_client_id: String,
+    pub var_partner_id: String,
+    pub client_name: String,
+    pub debt_code: Option<String>,
+    pub currency: String,
+    pub billing_month: String,
+    pub invoice_date: String,
+    pub age_days: i64,
+    pub outstanding: f64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AgingBuckets {
+    pub current: f64,
+    pub days_30: f64,
+    pub days_60: f64,
+    pub days_90: f64,
+    pub days_120_plus: f64,
+    pub total: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClientAging {
+    pub var_client_id: String,
+    pub client_name: String,
+    pub currency: String,
+    pub buckets: AgingBuckets,
+    pub unapplied_credit: f64,
+}
+
+/// Aging and unapplied credit summed over the clients billed in one currency.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CurrencyAging {
+    pub currency: String,
+    pub buckets: AgingBuckets,
+    pub unapplied_credit: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArAgingReport {
+    pub as_of: String,
+    pub clients: Vec<ClientAging>,
+    pub totals: Vec<CurrencyAging>,
+}
+
+impl AgingBuckets {
+    fn add(&mut self, age_days: i64, amount: f64, currency: &str) {
+        let bucket = match age_days {
+            i64::MIN..=29 => &mut self.current,
+            30..=59 => &mut self.days_30,
+            60..=89 => &mut self.days_60,
+            90..=119 => &mut self.days_90,
+            _ => &mut self.days_120_plus,
+        };
+        *bucket = round_amount(*bucket + amount, currency);
+        self.total = round_amount(self.total + amount, currency);
+    }
+
+    fn merge(&mut self, other: &AgingBuckets, currency: &str) {
+        self.current = round_amount(self.current + other.current, currency);
+        self.days_30 = round_amount(self.days_30 + other.days_30, currency);
+        self.days_60 = round_amount(self.days_60 + other.days_60, currency);
+        self.days_90 = round_amount(self.days_90 + other.days_90, currency);
+        self.days_120_plus = round_amount(self.days_120_plus + other.days_120_plus, currency);
+        self.total = round_amount(self.total + other.total, currency);
+    }
+}
+
+pub(super) fn create_tables(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS payments (
+            id TEXT PRIMARY KEY,
+            payment_number TEXT NOT NULL UNIQUE,
+            var_client_id TEXT NOT NULL,
+            payment_date TEXT NOT NULL,
+            amount REAL NOT NULL,
+            currency TEXT NOT NULL,
+            method TEXT NOT NULL,
+            reference TEXT,
+            notes TEXT,
+            created_at TEXT NOT NULL,
+            FOREIGN KEY (var_client_id) REFERENCES var_clients (id)
+        )",
+        [],
+    )?;
+
+    // `allocation_date` is the day an allocation takes effect: the payment
+    // date for allocations made with the payment, the day of allocation for
+    // later ones.
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS payment_allocations (
+            id TEXT PRIMARY KEY,
+            payment_id TEXT NOT NULL,
+            invoice_id TEXT NOT NULL,
+            amount REAL NOT NULL,
+            allocation_date TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            FOREIGN KEY (payment_id) REFERENCES payments (id),
+            FOREIGN KEY (invoice_id) REFERENCES var_client_invoices (id)
+        )",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_payment_allocations_invoice ON payment_allocations (invoice_id)",
+        [],
+    )?;
+
+    Ok(())
+}
+
+/// Issued invoices with a non-zero balance. When `as_of` is given, only
+/// invoices, notes and payments dated on or before it are taken into account.
+pub(super) fn open_invoices(conn: &Connection, as_of: Option<&str>) -> Result<Vec<OpenInvoice>> {
+    let mut stmt = conn.prepare(
+        "SELECT i.id, i.var_client_id, i.var_partner_id, c.client_name, c.debt_code, c.currency,
+                i.billing_month, COALESCE(i.invoice_date, i.billing_month || '-01') AS effective_date,
+                CAST(julianday(COALESCE(?1, date('now')))
+                     - julianday(COALESCE(i.invoice_date, i.billing_month || '-01')) AS INTEGER),
+                i.client_revenue + COALESCE(n.debited, 0) - COALESCE(n.credited, 0) - COALESCE(a.allocated, 0)
+         FROM var_client_invoices i
+         JOIN var_clients c ON c.id = i.var_client_id
+         LEFT JOIN (
+             SELECT invoice_id,
+                    SUM(CASE WHEN note_type = 'debit' THEN amount ELSE 0 END) AS debited,
+                    SUM(CASE WHEN note_type = 'credit' THEN amount ELSE 0 END) AS credited
+             FROM credit_notes
+             WHERE ?1 IS NULL OR note_date <= ?1
+             GROUP BY invoice_id
+         ) n ON n.invoice_id = i.id
+         LEFT JOIN (
+             SELECT invoice_id, SUM(amount) AS allocated
+             FROM payment_allocations
+             WHERE ?1 IS NULL OR allocation_date <= ?1
+             GROUP BY invoice_id
+         ) a ON a.invoice_id = i.id
+         WHERE i.invoice_status IN ('invoiced', 'paid')
+           AND (?1 IS NULL OR COALESCE(i.invoice_date, i.billing_month || '-01') <= ?1)
+         ORDER BY c.client_name, effective_date",
+    )?;
+
+    let rows = stmt.query_map(params![as_of], |row| {
+        let currency: String = row.get(5)?;
+        Ok(OpenInvoice {
+            invoice_id: row.get(0)?,
+            var_client_id: row.get(1)?,
+            var_partner_id: row.get(2)?,
+            client_name: row.get(3)?,
+            debt_code: row.get(4)?,
+            billing_month: row.get(6)?,
+            invoice_date: row.get(7)?,
+            age_days: row.get(8)?,
+            outstanding: round_amount(row.get(9)?, &currency),
+            currency,
+        })
+    })?;
+
+    let mut open = Vec::new();
+    for row in rows {
+        let invoice = row?;
+        if invoice.outstanding != 0.0 {
+            open.push(invoice);
+        }
+    }
+    Ok(open)
+}
+
+/// Balance still owing on a single invoice after notes and payments.
+pub(super) fn invoice_outstanding(conn: &Connection, invoice_id: &str) -> Result<f64> {
+    let (allocated, currency): (f64, String) = conn.query_row(
+        "SELECT (SELECT COALESCE(SUM(amount), 0) FROM payment_allocations WHERE invoice_id = i.id), c.currency
+         FROM var_client_invoices i
+         JOIN var_clients c ON c.id = i.var_client_id
+         WHERE i.id = ?1",
+        params![invoice_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    let net = super::credit_notes::invoice_net_amount(conn, invoice_id)?;
+    Ok(round_amount(net - allocated, &currency))
+}
+
+fn payment_unapplied(conn: &Connection, payment_id: &str) -> Result<(f64, String)> {
+    let (unapplied, currency): (f64, String) = conn.query_row(
+        "SELECT p.amount - COALESCE(SUM(pa.amount), 0), p.currency
+         FROM payments p
+         LEFT JOIN payment_allocations pa ON pa.payment_id = p.id
+         WHERE p.id = ?1
+         GROUP BY p.id",
+        params![payment_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    Ok((round_amount(unapplied, &currency), currency))
+}
+
+fn insert_allocations(
+    conn: &Connection,
+    payment_id: &str,
+    var_client_id: &str,
+    allocation_date: &str,
+    allocations: &[AllocationRequest],
+) -> Result<()> {
+    let (mut available, currency) = payment_unapplied(conn, payment_id)?;
+
+    for allocation in allocations {
+        let amount = round_amount(allocation.amount, &currency);
+        if amount <= 0.0 {
+            return Err(rule_violation("Allocation amounts must be greater than zero"));
+        }
+
+        let owner: Option<(String, String)> = conn
+            .query_row(
+                "SELECT var_client_id, invoice_status FROM var_client_invoices WHERE id = ?1",
+                params![allocation.invoice_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        match owner {
+            None => return Err(rule_violation(format!("Invoice {} not found", allocation.invoice_id))),
+            Some((owner, _)) if owner != var_client_id => {
+                return Err(rule_violation(format!(
+                    "Invoice {} belongs to a different client",
+                    allocation.invoice_id
+                )))
+            }
+            Some((_, status)) if status != "invoiced" => {
+                return Err(rule_violation(format!(
+                    "Invoice {} is {} and cannot take payments",
+                    allocation.invoice_id, status
+                )))
+            }
+            Some(_) => {}
+        }
+
+        let outstanding = invoice_outstanding(conn, &allocation.invoice_id)?;
+        if amount > outstanding {
+            return Err(rule_violation(format!(
+                "Allocation of {} exceeds the {} outstanding on invoice {}",
+                format_amount(amount, &currency),
+                format_amount(outstanding, &currency),
+                allocation.invoice_id
+            )));
+        }
+        if amount > available {
+            return Err(rule_violation(format!(
+                "Allocations exceed the {} left on the payment",
+                format_amount(available, &currency)
+            )));
+        }
+
+        conn.execute(
+            "INSERT INTO payment_allocations (id, payment_id, invoice_id, amount, allocation_date, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                generate_id(conn)?,
+                payment_id,
+                allocation.invoice_id,
+                amount,
+                allocation_date,
+                current_timestamp(conn)?
+            ],
+        )?;
+        available = round_amount(available - amount, &currency);
+
+        if invoice_outstanding(conn, &allocation.invoice_id)? <= 0.0 {
+            transition(conn, &allocation.invoice_id, "paid", Some("Settled in full by payment"), None)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub(super) fn load_payment(conn: &Connection, payment_id: &str) -> Result<Payment> {
+    let mut payment = conn.query_row(
+        "SELECT id, payment_number, var_client_id, payment_date, amount, currency, method,
+                reference, notes, created_at
+         FROM payments WHERE id = ?1",
+        params![payment_id],
+        |row| {
+            Ok(Payment {
+                id: row.get(0)?,
+                payment_number: row.get(1)?,
+                var_client_id: row.get(2)?,
+                payment_date: row.get(3)?,
+                amount: row.get(4)?,
+                currency: row.get(5)?,
+                method: row.get(6)?,
+                reference: row.get(7)?,
+                notes: row.get(8)?,
+                created_at: row.get(9)?,
+                allocations: Vec::new(),
+                unapplied_amount: 0.0,
+            })
+        },
+    )?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id, payment_id, invoice_id, amount, created_at
+         FROM payment_allocations WHERE payment_id = ?1
+         ORDER BY created_at",
+    )?;
+    let allocations = stmt.query_map(params![payment_id], |row| {
+        Ok(PaymentAllocation {
+            id: row.get(0)?,
+            payment_id: row.get(1)?,
+            invoice_id: row.get(2)?,
+            amount: row.get(3)?,
+            created_at: row.get(4)?,
+        })
+    })?;
+    payment.allocations = allocations.collect::<Result<_>>()?;
+    payment.unapplied_amount = round_amount(
+        payment.amount - payment.allocations.iter().map(|a| a.amount).sum::<f64>(),
+        &payment.currency,
+    );
+
+    Ok(payment)
+}
+
+/// Inserts a payment and its allocations. Callers own the transaction.
+pub(super) fn insert_payment(conn: &Connection, request: PaymentRequest) -> Result<String> {
+    if !PAYMENT_METHODS.contains(&request.method.as_str()) {
+        return Err(rule_violation(format!("Unknown payment method '{}'", request.method)));
+    }
+    let currency = checked_currency(&request.currency)?;
+    let amount = round_amount(request.amount, &currency);
+    if amount <= 0.0 {
+        return Err(rule_violation("Payment amount must be greater than zero"));
+    }
+
+    let client_currency: Option<String> = conn
+        .query_row(
+            "SELECT currency FROM var_clients WHERE id = ?1",
+            params![request.var_client_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let client_currency = client_currency
+        .ok_or_else(|| rule_violation(format!("VAR client {} not found", request.var_client_id)))?;
+    if normalize(&client_currency) != Some(currency.as_str()) {
+        return Err(rule_violation(format!(
+            "Payment currency {} does not match the client's currency {}",
+            request.currency, client_currency
+        )));
+    }
+
+    let id = generate_id(conn)?;
+    conn.execute(
+        "INSERT INTO payments
+         (id, payment_number, var_client_id, payment_date, amount, currency, method,
+          reference, notes, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        params![
+            id,
+            next_document_number(conn, "payment", "PMT")?,
+            request.var_client_id,
+            request.payment_date,
+            amount,
+            currency,
+            request.method,
+            request.reference,
+            request.notes,
+            current_timestamp(conn)?
+        ],
+    )?;
+
+    insert_allocations(
+        conn,
+        &id,
+        &request.var_client_id,
+        &request.payment_date,
+        &request.allocations,
+    )?;
+    Ok(id)
+}
+
+impl Database {
+    /// Records a payment and allocates it across the given invoices. Whatever
+    /// is not allocated stays on the payment as unapplied credit.
+    pub fn record_payment(&self, request: PaymentRequest) -> Result<Payment> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let id = insert_payment(&tx, request)?;
+        let payment = load_payment(&tx, &id)?;
+        tx.commit()?;
+        Ok(payment)
+    }
+
+    /// Applies unapplied credit on an existing payment to further invoices.
+    pub fn allocate_payment(&self, payment_id: &str, allocations: Vec<AllocationRequest>) -> Result<Payment> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let payment: Option<(String, String)> = tx
+            .query_row(
+                "SELECT var_client_id, max(payment_date, date('now')) FROM payments WHERE id = ?1",
+                params![payment_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        let (var_client_id, allocation_date) =
+            payment.ok_or_else(|| rule_violation(format!("Payment {} not found", payment_id)))?;
+
+        insert_allocations(&tx, payment_id, &var_client_id, &allocation_date, &allocations)?;
+        let payment = load_payment(&tx, payment_id)?;
+        tx.commit()?;
+        Ok(payment)
+    }
+
+    pub fn get_payments(&self, var_client_id: Option<&str>) -> Result<Vec<Payment>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id FROM payments
+             WHERE ?1 IS NULL OR var_client_id = ?1
+             ORDER BY payment_date DESC, payment_number DESC",
+        )?;
+        let ids = stmt
+            .query_map(params![var_client_id], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>>>()?;
+
+        ids.iter().map(|id| load_payment(&conn, id)).collect()
+    }
+
+    pub fn get_open_invoices(&self, as_of: Option<&str>) -> Result<Vec<OpenInvoice>> {
+        let conn = self.conn.lock().unwrap();
+        open_invoices(&conn, as_of)
+    }
+
+    /// Accounts-receivable aging as of `as_of`, bucketed by days since the
+    /// invoice date (falling back to the first of the billing month).
+    pub fn get_ar_aging(&self, as_of: &str) -> Result<ArAgingReport> {
+        let conn = self.conn.lock().unwrap();
+        let mut clients: Vec<ClientAging> = Vec::new();
+
+        for invoice in open_invoices(&conn, Some(as_of))? {
+            match clients.iter_mut().find(|c| c.var_client_id == invoice.var_client_id) {
+                Some(client) => client
+                    .buckets
+                    .add(invoice.age_days, invoice.outstanding, &invoice.currency),
+                None => {
+                    let mut buckets = AgingBuckets::default();
+                    buckets.add(invoice.age_days, invoice.outstanding, &invoice.currency);
+                    clients.push(ClientAging {
+                        var_client_id: invoice.var_client_id,
+                        client_name: invoice.client_name,
+                        currency: invoice.currency,
+                        buckets,
+                        unapplied_credit: 0.0,
+                    });
+                }
+            }
+        }
+
+        let mut stmt = conn.prepare(
+            "SELECT p.var_client_id, c.client_name, c.currency,
+                    SUM(p.amount - COALESCE(a.allocated, 0))
+             FROM payments p
+             JOIN var_clients c ON c.id = p.var_client_id
+             LEFT JOIN (
+                 SELECT payment_id, SUM(amount) AS allocated
+                 FROM payment_allocations
+                 WHERE allocation_date <= ?1
+                 GROUP BY payment_id
+             ) a ON a.payment_id = p.id
+             WHERE p.payment_date <= ?1
+             GROUP BY p.var_client_id",
+        )?;
+        let unapplied = stmt.query_map(params![as_of], |row| {
+            let currency: String = row.get(2)?;
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                round_amount(row.get(3)?, &currency),
+                currency,
+            ))
+        })?;
+        for row in unapplied {
+            let (var_client_id, client_name, credit, currency) = row?;
+            if credit <= 0.0 {
+                continue;
+            }
+            match clients.iter_mut().find(|c| c.var_client_id == var_client_id) {
+                Some(client) => client.unapplied_credit = credit,
+                None => clients.push(ClientAging {
+                    var_client_id,
+                    client_name,
+                    currency,
+                    buckets: AgingBuckets::default(),
+                    unapplied_credit: credit,
+                }),
+            }
+        }
+
+        let mut totals: Vec<CurrencyAging> = Vec::new();
+        for client in &clients {
+            let index = match totals.iter().position(|total| total.currency == client.currency) {
+                Some(index) => index,
+                None => {
+                    totals.push(CurrencyAging {
+                        currency: client.currency.clone(),
+                        buckets: AgingBuckets::default(),
+                        unapplied_credit: 0.0,
+                    });
+                    totals.len() - 1
+                }
+            };
+            let total = &mut totals[index];
+            total.buckets.merge(&client.buckets, &client.currency);
+            total.unapplied_credit = round_amount(total.unapplied_credit + client.unapplied_credit, &client.currency);
+        }
+        totals.sort_by(|a, b| a.currency.cmp(&b.currency));
+
+        Ok(ArAgingReport {
+            as_of: as_of.to_string(),
+            clients,
+            totals,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AllocationRequest, PaymentRequest};
+    use crate::database::test_support::{add_client, add_invoice, add_partner, test_db};
+    use crate::database::Database;
+
+    fn issue(db: &Database, id: &str, client: &str, billing_month: &str, revenue: f64) {
+        add_invoice(db, id, client, "p1", billing_month, revenue, 10.0);
+        db.set_var_invoice_status(id, "invoiced", None, None).unwrap();
+    }
+
+    fn payment(client: &str, currency: &str, amount: f64, allocations: &[(&str, f64)]) -> PaymentRequest {
+        PaymentRequest {
+            var_client_id: client.to_string(),
+            payment_date: "2026-10-05".to_string(),
+            amount,
+            currency: currency.to_string(),
+            method: "eft".to_string(),
+            reference: None,
+            notes: None,
+            allocations: allocations
+                .iter()
+                .map(|&(invoice_id, amount)| AllocationRequest { invoice_id: invoice_id.to_string(), amount })
+                .collect(),
+        }
+    }
+
+    fn status(db: &Database, id: &str) -> String {
+        db.get_var_client_invoices()
+            .unwrap()
+            .into_iter()
+            .find(|i| i.id == id)
+            .unwrap()
+            .invoice_status
+    }
+
+    #[test]
+    fn allocates_and_keeps_the_rest_as_credit() {
+        let db = test_db();
+        add_partner(&db, "p1", 10.0);
+        add_client(&db, "c1", "p1", 10.0, "ZAR");
+        issue(&db, "i1", "c1", "2026-08", 1000.0);
+        issue(&db, "i2", "c1", "2026-09", 1000.0);
+
+        let paid = db.record_payment(payment("c1", "ZAR", 1500.0, &[("i1", 1000.0)])).unwrap();
+        assert_eq!(paid.unapplied_amount, 500.0);
+        assert_eq!(status(&db, "i1"), "paid");
+
+        let paid = db
+            .allocate_payment(&paid.id, vec![AllocationRequest { invoice_id: "i2".to_string(), amount: 500.0 }])
+            .unwrap();
+        assert_eq!((paid.allocations.len(), paid.unapplied_amount), (2, 0.0));
+        assert_eq!(status(&db, "i2"), "invoiced");
+        let open = db.get_open_invoices(None).unwrap();
+        assert_eq!(open.len(), 1);
+        assert_eq!((open[0].invoice_id.as_str(), open[0].outstanding), ("i2", 500.0));
+    }
+
+    #[test]
+    fn rejects_over_allocation_and_unissued_invoices() {
+        let db = test_db();
+        add_partner(&db, "p1", 10.0);
+        add_client(&db, "c1", "p1", 10.0, "ZAR");
+        issue(&db, "i1", "c1", "2026-08", 1000.0);
+        issue(&db, "i2", "c1", "2026-09", 1000.0);
+        add_invoice(&db, "i3", "c1", "p1", "2026-10", 1000.0, 10.0);
+
+        let error = db.record_payment(payment("c1", "ZAR", 2000.0, &[("i1", 1200.0)])).unwrap_err();
+        assert!(error.to_string().contains("exceeds the ZAR 1,000.00 outstanding"), "{}", error);
+        let error = db
+            .record_payment(payment("c1", "ZAR", 1500.0, &[("i1", 1000.0), ("i2", 600.0)]))
+            .unwrap_err();
+        assert!(error.to_string().contains("exceed the ZAR 500.00 left"), "{}", error);
+        let error = db.record_payment(payment("c1", "ZAR", 1000.0, &[("i3", 1000.0)])).unwrap_err();
+        assert!(error.to_string().contains("is pending"), "{}", error);
+        assert!(db.get_payments(None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn ages_open_invoices_into_buckets_per_currency() {
+        let db = test_db();
+        add_partner(&db, "p1", 10.0);
+        add_client(&db, "c1", "p1", 10.0, "ZAR");
+        add_client(&db, "c2", "p1", 10.0, "USD");
+        issue(&db, "i1", "c1", "2026-10", 100.0);
+        issue(&db, "i2", "c1", "2026-09", 200.0);
+        issue(&db, "i3", "c1", "2026-08", 300.0);
+        issue(&db, "i4", "c1", "2026-07", 400.0);
+        issue(&db, "i5", "c1", "2026-05", 500.0);
+        issue(&db, "i6", "c2", "2026-09", 50.0);
+        add_invoice(&db, "i7", "c1", "p1", "2026-06", 999.0, 10.0);
+        db.record_payment(payment("c2", "USD", 80.0, &[("i6", 20.0)])).unwrap();
+
+        let aging = db.get_ar_aging("2026-10-15").unwrap();
+        let zar = aging.totals.iter().find(|t| t.currency == "ZAR").unwrap();
+        let buckets = &zar.buckets;
+        assert_eq!(
+            (buckets.current, buckets.days_30, buckets.days_60, buckets.days_90, buckets.days_120_plus),
+            (100.0, 200.0, 300.0, 400.0, 500.0)
+        );
+        assert_eq!(buckets.total, 1500.0);
+        let usd = aging.totals.iter().find(|t| t.currency == "USD").unwrap();
+        assert_eq!((usd.buckets.days_30, usd.unapplied_credit), (30.0, 60.0));
+
+        // Nothing paid or invoiced after the as-of date counts yet.
+        let aging = db.get_ar_aging("2026-09-15").unwrap();
+        let usd = aging.totals.iter().find(|t| t.currency == "USD").unwrap();
+        assert_eq!((usd.buckets.current, usd.unapplied_credit), (50.0, 0.0));
+    }
+}
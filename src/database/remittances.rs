@@ -0,0 +1,452 @@
+use super::exchange_rates::reporting_currency;
+use super::invoice_status::{current_status, transition};
+use super::payments::{insert_payment, invoice_outstanding, AllocationRequest, PaymentRequest};
+use super::{load_setting, rule_violation, save_setting, Database};
+use crate::currency::{format_amount, round_amount};
+use crate::tabular::{parse_amount, read_table, Table, TableOptions, TableRow};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+const MONTH_NAMES: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+/// Which remittance columns hold what. Unset columns are found by their usual
+/// header names; a line needs a client name or debt code, a month and an amount.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RemittanceMapping {
+    pub client_column: Option<String>,
+    pub debt_code_column: Option<String>,
+    pub month_column: Option<String>,
+    pub amount_column: Option<String>,
+    pub reference_column: Option<String>,
+}
+
+/// A remittance line that settled an invoice.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RemittanceMatch {
+    pub line: usize,
+    pub var_client_id: String,
+    pub client_name: String,
+    pub billing_month: String,
+    pub invoice_id: String,
+    pub payment_id: String,
+    pub amount: f64,
+}
+
+/// A remittance line that could not be applied. `kind` is one of
+/// `invalid_line`, `missing_client`, `no_invoice`, `invoice_not_issued`,
+/// `already_paid`, `amount_difference` or `payment_rejected`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RemittanceException {
+    pub line: usize,
+    pub kind: String,
+    pub client: String,
+    pub debt_code: String,
+    pub billing_month: Option<String>,
+    pub amount: Option<f64>,
+    pub expected_amount: Option<f64>,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RemittanceImportReport {
+    pub var_partner_id: String,
+    pub file_name: String,
+    pub lines_read: usize,
+    pub total_received: f64,
+    pub total_matched: f64,
+    pub matched: Vec<RemittanceMatch>,
+    pub exceptions: Vec<RemittanceException>,
+}
+
+struct RemittanceColumns {
+    client: Option<usize>,
+    debt_code: Option<usize>,
+    month: Option<usize>,
+    amount: Option<usize>,
+    reference: Option<usize>,
+}
+
+impl RemittanceColumns {
+    fn resolve(table: &Table, mapping: &RemittanceMapping) -> std::result::Result<Self, String> {
+        let column = |configured: &Option<String>, aliases: &[&str]| -> std::result::Result<Option<usize>, String> {
+            match configured {
+                Some(name) => table
+                    .find_column(&[name.as_str()])
+                    .map(Some)
+                    .ok_or_else(|| format!("Column '{}' is not in the file", name)),
+                None => Ok(table.find_column(aliases)),
+            }
+        };
+        let columns = RemittanceColumns {
+            client: column(
+                &mapping.client_column,
+                &["client", "client name", "customer", "customer name", "name"],
+            )?,
+            debt_code: column(
+                &mapping.debt_code_column,
+                &["debt code", "debtcode", "account", "account code", "customer code"],
+            )?,
+            month: column(
+                &mapping.month_column,
+                &["month", "billing month", "period", "invoice month"],
+            )?,
+            amount: column(
+                &mapping.amount_column,
+                &["amount", "amount paid", "paid", "payment", "total"],
+            )?,
+            reference: column(
+                &mapping.reference_column,
+                &["reference", "ref", "invoice", "invoice number"],
+            )?,
+        };
+        if columns.client.is_none() && columns.debt_code.is_none() {
+            return Err("The file needs a client name or debt code column".to_string());
+        }
+        if columns.month.is_none() || columns.amount.is_none() {
+            return Err("The file needs a month column and an amount column".to_string());
+        }
+        Ok(columns)
+    }
+}
+
+fn mapping_key(var_partner_id: &str) -> String {
+    format!("remittance_mapping.{}", var_partner_id)
+}
+
+/// Reads a billing month from the usual spellings: 2026-09, 2026/09/15,
+/// 09/2026, 15/09/2026, Sep 2026 or September-26.
+fn parse_billing_month(value: &str) -> Option<String> {
+    let tokens: Vec<String> = value
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    let year = |token: &str| -> Option<i32> {
+        match (token.len(), token.parse::<i32>().ok()?) {
+            (4, year) => Some(year),
+            (2, year) => Some(2000 + year),
+            _ => None,
+        }
+    };
+
+    let (year, month) = if let Some(name) = tokens.iter().find(|t| t.chars().all(|c| c.is_ascii_alphabetic())) {
+        let month = MONTH_NAMES.iter().position(|m| name.starts_with(m))? as u32 + 1;
+        let year = tokens.iter().filter(|t| *t != name).find_map(|t| year(t))?;
+        (year, month)
+    } else {
+        match tokens.as_slice() {
+            [first, second, ..] if first.len() == 4 => (year(first)?, second.parse().ok()?),
+            [month, last] if last.len() == 4 => (year(last)?, month.parse().ok()?),
+            [_, month, last] if last.len() == 4 => (year(last)?, month.parse().ok()?),
+            _ => return None,
+        }
+    };
+    (1..=12).contains(&month).then(|| format!("{:04}-{:02}", year, month))
+}
+
+/// Finds the partner's client for a line, preferring the debt code over the name.
+fn find_client(
+    conn: &Connection,
+    var_partner_id: &str,
+    debt_code: &str,
+    client: &str,
+) -> Result<Option<(String, String, String)>> {
+    conn.query_row(
+        "SELECT id, client_name, currency FROM var_clients
+         WHERE var_partner_id = ?1
+           AND ((?2 <> '' AND lower(trim(debt_code)) = lower(?2))
+                OR (?3 <> '' AND lower(trim(client_name)) = lower(?3)))
+         ORDER BY (?2 <> '' AND lower(trim(debt_code)) = lower(?2)) DESC, is_active DESC
+         LIMIT 1",
+        params![var_partner_id, debt_code, client],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )
+    .optional()
+}
+
+/// The currency a partner's remittance totals are kept in: that of their
+/// clients when they all share one, otherwise the reporting currency.
+fn remittance_currency(conn: &Connection, var_partner_id: &str) -> Result<String> {
+    let currencies = conn
+        .prepare("SELECT DISTINCT currency FROM var_clients WHERE var_partner_id = ?1")?
+        .query_map(params![var_partner_id], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>>>()?;
+    match currencies.as_slice() {
+        [currency] => Ok(currency.clone()),
+        _ => reporting_currency(conn),
+    }
+}
+
+struct LineContext<'a> {
+    row: &'a TableRow,
+    client: String,
+    debt_code: String,
+    billing_month: Option<String>,
+    amount: Option<f64>,
+}
+
+impl LineContext<'_> {
+    fn exception(&self, kind: &str, expected_amount: Option<f64>, message: impl Into<String>) -> RemittanceException {
+        RemittanceException {
+            line: self.row.line,
+            kind: kind.to_string(),
+            client: self.client.clone(),
+            debt_code: self.debt_code.clone(),
+            billing_month: self.billing_month.clone(),
+            amount: self.amount,
+            expected_amount,
+            message: message.into(),
+        }
+    }
+}
+
+fn apply_line(
+    conn: &Connection,
+    var_partner_id: &str,
+    columns: &RemittanceColumns,
+    context: &LineContext,
+    payment_date: &str,
+    file_name: &str,
+) -> Result<std::result::Result<RemittanceMatch, RemittanceException>> {
+    let (Some(billing_month), Some(amount)) = (&context.billing_month, context.amount) else {
+        return Ok(Err(context.exception(
+            "invalid_line",
+            None,
+            "The line needs a readable month and amount",
+        )));
+    };
+    let Some((var_client_id, client_name, currency)) =
+        find_client(conn, var_partner_id, &context.debt_code, &context.client)?
+    else {
+        return Ok(Err(context.exception(
+            "missing_client",
+            None,
+            "No client of this partner matches the line",
+        )));
+    };
+    let amount = round_amount(amount, &currency);
+
+    let invoice_id: Option<String> = conn
+        .query_row(
+            "SELECT id FROM var_client_invoices
+             WHERE var_client_id = ?1 AND billing_month = ?2 AND invoice_status NOT IN ('void', 'cancelled')",
+            params![var_client_id, billing_month],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let Some(invoice_id) = invoice_id else {
+        return Ok(Err(context.exception(
+            "no_invoice",
+            None,
+            format!("{} has no invoice for {}", client_name, billing_month),
+        )));
+    };
+
+    let status = current_status(conn, &invoice_id)?;
+    let outstanding = invoice_outstanding(conn, &invoice_id)?;
+    if status == "draft" {
+        return Ok(Err(context.exception(
+            "invoice_not_issued",
+            Some(outstanding),
+            "The invoice is still a draft",
+        )));
+    }
+    if outstanding <= 0.0 {
+        return Ok(Err(context.exception(
+            "already_paid",
+            Some(0.0),
+            "The invoice is already settled",
+        )));
+    }
+    if amount != outstanding {
+        return Ok(Err(context.exception(
+            "amount_difference",
+            Some(outstanding),
+            format!(
+                "Paid {} against {} outstanding",
+                format_amount(amount, &currency),
+                format_amount(outstanding, &currency)
+            ),
+        )));
+    }
+
+    // The partner paying is proof the invoice went out.
+    if status == "pending" {
+        transition(conn, &invoice_id, "invoiced", Some("Issued on partner remittance"), None)?;
+    }
+    let reference = match context.row.get(columns.reference) {
+        "" => format!("Remittance {} line {}", file_name, context.row.line),
+        reference => reference.to_string(),
+    };
+    // A payment the ledger refuses, such as one in the wrong currency, is
+    // the line's problem rather than the whole file's.
+    let payment_id = match insert_payment(
+        conn,
+        PaymentRequest {
+            var_client_id: var_client_id.clone(),
+            payment_date: payment_date.to_string(),
+            amount,
+            currency,
+            method: "eft".to_string(),
+            reference: Some(reference),
+            notes: Some(format!("Partner remittance {}", file_name)),
+            allocations: vec![AllocationRequest {
+                invoice_id: invoice_id.clone(),
+                amount,
+            }],
+        },
+    ) {
+        Ok(payment_id) => payment_id,
+        Err(rusqlite::Error::SqliteFailure(error, Some(message)))
+            if error.code == rusqlite::ErrorCode::ConstraintViolation =>
+        {
+            return Ok(Err(context.exception("payment_rejected", Some(outstanding), message)));
+        }
+        Err(e) => return Err(e),
+    };
+
+    Ok(Ok(RemittanceMatch {
+        line: context.row.line,
+        var_client_id,
+        client_name,
+        billing_month: billing_month.clone(),
+        invoice_id,
+        payment_id,
+        amount,
+    }))
+}
+
+impl Database {
+    pub fn get_remittance_mapping(&self, var_partner_id: &str) -> Result<RemittanceMapping> {
+        let conn = self.conn.lock().unwrap();
+        Ok(load_setting(&conn, &mapping_key(var_partner_id))?.unwrap_or_default())
+    }
+
+    /// Applies a partner's remittance advice. Lines whose amount settles the
+    /// client's invoice for the month in full are recorded as payments, which
+    /// marks the invoice paid; everything else is returned as an exception.
+    /// A mapping passed in is remembered for the partner's next import.
+    pub fn import_partner_remittance(
+        &self,
+        var_partner_id: &str,
+        path: &Path,
+        payment_date: &str,
+        mapping: Option<RemittanceMapping>,
+    ) -> Result<RemittanceImportReport> {
+        let table = read_table(path, &TableOptions::default()).map_err(rule_violation)?;
+        let file_name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let mut conn = self.conn.lock().unwrap();
+        let mut tx = conn.transaction()?;
+        let mapping = match mapping {
+            Some(mapping) => {
+                save_setting(&tx, &mapping_key(var_partner_id), &mapping)?;
+                mapping
+            }
+            None => load_setting(&tx, &mapping_key(var_partner_id))?.unwrap_or_default(),
+        };
+        let columns = RemittanceColumns::resolve(&table, &mapping).map_err(rule_violation)?;
+        let currency = remittance_currency(&tx, var_partner_id)?;
+
+        let mut report = RemittanceImportReport {
+            var_partner_id: var_partner_id.to_string(),
+            file_name: file_name.clone(),
+            lines_read: table.rows.len(),
+            total_received: 0.0,
+            total_matched: 0.0,
+            matched: Vec::new(),
+            exceptions: Vec::new(),
+        };
+        for row in &table.rows {
+            let context = LineContext {
+                row,
+                client: row.get(columns.client).to_string(),
+                debt_code: row.get(columns.debt_code).to_string(),
+                billing_month: parse_billing_month(row.get(columns.month)),
+                amount: parse_amount(row.get(columns.amount)),
+            };
+            report.total_received = round_amount(report.total_received + context.amount.unwrap_or(0.0), &currency);
+            // Each line gets its own savepoint so an exception leaves nothing behind.
+            let savepoint = tx.savepoint()?;
+            match apply_line(&savepoint, var_partner_id, &columns, &context, payment_date, &file_name)? {
+                Ok(matched) => {
+                    savepoint.commit()?;
+                    report.total_matched = round_amount(report.total_matched + matched.amount, &currency);
+                    report.matched.push(matched);
+                }
+                Err(exception) => report.exceptions.push(exception),
+            }
+        }
+
+        tx.commit()?;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_billing_month;
+    use crate::database::test_support::{add_client, add_invoice, add_partner, test_db};
+
+    #[test]
+    fn reads_the_usual_month_spellings() {
+        for value in [
+            "2026-09",
+            "2026/09/15",
+            "09/2026",
+            "15/09/2026",
+            "Sep 2026",
+            "September-26",
+        ] {
+            assert_eq!(parse_billing_month(value).as_deref(), Some("2026-09"), "{value}");
+        }
+        assert_eq!(parse_billing_month("2026-13"), None);
+        assert_eq!(parse_billing_month("soon"), None);
+    }
+
+    #[test]
+    fn settles_exact_lines_and_reports_the_rest() {
+        let db = test_db();
+        add_partner(&db, "p1", 20.0);
+        add_client(&db, "c1", "p1", 20.0, "ZAR");
+        add_client(&db, "c2", "p1", 20.0, "ZAR");
+        add_invoice(&db, "i1", "c1", "p1", "2026-09", 1000.0, 20.0);
+        add_invoice(&db, "i2", "c2", "p1", "2026-09", 1000.0, 20.0);
+
+        let path = std::env::temp_dir().join(format!("remittance-{}.csv", std::process::id()));
+        std::fs::write(
+            &path,
+            "Client,Debt Code,Month,Amount\n\
+             ,DC1,Sep 2026,\"1,000.00\"\n\
+             Client c2,,2026-09,900\n\
+             Client c2,,2026-08,1000\n\
+             Nobody,DX,2026-09,5\n",
+        )
+        .unwrap();
+        let report = db.import_partner_remittance("p1", &path, "2026-10-10", None).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(report.lines_read, 4);
+        assert_eq!((report.total_received, report.total_matched), (2905.0, 1000.0));
+        assert_eq!(report.matched.len(), 1);
+        assert_eq!(report.matched[0].invoice_id, "i1");
+        let kinds: Vec<_> = report.exceptions.iter().map(|e| e.kind.as_str()).collect();
+        assert_eq!(kinds, ["amount_difference", "no_invoice", "missing_client"]);
+        assert_eq!(report.exceptions[0].expected_amount, Some(1000.0));
+
+        let statuses: Vec<_> = db
+            .get_var_client_invoices()
+            .unwrap()
+            .into_iter()
+            .map(|invoice| (invoice.id, invoice.invoice_status))
+            .collect();
+        assert!(statuses.contains(&("i1".to_string(), "paid".to_string())));
+        assert!(statuses.contains(&("i2".to_string(), "pending".to_string())));
+    }
+}
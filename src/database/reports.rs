@@ -0,0 +1,793 @@
+use super::exchange_rates::{find_rate, reporting_currency};
+use super::partner_analytics::month_index;
+use super::{checked_currency, rule_violation, Database};
+use crate::currency::round_amount;
+use rusqlite::{params, Connection, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+pub const REPORT_TYPES: &[&str] = &[
+    "sm-forecast",
+    "revenue",
+    "client-summary",
+    "monthly-breakdown",
+    "licenses",
+    "payment-status",
+    "growth-metrics",
+];
+
+const MONTH_NAMES: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// What a report covers. `year` defaults to the current year. Without a
+/// partner, direct and VAR clients are reported together; `billing_model`
+/// and `currency` narrow the clients further. Amounts are in `currency`
+/// when it is given and in the reporting currency otherwise.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ReportParameters {
+    pub year: Option<i32>,
+    pub currency: Option<String>,
+    pub billing_model: Option<String>,
+    pub var_partner_id: Option<String>,
+}
+
+/// A report column. `kind` is `text`, `integer`, `amount`, `percent`, or
+/// `number` for a column mixing amounts and counts.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReportColumn {
+    pub key: String,
+    pub title: String,
+    pub kind: String,
+}
+
+/// A report as a table: each row holds one value per column, and `totals`
+/// is the summary row when the report has one.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReportResult {
+    pub report_type: String,
+    pub title: String,
+    pub year: i32,
+    pub currency: String,
+    pub billing_model: Option<String>,
+    pub var_partner_id: Option<String>,
+    pub columns: Vec<ReportColumn>,
+    pub rows: Vec<Vec<Value>>,
+    pub totals: Option<Vec<Value>>,
+    pub has_missing_rates: bool,
+}
+
+fn column(key: &str, title: impl Into<String>, kind: &str) -> ReportColumn {
+    ReportColumn {
+        key: key.to_string(),
+        title: title.into(),
+        kind: kind.to_string(),
+    }
+}
+
+/// A direct or VAR client as the reports see it. Months are billed from the
+/// deal start month until the month the client was deactivated.
+struct ReportClient {
+    id: String,
+    client_name: String,
+    debt_code: Option<String>,
+    partner_name: Option<String>,
+    users: i32,
+    billing_model: String,
+    currency: String,
+    months: [f64; 12],
+    deal_start_date: String,
+    anniversary_month: Option<i32>,
+    is_active: bool,
+    increase_rate: f64,
+    first_month: i32,
+    end_month: i32,
+}
+
+impl ReportClient {
+    fn is_billed(&self, year: i32, month: usize) -> bool {
+        let index = year * 12 + month as i32;
+        index >= self.first_month && index < self.end_month
+    }
+
+    fn scheduled(&self, year: i32, month: usize) -> f64 {
+        if self.is_billed(year, month) {
+            self.months[month]
+        } else {
+            0.0
+        }
+    }
+
+    /// Whether the client is billed in any month of `year`.
+    fn is_billed_in(&self, year: i32) -> bool {
+        (0..12).any(|month| self.is_billed(year, month))
+    }
+
+    /// The client's own annual increase compounded from `base_year` to `year`.
+    fn escalation(&self, base_year: i32, year: i32) -> f64 {
+        (1.0 + self.increase_rate / 100.0).powi((year - base_year).max(0))
+    }
+}
+
+fn load_clients(conn: &Connection, parameters: &ReportParameters) -> Result<Vec<ReportClient>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, client_name, debt_code, partner_name, users, billing_model, currency,
+                jan, feb, mar, apr, may, jun, jul, aug, sep, oct, nov, dec,
+                deal_start_date, anniversary_month, is_active, deactivated_at, custom_increase_rate
+         FROM (
+             SELECT c.id, c.client_name, c.debt_code, NULL AS partner_name, c.users, c.billing_model, c.currency,
+                    c.jan, c.feb, c.mar, c.apr, c.may, c.jun, c.jul, c.aug, c.sep, c.oct, c.nov, c.dec,
+                    c.deal_start_date, c.anniversary_month, c.is_active, NULL AS deactivated_at,
+                    c.custom_increase_rate
+             FROM clients c
+             WHERE ?1 IS NULL
+             UNION ALL
+             SELECT c.id, c.client_name, c.debt_code, p.name, c.users, c.billing_model, c.currency,
+                    c.jan, c.feb, c.mar, c.apr, c.may, c.jun, c.jul, c.aug, c.sep, c.oct, c.nov, c.dec,
+                    c.deal_start_date, c.anniversary_month, c.is_active, c.deactivated_at, c.custom_increase_rate
+             FROM var_clients c
+             JOIN var_partners p ON p.id = c.var_partner_id
+             WHERE ?1 IS NULL OR c.var_partner_id = ?1
+         )
+         WHERE (?2 IS NULL OR billing_model = ?2) AND (?3 IS NULL OR currency = ?3)
+         ORDER BY client_name COLLATE NOCASE, id",
+    )?;
+    let clients = stmt.query_map(
+        params![parameters.var_partner_id, parameters.billing_model, parameters.currency],
+        |row| {
+            let mut months = [0.0; 12];
+            for (index, month) in months.iter_mut().enumerate() {
+                *month = row.get(7 + index)?;
+            }
+            let deal_start_date: String = row.get(19)?;
+            let is_active = row.get::<_, i32>(21)? == 1;
+            let deactivated_at: Option<String> = row.get(22)?;
+            // Inactive direct clients have no deactivation date, so they are
+            // not billed at all.
+            let end_month = match (is_active, deactivated_at) {
+                (true, _) => i32::MAX,
+                (false, Some(date)) => date.get(..7).and_then(month_index).unwrap_or(i32::MIN),
+                (false, None) => i32::MIN,
+            };
+            Ok(ReportClient {
+                id: row.get(0)?,
+                client_name: row.get(1)?,
+                debt_code: row.get(2)?,
+                partner_name: row.get(3)?,
+                users: row.get(4)?,
+                billing_model: row.get(5)?,
+                currency: row.get(6)?,
+                months,
+                first_month: deal_start_date.get(..7).and_then(month_index).unwrap_or(i32::MIN),
+                deal_start_date,
+                anniversary_month: row.get(20)?,
+                is_active,
+                increase_rate: row.get::<_, Option<f64>>(23)?.unwrap_or(0.0),
+                end_month,
+            })
+        },
+    )?;
+    clients.collect()
+}
+
+/// Converts amounts into the report currency at the rate in force at the
+/// start of each month. Amounts without a rate are left out of the report.
+struct Converter<'a> {
+    conn: &'a Connection,
+    currency: String,
+    rates: HashMap<(String, String), Option<f64>>,
+    missing: bool,
+}
+
+impl<'a> Converter<'a> {
+    fn convert(&mut self, amount: f64, currency: &str, date: &str) -> Result<f64> {
+        if amount == 0.0 {
+            return Ok(0.0);
+        }
+        let key = (currency.to_string(), date.to_string());
+        let rate = match self.rates.get(&key) {
+            Some(rate) => *rate,
+            None => {
+                let rate = find_rate(self.conn, currency, &self.currency, date)?.map(|(rate, _)| rate);
+                self.rates.insert(key, rate);
+                rate
+            }
+        };
+        match rate {
+            Some(rate) => Ok(amount * rate),
+            None => {
+                self.missing = true;
+                Ok(0.0)
+            }
+        }
+    }
+
+    fn scheduled(&mut self, client: &ReportClient, year: i32, month: usize) -> Result<f64> {
+        let amount = client.scheduled(year, month);
+        self.convert(amount, &client.currency, &format!("{:04}-{:02}-01", year, month + 1))
+    }
+
+    /// A client's billing for `year` in its own currency and converted.
+    fn year(&mut self, client: &ReportClient, year: i32) -> Result<(f64, f64)> {
+        let (mut amount, mut converted) = (0.0, 0.0);
+        for month in 0..12 {
+            amount += client.scheduled(year, month);
+            converted += self.scheduled(client, year, month)?;
+        }
+        Ok((amount, converted))
+    }
+
+    fn money(&self, amount: f64) -> Value {
+        json!(round_amount(amount, &self.currency))
+    }
+}
+
+/// `part` as a percentage of `whole`, or null when there is no whole.
+fn percent(part: f64, whole: f64) -> Value {
+    if whole.abs() < 0.005 {
+        Value::Null
+    } else {
+        json!((part / whole * 10000.0).round() / 100.0)
+    }
+}
+
+struct ReportTable {
+    columns: Vec<ReportColumn>,
+    rows: Vec<Vec<Value>>,
+    totals: Option<Vec<Value>>,
+}
+
+/// Software maintenance over three years: the scheduled billing for `year`,
+/// then the next two years with each client's annual increase applied.
+/// Covers perpetual clients unless another billing model is asked for.
+fn sm_forecast(converter: &mut Converter, clients: &[ReportClient], year: i32) -> Result<ReportTable> {
+    let mut rows = Vec::new();
+    for forecast_year in year..year + 3 {
+        let (mut count, mut users, mut revenue) = (0, 0, 0.0);
+        for client in clients.iter().filter(|c| c.is_billed_in(forecast_year)) {
+            count += 1;
+            users += client.users;
+            let escalation = client.escalation(year, forecast_year);
+            for month in 0..12 {
+                revenue += converter.scheduled(client, forecast_year, month)? * escalation;
+            }
+        }
+        rows.push(vec![
+            json!(forecast_year),
+            json!(if forecast_year == year {
+                "Scheduled"
+            } else {
+                "Projected"
+            }),
+            json!(count),
+            json!(users),
+            converter.money(revenue),
+        ]);
+    }
+    Ok(ReportTable {
+        columns: vec![
+            column("year", "Year", "integer"),
+            column("basis", "Basis", "text"),
+            column("clients", "Clients", "integer"),
+            column("users", "Users", "integer"),
+            column("revenue", format!("S&M revenue ({})", converter.currency), "amount"),
+        ],
+        rows,
+        totals: None,
+    })
+}
+
+/// Scheduled billing for the year by billing model and client currency.
+fn revenue(converter: &mut Converter, clients: &[ReportClient], year: i32) -> Result<ReportTable> {
+    struct Group {
+        billing_model: String,
+        currency: String,
+        clients: i32,
+        amount: f64,
+        converted: f64,
+    }
+    let mut groups: Vec<Group> = Vec::new();
+    for client in clients.iter().filter(|c| c.is_billed_in(year)) {
+        let (amount, converted) = converter.year(client, year)?;
+        let position = groups
+            .iter()
+            .position(|g| g.billing_model == client.billing_model && g.currency == client.currency);
+        let group = match position {
+            Some(index) => &mut groups[index],
+            None => {
+                groups.push(Group {
+                    billing_model: client.billing_model.clone(),
+                    currency: client.currency.clone(),
+                    clients: 0,
+                    amount: 0.0,
+                    converted: 0.0,
+                });
+                groups.last_mut().unwrap()
+            }
+        };
+        group.clients += 1;
+        group.amount += amount;
+        group.converted += converted;
+    }
+    groups.sort_by(|a, b| (&a.billing_model, &a.currency).cmp(&(&b.billing_model, &b.currency)));
+    let total_clients: i32 = groups.iter().map(|g| g.clients).sum();
+    let total: f64 = groups.iter().map(|g| g.converted).sum();
+    Ok(ReportTable {
+        columns: vec![
+            column("billing_model", "Billing model", "text"),
+            column("currency", "Currency", "text"),
+            column("clients", "Clients", "integer"),
+            column("revenue", "Revenue", "amount"),
+            column("converted", format!("Revenue ({})", converter.currency), "amount"),
+            column("share", "Share %", "percent"),
+        ],
+        rows: groups
+            .iter()
+            .map(|group| {
+                vec![
+                    json!(group.billing_model),
+                    json!(group.currency),
+                    json!(group.clients),
+                    json!(round_amount(group.amount, &group.currency)),
+                    converter.money(group.converted),
+                    percent(group.converted, total),
+                ]
+            })
+            .collect(),
+        totals: Some(vec![
+            json!("Total"),
+            Value::Null,
+            json!(total_clients),
+            Value::Null,
+            converter.money(total),
+            Value::Null,
+        ]),
+    })
+}
+
+/// Every client, inactive ones included, with its billing for the year.
+fn client_summary(converter: &mut Converter, clients: &[ReportClient], year: i32) -> Result<ReportTable> {
+    let (mut users, mut total) = (0, 0.0);
+    let mut rows = Vec::new();
+    for client in clients {
+        let (amount, converted) = converter.year(client, year)?;
+        if client.is_active {
+            users += client.users;
+        }
+        total += converted;
+        rows.push(vec![
+            json!(client.client_name),
+            json!(client.debt_code),
+            json!(client.partner_name),
+            json!(client.users),
+            json!(client.billing_model),
+            json!(client.currency),
+            json!(client.deal_start_date),
+            json!(round_amount(amount, &client.currency)),
+            converter.money(converted),
+            json!(if client.is_active { "Yes" } else { "No" }),
+        ]);
+    }
+    Ok(ReportTable {
+        columns: vec![
+            column("client_name", "Client", "text"),
+            column("debt_code", "Debt code", "text"),
+            column("partner", "Partner", "text"),
+            column("users", "Users", "integer"),
+            column("billing_model", "Billing model", "text"),
+            column("currency", "Currency", "text"),
+            column("deal_start_date", "Deal start", "text"),
+            column("revenue", format!("{} billing", year), "amount"),
+            column(
+                "converted",
+                format!("{} billing ({})", year, converter.currency),
+                "amount",
+            ),
+            column("active", "Active", "text"),
+        ],
+        totals: Some(vec![
+            json!("Total"),
+            Value::Null,
+            Value::Null,
+            json!(users),
+            Value::Null,
+            Value::Null,
+            Value::Null,
+            Value::Null,
+            converter.money(total),
+            Value::Null,
+        ]),
+        rows,
+    })
+}
+
+/// Scheduled billing for each month of the year.
+fn monthly_breakdown(converter: &mut Converter, clients: &[ReportClient], year: i32) -> Result<ReportTable> {
+    let mut rows = Vec::new();
+    let mut total = 0.0;
+    for (month, name) in MONTH_NAMES.iter().enumerate() {
+        let (mut count, mut users, mut revenue) = (0, 0, 0.0);
+        for client in clients.iter().filter(|c| c.is_billed(year, month)) {
+            count += 1;
+            users += client.users;
+            revenue += converter.scheduled(client, year, month)?;
+        }
+        total += revenue;
+        rows.push(vec![
+            json!(format!("{} {}", name, year)),
+            json!(count),
+            json!(users),
+            converter.money(revenue),
+        ]);
+    }
+    Ok(ReportTable {
+        columns: vec![
+            column("month", "Month", "text"),
+            column("clients", "Clients billed", "integer"),
+            column("users", "Users", "integer"),
+            column("revenue", format!("Revenue ({})", converter.currency), "amount"),
+        ],
+        rows,
+        totals: Some(vec![json!("Total"), Value::Null, Value::Null, converter.money(total)]),
+    })
+}
+
+/// The billable share of a licence's annual value in `year`. Perpetual S&M
+/// is aligned to the anniversary month: a licence added before it is billed
+/// up to the anniversary, one added after it is free for the rest of that
+/// year and billed up to the next anniversary the year after. Other models
+/// bill from the licence's start month.
+fn licence_share(billing_model: &str, anniversary_month: i32, start: (i32, i32), year: i32) -> f64 {
+    let (start_year, start_month) = start;
+    if year < start_year {
+        return 0.0;
+    }
+    if billing_model != "perpetual" {
+        return if year == start_year {
+            f64::from(13 - start_month) / 12.0
+        } else {
+            1.0
+        };
+    }
+    match (year - start_year, start_month <= anniversary_month) {
+        (0, true) => f64::from(anniversary_month - start_month) / 12.0,
+        (0, false) => 0.0,
+        (1, false) => f64::from(12 - start_month + anniversary_month) / 12.0,
+        _ => 1.0,
+    }
+}
+
+/// Licence counts per client with the additional licences billable in the
+/// year, prorated to the anniversary month.
+fn licenses(converter: &mut Converter, conn: &Connection, clients: &[ReportClient], year: i32) -> Result<ReportTable> {
+    let mut stmt = conn.prepare(
+        "SELECT client_id, quantity, price_per_unit, start_date
+         FROM additional_licenses
+         WHERE is_active = 1 AND substr(start_date, 1, 4) <= ?1",
+    )?;
+    let mut additional: HashMap<String, Vec<(i32, f64, String)>> = HashMap::new();
+    for row in stmt.query_map(params![year.to_string()], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, i32>(1)?,
+            row.get::<_, f64>(2)?,
+            row.get::<_, String>(3)?,
+        ))
+    })? {
+        let (client_id, quantity, price, start_date) = row?;
+        additional
+            .entry(client_id)
+            .or_default()
+            .push((quantity, price, start_date));
+    }
+
+    let mut rows = Vec::new();
+    let (mut users, mut extra_users, mut total) = (0, 0, 0.0);
+    for client in clients
+        .iter()
+        .filter(|c| c.is_billed_in(year) || additional.contains_key(&c.id))
+    {
+        let anniversary = client.anniversary_month.filter(|m| (1..=12).contains(m)).unwrap_or(1);
+        let (mut quantity, mut annual, mut billable) = (0, 0.0, 0.0);
+        for (count, price, start_date) in additional.get(&client.id).into_iter().flatten() {
+            let Some(start) = start_date.get(..7).and_then(month_index) else {
+                continue;
+            };
+            let value = f64::from(*count) * price;
+            quantity += count;
+            annual += value;
+            billable += value * licence_share(&client.billing_model, anniversary, (start / 12, start % 12 + 1), year);
+        }
+        let months_billed = (0..12).filter(|&m| client.is_billed(year, m)).count();
+        let converted = converter.convert(billable, &client.currency, &format!("{:04}-12-01", year))?;
+        users += client.users;
+        extra_users += quantity;
+        total += converted;
+        rows.push(vec![
+            json!(client.client_name),
+            json!(client.debt_code),
+            json!(client.billing_model),
+            json!(client.users),
+            json!(client.deal_start_date),
+            json!(client.anniversary_month),
+            json!(months_billed),
+            json!(quantity),
+            json!(round_amount(annual, &client.currency)),
+            json!(round_amount(billable, &client.currency)),
+            json!(client.currency),
+            converter.money(converted),
+        ]);
+    }
+    Ok(ReportTable {
+        columns: vec![
+            column("client_name", "Client", "text"),
+            column("debt_code", "Debt code", "text"),
+            column("billing_model", "Billing model", "text"),
+            column("users", "Users", "integer"),
+            column("deal_start_date", "Deal start", "text"),
+            column("anniversary_month", "Anniversary month", "integer"),
+            column("months_billed", "Months billed", "integer"),
+            column("additional_licenses", "Additional licences", "integer"),
+            column("annual_value", "Annual licence value", "amount"),
+            column("billable", format!("{} licence billing", year), "amount"),
+            column("currency", "Currency", "text"),
+            column(
+                "converted",
+                format!("{} licence billing ({})", year, converter.currency),
+                "amount",
+            ),
+        ],
+        rows,
+        totals: Some(vec![
+            json!("Total"),
+            Value::Null,
+            Value::Null,
+            json!(users),
+            Value::Null,
+            Value::Null,
+            Value::Null,
+            json!(extra_users),
+            Value::Null,
+            Value::Null,
+            Value::Null,
+            converter.money(total),
+        ]),
+    })
+}
+
+/// Invoices for billing months in the year by where they stand: not yet
+/// issued, issued (net of credit and debit notes), paid and outstanding.
+/// Only VAR clients are invoiced, so direct clients do not appear.
+fn payment_status(
+    converter: &mut Converter,
+    conn: &Connection,
+    clients: &[ReportClient],
+    year: i32,
+) -> Result<ReportTable> {
+    let mut stmt = conn.prepare(
+        "SELECT i.var_client_id, i.invoice_status, COALESCE(i.invoice_date, i.billing_month || '-01'),
+                i.client_revenue,
+                i.client_revenue + COALESCE((SELECT SUM(CASE WHEN n.note_type = 'debit' THEN n.amount ELSE -n.amount END)
+                                             FROM credit_notes n WHERE n.invoice_id = i.id), 0),
+                COALESCE((SELECT SUM(a.amount) FROM payment_allocations a WHERE a.invoice_id = i.id), 0)
+         FROM var_client_invoices i
+         WHERE substr(i.billing_month, 1, 4) = ?1 AND i.invoice_status NOT IN ('void', 'cancelled')",
+    )?;
+    let invoices = stmt
+        .query_map(params![year.to_string()], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, f64>(3)?,
+                row.get::<_, f64>(4)?,
+                row.get::<_, f64>(5)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>>>()?;
+
+    // (invoices, amount) for: not issued, issued, paid, outstanding.
+    let mut lines = [(0, 0.0); 4];
+    let currencies: HashMap<&str, &str> = clients
+        .iter()
+        .filter(|c| c.partner_name.is_some())
+        .map(|c| (c.id.as_str(), c.currency.as_str()))
+        .collect();
+    for (client_id, status, date, revenue, net, paid) in &invoices {
+        let Some(currency) = currencies.get(client_id.as_str()) else {
+            continue;
+        };
+        if matches!(status.as_str(), "draft" | "pending") {
+            lines[0].0 += 1;
+            lines[0].1 += converter.convert(*revenue, currency, date)?;
+            continue;
+        }
+        let net = converter.convert(*net, currency, date)?;
+        let paid = converter.convert(*paid, currency, date)?;
+        lines[1] = (lines[1].0 + 1, lines[1].1 + net);
+        if paid > 0.0 {
+            lines[2] = (lines[2].0 + 1, lines[2].1 + paid);
+        }
+        if (net - paid).abs() >= 0.005 {
+            lines[3] = (lines[3].0 + 1, lines[3].1 + net - paid);
+        }
+    }
+    let issued = lines[1].1;
+    let labels = ["Not yet invoiced", "Invoiced", "Paid", "Outstanding"];
+    Ok(ReportTable {
+        columns: vec![
+            column("status", "Status", "text"),
+            column("invoices", "Invoices", "integer"),
+            column("amount", format!("Amount ({})", converter.currency), "amount"),
+            column("share", "% of invoiced", "percent"),
+        ],
+        rows: labels
+            .iter()
+            .zip(lines)
+            .enumerate()
+            .map(|(index, (label, (count, amount)))| {
+                vec![
+                    json!(label),
+                    json!(count),
+                    converter.money(amount),
+                    if index < 2 {
+                        Value::Null
+                    } else {
+                        percent(amount, issued)
+                    },
+                ]
+            })
+            .collect(),
+        totals: None,
+    })
+}
+
+/// The year against the one before, with next year projected from each
+/// client's schedule and annual increase.
+fn growth_metrics(converter: &mut Converter, clients: &[ReportClient], year: i32) -> Result<ReportTable> {
+    struct Figures {
+        revenue: f64,
+        clients: i32,
+        users: i32,
+        new_clients: i32,
+        churned_clients: i32,
+    }
+    let mut figures = Vec::new();
+    for figure_year in [year - 1, year, year + 1] {
+        let mut totals = Figures {
+            revenue: 0.0,
+            clients: 0,
+            users: 0,
+            new_clients: 0,
+            churned_clients: 0,
+        };
+        let (start, end) = (figure_year * 12, figure_year * 12 + 11);
+        for client in clients {
+            if client.is_billed_in(figure_year) {
+                totals.clients += 1;
+                totals.users += client.users;
+                let escalation = client.escalation(year, figure_year);
+                for month in 0..12 {
+                    totals.revenue += converter.scheduled(client, figure_year, month)? * escalation;
+                }
+            }
+            if (start..=end).contains(&client.first_month) {
+                totals.new_clients += 1;
+            }
+            if (start..=end).contains(&client.end_month) {
+                totals.churned_clients += 1;
+            }
+        }
+        figures.push(totals);
+    }
+    let [prior, current, projected] = [&figures[0], &figures[1], &figures[2]];
+    let count_row = |label: &str, value: fn(&Figures) -> i32| {
+        vec![
+            json!(label),
+            json!(value(prior)),
+            json!(value(current)),
+            percent(f64::from(value(current) - value(prior)), f64::from(value(prior))),
+            json!(value(projected)),
+        ]
+    };
+    let rows = vec![
+        vec![
+            json!(format!("Revenue ({})", converter.currency)),
+            converter.money(prior.revenue),
+            converter.money(current.revenue),
+            percent(current.revenue - prior.revenue, prior.revenue),
+            converter.money(projected.revenue),
+        ],
+        count_row("Clients billed", |f| f.clients),
+        count_row("Users", |f| f.users),
+        count_row("New clients", |f| f.new_clients),
+        count_row("Churned clients", |f| f.churned_clients),
+    ];
+    Ok(ReportTable {
+        columns: vec![
+            column("metric", "Metric", "text"),
+            column("prior", (year - 1).to_string(), "number"),
+            column("current", year.to_string(), "number"),
+            column("change", "Change %", "percent"),
+            column("projected", format!("{} projected", year + 1), "number"),
+        ],
+        rows,
+        totals: None,
+    })
+}
+
+fn report_title(report_type: &str) -> &'static str {
+    match report_type {
+        "sm-forecast" => "S&M Forecast",
+        "revenue" => "Revenue Analysis",
+        "client-summary" => "Client Summary",
+        "monthly-breakdown" => "Monthly Breakdown",
+        "licenses" => "License Audit",
+        "payment-status" => "Payment Status",
+        _ => "Growth Metrics",
+    }
+}
+
+impl Database {
+    /// Builds one of the `REPORT_TYPES` from the stored clients, licences and
+    /// invoices. Billing comes from each client's monthly schedule between its
+    /// deal start and deactivation.
+    pub fn get_report(&self, report_type: &str, mut parameters: ReportParameters) -> Result<ReportResult> {
+        if !REPORT_TYPES.contains(&report_type) {
+            return Err(rule_violation(format!(
+                "Unknown report '{}'; expected one of {}",
+                report_type,
+                REPORT_TYPES.join(", ")
+            )));
+        }
+        parameters.currency = match parameters.currency.as_deref().filter(|c| !c.trim().is_empty()) {
+            Some(currency) => Some(checked_currency(currency)?),
+            None => None,
+        };
+        parameters.billing_model = parameters.billing_model.filter(|m| !m.is_empty());
+        parameters.var_partner_id = parameters.var_partner_id.filter(|p| !p.is_empty());
+        if report_type == "sm-forecast" && parameters.billing_model.is_none() {
+            parameters.billing_model = Some("perpetual".to_string());
+        }
+
+        let conn = self.conn.lock().unwrap();
+        let year = match parameters.year {
+            Some(year) if (1000..=9999).contains(&year) => year,
+            Some(year) => return Err(rule_violation(format!("{} is not a valid year", year))),
+            None => conn.query_row("SELECT CAST(strftime('%Y', 'now') AS INTEGER)", [], |row| row.get(0))?,
+        };
+        let clients = load_clients(&conn, &parameters)?;
+        let mut converter = Converter {
+            conn: &conn,
+            currency: match &parameters.currency {
+                Some(currency) => currency.clone(),
+                None => reporting_currency(&conn)?,
+            },
+            rates: HashMap::new(),
+            missing: false,
+        };
+
+        let table = match report_type {
+            "sm-forecast" => sm_forecast(&mut converter, &clients, year)?,
+            "revenue" => revenue(&mut converter, &clients, year)?,
+            "client-summary" => client_summary(&mut converter, &clients, year)?,
+            "monthly-breakdown" => monthly_breakdown(&mut converter, &clients, year)?,
+            "licenses" => licenses(&mut converter, &conn, &clients, year)?,
+            "payment-status" => payment_status(&mut converter, &conn, &clients, year)?,
+            _ => growth_metrics(&mut converter, &clients, year)?,
+        };
+        Ok(ReportResult {
+            report_type: report_type.to_string(),
+            title: report_title(report_type).to_string(),
+            year,
+            currency: converter.currency,
+            billing_model: parameters.billing_model,
+            var_partner_id: parameters.var_partner_id,
+            columns: table.columns,
+            rows: table.rows,
+            totals: table.totals,
+            has_missing_rates: converter.missing,
+        })
+    }
+}
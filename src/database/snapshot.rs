@@ -0,0 +1,254 @@
+use super::{current_timestamp, debt_codes, rule_violation, Database};
+use rusqlite::types::{Value as SqlValue, ValueRef};
+use rusqlite::{params_from_iter, Connection, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+const SNAPSHOT_FORMAT: &str = "buildsmart-billing-snapshot";
+
+/// Bumped whenever a snapshot written by this version could not be loaded by
+/// an older one. Snapshots from older versions load, with newer columns left
+/// at their defaults.
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// Working tables that are not part of the data being moved.
+const TRANSIENT_TABLES: [&str; 1] = ["client_import_previews"];
+
+/// Columns replaced with a numbered placeholder in an anonymized snapshot.
+const PSEUDONYM_COLUMNS: [(&str, &str, &str); 3] = [
+    ("clients", "client_name", "Client"),
+    ("var_clients", "client_name", "VAR Client"),
+    ("var_partners", "name", "Partner"),
+];
+
+/// Free-text columns that may hold names or contact details, cleared in an
+/// anonymized snapshot.
+const REDACTED_COLUMNS: [(&str, &str); 17] = [
+    ("clients", "comments"),
+    ("var_clients", "comments"),
+    ("var_partners", "contact_person"),
+    ("var_partners", "email"),
+    ("var_partners", "phone"),
+    ("var_client_invoices", "notes"),
+    ("var_invoice_status_history", "note"),
+    ("credit_notes", "reason"),
+    ("commission_adjustments", "reason"),
+    ("commission_payouts", "reference"),
+    ("commission_payouts", "notes"),
+    ("payments", "reference"),
+    ("payments", "notes"),
+    ("bank_transactions", "description"),
+    ("bank_transactions", "reference"),
+    ("dunning_notices", "document"),
+    ("var_invoice_month_tracking", "invoiced_by"),
+];
+
+const REDACTED: &str = "[redacted]";
+
+/// A copy of every table, keyed by table name. Each row maps column names to
+/// their stored values; blobs are written as arrays of bytes.
+#[derive(Debug, Serialize, Deserialize)]
+struct DatabaseSnapshot {
+    format: String,
+    version: u32,
+    exported_at: String,
+    anonymized: bool,
+    tables: BTreeMap<String, Vec<Map<String, Value>>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SnapshotTableCount {
+    pub table_name: String,
+    pub rows: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SnapshotSummary {
+    pub version: u32,
+    pub exported_at: String,
+    pub anonymized: bool,
+    pub tables: Vec<SnapshotTableCount>,
+}
+
+impl DatabaseSnapshot {
+    fn summary(&self) -> SnapshotSummary {
+        SnapshotSummary {
+            version: self.version,
+            exported_at: self.exported_at.clone(),
+            anonymized: self.anonymized,
+            tables: self
+                .tables
+                .iter()
+                .map(|(table, rows)| SnapshotTableCount {
+                    table_name: table.clone(),
+                    rows: rows.len(),
+                })
+                .collect(),
+        }
+    }
+}
+
+fn data_tables(conn: &Connection) -> Result<Vec<String>> {
+    let mut stmt =
+        conn.prepare("SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name")?;
+    let tables = stmt.query_map([], |row| row.get::<_, String>(0))?;
+    tables
+        .filter(|table| !matches!(table, Ok(name) if TRANSIENT_TABLES.contains(&name.as_str())))
+        .collect()
+}
+
+fn table_columns(conn: &Connection, table: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({table})"))?;
+    let columns = stmt.query_map([], |row| row.get::<_, String>(1))?;
+    columns.collect()
+}
+
+fn to_json(value: ValueRef) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(number) => Value::from(number),
+        ValueRef::Real(number) => Value::from(number),
+        ValueRef::Text(text) => Value::from(String::from_utf8_lossy(text)),
+        ValueRef::Blob(bytes) => Value::from(bytes),
+    }
+}
+
+fn from_json(table: &str, column: &str, value: &Value) -> Result<SqlValue> {
+    Ok(match value {
+        Value::Null => SqlValue::Null,
+        Value::Bool(flag) => SqlValue::Integer(*flag as i64),
+        Value::Number(number) => match number.as_i64() {
+            Some(integer) => SqlValue::Integer(integer),
+            None => SqlValue::Real(number.as_f64().unwrap_or_default()),
+        },
+        Value::String(text) => SqlValue::Text(text.clone()),
+        Value::Array(bytes) => SqlValue::Blob(
+            bytes
+                .iter()
+                .map(|byte| byte.as_u64().and_then(|byte| u8::try_from(byte).ok()))
+                .collect::<Option<_>>()
+                .ok_or_else(|| rule_violation(format!("{table}.{column} holds an array that is not bytes")))?,
+        ),
+        Value::Object(_) => return Err(rule_violation(format!("{table}.{column} holds an object"))),
+    })
+}
+
+fn read_table(conn: &Connection, table: &str, anonymize: bool) -> Result<Vec<Map<String, Value>>> {
+    let columns = table_columns(conn, table)?;
+    let mut stmt = conn.prepare(&format!("SELECT * FROM {table} ORDER BY rowid"))?;
+    let mut rows = stmt.query([])?;
+    let mut records = Vec::new();
+    while let Some(row) = rows.next()? {
+        let mut record = Map::new();
+        for (index, column) in columns.iter().enumerate() {
+            let mut value = to_json(row.get_ref(index)?);
+            if anonymize && !value.is_null() {
+                if let Some((_, _, label)) = PSEUDONYM_COLUMNS.iter().find(|(t, c, _)| *t == table && c == column) {
+                    value = Value::from(format!("{} {}", label, records.len() + 1));
+                } else if REDACTED_COLUMNS.contains(&(table, column.as_str())) {
+                    value = Value::from(REDACTED);
+                }
+            }
+            record.insert(column.clone(), value);
+        }
+        records.push(record);
+    }
+    Ok(records)
+}
+
+fn write_table(conn: &Connection, table: &str, rows: &[Map<String, Value>]) -> Result<()> {
+    let columns = table_columns(conn, table)?;
+    for row in rows {
+        if let Some(unknown) = row.keys().find(|column| !columns.contains(column)) {
+            return Err(rule_violation(format!(
+                "The snapshot has an unknown column {table}.{unknown}"
+            )));
+        }
+        let names: Vec<&str> = row.keys().map(String::as_str).collect();
+        let values = row
+            .iter()
+            .map(|(column, value)| from_json(table, column, value))
+            .collect::<Result<Vec<_>>>()?;
+        let placeholders = (1..=names.len()).map(|index| format!("?{index}")).collect::<Vec<_>>();
+        conn.execute(
+            &format!(
+                "INSERT INTO {table} ({}) VALUES ({})",
+                names.join(", "),
+                placeholders.join(", ")
+            ),
+            params_from_iter(values),
+        )?;
+    }
+    Ok(())
+}
+
+impl Database {
+    /// Writes every table, inactive rows included, to one JSON document. An
+    /// anonymized snapshot replaces client and partner names with numbered
+    /// placeholders and clears free text that may identify people.
+    pub fn export_snapshot(&self, path: &Path, anonymize: bool) -> Result<SnapshotSummary> {
+        let conn = self.conn.lock().unwrap();
+        let mut snapshot = DatabaseSnapshot {
+            format: SNAPSHOT_FORMAT.to_string(),
+            version: SNAPSHOT_VERSION,
+            exported_at: current_timestamp(&conn)?,
+            anonymized: anonymize,
+            tables: BTreeMap::new(),
+        };
+        for table in data_tables(&conn)? {
+            let rows = read_table(&conn, &table, anonymize)?;
+            snapshot.tables.insert(table, rows);
+        }
+        let file =
+            File::create(path).map_err(|e| rule_violation(format!("Cannot create {}: {}", path.display(), e)))?;
+        serde_json::to_writer_pretty(BufWriter::new(file), &snapshot).map_err(|e| rule_violation(e.to_string()))?;
+        Ok(snapshot.summary())
+    }
+
+    /// Loads a snapshot into this database, which must hold no data yet. The
+    /// whole snapshot is loaded or nothing is.
+    pub fn import_snapshot(&self, path: &Path) -> Result<SnapshotSummary> {
+        let file = File::open(path).map_err(|e| rule_violation(format!("Cannot open {}: {}", path.display(), e)))?;
+        let snapshot: DatabaseSnapshot = serde_json::from_reader(BufReader::new(file))
+            .map_err(|e| rule_violation(format!("Not a database snapshot: {}", e)))?;
+        if snapshot.format != SNAPSHOT_FORMAT {
+            return Err(rule_violation("Not a database snapshot"));
+        }
+        if snapshot.version > SNAPSHOT_VERSION {
+            return Err(rule_violation(format!(
+                "The snapshot is version {}; this version of the app reads up to version {}",
+                snapshot.version, SNAPSHOT_VERSION
+            )));
+        }
+
+        let mut conn = self.conn.lock().unwrap();
+        let tables = data_tables(&conn)?;
+        for table in &tables {
+            let rows: i64 = conn.query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |row| row.get(0))?;
+            if rows > 0 {
+                return Err(rule_violation(format!(
+                    "Snapshots can only be imported into an empty database; {} already has data",
+                    table
+                )));
+            }
+        }
+        if let Some(unknown) = snapshot.tables.keys().find(|table| !tables.contains(table)) {
+            return Err(rule_violation(format!("The snapshot has an unknown table {}", unknown)));
+        }
+
+        let tx = conn.transaction()?;
+        // Rows reference each other across tables; check keys once all are in.
+        tx.execute_batch("PRAGMA defer_foreign_keys = ON")?;
+        debt_codes::drop_constraints(&tx)?;
+        for (table, rows) in &snapshot.tables {
+            write_table(&tx, table, rows)?;
+        }
+        debt_codes::create_tables(&tx)?;
+        tx.commit()?;
+        Ok(snapshot.summary())
+    }
+}
@@ -0,0 +1,102 @@
+//! Fixtures shared by the database unit tests: an in-memory database and
+//! minimal partners, clients and invoices.
+
+use super::{Database, VarClient, VarClientInvoice, VarPartner};
+use std::path::PathBuf;
+
+pub(crate) fn test_db() -> Database {
+    Database::new(PathBuf::from(":memory:")).unwrap()
+}
+
+pub(crate) fn add_partner(db: &Database, id: &str, commission_rate: f64) {
+    db.add_var_partner(VarPartner {
+        id: id.to_string(),
+        name: format!("Partner {}", id),
+        region: "Gauteng".to_string(),
+        contact_person: "Contact".to_string(),
+        email: format!("{}@example.com", id),
+        phone: None,
+        commission_rate,
+        is_active: true,
+    })
+    .unwrap();
+}
+
+/// A client billed 1,000 a month since January 2026, with debt code
+/// `D<ID>` (upper-cased).
+pub(crate) fn add_client(db: &Database, id: &str, var_partner_id: &str, commission_rate: f64, currency: &str) {
+    db.add_var_client(client(id, var_partner_id, commission_rate, currency))
+        .unwrap();
+}
+
+pub(crate) fn client(id: &str, var_partner_id: &str, commission_rate: f64, currency: &str) -> VarClient {
+    VarClient {
+        id: id.to_string(),
+        client_name: format!("Client {}", id),
+        debt_code: Some(format!("D{}", id.to_uppercase())),
+        users: 10,
+        billing_model: "subscription".to_string(),
+        currency: currency.to_string(),
+        jan: 1000.0,
+        feb: 1000.0,
+        mar: 1000.0,
+        apr: 1000.0,
+        may: 1000.0,
+        jun: 1000.0,
+        jul: 1000.0,
+        aug: 1000.0,
+        sep: 1000.0,
+        oct: 1000.0,
+        nov: 1000.0,
+        dec: 1000.0,
+        total: 12000.0,
+        comments: None,
+        deal_start_date: "2026-01-01".to_string(),
+        anniversary_month: None,
+        billing_frequency: None,
+        installment_months: None,
+        monthly_factor: None,
+        implementation_fee: None,
+        implementation_months: None,
+        implementation_start_date: None,
+        implementation_complete_date: None,
+        subscription_duration: None,
+        var_partner_id: var_partner_id.to_string(),
+        commission_rate,
+        is_active: true,
+        created_at: "2026-01-01".to_string(),
+        custom_increase_rate: None,
+        future_year_data: None,
+        base_year_data: None,
+    }
+}
+
+/// A pending invoice dated the first of `billing_month`, at the client's
+/// commission rate as passed in.
+pub(crate) fn add_invoice(
+    db: &Database,
+    id: &str,
+    var_client_id: &str,
+    var_partner_id: &str,
+    billing_month: &str,
+    client_revenue: f64,
+    commission_rate: f64,
+) {
+    db.create_var_client_invoice(VarClientInvoice {
+        id: id.to_string(),
+        var_client_id: var_client_id.to_string(),
+        var_partner_id: var_partner_id.to_string(),
+        billing_month: billing_month.to_string(),
+        users: 10,
+        client_revenue,
+        commission_rate,
+        commission_amount: client_revenue * commission_rate / 100.0,
+        invoice_date: Some(format!("{}-01", billing_month)),
+        invoice_status: "pending".to_string(),
+        notes: None,
+        created_at: String::new(),
+        updated_at: String::new(),
+        commission_source: None,
+    })
+    .unwrap();
+}
@@ -0,0 +1,871 @@
+//! File exports of backend-built documents. Each export writes to a path the
+//! user picked in the frontend.
+
+use crate::currency::{format_amount, minor_units};
+use crate::database::{
+    AccountingJournal, BillingDocument, ImportTemplate, PartnerPerformanceReport, PartnerStatement, PerformanceFigures,
+    ReportResult,
+};
+use crate::pdf::{Align, Column, PdfDocument, PdfImage, BODY_SIZE, LINE_HEIGHT};
+use rust_xlsxwriter::{Format, Workbook};
+use serde_json::Value;
+use std::path::Path;
+
+pub const EXPORT_FORMATS: &[&str] = &["csv", "xlsx", "pdf"];
+
+/// Accounting packages journals can be exported for.
+pub const JOURNAL_FORMATS: &[&str] = &["xero", "sage", "quickbooks"];
+
+/// A spreadsheet cell; numbers stay numeric in XLSX.
+enum Cell {
+    Text(String),
+    Number(f64),
+    Heading(String),
+}
+
+fn text(value: &str) -> Cell {
+    Cell::Text(value.to_string())
+}
+
+/// The statement laid out as rows, shared by the CSV and XLSX exports.
+fn statement_rows(statement: &PartnerStatement) -> Vec<Vec<Cell>> {
+    let converted = format!("Amount ({})", statement.currency);
+    let mut rows = vec![
+        vec![Cell::Heading("Commission statement".to_string())],
+        vec![text("Partner"), text(&statement.partner_name)],
+        vec![text("Region"), text(&statement.region)],
+        vec![text("Period"), text(&statement.period_from), text(&statement.period_to)],
+        vec![text("Currency"), text(&statement.currency)],
+        Vec::new(),
+        vec![Cell::Heading("Summary".to_string())],
+        vec![text("Opening balance"), Cell::Number(statement.opening_balance)],
+        vec![text("Commissions earned"), Cell::Number(statement.commissions_earned)],
+        vec![text("Adjustments"), Cell::Number(statement.adjustments)],
+        vec![text("Payments made"), Cell::Number(-statement.payments_made)],
+        vec![text("Closing balance"), Cell::Number(statement.closing_balance)],
+    ];
+    if statement.has_missing_rates {
+        rows.push(vec![text(
+            "Some amounts have no exchange rate and are excluded from the totals",
+        )]);
+    }
+
+    rows.push(Vec::new());
+    rows.push(
+        [
+            "Client",
+            "Debt code",
+            "Currency",
+            "Invoices",
+            "Users",
+            "Revenue",
+            "Commission",
+            converted.as_str(),
+        ]
+        .iter()
+        .map(|h| Cell::Heading(h.to_string()))
+        .collect(),
+    );
+    for line in &statement.client_lines {
+        rows.push(vec![
+            text(&line.client_name),
+            text(line.debt_code.as_deref().unwrap_or("")),
+            text(&line.currency),
+            Cell::Number(line.invoice_count as f64),
+            Cell::Number(line.users as f64),
+            Cell::Number(line.client_revenue),
+            Cell::Number(line.commission_amount),
+            Cell::Number(line.statement_amount),
+        ]);
+    }
+
+    for (title, entries) in [
+        ("Adjustments", &statement.adjustment_lines),
+        ("Payments", &statement.payment_lines),
+    ] {
+        rows.push(Vec::new());
+        rows.push(vec![Cell::Heading(title.to_string())]);
+        rows.push(
+            [
+                "Date",
+                "Reference",
+                "Description",
+                "Currency",
+                "Amount",
+                converted.as_str(),
+            ]
+            .iter()
+            .map(|h| Cell::Heading(h.to_string()))
+            .collect(),
+        );
+        for entry in entries {
+            rows.push(vec![
+                text(&entry.date),
+                text(&entry.reference),
+                text(&entry.description),
+                text(&entry.currency),
+                Cell::Number(entry.amount),
+                Cell::Number(entry.statement_amount),
+            ]);
+        }
+    }
+    rows
+}
+
+const PERFORMANCE_HEADINGS: &[&str] = &[
+    "Revenue",
+    "Prior revenue",
+    "Growth %",
+    "Active clients",
+    "Users",
+    "New clients",
+    "Churned clients",
+    "Commission",
+    "Commission %",
+];
+
+fn performance_cells(figures: &PerformanceFigures) -> Vec<Cell> {
+    let percent = |value: Option<f64>| value.map(Cell::Number).unwrap_or_else(|| text(""));
+    vec![
+        Cell::Number(figures.revenue),
+        Cell::Number(figures.prior_revenue),
+        percent(figures.revenue_growth_percent),
+        Cell::Number(figures.active_clients as f64),
+        Cell::Number(figures.users as f64),
+        Cell::Number(figures.new_clients as f64),
+        Cell::Number(figures.churned_clients as f64),
+        Cell::Number(figures.commission_cost),
+        percent(figures.commission_share_percent),
+    ]
+}
+
+/// The performance report laid out as rows, shared by the CSV and XLSX exports.
+fn performance_rows(report: &PartnerPerformanceReport) -> Vec<Vec<Cell>> {
+    let headings = |leading: &[&str]| -> Vec<Cell> {
+        leading
+            .iter()
+            .chain(PERFORMANCE_HEADINGS)
+            .map(|h| Cell::Heading(h.to_string()))
+            .collect()
+    };
+    let mut rows = vec![
+        vec![Cell::Heading("Partner performance".to_string())],
+        vec![text("Period"), text(&report.period_from), text(&report.period_to)],
+        vec![text("Compared with"), text(&report.prior_from), text(&report.prior_to)],
+        vec![text("Currency"), text(&report.currency)],
+    ];
+    if report.has_missing_rates {
+        rows.push(vec![text(
+            "Some amounts have no exchange rate and are excluded from the totals",
+        )]);
+    }
+
+    rows.push(Vec::new());
+    rows.push(headings(&["Rank", "Partner", "Region"]));
+    for partner in &report.partners {
+        let mut row = vec![
+            Cell::Number(partner.rank as f64),
+            text(&partner.name),
+            text(&partner.region),
+        ];
+        row.extend(performance_cells(&partner.figures));
+        rows.push(row);
+    }
+
+    rows.push(Vec::new());
+    rows.push(headings(&["Rank", "Region", "Partners"]));
+    for region in &report.regions {
+        let mut row = vec![
+            Cell::Number(region.rank as f64),
+            text(&region.region),
+            Cell::Number(region.partner_count as f64),
+        ];
+        row.extend(performance_cells(&region.figures));
+        rows.push(row);
+    }
+    let mut total = vec![text(""), Cell::Heading("Total".to_string()), text("")];
+    total.extend(performance_cells(&report.totals));
+    rows.push(total);
+    rows
+}
+
+/// A report as a plain table: headings, rows, then the totals row.
+fn report_rows(report: &ReportResult) -> Vec<Vec<Cell>> {
+    let cell = |value: &Value| match value {
+        Value::Number(number) => Cell::Number(number.as_f64().unwrap_or_default()),
+        Value::String(value) => Cell::Text(value.clone()),
+        Value::Null => Cell::Text(String::new()),
+        other => Cell::Text(other.to_string()),
+    };
+    let mut rows = vec![report
+        .columns
+        .iter()
+        .map(|column| Cell::Heading(column.title.clone()))
+        .collect()];
+    rows.extend(
+        report
+            .rows
+            .iter()
+            .chain(report.totals.iter())
+            .map(|row| row.iter().map(cell).collect()),
+    );
+    if report.has_missing_rates {
+        rows.push(vec![text(
+            "Some amounts have no exchange rate and are excluded from the totals",
+        )]);
+    }
+    rows
+}
+
+/// "2026-08-31" as "31/08/2026", or as "08/31/2026" with `month_first`.
+fn journal_date(date: &str, month_first: bool) -> String {
+    match (date.get(..4), date.get(5..7), date.get(8..10)) {
+        (Some(year), Some(month), Some(day)) if month_first => format!("{}/{}/{}", month, day, year),
+        (Some(year), Some(month), Some(day)) => format!("{}/{}/{}", day, month, year),
+        _ => date.to_string(),
+    }
+}
+
+fn journal_amount(amount: f64, currency: &str) -> String {
+    format!("{:.*}", minor_units(currency) as usize, amount)
+}
+
+/// Xero's manual journal import: lines sharing a narration and date form
+/// one journal, with debits positive and credits negative.
+fn xero_rows(journal: &AccountingJournal) -> Result<Vec<Vec<Cell>>, String> {
+    if journal.xero_tax_rate.is_empty() {
+        return Err("Set the Xero tax rate in the account mapping before exporting".to_string());
+    }
+    let mut rows = vec![["*Narration", "*Date", "Description", "*AccountCode", "*TaxRate", "*Amount"]
+        .iter()
+        .map(|title| text(title))
+        .collect()];
+    for entry in &journal.entries {
+        let narration = format!("{} {}", entry.reference, entry.contact_name);
+        for line in &entry.lines {
+            rows.push(vec![
+                text(&narration),
+                text(&journal_date(&entry.date, false)),
+                text(&entry.description),
+                text(&line.account_code),
+                text(&journal.xero_tax_rate),
+                text(&journal_amount(line.debit - line.credit, &journal.currency)),
+            ]);
+        }
+    }
+    Ok(rows)
+}
+
+/// Sage's audit trail import, posting each line as a journal debit (JD) or
+/// credit (JC) to a nominal account.
+fn sage_rows(journal: &AccountingJournal) -> Result<Vec<Vec<Cell>>, String> {
+    if journal.sage_tax_code.is_empty() {
+        return Err("Set the Sage tax code in the account mapping before exporting".to_string());
+    }
+    let mut rows = vec![[
+        "Type",
+        "Account Reference",
+        "Nominal A/C Ref",
+        "Department Code",
+        "Date",
+        "Reference",
+        "Details",
+        "Net Amount",
+        "Tax Code",
+        "Tax Amount",
+    ]
+    .iter()
+    .map(|title| text(title))
+    .collect()];
+    for entry in &journal.entries {
+        let details: String = format!("{} {}", entry.contact_name, entry.description).chars().take(60).collect();
+        for line in &entry.lines {
+            let (kind, amount) = if line.debit > 0.0 { ("JD", line.debit) } else { ("JC", line.credit) };
+            rows.push(vec![
+                text(kind),
+                text(""),
+                text(&line.account_code),
+                text(""),
+                text(&journal_date(&entry.date, false)),
+                text(&entry.reference),
+                text(&details),
+                text(&journal_amount(amount, &journal.currency)),
+                text(&journal.sage_tax_code),
+                text(&journal_amount(0.0, &journal.currency)),
+            ]);
+        }
+    }
+    Ok(rows)
+}
+
+/// QuickBooks Desktop IIF: tab-separated general journal transactions, each
+/// a TRNS line followed by SPL lines, with credits negative.
+fn write_iif(journal: &AccountingJournal, path: &Path) -> Result<(), String> {
+    let field = |value: &str| value.replace(['\t', '\n', '\r'], " ");
+    let columns = "TRNSTYPE\tDATE\tACCNT\tNAME\tAMOUNT\tDOCNUM\tMEMO";
+    let mut out = format!("!TRNS\t{}\n!SPL\t{}\n!ENDTRNS\n", columns, columns);
+    for entry in &journal.entries {
+        for (index, line) in entry.lines.iter().enumerate() {
+            out.push_str(&format!(
+                "{}\tGENERAL JOURNAL\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                if index == 0 { "TRNS" } else { "SPL" },
+                journal_date(&entry.date, true),
+                field(&line.account_code),
+                field(&entry.contact_name),
+                journal_amount(line.debit - line.credit, &journal.currency),
+                field(&entry.reference),
+                field(&entry.description)
+            ));
+        }
+        out.push_str("ENDTRNS\n");
+    }
+    std::fs::write(path, out).map_err(|e| e.to_string())
+}
+
+fn write_csv(rows: &[Vec<Cell>], path: &Path) -> Result<(), String> {
+    let mut writer = csv::WriterBuilder::new()
+        .flexible(true)
+        .from_path(path)
+        .map_err(|e| e.to_string())?;
+    for row in rows {
+        let record: Vec<String> = row
+            .iter()
+            .map(|cell| match cell {
+                Cell::Text(value) | Cell::Heading(value) => value.clone(),
+                Cell::Number(value) => value.to_string(),
+            })
+            .collect();
+        // Blank separator rows are dropped; csv would write them as `""`.
+        if !record.is_empty() {
+            writer.write_record(&record).map_err(|e| e.to_string())?;
+        }
+    }
+    writer.flush().map_err(|e| e.to_string())
+}
+
+fn write_xlsx(rows: &[Vec<Cell>], sheet_name: &str, path: &Path) -> Result<(), String> {
+    let mut workbook = Workbook::new();
+    let heading = Format::new().set_bold();
+    let number = Format::new().set_num_format("#,##0.00");
+    let worksheet = workbook.add_worksheet();
+    worksheet.set_name(sheet_name).map_err(|e| e.to_string())?;
+    worksheet.set_column_width(0, 28).map_err(|e| e.to_string())?;
+    worksheet.set_column_width(2, 28).map_err(|e| e.to_string())?;
+
+    for (row_index, row) in rows.iter().enumerate() {
+        for (col_index, cell) in row.iter().enumerate() {
+            let (row_index, col_index) = (row_index as u32, col_index as u16);
+            match cell {
+                Cell::Text(value) => worksheet.write_string(row_index, col_index, value),
+                Cell::Heading(value) => worksheet.write_string_with_format(row_index, col_index, value, &heading),
+                Cell::Number(value) => worksheet.write_number_with_format(row_index, col_index, *value, &number),
+            }
+            .map_err(|e| e.to_string())?;
+        }
+    }
+    workbook.save(path).map_err(|e| e.to_string())
+}
+
+fn write_statement_pdf(statement: &PartnerStatement, path: &Path) -> Result<(), String> {
+    let currency = statement.currency.as_str();
+    let mut pdf = PdfDocument::new();
+    let width = pdf.content_width();
+
+    pdf.heading("Commission Statement");
+    pdf.line(&statement.partner_name);
+    pdf.line(&format!("{} | {}", statement.region, statement.contact_person));
+    pdf.line(&statement.email);
+    pdf.line(&format!("Period: {} to {}", statement.period_from, statement.period_to));
+
+    pdf.subheading("Summary");
+    let summary_width = width / 2.0;
+    pdf.label_value(
+        "Opening balance",
+        &format_amount(statement.opening_balance, currency),
+        summary_width,
+        false,
+    );
+    pdf.label_value(
+        "Commissions earned",
+        &format_amount(statement.commissions_earned, currency),
+        summary_width,
+        false,
+    );
+    pdf.label_value(
+        "Adjustments",
+        &format_amount(statement.adjustments, currency),
+        summary_width,
+        false,
+    );
+    pdf.label_value(
+        "Payments made",
+        &format_amount(-statement.payments_made, currency),
+        summary_width,
+        false,
+    );
+    pdf.rule(summary_width);
+    pdf.label_value(
+        "Closing balance",
+        &format_amount(statement.closing_balance, currency),
+        summary_width,
+        true,
+    );
+    if statement.has_missing_rates {
+        pdf.line("Some amounts have no exchange rate and are excluded from the totals.");
+    }
+
+    pdf.subheading("Commission by client");
+    let client_columns = [
+        Column {
+            title: "Client",
+            width: 150.0,
+            align: Align::Left,
+        },
+        Column {
+            title: "Debt code",
+            width: 65.0,
+            align: Align::Left,
+        },
+        Column {
+            title: "Invoices",
+            width: 45.0,
+            align: Align::Right,
+        },
+        Column {
+            title: "Users",
+            width: 40.0,
+            align: Align::Right,
+        },
+        Column {
+            title: "Revenue",
+            width: 95.0,
+            align: Align::Right,
+        },
+        Column {
+            title: "Commission",
+            width: width - 395.0,
+            align: Align::Right,
+        },
+    ];
+    let client_rows: Vec<Vec<String>> = statement
+        .client_lines
+        .iter()
+        .map(|line| {
+            vec![
+                line.client_name.clone(),
+                line.debt_code.clone().unwrap_or_default(),
+                line.invoice_count.to_string(),
+                line.users.to_string(),
+                format_amount(line.client_revenue, &line.currency),
+                format_amount(line.statement_amount, currency),
+            ]
+        })
+        .collect();
+    pdf.table(&client_columns, &client_rows);
+
+    for (title, entries) in [
+        ("Adjustments", &statement.adjustment_lines),
+        ("Payments", &statement.payment_lines),
+    ] {
+        if entries.is_empty() {
+            continue;
+        }
+        pdf.subheading(title);
+        let columns = [
+            Column {
+                title: "Date",
+                width: 70.0,
+                align: Align::Left,
+            },
+            Column {
+                title: "Reference",
+                width: 80.0,
+                align: Align::Left,
+            },
+            Column {
+                title: "Description",
+                width: width - 250.0,
+                align: Align::Left,
+            },
+            Column {
+                title: "Amount",
+                width: 100.0,
+                align: Align::Right,
+            },
+        ];
+        let rows: Vec<Vec<String>> = entries
+            .iter()
+            .map(|entry| {
+                vec![
+                    entry.date.clone(),
+                    entry.reference.clone(),
+                    entry.description.clone(),
+                    format_amount(entry.statement_amount, currency),
+                ]
+            })
+            .collect();
+        pdf.table(&columns, &rows);
+    }
+
+    pdf.save(path).map_err(|e| e.to_string())
+}
+
+fn write_performance_pdf(report: &PartnerPerformanceReport, path: &Path) -> Result<(), String> {
+    let currency = report.currency.as_str();
+    let percent = |value: Option<f64>| value.map(|v| format!("{:.1}%", v)).unwrap_or_default();
+    let mut pdf = PdfDocument::new();
+    let width = pdf.content_width();
+
+    pdf.heading("Partner Performance");
+    pdf.line(&format!(
+        "Period: {} to {} (compared with {} to {})",
+        report.period_from, report.period_to, report.prior_from, report.prior_to
+    ));
+    pdf.line(&format!("Amounts in {}", currency));
+    if report.has_missing_rates {
+        pdf.line("Some amounts have no exchange rate and are excluded from the totals.");
+    }
+
+    for (title, rows) in [
+        (
+            "Partners",
+            report
+                .partners
+                .iter()
+                .map(|p| (p.rank.to_string(), p.name.clone(), &p.figures))
+                .collect::<Vec<_>>(),
+        ),
+        (
+            "Regions",
+            report
+                .regions
+                .iter()
+                .map(|r| (r.rank.to_string(), r.region.clone(), &r.figures))
+                .chain([(String::new(), "Total".to_string(), &report.totals)])
+                .collect(),
+        ),
+    ] {
+        pdf.subheading(title);
+        let columns = [
+            Column {
+                title: "#",
+                width: 25.0,
+                align: Align::Right,
+            },
+            Column {
+                title: "Name",
+                width: width - 410.0,
+                align: Align::Left,
+            },
+            Column {
+                title: "Revenue",
+                width: 85.0,
+                align: Align::Right,
+            },
+            Column {
+                title: "Growth",
+                width: 55.0,
+                align: Align::Right,
+            },
+            Column {
+                title: "Clients",
+                width: 45.0,
+                align: Align::Right,
+            },
+            Column {
+                title: "New",
+                width: 35.0,
+                align: Align::Right,
+            },
+            Column {
+                title: "Churned",
+                width: 50.0,
+                align: Align::Right,
+            },
+            Column {
+                title: "Commission",
+                width: 80.0,
+                align: Align::Right,
+            },
+            Column {
+                title: "Share",
+                width: 35.0,
+                align: Align::Right,
+            },
+        ];
+        let rows: Vec<Vec<String>> = rows
+            .into_iter()
+            .map(|(rank, name, figures)| {
+                vec![
+                    rank,
+                    name,
+                    format_amount(figures.revenue, currency),
+                    percent(figures.revenue_growth_percent),
+                    figures.active_clients.to_string(),
+                    figures.new_clients.to_string(),
+                    figures.churned_clients.to_string(),
+                    format_amount(figures.commission_cost, currency),
+                    percent(figures.commission_share_percent),
+                ]
+            })
+            .collect();
+        pdf.table(&columns, &rows);
+    }
+
+    pdf.save(path).map_err(|e| e.to_string())
+}
+
+fn quantity(value: f64) -> String {
+    if value.fract() == 0.0 {
+        format!("{}", value as i64)
+    } else {
+        format!("{:.2}", value)
+    }
+}
+
+fn write_document_pdf(document: &BillingDocument, path: &Path) -> Result<(), String> {
+    let currency = document.currency.as_str();
+    let company = &document.company;
+    let mut pdf = PdfDocument::new();
+    let width = pdf.content_width();
+
+    // Letterhead: the logo on the left, company details right-aligned.
+    let top = pdf.cursor();
+    let logo_height = match &document.logo {
+        Some(logo) => pdf.image(PdfImage::from_bytes(logo)?, 0.0, 160.0, 60.0),
+        None => 0.0,
+    };
+    pdf.text_right(width, &company.company_name, 12.0, true);
+    pdf.advance(16.0);
+    let mut details = company.address_lines.clone();
+    for (label, value) in [
+        ("Registration no. ", &company.registration_number),
+        ("VAT no. ", &company.vat_number),
+    ] {
+        if !value.trim().is_empty() {
+            details.push(format!("{}{}", label, value.trim()));
+        }
+    }
+    let contact: Vec<&str> = [&company.email, &company.phone, &company.website]
+        .into_iter()
+        .map(|value| value.trim())
+        .filter(|value| !value.is_empty())
+        .collect();
+    if !contact.is_empty() {
+        details.push(contact.join(" | "));
+    }
+    for detail in &details {
+        pdf.text_right(width, detail, BODY_SIZE, false);
+        pdf.advance(LINE_HEIGHT);
+    }
+    pdf.move_below(top - logo_height - LINE_HEIGHT);
+    pdf.advance(LINE_HEIGHT);
+
+    pdf.heading(&document.title);
+    let mut particulars = vec![
+        ("Reference", document.reference.clone()),
+        ("Date", document.issue_date.clone()),
+    ];
+    if let Some(due_date) = &document.due_date {
+        particulars.push(("Due date", due_date.clone()));
+    }
+    if let Some(period) = &document.period {
+        particulars.push(("Period", period.clone()));
+    }
+    let recipient: Vec<&str> = std::iter::once(document.recipient_name.as_str())
+        .chain(document.recipient_lines.iter().map(String::as_str))
+        .collect();
+    for index in 0..recipient.len().max(particulars.len()) {
+        if let Some(text) = recipient.get(index) {
+            pdf.text_at(0.0, text, BODY_SIZE, index == 0);
+        }
+        if let Some((label, value)) = particulars.get(index) {
+            pdf.text_at(width * 0.6, label, BODY_SIZE, true);
+            pdf.text_right(width, value, BODY_SIZE, false);
+        }
+        pdf.advance(LINE_HEIGHT);
+    }
+    pdf.advance(LINE_HEIGHT);
+
+    // Only the columns some line actually uses.
+    let lines = &document.lines;
+    let has_dates = lines.iter().any(|line| line.date.is_some());
+    let has_references = lines.iter().any(|line| line.reference.is_some());
+    let has_quantities = lines.iter().any(|line| line.quantity.is_some());
+    let has_prices = lines.iter().any(|line| line.unit_price.is_some());
+    let has_balances = lines.iter().any(|line| line.balance.is_some());
+    let column = |title, width, align| Column { title, width, align };
+    let mut columns = Vec::new();
+    if has_dates {
+        columns.push(column("Date", 58.0, Align::Left));
+    }
+    if has_references {
+        columns.push(column("Reference", 70.0, Align::Left));
+    }
+    columns.push(column("Description", 0.0, Align::Left));
+    if has_quantities {
+        columns.push(column("Qty", 30.0, Align::Right));
+    }
+    if has_prices {
+        columns.push(column("Unit price", 75.0, Align::Right));
+    }
+    columns.push(column("Amount", 80.0, Align::Right));
+    if has_balances {
+        columns.push(column("Balance", 80.0, Align::Right));
+    }
+    let used: f64 = columns.iter().map(|c| c.width).sum();
+    if let Some(description) = columns.iter_mut().find(|c| c.title == "Description") {
+        description.width = width - used;
+    }
+    let rows: Vec<Vec<String>> = lines
+        .iter()
+        .map(|line| {
+            let mut cells = Vec::new();
+            if has_dates {
+                cells.push(line.date.clone().unwrap_or_default());
+            }
+            if has_references {
+                cells.push(line.reference.clone().unwrap_or_default());
+            }
+            cells.push(line.description.clone());
+            if has_quantities {
+                cells.push(line.quantity.map(quantity).unwrap_or_default());
+            }
+            if has_prices {
+                cells.push(line.unit_price.map(|p| format_amount(p, currency)).unwrap_or_default());
+            }
+            cells.push(format_amount(line.amount, currency));
+            if has_balances {
+                cells.push(line.balance.map(|b| format_amount(b, currency)).unwrap_or_default());
+            }
+            cells
+        })
+        .collect();
+    pdf.table(&columns, &rows);
+
+    pdf.advance(LINE_HEIGHT / 2.0);
+    pdf.keep_space(LINE_HEIGHT * document.totals.len() as f64);
+    let totals_left = width * 0.55;
+    for total in &document.totals {
+        if total.emphasis {
+            pdf.advance(3.0);
+            pdf.rule_from(totals_left, width - totals_left);
+        }
+        pdf.text_at(totals_left, &total.label, BODY_SIZE, total.emphasis);
+        pdf.text_right(width, &format_amount(total.amount, currency), BODY_SIZE, total.emphasis);
+        pdf.advance(LINE_HEIGHT);
+    }
+
+    if !document.notes.is_empty() {
+        pdf.advance(LINE_HEIGHT / 2.0);
+        for note in &document.notes {
+            pdf.line(note);
+        }
+    }
+
+    if let Some(reference) = &document.payment_reference {
+        let bank = &company.bank;
+        let fields = [
+            ("Bank", &bank.bank_name),
+            ("Account name", &bank.account_name),
+            ("Account number", &bank.account_number),
+            ("Branch code", &bank.branch_code),
+            ("SWIFT code", &bank.swift_code),
+        ];
+        if fields.iter().any(|(_, value)| !value.trim().is_empty()) {
+            pdf.subheading("Payment details");
+            let details_width = width / 2.0;
+            for (label, value) in fields {
+                if !value.trim().is_empty() {
+                    pdf.label_value(label, value.trim(), details_width, false);
+                }
+            }
+            pdf.label_value("Payment reference", reference, details_width, true);
+        }
+    }
+
+    if !company.footer_note.trim().is_empty() {
+        pdf.advance(LINE_HEIGHT);
+        pdf.line(company.footer_note.trim());
+    }
+
+    pdf.save(path).map_err(|e| e.to_string())
+}
+
+fn unknown_format(format: &str) -> String {
+    format!(
+        "Unknown export format '{}'; expected one of {}",
+        format,
+        EXPORT_FORMATS.join(", ")
+    )
+}
+
+/// Writes a partner statement as `csv`, `xlsx` or `pdf`.
+pub fn export_partner_statement(statement: &PartnerStatement, format: &str, path: &Path) -> Result<(), String> {
+    match format {
+        "csv" => write_csv(&statement_rows(statement), path),
+        "xlsx" => write_xlsx(&statement_rows(statement), "Statement", path),
+        "pdf" => write_statement_pdf(statement, path),
+        other => Err(unknown_format(other)),
+    }
+}
+
+/// Renders an invoice or statement as a PDF at `path`.
+pub fn export_billing_document(document: &BillingDocument, path: &Path) -> Result<(), String> {
+    write_document_pdf(document, path)
+}
+
+/// Writes journals in the import format of `xero`, `sage` or `quickbooks`.
+pub fn export_accounting_journal(journal: &AccountingJournal, format: &str, path: &Path) -> Result<(), String> {
+    match format {
+        "xero" => write_csv(&xero_rows(journal)?, path),
+        "sage" => write_csv(&sage_rows(journal)?, path),
+        "quickbooks" => write_iif(journal, path),
+        other => Err(format!(
+            "Unknown accounting format '{}'; expected one of {}",
+            other,
+            JOURNAL_FORMATS.join(", ")
+        )),
+    }
+}
+
+fn template_rows(template: &ImportTemplate) -> Vec<Vec<Cell>> {
+    template
+        .rows()
+        .into_iter()
+        .enumerate()
+        .map(|(index, row)| {
+            row.into_iter()
+                .map(|cell| if index == 1 { Cell::Heading(cell) } else { Cell::Text(cell) })
+                .collect()
+        })
+        .collect()
+}
+
+/// Writes a client import template as `csv` or `xlsx`.
+pub fn export_import_template(template: &ImportTemplate, format: &str, path: &Path) -> Result<(), String> {
+    match format {
+        "csv" => write_csv(&template_rows(template), path),
+        "xlsx" => write_xlsx(&template_rows(template), "Clients", path),
+        other => Err(format!("Unknown template format '{}'; expected csv or xlsx", other)),
+    }
+}
+
+/// Writes a report table as `csv` or `xlsx`.
+pub fn export_report(report: &ReportResult, format: &str, path: &Path) -> Result<(), String> {
+    match format {
+        "csv" => write_csv(&report_rows(report), path),
+        "xlsx" => write_xlsx(&report_rows(report), &report.title, path),
+        other => Err(format!("Unknown report format '{}'; expected csv or xlsx", other)),
+    }
+}
+
+/// Writes a partner performance report as `csv`, `xlsx` or `pdf`.
+pub fn export_partner_performance(report: &PartnerPerformanceReport, format: &str, path: &Path) -> Result<(), String> {
+    match format {
+        "csv" => write_csv(&performance_rows(report), path),
+        "xlsx" => write_xlsx(&performance_rows(report), "Performance", path),
+        "pdf" => write_performance_pdf(report, path),
+        other => Err(unknown_format(other)),
+    }
+}
@@ -1,6 +1,25 @@
+mod bank_files;
+mod currency;
 mod database;
+mod export;
+mod pdf;
+mod tabular;
 
-use database::{AdditionalLicense, Client, Database, VarClient, VarClientInvoice, VarInvoiceTracking, VarPartner};
+use database::{
+    AccountBalance, AccountingJournal, AccountMapping, AdditionalLicense, AllocationRequest,
+    ArAgingReport, BankMatchCandidate, BankStatementImportReport, BankStatementOptions,
+    BankTransaction, ClawbackRule, Client, ClientImportOptions, ClientImportPreview,
+    ClientImportReport, ClientUpsert, CommissionAdjustment, CommissionAdjustmentRequest,
+    CommissionCalculation, CommissionLine, CommissionPayout, CommissionPayoutRequest,
+    CommissionRateHistory, CommissionRateRequest, CommissionRuleSet, CommissionSplit,
+    CompanyProfile, ConvertedAmount, CreditNote, CreditNoteRequest, CurrencyNormalizationReport,
+    Database, DebtCodeReport, DistributorRollup, DunningCandidate, DunningNotice, DunningSettings,
+    ExchangeRate, ExchangeRateRequest, ImportTemplate, InvoiceRunSummary, InvoiceStatusChange,
+    OpenInvoice, PartnerHierarchyNode, PartnerPerformanceReport, PartnerStatement, Payment,
+    PaymentRequest, RateImportSummary, RemittanceImportReport, RemittanceMapping, ReportingTotals,
+    ReportParameters, ReportResult, ResolvedCommissionRate, SnapshotSummary, UninvoicedVarClient,
+    VarClient, VarClientInvoice, VarInvoiceMonthTracking, VarInvoiceTracking, VarPartner,
+};
 use std::path::PathBuf;
 use std::sync::Mutex;
 use tauri::State;
@@ -38,6 +57,13 @@ fn update_client(client: Client, state: State<AppState>) -> Result<(), String> {
     db.update_client(client).map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+fn upsert_client(client: Client, state: State<AppState>) -> Result<ClientUpsert, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.upsert_client(client).map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 fn delete_client(id: String, state: State<AppState>) -> Result<(), String> {
     let db_lock = state.db.lock().unwrap();
@@ -88,14 +114,21 @@ fn add_var_client(client: VarClient, state: State<AppState>) -> Result<(), Strin
 }
 
 #[tauri::command]
-fn update_var_client(client: VarClient, state: State<AppState>) -> Result<(), String> {
+fn update_var_client(client: VarClient, state: State<AppState>) -> Result<Vec<CommissionAdjustment>, String> {
     let db_lock = state.db.lock().unwrap();
     let db = db_lock.as_ref().ok_or("Database not initialized")?;
     db.update_var_client(client).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-fn delete_var_client(id: String, state: State<AppState>) -> Result<(), String> {
+fn upsert_var_client(client: VarClient, state: State<AppState>) -> Result<ClientUpsert, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.upsert_var_client(client).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn delete_var_client(id: String, state: State<AppState>) -> Result<Vec<CommissionAdjustment>, String> {
     let db_lock = state.db.lock().unwrap();
     let db = db_lock.as_ref().ok_or("Database not initialized")?;
     db.delete_var_client(&id).map_err(|e| e.to_string())
@@ -189,10 +222,24 @@ fn delete_var_client_invoice(id: String, state: State<AppState>) -> Result<(), S
 }
 
 #[tauri::command]
-fn toggle_var_invoice_status(var_client_id: String, is_invoiced: bool, state: State<AppState>) -> Result<(), String> {
+fn toggle_var_invoice_status(
+    var_client_id: String,
+    is_invoiced: bool,
+    billing_month: Option<String>,
+    invoiced_by: Option<String>,
+    invoice_id: Option<String>,
+    state: State<AppState>,
+) -> Result<(), String> {
     let db_lock = state.db.lock().unwrap();
     let db = db_lock.as_ref().ok_or("Database not initialized")?;
-    db.toggle_var_invoice_status(&var_client_id, is_invoiced).map_err(|e| e.to_string())
+    db.toggle_var_invoice_status(
+        &var_client_id,
+        is_invoiced,
+        billing_month.as_deref(),
+        invoiced_by.as_deref(),
+        invoice_id.as_deref(),
+    )
+    .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -202,6 +249,823 @@ fn get_var_invoice_tracking(state: State<AppState>) -> Result<Vec<VarInvoiceTrac
     db.get_var_invoice_tracking().map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+fn get_var_invoice_month_tracking(
+    billing_month: String,
+    state: State<AppState>,
+) -> Result<Vec<VarInvoiceMonthTracking>, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.get_var_invoice_month_tracking(&billing_month)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_uninvoiced_var_clients(
+    billing_month: String,
+    state: State<AppState>,
+) -> Result<Vec<UninvoicedVarClient>, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.get_uninvoiced_var_clients(&billing_month).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn create_credit_note(request: CreditNoteRequest, state: State<AppState>) -> Result<CreditNote, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.create_credit_note(request).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_credit_notes(invoice_id: Option<String>, state: State<AppState>) -> Result<Vec<CreditNote>, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.get_credit_notes(invoice_id.as_deref()).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_var_client_balances(state: State<AppState>) -> Result<Vec<AccountBalance>, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.get_var_client_balances().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_var_partner_balances(state: State<AppState>) -> Result<Vec<AccountBalance>, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.get_var_partner_balances().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn record_payment(request: PaymentRequest, state: State<AppState>) -> Result<Payment, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.record_payment(request).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn allocate_payment(
+    payment_id: String,
+    allocations: Vec<AllocationRequest>,
+    state: State<AppState>,
+) -> Result<Payment, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.allocate_payment(&payment_id, allocations).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_payments(var_client_id: Option<String>, state: State<AppState>) -> Result<Vec<Payment>, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.get_payments(var_client_id.as_deref()).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_open_invoices(as_of: Option<String>, state: State<AppState>) -> Result<Vec<OpenInvoice>, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.get_open_invoices(as_of.as_deref()).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_ar_aging(as_of: String, state: State<AppState>) -> Result<ArAgingReport, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.get_ar_aging(&as_of).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_dunning_settings(state: State<AppState>) -> Result<DunningSettings, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.get_dunning_settings().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn update_dunning_settings(settings: DunningSettings, state: State<AppState>) -> Result<(), String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.update_dunning_settings(settings).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_dunning_candidates(as_of: String, state: State<AppState>) -> Result<Vec<DunningCandidate>, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.get_dunning_candidates(&as_of).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn run_dunning(as_of: String, state: State<AppState>) -> Result<Vec<DunningNotice>, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.run_dunning(&as_of).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_dunning_notices(var_client_id: Option<String>, state: State<AppState>) -> Result<Vec<DunningNotice>, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.get_dunning_notices(var_client_id.as_deref()).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_exchange_rates(state: State<AppState>) -> Result<Vec<ExchangeRate>, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.get_exchange_rates().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn add_exchange_rate(rate: ExchangeRateRequest, state: State<AppState>) -> Result<ExchangeRate, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.add_exchange_rate(rate).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn delete_exchange_rate(id: String, state: State<AppState>) -> Result<(), String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.delete_exchange_rate(&id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn import_exchange_rates_csv(path: String, state: State<AppState>) -> Result<RateImportSummary, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.import_exchange_rates_csv(&PathBuf::from(path)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_reporting_currency(state: State<AppState>) -> Result<String, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.get_reporting_currency().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_reporting_currency(currency: String, state: State<AppState>) -> Result<(), String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.set_reporting_currency(&currency).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn convert_to_reporting_currency(
+    amount: f64,
+    currency: String,
+    date: String,
+    state: State<AppState>,
+) -> Result<ConvertedAmount, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.convert_to_reporting_currency(amount, &currency, &date)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_reporting_totals(as_of: String, state: State<AppState>) -> Result<ReportingTotals, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.get_reporting_totals(&as_of).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn normalize_currency_codes(state: State<AppState>) -> Result<CurrencyNormalizationReport, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.normalize_currency_codes().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_commission_rule_sets(
+    var_partner_id: Option<String>,
+    state: State<AppState>,
+) -> Result<Vec<CommissionRuleSet>, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.get_commission_rule_sets(var_partner_id.as_deref())
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn add_commission_rule_set(rule_set: CommissionRuleSet, state: State<AppState>) -> Result<(), String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.add_commission_rule_set(rule_set).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn update_commission_rule_set(rule_set: CommissionRuleSet, state: State<AppState>) -> Result<(), String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.update_commission_rule_set(rule_set).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn delete_commission_rule_set(id: String, state: State<AppState>) -> Result<(), String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.delete_commission_rule_set(&id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn recalculate_var_commissions(
+    billing_month: String,
+    state: State<AppState>,
+) -> Result<Vec<CommissionCalculation>, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.recalculate_var_commissions(&billing_month)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_commission_rate_history(
+    var_partner_id: String,
+    var_client_id: Option<String>,
+    state: State<AppState>,
+) -> Result<Vec<CommissionRateHistory>, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.get_commission_rate_history(&var_partner_id, var_client_id.as_deref())
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn add_commission_rate(
+    request: CommissionRateRequest,
+    state: State<AppState>,
+) -> Result<CommissionRateHistory, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.add_commission_rate(request).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn delete_commission_rate(id: String, state: State<AppState>) -> Result<(), String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.delete_commission_rate(&id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn resolve_commission_rate(
+    var_client_id: String,
+    billing_month: String,
+    state: State<AppState>,
+) -> Result<ResolvedCommissionRate, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.resolve_commission_rate(&var_client_id, &billing_month)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn run_var_invoices(
+    billing_month: String,
+    invoice_date: Option<String>,
+    state: State<AppState>,
+) -> Result<InvoiceRunSummary, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.run_var_invoices(&billing_month, invoice_date.as_deref())
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn record_commission_payout(
+    request: CommissionPayoutRequest,
+    state: State<AppState>,
+) -> Result<CommissionPayout, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.record_commission_payout(request).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_commission_payouts(
+    var_partner_id: Option<String>,
+    state: State<AppState>,
+) -> Result<Vec<CommissionPayout>, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.get_commission_payouts(var_partner_id.as_deref())
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn add_commission_adjustment(
+    request: CommissionAdjustmentRequest,
+    state: State<AppState>,
+) -> Result<CommissionAdjustment, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.add_commission_adjustment(request).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_commission_adjustments(
+    var_partner_id: Option<String>,
+    state: State<AppState>,
+) -> Result<Vec<CommissionAdjustment>, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.get_commission_adjustments(var_partner_id.as_deref())
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_partner_statement(
+    var_partner_id: String,
+    period_from: String,
+    period_to: String,
+    state: State<AppState>,
+) -> Result<PartnerStatement, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.get_partner_statement(&var_partner_id, &period_from, &period_to)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn export_partner_statement(
+    var_partner_id: String,
+    period_from: String,
+    period_to: String,
+    format: String,
+    path: String,
+    state: State<AppState>,
+) -> Result<(), String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    let statement = db
+        .get_partner_statement(&var_partner_id, &period_from, &period_to)
+        .map_err(|e| e.to_string())?;
+    export::export_partner_statement(&statement, &format, &PathBuf::from(path))
+}
+
+#[tauri::command]
+fn set_var_invoice_status(
+    invoice_id: String,
+    status: String,
+    note: Option<String>,
+    changed_by: Option<String>,
+    state: State<AppState>,
+) -> Result<InvoiceStatusChange, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.set_var_invoice_status(&invoice_id, &status, note.as_deref(), changed_by.as_deref())
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_var_invoice_status_history(
+    invoice_id: String,
+    state: State<AppState>,
+) -> Result<Vec<InvoiceStatusChange>, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.get_var_invoice_status_history(&invoice_id)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_remittance_mapping(var_partner_id: String, state: State<AppState>) -> Result<RemittanceMapping, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.get_remittance_mapping(&var_partner_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn import_partner_remittance(
+    var_partner_id: String,
+    path: String,
+    payment_date: String,
+    mapping: Option<RemittanceMapping>,
+    state: State<AppState>,
+) -> Result<RemittanceImportReport, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.import_partner_remittance(&var_partner_id, &PathBuf::from(path), &payment_date, mapping)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_clawback_rules(state: State<AppState>) -> Result<Vec<ClawbackRule>, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.get_clawback_rules().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_clawback_rule(rule: ClawbackRule, state: State<AppState>) -> Result<(), String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.set_clawback_rule(rule).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn delete_clawback_rule(var_partner_id: String, state: State<AppState>) -> Result<(), String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.delete_clawback_rule(&var_partner_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_partner_hierarchy(state: State<AppState>) -> Result<Vec<PartnerHierarchyNode>, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.get_partner_hierarchy().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_partner_parent(
+    var_partner_id: String,
+    parent_partner_id: Option<String>,
+    parent_share_percent: Option<f64>,
+    state: State<AppState>,
+) -> Result<(), String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.set_partner_parent(&var_partner_id, parent_partner_id.as_deref(), parent_share_percent)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_commission_splits(state: State<AppState>) -> Result<Vec<CommissionSplit>, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.get_commission_splits().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_commission_split(split: CommissionSplit, state: State<AppState>) -> Result<(), String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.set_commission_split(split).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn delete_commission_split(var_client_id: String, state: State<AppState>) -> Result<(), String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.delete_commission_split(&var_client_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_commission_lines(
+    var_partner_id: Option<String>,
+    billing_month: Option<String>,
+    state: State<AppState>,
+) -> Result<Vec<CommissionLine>, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.get_commission_lines(var_partner_id.as_deref(), billing_month.as_deref())
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_distributor_rollup(
+    var_partner_id: String,
+    from_month: String,
+    to_month: String,
+    state: State<AppState>,
+) -> Result<DistributorRollup, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.get_distributor_rollup(&var_partner_id, &from_month, &to_month)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_partner_performance(
+    period_from: String,
+    period_to: String,
+    region: Option<String>,
+    state: State<AppState>,
+) -> Result<PartnerPerformanceReport, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.get_partner_performance(&period_from, &period_to, region.as_deref())
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn export_partner_performance(
+    period_from: String,
+    period_to: String,
+    region: Option<String>,
+    format: String,
+    path: String,
+    state: State<AppState>,
+) -> Result<(), String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    let report = db
+        .get_partner_performance(&period_from, &period_to, region.as_deref())
+        .map_err(|e| e.to_string())?;
+    export::export_partner_performance(&report, &format, &PathBuf::from(path))
+}
+
+#[tauri::command]
+fn get_debt_code_report(state: State<AppState>) -> Result<DebtCodeReport, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.get_debt_code_report().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn apply_debt_code_constraints(state: State<AppState>) -> Result<DebtCodeReport, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.apply_debt_code_constraints().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn import_clients(
+    path: String,
+    options: ClientImportOptions,
+    state: State<AppState>,
+) -> Result<ClientImportReport, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.import_clients(&PathBuf::from(path), options)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn preview_client_import(
+    path: String,
+    options: ClientImportOptions,
+    state: State<AppState>,
+) -> Result<ClientImportPreview, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.preview_client_import(&PathBuf::from(path), options)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn confirm_client_import(token: String, state: State<AppState>) -> Result<ClientImportReport, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.confirm_client_import(&token).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn discard_client_import(token: String, state: State<AppState>) -> Result<(), String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.discard_client_import(&token).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn export_database_snapshot(path: String, anonymize: bool, state: State<AppState>) -> Result<SnapshotSummary, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.export_snapshot(&PathBuf::from(path), anonymize).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn import_database_snapshot(path: String, state: State<AppState>) -> Result<SnapshotSummary, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.import_snapshot(&PathBuf::from(path)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_report(
+    report_type: String,
+    parameters: ReportParameters,
+    state: State<AppState>,
+) -> Result<ReportResult, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.get_report(&report_type, parameters).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn export_report(
+    report_type: String,
+    parameters: ReportParameters,
+    format: String,
+    path: String,
+    state: State<AppState>,
+) -> Result<(), String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    let report = db.get_report(&report_type, parameters).map_err(|e| e.to_string())?;
+    export::export_report(&report, &format, &PathBuf::from(path))
+}
+
+#[tauri::command]
+fn list_import_sheets(path: String) -> Result<Vec<tabular::SheetSummary>, String> {
+    tabular::list_sheets(&PathBuf::from(path))
+}
+
+#[tauri::command]
+fn get_import_templates() -> Result<Vec<ImportTemplate>, String> {
+    Ok(database::import_templates())
+}
+
+#[tauri::command]
+fn export_import_template(template: String, format: String, path: String) -> Result<(), String> {
+    let template = database::import_template(&template).map_err(|e| e.to_string())?;
+    export::export_import_template(&template, &format, &PathBuf::from(path))
+}
+
+#[tauri::command]
+fn get_company_profile(state: State<AppState>) -> Result<CompanyProfile, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.get_company_profile().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn update_company_profile(profile: CompanyProfile, state: State<AppState>) -> Result<(), String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.update_company_profile(profile).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn set_company_logo(path: String, state: State<AppState>) -> Result<(), String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.set_company_logo(&PathBuf::from(path)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn clear_company_logo(state: State<AppState>) -> Result<(), String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.clear_company_logo().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn render_client_invoice(invoice_id: String, path: String, state: State<AppState>) -> Result<(), String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    let document = db.get_client_invoice_document(&invoice_id).map_err(|e| e.to_string())?;
+    export::export_billing_document(&document, &PathBuf::from(path))
+}
+
+#[tauri::command]
+fn render_commission_invoice(
+    var_partner_id: String,
+    billing_month: String,
+    path: String,
+    state: State<AppState>,
+) -> Result<(), String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    let document = db
+        .get_commission_invoice_document(&var_partner_id, &billing_month)
+        .map_err(|e| e.to_string())?;
+    export::export_billing_document(&document, &PathBuf::from(path))
+}
+
+#[tauri::command]
+fn render_client_statement(
+    var_client_id: String,
+    period_from: String,
+    period_to: String,
+    path: String,
+    state: State<AppState>,
+) -> Result<(), String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    let document = db
+        .get_client_statement_document(&var_client_id, &period_from, &period_to)
+        .map_err(|e| e.to_string())?;
+    export::export_billing_document(&document, &PathBuf::from(path))
+}
+
+#[tauri::command]
+fn render_partner_statement(
+    var_partner_id: String,
+    period_from: String,
+    period_to: String,
+    path: String,
+    state: State<AppState>,
+) -> Result<(), String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    let document = db
+        .get_partner_statement_document(&var_partner_id, &period_from, &period_to)
+        .map_err(|e| e.to_string())?;
+    export::export_billing_document(&document, &PathBuf::from(path))
+}
+
+#[tauri::command]
+fn get_account_mapping(state: State<AppState>) -> Result<AccountMapping, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.get_account_mapping().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn update_account_mapping(mapping: AccountMapping, state: State<AppState>) -> Result<(), String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.update_account_mapping(mapping).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_accounting_journal(
+    period_from: String,
+    period_to: String,
+    state: State<AppState>,
+) -> Result<AccountingJournal, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.get_accounting_journal(&period_from, &period_to)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn export_accounting_journal(
+    period_from: String,
+    period_to: String,
+    format: String,
+    path: String,
+    state: State<AppState>,
+) -> Result<(), String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    let journal = db
+        .get_accounting_journal(&period_from, &period_to)
+        .map_err(|e| e.to_string())?;
+    export::export_accounting_journal(&journal, &format, &PathBuf::from(path))
+}
+
+#[tauri::command]
+fn get_bank_csv_mapping(bank_account: String, state: State<AppState>) -> Result<bank_files::BankCsvMapping, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.get_bank_csv_mapping(&bank_account).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn import_bank_statement(
+    path: String,
+    options: BankStatementOptions,
+    state: State<AppState>,
+) -> Result<BankStatementImportReport, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.import_bank_statement(&PathBuf::from(path), options)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_bank_transactions(status: Option<String>, state: State<AppState>) -> Result<Vec<BankTransaction>, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.get_bank_transactions(status.as_deref()).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_bank_match_candidates(
+    transaction_id: String,
+    state: State<AppState>,
+) -> Result<Vec<BankMatchCandidate>, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.get_bank_match_candidates(&transaction_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn match_bank_transactions(state: State<AppState>) -> Result<usize, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.match_bank_transactions().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn confirm_bank_match(
+    transaction_id: String,
+    invoice_id: Option<String>,
+    state: State<AppState>,
+) -> Result<Payment, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.confirm_bank_match(&transaction_id, invoice_id.as_deref())
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn ignore_bank_transaction(transaction_id: String, state: State<AppState>) -> Result<BankTransaction, String> {
+    let db_lock = state.db.lock().unwrap();
+    let db = db_lock.as_ref().ok_or("Database not initialized")?;
+    db.ignore_bank_transaction(&transaction_id).map_err(|e| e.to_string())
+}
+
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
@@ -221,6 +1085,7 @@ pub fn run() {
             get_clients,
             add_client,
             update_client,
+            upsert_client,
             delete_client,
             get_var_partners,
             add_var_partner,
@@ -229,6 +1094,7 @@ pub fn run() {
             get_var_clients,
             add_var_client,
             update_var_client,
+            upsert_var_client,
             delete_var_client,
             get_additional_licenses,
             add_additional_license,
@@ -240,6 +1106,95 @@ pub fn run() {
             delete_var_client_invoice,
             toggle_var_invoice_status,
             get_var_invoice_tracking,
+            get_var_invoice_month_tracking,
+            get_uninvoiced_var_clients,
+            create_credit_note,
+            get_credit_notes,
+            get_var_client_balances,
+            get_var_partner_balances,
+            record_payment,
+            allocate_payment,
+            get_payments,
+            get_open_invoices,
+            get_ar_aging,
+            get_dunning_settings,
+            update_dunning_settings,
+            get_dunning_candidates,
+            run_dunning,
+            get_dunning_notices,
+            get_exchange_rates,
+            add_exchange_rate,
+            delete_exchange_rate,
+            import_exchange_rates_csv,
+            get_reporting_currency,
+            set_reporting_currency,
+            convert_to_reporting_currency,
+            get_reporting_totals,
+            normalize_currency_codes,
+            get_commission_rule_sets,
+            add_commission_rule_set,
+            update_commission_rule_set,
+            delete_commission_rule_set,
+            recalculate_var_commissions,
+            get_commission_rate_history,
+            add_commission_rate,
+            delete_commission_rate,
+            resolve_commission_rate,
+            run_var_invoices,
+            record_commission_payout,
+            get_commission_payouts,
+            add_commission_adjustment,
+            get_commission_adjustments,
+            get_partner_statement,
+            export_partner_statement,
+            set_var_invoice_status,
+            get_var_invoice_status_history,
+            get_remittance_mapping,
+            import_partner_remittance,
+            get_clawback_rules,
+            set_clawback_rule,
+            delete_clawback_rule,
+            get_partner_hierarchy,
+            set_partner_parent,
+            get_commission_splits,
+            set_commission_split,
+            delete_commission_split,
+            get_commission_lines,
+            get_distributor_rollup,
+            get_partner_performance,
+            export_partner_performance,
+            get_debt_code_report,
+            apply_debt_code_constraints,
+            import_clients,
+            preview_client_import,
+            confirm_client_import,
+            discard_client_import,
+            list_import_sheets,
+            get_import_templates,
+            export_import_template,
+            get_report,
+            export_report,
+            get_company_profile,
+            update_company_profile,
+            set_company_logo,
+            clear_company_logo,
+            render_client_invoice,
+            render_commission_invoice,
+            render_client_statement,
+            render_partner_statement,
+            get_account_mapping,
+            update_account_mapping,
+            get_accounting_journal,
+            export_accounting_journal,
+            get_bank_csv_mapping,
+            import_bank_statement,
+            get_bank_transactions,
+            get_bank_match_candidates,
+            match_bank_transactions,
+            confirm_bank_match,
+            ignore_bank_transaction,
+            export_database_snapshot,
+            import_database_snapshot,
             pick_database_file,
             save_database_file,
         ])
@@ -0,0 +1,450 @@
+//! A small PDF writer for statements and invoices. It lays out text, rules,
+//! simple tables and JPEG or PNG images on A4 pages using the built-in
+//! Helvetica fonts, and writes no timestamps or ids so the same input always
+//! produces the same bytes.
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::io::Write;
+use std::path::Path;
+
+const PAGE_WIDTH: f64 = 595.28;
+const PAGE_HEIGHT: f64 = 841.89;
+const MARGIN: f64 = 50.0;
+pub const BODY_SIZE: f64 = 9.0;
+pub const LINE_HEIGHT: f64 = 13.0;
+
+/// Helvetica advance widths for ASCII 32..=126, in thousandths of the font size.
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556, 556, 556, 556, 556,
+    556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833,
+    722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556,
+    556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334,
+    260, 334, 584,
+];
+
+#[derive(Clone, Copy)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+/// A table column; `width` is in points.
+pub struct Column<'a> {
+    pub title: &'a str,
+    pub width: f64,
+    pub align: Align,
+}
+
+/// An image ready to embed: JPEG data is passed through as is, PNG pixels
+/// are recompressed, with any transparency kept as a separate soft mask.
+pub struct PdfImage {
+    width: u32,
+    height: u32,
+    color_space: &'static str,
+    filter: &'static str,
+    data: Vec<u8>,
+    alpha: Option<Vec<u8>>,
+}
+
+fn deflate(bytes: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(bytes)?;
+    encoder.finish()
+}
+
+impl PdfImage {
+    /// Reads a JPEG or PNG image, telling them apart by their signature.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.starts_with(&[0xFF, 0xD8]) {
+            Self::from_jpeg(bytes)
+        } else if bytes.starts_with(b"\x89PNG") {
+            Self::from_png(bytes)
+        } else {
+            Err("Only JPEG and PNG images are supported".to_string())
+        }
+    }
+
+    fn from_jpeg(bytes: &[u8]) -> Result<Self, String> {
+        // Walk the marker segments to the start-of-frame, which holds the size.
+        let mut index = 2;
+        while index + 4 <= bytes.len() {
+            if bytes[index] != 0xFF {
+                break;
+            }
+            let marker = bytes[index + 1];
+            let length = u16::from_be_bytes([bytes[index + 2], bytes[index + 3]]) as usize;
+            let is_frame = matches!(marker, 0xC0..=0xCF) && !matches!(marker, 0xC4 | 0xC8 | 0xCC);
+            if is_frame && index + 10 <= bytes.len() {
+                let height = u16::from_be_bytes([bytes[index + 5], bytes[index + 6]]) as u32;
+                let width = u16::from_be_bytes([bytes[index + 7], bytes[index + 8]]) as u32;
+                let color_space = match bytes[index + 9] {
+                    1 => "DeviceGray",
+                    3 => "DeviceRGB",
+                    _ => return Err("Only greyscale and RGB JPEG images are supported".to_string()),
+                };
+                return Ok(PdfImage {
+                    width,
+                    height,
+                    color_space,
+                    filter: "DCTDecode",
+                    data: bytes.to_vec(),
+                    alpha: None,
+                });
+            }
+            index += 2 + length;
+        }
+        Err("The JPEG image has no frame header".to_string())
+    }
+
+    fn from_png(bytes: &[u8]) -> Result<Self, String> {
+        let mut decoder = png::Decoder::new(bytes);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder
+            .read_info()
+            .map_err(|e| format!("Cannot read the PNG image: {}", e))?;
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let frame = reader
+            .next_frame(&mut pixels)
+            .map_err(|e| format!("Cannot read the PNG image: {}", e))?;
+        pixels.truncate(frame.buffer_size());
+
+        let (color_space, channels, has_alpha) = match frame.color_type {
+            png::ColorType::Grayscale => ("DeviceGray", 1, false),
+            png::ColorType::GrayscaleAlpha => ("DeviceGray", 2, true),
+            png::ColorType::Rgb => ("DeviceRGB", 3, false),
+            png::ColorType::Rgba => ("DeviceRGB", 4, true),
+            png::ColorType::Indexed => return Err("Cannot expand the PNG palette".to_string()),
+        };
+        let (color, alpha) = if has_alpha {
+            let mut color = Vec::with_capacity(pixels.len());
+            let mut alpha = Vec::with_capacity(pixels.len() / channels);
+            for pixel in pixels.chunks_exact(channels) {
+                color.extend_from_slice(&pixel[..channels - 1]);
+                alpha.push(pixel[channels - 1]);
+            }
+            (color, Some(alpha))
+        } else {
+            (pixels, None)
+        };
+
+        let compress = |bytes: &[u8]| deflate(bytes).map_err(|e| e.to_string());
+        Ok(PdfImage {
+            width: frame.width,
+            height: frame.height,
+            color_space,
+            filter: "FlateDecode",
+            data: compress(&color)?,
+            alpha: alpha.as_deref().map(compress).transpose()?,
+        })
+    }
+
+    fn object(&self, color_space: &str, data: &[u8], smask: Option<usize>) -> Vec<u8> {
+        let mut object = format!(
+            "<< /Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /{} /BitsPerComponent 8 \
+             /Filter /{}{} /Length {} >>\nstream\n",
+            self.width,
+            self.height,
+            color_space,
+            self.filter,
+            smask.map(|id| format!(" /SMask {} 0 R", id)).unwrap_or_default(),
+            data.len()
+        )
+        .into_bytes();
+        object.extend_from_slice(data);
+        object.extend_from_slice(b"\nendstream");
+        object
+    }
+}
+
+pub struct PdfDocument {
+    pages: Vec<Vec<u8>>,
+    page: Vec<u8>,
+    y: f64,
+    images: Vec<PdfImage>,
+}
+
+impl Default for PdfDocument {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Width of `text` in points, using regular Helvetica metrics (close enough
+/// for the bold face when aligning numbers).
+pub fn text_width(text: &str, size: f64) -> f64 {
+    let units: u32 = text
+        .chars()
+        .map(|c| match c as u32 {
+            code @ 32..=126 => HELVETICA_WIDTHS[(code - 32) as usize] as u32,
+            _ => 556,
+        })
+        .sum();
+    units as f64 * size / 1000.0
+}
+
+/// Shortens `text` with "..." until it fits in `width` points.
+fn fit_text(text: &str, width: f64, size: f64) -> String {
+    if text_width(text, size) <= width {
+        return text.to_string();
+    }
+    let mut fitted: String = text.to_string();
+    while !fitted.is_empty() && text_width(&format!("{}...", fitted), size) > width {
+        fitted.pop();
+    }
+    format!("{}...", fitted.trim_end())
+}
+
+/// Encodes text as a PDF string literal in WinAnsi, replacing anything outside Latin-1.
+fn pdf_string(text: &str) -> Vec<u8> {
+    let mut out = vec![b'('];
+    for c in text.chars() {
+        let byte = if (c as u32) < 256 { c as u32 as u8 } else { b'?' };
+        if matches!(byte, b'(' | b')' | b'\\') {
+            out.push(b'\\');
+        }
+        out.push(byte);
+    }
+    out.push(b')');
+    out
+}
+
+impl PdfDocument {
+    pub fn new() -> Self {
+        PdfDocument {
+            pages: Vec::new(),
+            page: Vec::new(),
+            y: PAGE_HEIGHT - MARGIN,
+            images: Vec::new(),
+        }
+    }
+
+    /// Usable width between the left and right margins.
+    pub fn content_width(&self) -> f64 {
+        PAGE_WIDTH - 2.0 * MARGIN
+    }
+
+    /// Distance of the current baseline from the bottom of the page.
+    pub fn cursor(&self) -> f64 {
+        self.y
+    }
+
+    /// Draws text with its left edge at `x` on the current baseline.
+    pub fn text_at(&mut self, x: f64, text: &str, size: f64, bold: bool) {
+        let font = if bold { "F2" } else { "F1" };
+        self.page
+            .extend_from_slice(format!("BT /{} {:.2} Tf {:.2} {:.2} Td ", font, size, MARGIN + x, self.y).as_bytes());
+        self.page.extend_from_slice(&pdf_string(text));
+        self.page.extend_from_slice(b" Tj ET\n");
+    }
+
+    /// Draws text so that it ends at `x`.
+    pub fn text_right(&mut self, x: f64, text: &str, size: f64, bold: bool) {
+        self.text_at(x - text_width(text, size), text, size, bold);
+    }
+
+    /// Moves down by `height` points, starting a new page when the bottom margin is reached.
+    pub fn advance(&mut self, height: f64) {
+        self.y -= height;
+        if self.y < MARGIN + LINE_HEIGHT {
+            self.new_page();
+        }
+    }
+
+    /// Draws `image` at `x`, scaled to fit within `max_width` by `max_height`
+    /// points, with its top level with the top of the current line. The
+    /// cursor does not move; the drawn height is returned.
+    pub fn image(&mut self, image: PdfImage, x: f64, max_width: f64, max_height: f64) -> f64 {
+        let scale = (max_width / image.width as f64).min(max_height / image.height as f64);
+        let (width, height) = (image.width as f64 * scale, image.height as f64 * scale);
+        self.images.push(image);
+        self.page.extend_from_slice(
+            format!(
+                "q {:.2} 0 0 {:.2} {:.2} {:.2} cm /Im{} Do Q\n",
+                width,
+                height,
+                MARGIN + x,
+                self.y + BODY_SIZE - height,
+                self.images.len()
+            )
+            .as_bytes(),
+        );
+        height
+    }
+
+    /// Moves down until the cursor is at or below `y`.
+    pub fn move_below(&mut self, y: f64) {
+        if self.y > y {
+            self.advance(self.y - y);
+        }
+    }
+
+    pub fn new_page(&mut self) {
+        self.pages.push(std::mem::take(&mut self.page));
+        self.y = PAGE_HEIGHT - MARGIN;
+    }
+
+    /// Starts a new page unless `height` points still fit on this one.
+    pub fn keep_space(&mut self, height: f64) {
+        if self.y - height < MARGIN + LINE_HEIGHT {
+            self.new_page();
+        }
+    }
+
+    pub fn heading(&mut self, text: &str) {
+        self.keep_space(30.0);
+        self.text_at(0.0, text, 16.0, true);
+        self.advance(24.0);
+    }
+
+    pub fn subheading(&mut self, text: &str) {
+        self.keep_space(2.0 * LINE_HEIGHT);
+        self.advance(4.0);
+        self.text_at(0.0, text, 11.0, true);
+        self.advance(LINE_HEIGHT + 2.0);
+    }
+
+    pub fn line(&mut self, text: &str) {
+        self.text_at(0.0, text, BODY_SIZE, false);
+        self.advance(LINE_HEIGHT);
+    }
+
+    /// A label on the left with its value right-aligned at `width`.
+    pub fn label_value(&mut self, label: &str, value: &str, width: f64, bold: bool) {
+        self.text_at(0.0, label, BODY_SIZE, bold);
+        self.text_right(width, value, BODY_SIZE, bold);
+        self.advance(LINE_HEIGHT);
+    }
+
+    /// A horizontal rule across `width` points, just below the previous line.
+    pub fn rule(&mut self, width: f64) {
+        self.rule_from(0.0, width);
+    }
+
+    /// A horizontal rule starting `x` points in from the left margin.
+    pub fn rule_from(&mut self, x: f64, width: f64) {
+        let y = self.y + LINE_HEIGHT - 3.0;
+        self.page.extend_from_slice(
+            format!("0.5 w {:.2} {:.2} m {:.2} {:.2} l S\n", MARGIN + x, y, MARGIN + x + width, y).as_bytes(),
+        );
+    }
+
+    /// Draws a table with a bold header row that repeats on every page it spans.
+    pub fn table(&mut self, columns: &[Column], rows: &[Vec<String>]) {
+        let width: f64 = columns.iter().map(|c| c.width).sum();
+        self.keep_space(3.0 * LINE_HEIGHT);
+        self.table_row(columns, columns.iter().map(|c| c.title.to_string()).collect(), true);
+        self.rule(width);
+        for row in rows {
+            if self.y - LINE_HEIGHT < MARGIN + LINE_HEIGHT {
+                self.new_page();
+                self.table_row(columns, columns.iter().map(|c| c.title.to_string()).collect(), true);
+                self.rule(width);
+            }
+            self.table_row(columns, row.clone(), false);
+        }
+    }
+
+    fn table_row(&mut self, columns: &[Column], cells: Vec<String>, bold: bool) {
+        let mut x = 0.0;
+        for (column, cell) in columns.iter().zip(cells) {
+            match column.align {
+                Align::Left => self.text_at(x, &fit_text(&cell, column.width - 4.0, BODY_SIZE), BODY_SIZE, bold),
+                Align::Right => self.text_right(x + column.width - 4.0, &cell, BODY_SIZE, bold),
+            }
+            x += column.width;
+        }
+        self.advance(LINE_HEIGHT);
+    }
+
+    /// Serialises the document, adding "Page n of m" footers.
+    pub fn into_bytes(mut self) -> Vec<u8> {
+        if !self.page.is_empty() || self.pages.is_empty() {
+            self.pages.push(std::mem::take(&mut self.page));
+        }
+        let page_count = self.pages.len();
+
+        let mut objects: Vec<Vec<u8>> = vec![
+            b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+            Vec::new(),
+            b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>".to_vec(),
+            b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica-Bold /Encoding /WinAnsiEncoding >>".to_vec(),
+        ];
+        let mut image_refs = Vec::new();
+        for (index, image) in self.images.iter().enumerate() {
+            let image_id = objects.len() + 1;
+            let smask = image.alpha.as_ref().map(|_| image_id + 1);
+            objects.push(image.object(image.color_space, &image.data, smask));
+            if let Some(alpha) = &image.alpha {
+                objects.push(image.object("DeviceGray", alpha, None));
+            }
+            image_refs.push(format!("/Im{} {} 0 R", index + 1, image_id));
+        }
+        let x_objects = if image_refs.is_empty() {
+            String::new()
+        } else {
+            format!(" /XObject << {} >>", image_refs.join(" "))
+        };
+
+        let mut kids = Vec::new();
+        for (index, mut content) in self.pages.into_iter().enumerate() {
+            let footer = format!("Page {} of {}", index + 1, page_count);
+            content.extend_from_slice(
+                format!(
+                    "BT /F1 8 Tf {:.2} {:.2} Td ",
+                    PAGE_WIDTH - MARGIN - text_width(&footer, 8.0),
+                    MARGIN / 2.0
+                )
+                .as_bytes(),
+            );
+            content.extend_from_slice(&pdf_string(&footer));
+            content.extend_from_slice(b" Tj ET\n");
+
+            let page_id = objects.len() + 1;
+            kids.push(format!("{} 0 R", page_id));
+            objects.push(
+                format!(
+                    "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] \
+                     /Resources << /Font << /F1 3 0 R /F2 4 0 R >>{} >> /Contents {} 0 R >>",
+                    PAGE_WIDTH,
+                    PAGE_HEIGHT,
+                    x_objects,
+                    page_id + 1
+                )
+                .into_bytes(),
+            );
+            let mut stream = format!("<< /Length {} >>\nstream\n", content.len()).into_bytes();
+            stream.extend_from_slice(&content);
+            stream.extend_from_slice(b"endstream");
+            objects.push(stream);
+        }
+        objects[1] = format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids.join(" "), page_count).into_bytes();
+
+        let mut out = b"%PDF-1.4\n".to_vec();
+        let mut offsets = Vec::new();
+        for (index, object) in objects.iter().enumerate() {
+            offsets.push(out.len());
+            out.extend_from_slice(format!("{} 0 obj\n", index + 1).as_bytes());
+            out.extend_from_slice(object);
+            out.extend_from_slice(b"\nendobj\n");
+        }
+        let xref = out.len();
+        out.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+        for offset in offsets {
+            out.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+        }
+        out.extend_from_slice(
+            format!(
+                "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+                objects.len() + 1,
+                xref
+            )
+            .as_bytes(),
+        );
+        out
+    }
+
+    pub fn save(self, path: &Path) -> std::io::Result<()> {
+        std::fs::write(path, self.into_bytes())
+    }
+}
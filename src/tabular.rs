@@ -0,0 +1,454 @@
+//! Reads a CSV or JSON file, or one sheet of an XLSX, XLS or ODS workbook,
+//! into plain text cells, so imports can handle every format the same way.
+
+use calamine::{open_workbook_auto, Data, ExcelDateTime, ExcelDateTimeType, Range, Reader};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+pub const TABLE_EXTENSIONS: &[&str] = &["csv", "json", "xlsx", "xlsm", "xlsb", "xls", "ods"];
+
+/// Number of leading rows returned with each sheet, for picking the header row.
+const PREVIEW_ROWS: usize = 10;
+
+/// Where the data sits in a file. `sheet` names a workbook sheet (the first
+/// one when empty) and `header_row` is the 1-based row holding the column
+/// names (the first non-empty row when empty).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TableOptions {
+    pub sheet: Option<String>,
+    pub header_row: Option<usize>,
+}
+
+/// A sheet the user can import from, with its first rows as a preview.
+/// CSV and JSON files have a single sheet named after the file.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SheetSummary {
+    pub name: String,
+    pub row_count: usize,
+    pub column_count: usize,
+    pub preview: Vec<TableRow>,
+}
+
+/// A data row with its 1-based line (or spreadsheet row) number in the file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableRow {
+    pub line: usize,
+    pub cells: Vec<String>,
+}
+
+impl TableRow {
+    /// The trimmed cell at `index`, or an empty string when the column is unmapped or missing.
+    pub fn get(&self, index: Option<usize>) -> &str {
+        index
+            .and_then(|i| self.cells.get(i))
+            .map(|cell| cell.trim())
+            .unwrap_or("")
+    }
+}
+
+pub struct Table {
+    pub headers: Vec<String>,
+    pub rows: Vec<TableRow>,
+}
+
+impl Table {
+    /// Index of the first header matching one of `names`, ignoring case, spaces and underscores.
+    pub fn find_column(&self, names: &[&str]) -> Option<usize> {
+        let key = |value: &str| value.trim().to_lowercase().replace([' ', '_', '-'], "");
+        names
+            .iter()
+            .find_map(|name| self.headers.iter().position(|header| key(header) == key(name)))
+    }
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .unwrap_or_default()
+}
+
+fn unsupported(extension: &str) -> String {
+    format!(
+        "Unsupported file type '{}'; expected one of {}",
+        extension,
+        TABLE_EXTENSIONS.join(", ")
+    )
+}
+
+pub fn read_table(path: &Path, options: &TableOptions) -> Result<Table, String> {
+    let rows = match extension(path).as_str() {
+        "json" => return read_json(path),
+        "csv" => read_csv(path)?,
+        "xlsx" | "xlsm" | "xlsb" | "xls" | "ods" => sheet_rows(&read_sheet(path, options.sheet.as_deref())?),
+        other => return Err(unsupported(other)),
+    };
+    split_header(rows, options.header_row)
+}
+
+/// Lists the sheets of a workbook, or the single table of a CSV or JSON file.
+pub fn list_sheets(path: &Path) -> Result<Vec<SheetSummary>, String> {
+    let summary = |name: String, rows: Vec<TableRow>| SheetSummary {
+        name,
+        row_count: rows.len(),
+        column_count: rows.iter().map(|r| r.cells.len()).max().unwrap_or(0),
+        preview: rows.into_iter().take(PREVIEW_ROWS).collect(),
+    };
+    let file_name = path
+        .file_stem()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    match extension(path).as_str() {
+        "csv" => Ok(vec![summary(file_name, read_csv(path)?)]),
+        "json" => {
+            let table = read_json(path)?;
+            let mut rows = vec![TableRow {
+                line: 0,
+                cells: table.headers,
+            }];
+            rows.extend(table.rows);
+            Ok(vec![summary(file_name, rows)])
+        }
+        "xlsx" | "xlsm" | "xlsb" | "xls" | "ods" => {
+            let mut workbook =
+                open_workbook_auto(path).map_err(|e| format!("Could not open {}: {}", path.display(), e))?;
+            let mut sheets = Vec::new();
+            for name in workbook.sheet_names() {
+                let range = workbook
+                    .worksheet_range(&name)
+                    .map_err(|e| format!("Could not read sheet '{}': {}", name, e))?;
+                sheets.push(summary(name, sheet_rows(&range)));
+            }
+            Ok(sheets)
+        }
+        other => Err(unsupported(other)),
+    }
+}
+
+/// Uses the requested row, or the first row, as headers and the rows below it as data.
+fn split_header(rows: Vec<TableRow>, header_row: Option<usize>) -> Result<Table, String> {
+    let position = match header_row {
+        Some(line) => rows
+            .iter()
+            .position(|row| row.line == line)
+            .ok_or_else(|| format!("Row {} is empty or outside the sheet", line))?,
+        None => 0,
+    };
+    let mut rows = rows.into_iter().skip(position);
+    let headers = match rows.next() {
+        Some(row) => row.cells.iter().map(|cell| cell.trim().to_string()).collect(),
+        None => Vec::new(),
+    };
+    Ok(Table {
+        headers,
+        rows: rows.collect(),
+    })
+}
+
+/// Every non-empty CSV record, headers included.
+fn read_csv(path: &Path) -> Result<Vec<TableRow>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_path(path)
+        .map_err(|e| format!("Could not open {}: {}", path.display(), e))?;
+
+    let mut rows = Vec::new();
+    for (index, record) in reader.records().enumerate() {
+        let record = record.map_err(|e| format!("Line {}: {}", index + 1, e))?;
+        let line = record.position().map(|p| p.line() as usize).unwrap_or(index + 1);
+        let mut cells: Vec<String> = record.iter().map(str::to_string).collect();
+        if let Some(first) = cells.first_mut().filter(|_| index == 0) {
+            *first = first.trim_start_matches('\u{feff}').to_string();
+        }
+        if cells.iter().any(|cell| !cell.trim().is_empty()) {
+            rows.push(TableRow { line, cells });
+        }
+    }
+    Ok(rows)
+}
+
+/// Reads an array of flat objects; the headers are every key in first-seen order.
+fn read_json(path: &Path) -> Result<Table, String> {
+    let content = std::fs::read_to_string(path).map_err(|e| format!("Could not open {}: {}", path.display(), e))?;
+    let value: serde_json::Value = serde_json::from_str(&content).map_err(|e| format!("Invalid JSON: {}", e))?;
+    let records = value.as_array().ok_or("The JSON file must hold an array of records")?;
+
+    let mut headers: Vec<String> = Vec::new();
+    for record in records {
+        for key in record.as_object().into_iter().flat_map(|o| o.keys()) {
+            if !headers.contains(key) {
+                headers.push(key.clone());
+            }
+        }
+    }
+
+    let mut rows = Vec::new();
+    for (index, record) in records.iter().enumerate() {
+        let object = record
+            .as_object()
+            .ok_or_else(|| format!("Record {} is not an object", index + 1))?;
+        let cells: Vec<String> = headers
+            .iter()
+            .map(|header| match object.get(header) {
+                None | Some(serde_json::Value::Null) => String::new(),
+                Some(serde_json::Value::String(text)) => text.clone(),
+                Some(other) => other.to_string(),
+            })
+            .collect();
+        if cells.iter().any(|cell| !cell.trim().is_empty()) {
+            rows.push(TableRow { line: index + 1, cells });
+        }
+    }
+    Ok(Table { headers, rows })
+}
+
+fn read_sheet(path: &Path, sheet: Option<&str>) -> Result<Range<Data>, String> {
+    let mut workbook = open_workbook_auto(path).map_err(|e| format!("Could not open {}: {}", path.display(), e))?;
+    match sheet.filter(|name| !name.is_empty()) {
+        Some(name) => workbook
+            .worksheet_range(name)
+            .map_err(|e| format!("Could not read sheet '{}': {}", name, e)),
+        None => workbook
+            .worksheet_range_at(0)
+            .ok_or("The workbook has no sheets")?
+            .map_err(|e| format!("Could not read the first sheet: {}", e)),
+    }
+}
+
+/// Every non-empty row of a sheet, numbered as the spreadsheet shows them.
+fn sheet_rows(range: &Range<Data>) -> Vec<TableRow> {
+    let first_row = range.start().map(|(row, _)| row as usize).unwrap_or(0);
+    range
+        .rows()
+        .enumerate()
+        .map(|(index, row)| TableRow {
+            line: first_row + index + 1,
+            cells: row.iter().map(cell_text).collect(),
+        })
+        .filter(|row| row.cells.iter().any(|cell| !cell.trim().is_empty()))
+        .collect()
+}
+
+/// Cell text as a CSV export would show it: dates become YYYY-MM-DD and
+/// numbers lose any currency or thousands formatting.
+fn cell_text(cell: &Data) -> String {
+    match cell {
+        Data::DateTime(value) => {
+            let serial = value.as_f64();
+            if *value == ExcelDateTime::new(serial, ExcelDateTimeType::TimeDelta, false)
+                || *value == ExcelDateTime::new(serial, ExcelDateTimeType::TimeDelta, true)
+            {
+                serial.to_string()
+            } else if *value == ExcelDateTime::new(serial, ExcelDateTimeType::DateTime, true) {
+                // 1904-based workbooks count from 1904-01-01, 1462 days after the 1900 epoch.
+                excel_serial_date(serial + 1462.0)
+            } else {
+                excel_serial_date(serial)
+            }
+        }
+        Data::DateTimeIso(value) => value.get(..10).unwrap_or(value).to_string(),
+        Data::Float(value) if value.fract() == 0.0 && value.abs() < 1e15 => format!("{}", *value as i64),
+        Data::Error(_) => String::new(),
+        other => other.to_string(),
+    }
+}
+
+/// Converts an Excel (1900 date system) serial number to YYYY-MM-DD.
+fn excel_serial_date(serial: f64) -> String {
+    // Serial 25569 is 1970-01-01; days-from-civil inverse after Howard Hinnant.
+    let days = serial.floor() as i64 - 25569 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Parses an amount such as `R 1 234,00`, `1,234.50`, `1.234,50` or
+/// `(250.00)`. The last `.` or `,` is the decimal separator unless it is
+/// clearly grouping thousands, as a lone `,` followed by exactly three
+/// digits is. A negative amount takes one marker: a sign at the start or
+/// end, parentheses, or a `DR` suffix (`CR` marks a credit).
+/// A currency symbol or code may lead the number; any other text gives None.
+pub fn parse_amount(value: &str) -> Option<f64> {
+    let mut text = value.trim();
+    let mut sign: Option<bool> = None;
+    let mut mark = |negative: bool| sign.replace(negative).is_none();
+
+    let upper = text.to_ascii_uppercase();
+    if let Some(negative) = [("CR", false), ("DR", true)]
+        .iter()
+        .find(|(suffix, _)| upper.ends_with(suffix))
+        .map(|(_, negative)| *negative)
+    {
+        text = text[..text.len() - 2].trim_end();
+        mark(negative);
+    }
+    if let Some(inner) = text.strip_prefix('(').and_then(|t| t.strip_suffix(')')) {
+        text = inner.trim();
+        if !mark(true) {
+            return None;
+        }
+    }
+    for _ in 0..2 {
+        // A sign may sit before or after a currency prefix: `-R 100`, `R -100`.
+        if let Some(rest) = text.strip_prefix(['-', '+']) {
+            if !mark(text.starts_with('-')) {
+                return None;
+            }
+            text = rest.trim_start();
+        }
+        let start = text.find(|c: char| c.is_ascii_digit() || matches!(c, '.' | ',' | '-' | '+'))?;
+        let prefix = text[..start].trim();
+        let is_symbol = matches!(prefix, "" | "$" | "€" | "£" | "¥" | "₹");
+        let is_code = (1..=3).contains(&prefix.len()) && prefix.chars().all(|c| c.is_ascii_alphabetic());
+        if !is_symbol && !is_code {
+            return None;
+        }
+        text = &text[start..];
+    }
+    if let Some(rest) = text.strip_suffix(['-', '+']) {
+        if !mark(text.ends_with('-')) {
+            return None;
+        }
+        text = rest.trim_end();
+    }
+
+    let grouping = |c: char| matches!(c, ' ' | '\u{a0}' | '\'');
+    if !text
+        .chars()
+        .all(|c| c.is_ascii_digit() || matches!(c, '.' | ',') || grouping(c))
+    {
+        return None;
+    }
+    let digits: String = text.chars().filter(|c| !grouping(*c)).collect();
+    if !digits.chars().any(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let negative = sign == Some(true);
+    let decimal = match (digits.rfind('.'), digits.rfind(',')) {
+        (Some(dot), Some(comma)) => Some(if dot > comma { '.' } else { ',' }),
+        (Some(_), None) if digits.matches('.').count() == 1 => Some('.'),
+        (None, Some(comma)) if digits.matches(',').count() == 1 && digits.len() - comma - 1 != 3 => Some(','),
+        _ => None,
+    };
+    let number = match decimal {
+        Some(separator) => {
+            let (whole, fraction) = digits.rsplit_once(separator)?;
+            format!("{}.{}", whole.replace(['.', ','], ""), fraction)
+        }
+        None => digits.replace(['.', ','], ""),
+    };
+    let amount: f64 = number.parse().ok()?;
+    Some(if negative { -amount } else { amount })
+}
+
+/// Reads a calendar date as YYYY-MM-DD from 2026-03-15, 2026/03/15,
+/// 15/03/2026 (day first), 15 Mar 2026, March 15, 2026 or an Excel serial
+/// day number such as 46096. Anything after the date, such as a time, is
+/// ignored.
+pub fn parse_date(value: &str) -> Option<String> {
+    // Serials between 1954 and 2119; smaller numbers are not dates.
+    if let Ok(serial) = value.trim().parse::<f64>() {
+        return (20_000.0..80_000.0).contains(&serial).then(|| excel_serial_date(serial));
+    }
+    const MONTHS: [&str; 12] = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+    let tokens: Vec<String> = value
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .take(3)
+        .map(str::to_lowercase)
+        .collect();
+    let [first, second, third] = tokens.as_slice() else {
+        return None;
+    };
+    let month_name = |token: &str| -> Option<u32> {
+        let position = MONTHS.iter().position(|m| token.len() >= 3 && token.starts_with(m))?;
+        Some(position as u32 + 1)
+    };
+
+    let (year, month, day): (i32, u32, u32) = if first.len() == 4 {
+        (first.parse().ok()?, second.parse().ok()?, third.parse().ok()?)
+    } else if let Some(month) = month_name(second) {
+        (third.parse().ok()?, month, first.parse().ok()?)
+    } else if let Some(month) = month_name(first) {
+        (third.parse().ok()?, month, second.parse().ok()?)
+    } else if third.len() == 4 {
+        (third.parse().ok()?, second.parse().ok()?, first.parse().ok()?)
+    } else {
+        return None;
+    };
+
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        1..=12 => 31,
+        _ => return None,
+    };
+    (year >= 1900 && (1..=days).contains(&day)).then(|| format!("{:04}-{:02}-{:02}", year, month, day))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_amount;
+
+    #[test]
+    fn parses_grouped_and_decimal_amounts() {
+        assert_eq!(parse_amount("1,234.50"), Some(1234.5));
+        assert_eq!(parse_amount("1.234,50"), Some(1234.5));
+        assert_eq!(parse_amount("R 1 234,00"), Some(1234.0));
+        assert_eq!(parse_amount("ZAR 250"), Some(250.0));
+        assert_eq!(parse_amount("$12.30"), Some(12.3));
+        assert_eq!(parse_amount("1,234"), Some(1234.0));
+        assert_eq!(parse_amount("1,234,567"), Some(1234567.0));
+        assert_eq!(parse_amount("1,5"), Some(1.5));
+        assert_eq!(parse_amount("12,5"), Some(12.5));
+        assert_eq!(parse_amount("0,5"), Some(0.5));
+        assert_eq!(parse_amount("12,50"), Some(12.5));
+        assert_eq!(parse_amount("1,2345"), Some(1.2345));
+    }
+
+    #[test]
+    fn reads_one_negative_marker() {
+        assert_eq!(parse_amount("-250.00"), Some(-250.0));
+        assert_eq!(parse_amount("250.00-"), Some(-250.0));
+        assert_eq!(parse_amount("(250.00)"), Some(-250.0));
+        assert_eq!(parse_amount("-R 250"), Some(-250.0));
+        assert_eq!(parse_amount("R -250"), Some(-250.0));
+        assert_eq!(parse_amount("+250"), Some(250.0));
+        assert_eq!(parse_amount("--250"), None);
+        assert_eq!(parse_amount("-(250)"), None);
+        assert_eq!(parse_amount("-250-"), None);
+    }
+
+    #[test]
+    fn handles_credit_and_debit_suffixes() {
+        assert_eq!(parse_amount("250.00 CR"), Some(250.0));
+        assert_eq!(parse_amount("250.00 DR"), Some(-250.0));
+        assert_eq!(parse_amount("250.00dr"), Some(-250.0));
+        assert_eq!(parse_amount("-250.00 DR"), None);
+    }
+
+    #[test]
+    fn rejects_other_text() {
+        assert_eq!(parse_amount(""), None);
+        assert_eq!(parse_amount("n/a"), None);
+        assert_eq!(parse_amount("INV-2026-03"), None);
+        assert_eq!(parse_amount("12-05"), None);
+        assert_eq!(parse_amount("250 paid"), None);
+        assert_eq!(parse_amount("Invoice 250"), None);
+    }
+}